@@ -1,18 +1,27 @@
+pub mod cancel;
+pub mod change_notify;
 pub mod close;
 pub mod create;
+pub mod echo;
 pub mod error;
 pub mod flush;
+pub mod ioctl;
+pub mod lock;
 pub mod logoff;
 pub mod negotiate;
+pub mod oplock_break;
+pub mod query_directory;
+pub mod query_info;
 pub mod read;
 pub mod session_setup;
+pub mod set_info;
 pub mod tree_connect;
 pub mod tree_disconnect;
 pub mod write;
 
 use crate::header::Command;
 use crate::ntstatus::NTStatus;
-use crate::Dialect;
+use crate::{Dialect, Serialize};
 use num_derive::FromPrimitive;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
@@ -20,12 +29,22 @@ pub enum RequestBody<'a> {
     Negotiate(negotiate::Request<'a>),
     SessionSetup(session_setup::Request<'a>),
     Logoff,
-    TreeConnect(tree_connect::Request),
+    TreeConnect(tree_connect::Request<'a>),
     TreeDisconnect,
-    Create(create::Request),
+    Create(create::Request<'a>),
     Close(close::Request),
     Flush(flush::Request),
     Read(read::Request<'a>),
+    Write(write::Request<'a>),
+    Lock(lock::Request),
+    Ioctl(ioctl::Request<'a>),
+    QueryDirectory(query_directory::Request),
+    QueryInfo(query_info::Request<'a>),
+    SetInfo(set_info::Request<'a>),
+    ChangeNotify(change_notify::Request),
+    OplockBreak(oplock_break::Request),
+    Cancel,
+    Echo,
     NotImplemented { command: Command, body: &'a [u8] },
 }
 
@@ -36,11 +55,20 @@ pub enum ResponseBody<'a> {
     Logoff,
     TreeConnect(tree_connect::Response),
     TreeDisconnect,
-    Create(create::Response),
+    Create(create::Response<'a>),
     Close(close::Response),
     Flush,
-    Error(error::Response),
+    Error(error::Response<'a>),
     Read(read::Response<'a>),
+    Write(write::Response<'a>),
+    Lock,
+    Ioctl(ioctl::Response<'a>),
+    QueryDirectory(query_directory::Response<'a>),
+    QueryInfo(query_info::Response<'a>),
+    SetInfo,
+    ChangeNotify(change_notify::Response),
+    OplockBreak(oplock_break::Response),
+    Echo,
     NotImplemented { command: Command, body: &'a [u8] },
 }
 
@@ -62,14 +90,18 @@ pub enum Channel<'a> {
 
 pub trait Body<'a>
 where
-    Self: Sized,
+    Self: Sized + Serialize,
 {
     fn parse(
         body: &'a [u8],
         dialect: Dialect,
         command: Command,
         status: Option<NTStatus>,
+        message_id: u64,
     ) -> Result<Self, nom::Err<&'a [u8]>>;
+
+    /// The command that needs to be put into the header of this body.
+    fn command(&self) -> Command;
 }
 
 impl<'a> Body<'a> for RequestBody<'a> {
@@ -78,6 +110,7 @@ impl<'a> Body<'a> for RequestBody<'a> {
         dialect: Dialect,
         command: Command,
         _status: Option<NTStatus>,
+        _message_id: u64,
     ) -> Result<Self, nom::Err<&'a [u8]>> {
         let cmd = match command {
             Command::Negotiate => RequestBody::Negotiate(negotiate::parse(body)?.1),
@@ -97,24 +130,199 @@ impl<'a> Body<'a> for RequestBody<'a> {
             Command::Close => RequestBody::Close(close::parse_request(body)?.1),
             Command::Flush => RequestBody::Flush(flush::parse_request(body)?.1),
             Command::Read => RequestBody::Read(read::parse_request(body, dialect)?.1),
-            _ => RequestBody::NotImplemented { command, body },
+            Command::Write => RequestBody::Write(write::parse_request(body, dialect)?.1),
+            Command::Lock => RequestBody::Lock(lock::parse_request(body, dialect)?.1),
+            Command::Ioctl => RequestBody::Ioctl(ioctl::parse_request(body)?.1),
+            Command::QueryDirectory => {
+                RequestBody::QueryDirectory(query_directory::parse_request(body)?.1)
+            }
+            Command::QueryInfo => RequestBody::QueryInfo(query_info::parse_request(body)?.1),
+            Command::SetInfo => RequestBody::SetInfo(set_info::parse_request(body)?.1),
+            Command::ChangeNotify => {
+                RequestBody::ChangeNotify(change_notify::parse_request(body)?.1)
+            }
+            Command::OplockBreak => RequestBody::OplockBreak(oplock_break::parse_request(body)?.1),
+            Command::Cancel => {
+                cancel::parse_request(body)?;
+                RequestBody::Cancel
+            }
+            Command::Echo => {
+                echo::parse_request(body)?;
+                RequestBody::Echo
+            }
         };
         Ok(cmd)
     }
+
+    fn command(&self) -> Command {
+        match self {
+            RequestBody::Negotiate(_) => Command::Negotiate,
+            RequestBody::SessionSetup(_) => Command::SessionSetup,
+            RequestBody::Logoff => Command::Logoff,
+            RequestBody::TreeConnect(_) => Command::TreeConnect,
+            RequestBody::TreeDisconnect => Command::TreeDisconnect,
+            RequestBody::Create(_) => Command::Create,
+            RequestBody::Close(_) => Command::Close,
+            RequestBody::Flush(_) => Command::Flush,
+            RequestBody::Read(_) => Command::Read,
+            RequestBody::Write(_) => Command::Write,
+            RequestBody::Lock(_) => Command::Lock,
+            RequestBody::Ioctl(_) => Command::Ioctl,
+            RequestBody::QueryDirectory(_) => Command::QueryDirectory,
+            RequestBody::QueryInfo(_) => Command::QueryInfo,
+            RequestBody::SetInfo(_) => Command::SetInfo,
+            RequestBody::ChangeNotify(_) => Command::ChangeNotify,
+            RequestBody::OplockBreak(_) => Command::OplockBreak,
+            RequestBody::Cancel => Command::Cancel,
+            RequestBody::Echo => Command::Echo,
+            RequestBody::NotImplemented { command, .. } => *command,
+        }
+    }
+}
+
+impl Serialize for RequestBody<'_> {
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect) {
+        match self {
+            RequestBody::Negotiate(body) => body.write_to(out, dialect),
+            RequestBody::SessionSetup(body) => body.write_to(out, dialect),
+            RequestBody::Logoff => logoff::write_request(out),
+            RequestBody::TreeConnect(body) => body.write_to(out, dialect),
+            RequestBody::TreeDisconnect => tree_disconnect::write_request(out),
+            RequestBody::Create(body) => body.write_to(out, dialect),
+            RequestBody::Close(body) => body.write_to(out, dialect),
+            RequestBody::Flush(body) => body.write_to(out, dialect),
+            RequestBody::Read(body) => body.write_to(out, dialect),
+            RequestBody::Write(body) => body.write_to(out, dialect),
+            RequestBody::Lock(body) => body.write_to(out, dialect),
+            RequestBody::Ioctl(body) => body.write_to(out, dialect),
+            RequestBody::QueryDirectory(body) => body.write_to(out, dialect),
+            RequestBody::QueryInfo(body) => body.write_to(out, dialect),
+            RequestBody::SetInfo(body) => body.write_to(out, dialect),
+            RequestBody::ChangeNotify(body) => body.write_to(out, dialect),
+            RequestBody::OplockBreak(body) => body.write_to(out, dialect),
+            RequestBody::Cancel => cancel::write_request(out),
+            RequestBody::Echo => echo::write_request(out),
+            RequestBody::NotImplemented { body, .. } => out.extend_from_slice(body),
+        }
+    }
 }
 
 impl<'a> Body<'a> for ResponseBody<'a> {
     fn parse(
         body: &'a [u8],
-        _dialect: Dialect,
+        dialect: Dialect,
         command: Command,
         status: Option<NTStatus>,
+        message_id: u64,
     ) -> Result<Self, nom::Err<&'a [u8]>> {
         let status = status.unwrap();
-        if !status.is_success() {
-            return Ok(ResponseBody::NotImplemented { command, body });
+        if error::is_error_response(command, status, body) {
+            return Ok(ResponseBody::Error(
+                error::parse_response(body, command, status)?.1,
+            ));
+        }
+        let cmd = match command {
+            Command::Negotiate => ResponseBody::Negotiate(negotiate::parse_response(body)?.1),
+            Command::SessionSetup => {
+                ResponseBody::SessionSetup(session_setup::parse_response(body)?.1)
+            }
+            Command::Logoff => {
+                logoff::parse_response(body)?;
+                ResponseBody::Logoff
+            }
+            Command::TreeConnect => {
+                ResponseBody::TreeConnect(tree_connect::parse_response(body)?.1)
+            }
+            Command::TreeDisconnect => {
+                tree_disconnect::parse_response(body)?;
+                ResponseBody::TreeDisconnect
+            }
+            Command::Create => ResponseBody::Create(create::parse_response(body, dialect)?.1),
+            Command::Close => ResponseBody::Close(close::parse_response(body)?.1),
+            Command::Flush => {
+                flush::parse_response(body)?;
+                ResponseBody::Flush
+            }
+            Command::Read => ResponseBody::Read(read::parse_response(body)?.1),
+            Command::Write => ResponseBody::Write(write::parse_response(body)?.1),
+            Command::Lock => {
+                lock::parse_response(body)?;
+                ResponseBody::Lock
+            }
+            Command::Ioctl => ResponseBody::Ioctl(ioctl::parse_response(body)?.1),
+            Command::QueryDirectory => {
+                ResponseBody::QueryDirectory(query_directory::parse_response(body)?.1)
+            }
+            Command::QueryInfo => ResponseBody::QueryInfo(query_info::parse_response(body)?.1),
+            Command::SetInfo => {
+                set_info::parse_response(body)?;
+                ResponseBody::SetInfo
+            }
+            Command::ChangeNotify => {
+                ResponseBody::ChangeNotify(change_notify::parse_response(body)?.1)
+            }
+            Command::OplockBreak => {
+                ResponseBody::OplockBreak(oplock_break::parse_response(body, message_id)?.1)
+            }
+            Command::Echo => {
+                echo::parse_response(body)?;
+                ResponseBody::Echo
+            }
+            _ => ResponseBody::NotImplemented { command, body },
+        };
+        Ok(cmd)
+    }
+
+    fn command(&self) -> Command {
+        match self {
+            ResponseBody::Negotiate(_) => Command::Negotiate,
+            ResponseBody::SessionSetup(_) => Command::SessionSetup,
+            ResponseBody::Logoff => Command::Logoff,
+            ResponseBody::TreeConnect(_) => Command::TreeConnect,
+            ResponseBody::TreeDisconnect => Command::TreeDisconnect,
+            ResponseBody::Create(_) => Command::Create,
+            ResponseBody::Close(_) => Command::Close,
+            ResponseBody::Flush => Command::Flush,
+            ResponseBody::Error(body) => body.command,
+            ResponseBody::Read(_) => Command::Read,
+            ResponseBody::Write(_) => Command::Write,
+            ResponseBody::Lock => Command::Lock,
+            ResponseBody::Ioctl(_) => Command::Ioctl,
+            ResponseBody::QueryDirectory(_) => Command::QueryDirectory,
+            ResponseBody::QueryInfo(_) => Command::QueryInfo,
+            ResponseBody::SetInfo => Command::SetInfo,
+            ResponseBody::ChangeNotify(_) => Command::ChangeNotify,
+            ResponseBody::OplockBreak(_) => Command::OplockBreak,
+            ResponseBody::Echo => Command::Echo,
+            ResponseBody::NotImplemented { command, .. } => *command,
+        }
+    }
+}
+
+impl Serialize for ResponseBody<'_> {
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect) {
+        match self {
+            ResponseBody::Negotiate(body) => body.write_to(out, dialect),
+            ResponseBody::SessionSetup(body) => body.write_to(out, dialect),
+            ResponseBody::Logoff => logoff::write_response(out),
+            ResponseBody::TreeConnect(body) => body.write_to(out, dialect),
+            ResponseBody::TreeDisconnect => tree_disconnect::write_response(out),
+            ResponseBody::Create(body) => body.write_to(out, dialect),
+            ResponseBody::Close(body) => body.write_to(out, dialect),
+            ResponseBody::Flush => flush::write_response(out),
+            ResponseBody::Error(body) => body.write_to(out, dialect),
+            ResponseBody::Read(body) => body.write_to(out, dialect),
+            ResponseBody::Write(body) => body.write_to(out, dialect),
+            ResponseBody::Lock => lock::write_response(out),
+            ResponseBody::Ioctl(body) => body.write_to(out, dialect),
+            ResponseBody::QueryDirectory(body) => body.write_to(out, dialect),
+            ResponseBody::QueryInfo(body) => body.write_to(out, dialect),
+            ResponseBody::SetInfo => set_info::write_response(out),
+            ResponseBody::ChangeNotify(body) => body.write_to(out, dialect),
+            ResponseBody::OplockBreak(body) => body.write_to(out, dialect),
+            ResponseBody::Echo => echo::write_response(out),
+            ResponseBody::NotImplemented { body, .. } => out.extend_from_slice(body),
         }
-        Ok(ResponseBody::Error(error::Response { status, command }))
     }
 }
 
@@ -125,3 +333,20 @@ fn create_channel(buffer: &[u8], channel_type: ChannelType) -> Channel<'_> {
         ChannelType::RdmaV1Invalidate => Channel::RdmaV1Invalidate(buffer),
     }
 }
+
+impl<'a> Channel<'a> {
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            Channel::None => ChannelType::None,
+            Channel::RdmaV1(_) => ChannelType::RdmaV1,
+            Channel::RdmaV1Invalidate(_) => ChannelType::RdmaV1Invalidate,
+        }
+    }
+
+    fn buffer(&self) -> &'a [u8] {
+        match self {
+            Channel::None => &[],
+            Channel::RdmaV1(buf) | Channel::RdmaV1Invalidate(buf) => buf,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::header::{self, SyncType};
+use nom::*;
+
+const REQUEST_STRUCTURE_SIZE: u16 = 4;
+
+/// A cancel request has no fields and is never answered.
+///
+/// The request to be canceled is identified by the header alone: the `message_id`
+/// of the original request for `SyncType::Sync` or its `async_id` for
+/// `SyncType::Async` once the server sent an interim response.
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_request(data: &[u8]) -> IResult<&[u8], ()> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
+        take!(2) >> /* reserved */
+        (())
+    )
+}
+
+pub fn write_request(out: &mut Vec<u8>) {
+    out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+    out.extend_from_slice(&[0; 2]); /* reserved */
+}
+
+/// Returns whether the CANCEL request with header `cancel` targets the request
+/// with header `target`.
+///
+/// `async_id` is the id the server assigned to `target` in its interim response,
+/// if it sent one. A synchronous CANCEL matches on the `message_id` of `target`
+/// while an asynchronous one matches on `async_id`.
+pub fn cancels(cancel: &header::Request, target: &header::Request, async_id: Option<u64>) -> bool {
+    match cancel.sync_type {
+        SyncType::Sync { .. } => cancel.message_id == target.message_id,
+        SyncType::Async { async_id: id } => async_id == Some(id),
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{string_to_utf16le, utf16le_to_string, Dialect, FileId, Serialize};
+use bitflags::bitflags;
+use nom::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+const REQUEST_STRUCTURE_SIZE: u16 = 32;
+const RESPONSE_STRUCTURE_SIZE: u16 = 9;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
+const WATCH_TREE: u16 = 0x01;
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Request {
+    pub watch_tree: bool,
+    pub output_buffer_length: u32,
+    pub file_id: FileId,
+    pub completion_filter: CompletionFilter,
+}
+
+/// The final response to a change notify request.
+///
+/// It is usually sent asynchronously after an interim response with `StatusPending`.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Response {
+    pub changes: Vec<FileNotifyInformation>,
+}
+
+bitflags! {
+    pub struct CompletionFilter: u32 {
+        const FILE_NAME = 0x0001;
+        const DIR_NAME = 0x0002;
+        const ATTRIBUTES = 0x0004;
+        const SIZE = 0x0008;
+        const LAST_WRITE = 0x0010;
+        const LAST_ACCESS = 0x0020;
+        const CREATION = 0x0040;
+        const EA = 0x0080;
+        const SECURITY = 0x0100;
+        const STREAM_NAME = 0x0200;
+        const STREAM_SIZE = 0x0400;
+        const STREAM_WRITE = 0x0800;
+    }
+}
+
+#[repr(u32)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Added = 0x01,
+    Removed = 0x02,
+    Modified = 0x03,
+    RenamedOldName = 0x04,
+    RenamedNewName = 0x05,
+    AddedStream = 0x06,
+    RemovedStream = 0x07,
+    ModifiedStream = 0x08,
+    RemovedByDelete = 0x09,
+    IdNotTunnelled = 0x0A,
+    TunnelledIdCollision = 0x0B,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileNotifyInformation {
+    pub action: Action,
+    /// Path relative to the watched directory
+    pub file_name: String,
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
+        flags: verify!(le_u16, |x| x & !WATCH_TREE == 0) >>
+        output_buffer_length: le_u32 >>
+        file_id: map!(take!(16), FileId::from_slice) >>
+        completion_filter: map_opt!(le_u32, CompletionFilter::from_bits) >>
+        take!(4) >> /* reserved */
+        (Request {
+            watch_tree: flags & WATCH_TREE != 0,
+            output_buffer_length,
+            file_id,
+            completion_filter,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_file_notify_information(data: &[u8]) -> IResult<&[u8], (u32, FileNotifyInformation)> {
+    do_parse!(data,
+        next_entry_offset: le_u32 >>
+        action: map_opt!(le_u32, Action::from_u32) >>
+        file_name_length: le_u32 >>
+        file_name: map_res!(take!(file_name_length), utf16le_to_string) >>
+        ((next_entry_offset, FileNotifyInformation {
+            action,
+            file_name,
+        }))
+    )
+}
+
+fn parse_changes(data: &[u8]) -> IResult<&[u8], Vec<FileNotifyInformation>> {
+    let mut changes = Vec::new();
+    let mut cur = data;
+    while !cur.is_empty() {
+        let (remaining, (next, change)) = parse_file_notify_information(cur)?;
+        changes.push(change);
+        if next == 0 {
+            return Ok((remaining, changes));
+        }
+        if (next as usize) < cur.len() - remaining.len() {
+            return Err(Err::Error(error_position!(cur, ErrorKind::Verify)));
+        }
+        cur = take!(cur, next)?.0;
+    }
+    Ok((cur, changes))
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        output_buffer_offset: le_u16 >>
+        output_buffer_length: le_u32 >>
+        verify!(
+            value!(output_buffer_offset),
+            |offset| output_buffer_length == 0 || offset >= RESPONSE_CONSTANT_SIZE
+        ) >>
+        changes: preceded!(
+            take!(if output_buffer_length > 0 {
+                output_buffer_offset - RESPONSE_CONSTANT_SIZE
+            } else {
+                0
+            }),
+            length_value!(value!(output_buffer_length), parse_changes)
+        ) >>
+        (Response {
+            changes,
+        })
+    )
+}
+
+impl Serialize for Request {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let flags = if self.watch_tree { WATCH_TREE } else { 0 };
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&self.output_buffer_length.to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(&self.completion_filter.bits().to_le_bytes());
+        out.extend_from_slice(&[0; 4]); /* reserved */
+    }
+}
+
+impl Serialize for Response {
+    /// Every entry but the last is padded to 4 bytes and linked to its successor
+    /// with `NextEntryOffset`.
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&RESPONSE_CONSTANT_SIZE.to_le_bytes());
+        let length_pos = out.len();
+        out.extend_from_slice(&[0; 4]); /* output buffer length */
+        let buffer_start = out.len();
+        for (i, change) in self.changes.iter().enumerate() {
+            let start = out.len();
+            let name = string_to_utf16le(&change.file_name);
+            out.extend_from_slice(&[0; 4]); /* next entry offset */
+            out.extend_from_slice(&(change.action as u32).to_le_bytes());
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(&name);
+            if i + 1 < self.changes.len() {
+                out.resize(out.len() + (4 - (out.len() - start) % 4) % 4, 0);
+                let next = (out.len() - start) as u32;
+                out[start..start + 4].copy_from_slice(&next.to_le_bytes());
+            }
+        }
+        let length = (out.len() - buffer_start) as u32;
+        out[length_pos..length_pos + 4].copy_from_slice(&length.to_le_bytes());
+        if self.changes.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use crate::FileId;
+use crate::{
+    filetime_to_systemtime, systemtime_to_filetime, Dialect, FileAttributes, FileId, Serialize,
+};
 use bitflags::bitflags;
 use nom::*;
+use std::convert::TryFrom;
 use std::time::SystemTime;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 24;
+const RESPONSE_STRUCTURE_SIZE: u16 = 60;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Request {
@@ -20,7 +24,7 @@ pub struct Response {
     pub change_time: SystemTime,
     pub allocation_size: u64,
     pub end_of_file: u64,
-    pub file_attributes: u32, // TODO: add type
+    pub file_attributes: FileAttributes,
 }
 
 bitflags! {
@@ -30,11 +34,11 @@ bitflags! {
 }
 
 #[rustfmt::skip]
-#[allow(clippy::cyclomatic_complexity, clippy::cast_possible_truncation)]
+#[allow(clippy::cyclomatic_complexity)]
 pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
     do_parse!(data,
         verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
-        flags: map_opt!(le_u16, |x| Flags::from_bits(x as u8)) >>
+        flags: map_opt!(le_u16, |x| u8::try_from(x).ok().and_then(Flags::from_bits)) >>
         take!(4) >> /* reserved */
         file_id: map!(take!(16), FileId::from_slice) >>
         (Request {
@@ -43,3 +47,62 @@ pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
         })
     )
 }
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        flags: map_opt!(le_u16, |x| u8::try_from(x).ok().and_then(Flags::from_bits)) >>
+        take!(4) >> /* reserved */
+        creation_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_access_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_write_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        change_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        allocation_size: le_u64 >>
+        end_of_file: le_u64 >>
+        file_attributes: map!(le_u32, FileAttributes::from_bits_truncate) >>
+        (Response {
+            postquery_attrib: flags.contains(Flags::POSTQUERY_ATTRIB),
+            creation_time,
+            last_access_time,
+            last_write_time,
+            change_time,
+            allocation_size,
+            end_of_file,
+            file_attributes,
+        })
+    )
+}
+
+impl Serialize for Request {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut flags = Flags::empty();
+        flags.set(Flags::POSTQUERY_ATTRIB, self.postquery_attrib);
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&u16::from(flags.bits()).to_le_bytes());
+        out.extend_from_slice(&[0; 4]); /* reserved */
+        out.extend_from_slice(&*self.file_id);
+    }
+}
+
+impl Serialize for Response {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut flags = Flags::empty();
+        flags.set(Flags::POSTQUERY_ATTRIB, self.postquery_attrib);
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&u16::from(flags.bits()).to_le_bytes());
+        out.extend_from_slice(&[0; 4]); /* reserved */
+        for time in &[
+            self.creation_time,
+            self.last_access_time,
+            self.last_write_time,
+            self.change_time,
+        ] {
+            out.extend_from_slice(&systemtime_to_filetime(*time).to_le_bytes());
+        }
+        out.extend_from_slice(&self.allocation_size.to_le_bytes());
+        out.extend_from_slice(&self.end_of_file.to_le_bytes());
+        out.extend_from_slice(&self.file_attributes.bits().to_le_bytes());
+    }
+}
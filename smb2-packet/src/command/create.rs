@@ -1,46 +1,74 @@
+use crate::ntstatus::NTStatus;
 use crate::utf16le_to_string;
-use crate::Dialect;
-use crate::FileId;
+use crate::{
+    align8, filetime_to_systemtime, pad8, string_to_utf16le, systemtime_to_filetime, Dialect,
+    Serialize,
+};
+use crate::{AccessMask, FileAttributes, FileId};
 use bitflags::bitflags;
 use nom::*;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
+use std::convert::{TryFrom, TryInto};
+use std::ops::Deref;
 use std::time::SystemTime;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 57;
 const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 89;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
+const CONTEXT_HEADER_SIZE: u16 = 16;
+const MAXIMAL_ACCESS: &[u8] = b"MxAc";
+const QUERY_ON_DISK_ID: &[u8] = b"QFid";
+const DURABLE_HANDLE_REQUEST: &[u8] = b"DHnQ";
+const DURABLE_HANDLE_RECONNECT: &[u8] = b"DHnC";
+const DURABLE_HANDLE_REQUEST_V2: &[u8] = b"DH2Q";
+const DURABLE_HANDLE_RECONNECT_V2: &[u8] = b"DH2C";
+const REQUEST_LEASE: &[u8] = b"RqLs";
+const TIMEWARP_TOKEN: &[u8] = b"TWrp";
+const EXTENDED_ATTRIBUTES: &[u8] = b"ExtA";
+const SECURITY_DESCRIPTOR: &[u8] = b"SecD";
+const ALLOCATION_SIZE: &[u8] = b"AlSi";
+const APPLE: &[u8] = b"AAPL";
+const LEASE_V1_SIZE: u32 = 32;
+const LEASE_V2_SIZE: u32 = 52;
+const LEASE_FLAG_BREAK_IN_PROGRESS: u32 = 0x02;
+const LEASE_FLAG_PARENT_LEASE_KEY_SET: u32 = 0x04;
+const DURABLE_HANDLE_FLAG_PERSISTENT: u32 = 0x02;
+const APPLE_SERVER_QUERY: u32 = 0x01;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-pub struct Request {
+pub struct Request<'a> {
     pub requested_oplock_level: OplockLevel,
     pub impersonation_level: ImpersonationLevel,
-    pub desired_access: u32,  // TODO: add proper type
-    pub file_attributes: u32, // TODO: add type
+    pub desired_access: AccessMask,
+    pub file_attributes: FileAttributes,
     pub share_access: ShareAccess,
     pub create_disposition: Disposition,
-    pub create_options: u32, // TODO: add type
+    pub create_options: CreateOptions,
     pub name: String,
-    // TODO: add contexts
+    pub contexts: Vec<CreateContext<'a>>,
 }
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-pub struct Response {
+pub struct Response<'a> {
     pub oplock_level: OplockLevel,
     pub flags: Flags,
     pub create_action: Action,
     pub creation_time: SystemTime,
     pub last_access_time: SystemTime,
+    pub last_write_time: SystemTime,
     pub change_time: SystemTime,
     pub allocation_size: u64,
     pub end_of_file: u64,
-    pub file_attributes: u32, // TODO: add type
+    pub file_attributes: FileAttributes,
     pub file_id: FileId,
-    // TODO: add create contexts
+    pub contexts: Vec<CreateContext<'a>>,
 }
 
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum OplockLevel {
     No = 0x00,
     II = 0x01,
@@ -49,9 +77,45 @@ pub enum OplockLevel {
     Lease = 0xFF,
 }
 
+/// Client chosen identifier of a lease.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LeaseKey {
+    data: [u8; 16],
+}
+
+impl LeaseKey {
+    pub(crate) fn from_slice(key: &[u8]) -> Self {
+        let data: [u8; 16] = key.try_into().unwrap();
+        Self { data }
+    }
+}
+
+impl From<[u8; 16]> for LeaseKey {
+    fn from(data: [u8; 16]) -> Self {
+        Self { data }
+    }
+}
+
+impl Deref for LeaseKey {
+    type Target = [u8; 16];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+bitflags! {
+    pub struct LeaseState: u32 {
+        const READ_CACHING = 0x01;
+        const HANDLE_CACHING = 0x02;
+        const WRITE_CACHING = 0x04;
+    }
+}
+
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum ImpersonationLevel {
     Anonymous = 0x00,
     Identification = 0x01,
@@ -69,7 +133,7 @@ bitflags! {
 
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum Disposition {
     Supersede = 0x00,
     Open = 0x01,
@@ -79,6 +143,32 @@ pub enum Disposition {
     OverwriteIf = 0x05,
 }
 
+bitflags! {
+    pub struct CreateOptions: u32 {
+        const DIRECTORY_FILE = 0x0000_0001;
+        const WRITE_THROUGH = 0x0000_0002;
+        const SEQUENTIAL_ONLY = 0x0000_0004;
+        const NO_INTERMEDIATE_BUFFERING = 0x0000_0008;
+        const SYNCHRONOUS_IO_ALERT = 0x0000_0010;
+        const SYNCHRONOUS_IO_NONALERT = 0x0000_0020;
+        const NON_DIRECTORY_FILE = 0x0000_0040;
+        const COMPLETE_IF_OPLOCKED = 0x0000_0100;
+        const NO_EA_KNOWLEDGE = 0x0000_0200;
+        const OPEN_REMOTE_INSTANCE = 0x0000_0400;
+        const RANDOM_ACCESS = 0x0000_0800;
+        const DELETE_ON_CLOSE = 0x0000_1000;
+        const OPEN_BY_FILE_ID = 0x0000_2000;
+        const OPEN_FOR_BACKUP_INTENT = 0x0000_4000;
+        const NO_COMPRESSION = 0x0000_8000;
+        const OPEN_REQUIRING_OPLOCK = 0x0001_0000;
+        const DISALLOW_EXCLUSIVE = 0x0002_0000;
+        const RESERVE_OPFILTER = 0x0010_0000;
+        const OPEN_REPARSE_POINT = 0x0020_0000;
+        const OPEN_NO_RECALL = 0x0040_0000;
+        const OPEN_FOR_FREE_SPACE_QUERY = 0x0080_0000;
+    }
+}
+
 bitflags! {
     pub struct Flags: u8 {
         const REPARSEPOINT = 0x01;
@@ -87,7 +177,7 @@ bitflags! {
 
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum Action {
     Superseded = 0x00,
     Opened = 0x01,
@@ -95,6 +185,584 @@ pub enum Action {
     Overwritten = 0x03,
 }
 
+/// Create contexts that can be attached to create requests and responses.
+///
+/// Some contexts share their name between request and response but differ in
+/// their content which is reflected by separate variants.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum CreateContext<'a> {
+    /// Asks for the maximal access of the user optionally as of a point in time
+    MaximalAccessRequest {
+        timestamp: Option<SystemTime>,
+    },
+    MaximalAccessResponse {
+        query_status: NTStatus,
+        maximal_access: AccessMask,
+    },
+    QueryOnDiskId,
+    OnDiskId {
+        disk_file_id: u64,
+        volume_id: u64,
+    },
+    DurableHandleRequest,
+    DurableHandleResponse,
+    DurableHandleReconnect(FileId),
+    DurableHandleRequestV2(DurableHandleRequestV2),
+    DurableHandleResponseV2 {
+        timeout: u32,
+        persistent: bool,
+    },
+    DurableHandleReconnectV2(DurableHandleReconnectV2),
+    /// Lease request or the lease granted by the server
+    Lease(Lease),
+    LeaseV2(LeaseV2),
+    /// Opens a previous version of the file
+    Timewarp(SystemTime),
+    ExtendedAttributes(Vec<ExtendedAttribute<'a>>),
+    /// Self relative security descriptor applied to a newly created file
+    SecurityDescriptor(&'a [u8]),
+    AllocationSize(u64),
+    AppleServerQuery(AppleServerQuery),
+    AppleServerQueryResponse(AppleServerQueryResponse),
+    Unknown {
+        name: &'a [u8],
+        data: &'a [u8],
+    },
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DurableHandleRequestV2 {
+    /// Milliseconds the handle is preserved after a disconnect
+    pub timeout: u32,
+    pub persistent: bool,
+    pub create_guid: [u8; 16],
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DurableHandleReconnectV2 {
+    pub file_id: FileId,
+    pub create_guid: [u8; 16],
+    pub persistent: bool,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Lease {
+    pub lease_key: LeaseKey,
+    pub lease_state: LeaseState,
+    /// Only set by the server when the lease is being broken
+    pub break_in_progress: bool,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct LeaseV2 {
+    pub lease_key: LeaseKey,
+    pub lease_state: LeaseState,
+    pub break_in_progress: bool,
+    pub parent_lease_key: Option<LeaseKey>,
+    pub epoch: u16,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ExtendedAttribute<'a> {
+    pub flags: u8,
+    pub name: String,
+    pub value: &'a [u8],
+}
+
+bitflags! {
+    pub struct AppleQueryBitmap: u64 {
+        const SERVER_CAPABILITIES = 0x01;
+        const VOLUME_CAPABILITIES = 0x02;
+        const MODEL_INFO = 0x04;
+    }
+}
+
+/// Query of the AAPL context used by macOS clients to discover server extensions.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct AppleServerQuery {
+    pub request_bitmap: AppleQueryBitmap,
+    pub client_capabilities: u64,
+}
+
+/// Answer to `AppleServerQuery` where every field present is flagged in the reply bitmap.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct AppleServerQueryResponse {
+    pub server_capabilities: Option<u64>,
+    pub volume_capabilities: Option<u64>,
+    pub model: Option<String>,
+}
+
+impl<'a> CreateContext<'a> {
+    #[rustfmt::skip]
+    #[allow(clippy::cyclomatic_complexity)]
+    fn parse_request(data: &'a [u8], name: &'a [u8]) -> IResult<&'a [u8], CreateContext<'a>> {
+        match name {
+            MAXIMAL_ACCESS => map!(data,
+                cond_with_error!(!data.is_empty(), map_opt!(le_u64, filetime_to_systemtime)),
+                |timestamp| CreateContext::MaximalAccessRequest { timestamp }
+            ),
+            QUERY_ON_DISK_ID => value!(data, CreateContext::QueryOnDiskId),
+            DURABLE_HANDLE_REQUEST => value!(data, CreateContext::DurableHandleRequest, take!(16)),
+            DURABLE_HANDLE_RECONNECT => map!(data,
+                map!(take!(16), FileId::from_slice),
+                CreateContext::DurableHandleReconnect
+            ),
+            DURABLE_HANDLE_REQUEST_V2 => do_parse!(data,
+                timeout: le_u32 >>
+                flags: verify!(le_u32, |x| x & !DURABLE_HANDLE_FLAG_PERSISTENT == 0) >>
+                take!(8) >> /* reserved */
+                create_guid: map!(take!(16), to_guid) >>
+                (CreateContext::DurableHandleRequestV2(DurableHandleRequestV2 {
+                    timeout,
+                    persistent: flags & DURABLE_HANDLE_FLAG_PERSISTENT != 0,
+                    create_guid,
+                }))
+            ),
+            DURABLE_HANDLE_RECONNECT_V2 => do_parse!(data,
+                file_id: map!(take!(16), FileId::from_slice) >>
+                create_guid: map!(take!(16), to_guid) >>
+                flags: verify!(le_u32, |x| x & !DURABLE_HANDLE_FLAG_PERSISTENT == 0) >>
+                (CreateContext::DurableHandleReconnectV2(DurableHandleReconnectV2 {
+                    file_id,
+                    create_guid,
+                    persistent: flags & DURABLE_HANDLE_FLAG_PERSISTENT != 0,
+                }))
+            ),
+            REQUEST_LEASE => parse_lease(data),
+            TIMEWARP_TOKEN => map!(data,
+                map_opt!(le_u64, filetime_to_systemtime),
+                CreateContext::Timewarp
+            ),
+            EXTENDED_ATTRIBUTES => map!(data,
+                parse_extended_attributes,
+                CreateContext::ExtendedAttributes
+            ),
+            SECURITY_DESCRIPTOR => map!(data, rest, CreateContext::SecurityDescriptor),
+            ALLOCATION_SIZE => map!(data, le_u64, CreateContext::AllocationSize),
+            APPLE => alt!(data, parse_apple_server_query | apply!(parse_unknown, name)),
+            _ => parse_unknown(data, name),
+        }
+    }
+
+    #[rustfmt::skip]
+    #[allow(clippy::cyclomatic_complexity)]
+    fn parse_response(data: &'a [u8], name: &'a [u8]) -> IResult<&'a [u8], CreateContext<'a>> {
+        match name {
+            MAXIMAL_ACCESS => do_parse!(data,
+                query_status: map_opt!(le_u32, FromPrimitive::from_u32) >>
+                maximal_access: map!(le_u32, AccessMask::from_bits_truncate) >>
+                (CreateContext::MaximalAccessResponse {
+                    query_status,
+                    maximal_access,
+                })
+            ),
+            QUERY_ON_DISK_ID => do_parse!(data,
+                disk_file_id: le_u64 >>
+                volume_id: le_u64 >>
+                take!(16) >> /* reserved */
+                (CreateContext::OnDiskId {
+                    disk_file_id,
+                    volume_id,
+                })
+            ),
+            DURABLE_HANDLE_REQUEST => value!(data, CreateContext::DurableHandleResponse, take!(8)),
+            DURABLE_HANDLE_REQUEST_V2 => do_parse!(data,
+                timeout: le_u32 >>
+                flags: verify!(le_u32, |x| x & !DURABLE_HANDLE_FLAG_PERSISTENT == 0) >>
+                (CreateContext::DurableHandleResponseV2 {
+                    timeout,
+                    persistent: flags & DURABLE_HANDLE_FLAG_PERSISTENT != 0,
+                })
+            ),
+            REQUEST_LEASE => parse_lease(data),
+            APPLE => alt!(data, parse_apple_server_query_response | apply!(parse_unknown, name)),
+            _ => parse_unknown(data, name),
+        }
+    }
+
+    fn name(&self) -> &'a [u8] {
+        match self {
+            CreateContext::MaximalAccessRequest { .. }
+            | CreateContext::MaximalAccessResponse { .. } => MAXIMAL_ACCESS,
+            CreateContext::QueryOnDiskId | CreateContext::OnDiskId { .. } => QUERY_ON_DISK_ID,
+            CreateContext::DurableHandleRequest | CreateContext::DurableHandleResponse => {
+                DURABLE_HANDLE_REQUEST
+            }
+            CreateContext::DurableHandleReconnect(_) => DURABLE_HANDLE_RECONNECT,
+            CreateContext::DurableHandleRequestV2(_)
+            | CreateContext::DurableHandleResponseV2 { .. } => DURABLE_HANDLE_REQUEST_V2,
+            CreateContext::DurableHandleReconnectV2(_) => DURABLE_HANDLE_RECONNECT_V2,
+            CreateContext::Lease(_) | CreateContext::LeaseV2(_) => REQUEST_LEASE,
+            CreateContext::Timewarp(_) => TIMEWARP_TOKEN,
+            CreateContext::ExtendedAttributes(_) => EXTENDED_ATTRIBUTES,
+            CreateContext::SecurityDescriptor(_) => SECURITY_DESCRIPTOR,
+            CreateContext::AllocationSize(_) => ALLOCATION_SIZE,
+            CreateContext::AppleServerQuery(_) | CreateContext::AppleServerQueryResponse(_) => {
+                APPLE
+            }
+            CreateContext::Unknown { name, .. } => name,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_data(&self, out: &mut Vec<u8>) {
+        match self {
+            CreateContext::MaximalAccessRequest { timestamp } => {
+                if let Some(timestamp) = timestamp {
+                    out.extend_from_slice(&systemtime_to_filetime(*timestamp).to_le_bytes());
+                }
+            }
+            CreateContext::MaximalAccessResponse {
+                query_status,
+                maximal_access,
+            } => {
+                out.extend_from_slice(&(*query_status as u32).to_le_bytes());
+                out.extend_from_slice(&maximal_access.bits().to_le_bytes());
+            }
+            CreateContext::QueryOnDiskId => (),
+            CreateContext::OnDiskId {
+                disk_file_id,
+                volume_id,
+            } => {
+                out.extend_from_slice(&disk_file_id.to_le_bytes());
+                out.extend_from_slice(&volume_id.to_le_bytes());
+                out.extend_from_slice(&[0; 16]); /* reserved */
+            }
+            CreateContext::DurableHandleRequest => out.extend_from_slice(&[0; 16]),
+            CreateContext::DurableHandleResponse => out.extend_from_slice(&[0; 8]),
+            CreateContext::DurableHandleReconnect(file_id) => out.extend_from_slice(&**file_id),
+            CreateContext::DurableHandleRequestV2(request) => {
+                out.extend_from_slice(&request.timeout.to_le_bytes());
+                out.extend_from_slice(&durable_handle_flags(request.persistent).to_le_bytes());
+                out.extend_from_slice(&[0; 8]); /* reserved */
+                out.extend_from_slice(&request.create_guid);
+            }
+            CreateContext::DurableHandleResponseV2 {
+                timeout,
+                persistent,
+            } => {
+                out.extend_from_slice(&timeout.to_le_bytes());
+                out.extend_from_slice(&durable_handle_flags(*persistent).to_le_bytes());
+            }
+            CreateContext::DurableHandleReconnectV2(reconnect) => {
+                out.extend_from_slice(&*reconnect.file_id);
+                out.extend_from_slice(&reconnect.create_guid);
+                out.extend_from_slice(&durable_handle_flags(reconnect.persistent).to_le_bytes());
+            }
+            CreateContext::Lease(lease) => {
+                let flags = if lease.break_in_progress {
+                    LEASE_FLAG_BREAK_IN_PROGRESS
+                } else {
+                    0
+                };
+                out.extend_from_slice(&*lease.lease_key);
+                out.extend_from_slice(&lease.lease_state.bits().to_le_bytes());
+                out.extend_from_slice(&flags.to_le_bytes());
+                out.extend_from_slice(&[0; 8]); /* lease duration */
+            }
+            CreateContext::LeaseV2(lease) => write_lease_v2(out, lease),
+            CreateContext::Timewarp(timestamp) => {
+                out.extend_from_slice(&systemtime_to_filetime(*timestamp).to_le_bytes());
+            }
+            CreateContext::ExtendedAttributes(attributes) => {
+                write_extended_attributes(out, attributes);
+            }
+            CreateContext::SecurityDescriptor(data) | CreateContext::Unknown { data, .. } => {
+                out.extend_from_slice(data);
+            }
+            CreateContext::AllocationSize(size) => out.extend_from_slice(&size.to_le_bytes()),
+            CreateContext::AppleServerQuery(query) => {
+                out.extend_from_slice(&APPLE_SERVER_QUERY.to_le_bytes());
+                out.extend_from_slice(&[0; 4]); /* reserved */
+                out.extend_from_slice(&query.request_bitmap.bits().to_le_bytes());
+                out.extend_from_slice(&query.client_capabilities.to_le_bytes());
+            }
+            CreateContext::AppleServerQueryResponse(response) => {
+                write_apple_server_query_response(out, response);
+            }
+        }
+    }
+}
+
+fn write_lease_v2(out: &mut Vec<u8>, lease: &LeaseV2) {
+    let mut flags = 0;
+    if lease.break_in_progress {
+        flags |= LEASE_FLAG_BREAK_IN_PROGRESS;
+    }
+    if lease.parent_lease_key.is_some() {
+        flags |= LEASE_FLAG_PARENT_LEASE_KEY_SET;
+    }
+    out.extend_from_slice(&*lease.lease_key);
+    out.extend_from_slice(&lease.lease_state.bits().to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&[0; 8]); /* lease duration */
+    match lease.parent_lease_key {
+        Some(key) => out.extend_from_slice(&*key),
+        None => out.extend_from_slice(&[0; 16]),
+    }
+    out.extend_from_slice(&lease.epoch.to_le_bytes());
+    out.extend_from_slice(&[0; 2]); /* reserved */
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_apple_server_query_response(out: &mut Vec<u8>, response: &AppleServerQueryResponse) {
+    let mut bitmap = AppleQueryBitmap::empty();
+    bitmap.set(
+        AppleQueryBitmap::SERVER_CAPABILITIES,
+        response.server_capabilities.is_some(),
+    );
+    bitmap.set(
+        AppleQueryBitmap::VOLUME_CAPABILITIES,
+        response.volume_capabilities.is_some(),
+    );
+    bitmap.set(AppleQueryBitmap::MODEL_INFO, response.model.is_some());
+    out.extend_from_slice(&APPLE_SERVER_QUERY.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); /* reserved */
+    out.extend_from_slice(&bitmap.bits().to_le_bytes());
+    if let Some(capabilities) = response.server_capabilities {
+        out.extend_from_slice(&capabilities.to_le_bytes());
+    }
+    if let Some(capabilities) = response.volume_capabilities {
+        out.extend_from_slice(&capabilities.to_le_bytes());
+    }
+    if let Some(model) = &response.model {
+        let model = string_to_utf16le(model);
+        out.extend_from_slice(&[0; 4]); /* reserved */
+        out.extend_from_slice(&(model.len() as u32).to_le_bytes());
+        out.extend_from_slice(&model);
+    }
+}
+
+fn to_guid(data: &[u8]) -> [u8; 16] {
+    data.try_into().unwrap()
+}
+
+fn durable_handle_flags(persistent: bool) -> u32 {
+    if persistent {
+        DURABLE_HANDLE_FLAG_PERSISTENT
+    } else {
+        0
+    }
+}
+
+fn parse_unknown<'a>(data: &'a [u8], name: &'a [u8]) -> IResult<&'a [u8], CreateContext<'a>> {
+    map!(data, rest, |data| CreateContext::Unknown { name, data })
+}
+
+/// Version 1 and 2 leases share the same name and are told apart by their size.
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_lease(data: &[u8]) -> IResult<&[u8], CreateContext> {
+    do_parse!(data,
+        verify!(
+            value!(data.len()),
+            |x| x == LEASE_V1_SIZE as usize || x == LEASE_V2_SIZE as usize
+        ) >>
+        lease_key: map!(take!(16), LeaseKey::from_slice) >>
+        lease_state: map_opt!(le_u32, LeaseState::from_bits) >>
+        flags: verify!(
+            le_u32,
+            |x| x & !(LEASE_FLAG_BREAK_IN_PROGRESS | LEASE_FLAG_PARENT_LEASE_KEY_SET) == 0
+        ) >>
+        take!(8) >> /* lease duration */
+        v2: cond_with_error!(
+            data.len() == LEASE_V2_SIZE as usize,
+            do_parse!(
+                parent_lease_key: map!(take!(16), LeaseKey::from_slice) >>
+                epoch: le_u16 >>
+                take!(2) >> /* reserved */
+                ((parent_lease_key, epoch))
+            )
+        ) >>
+        (match v2 {
+            None => CreateContext::Lease(Lease {
+                lease_key,
+                lease_state,
+                break_in_progress: flags & LEASE_FLAG_BREAK_IN_PROGRESS != 0,
+            }),
+            Some((parent_lease_key, epoch)) => CreateContext::LeaseV2(LeaseV2 {
+                lease_key,
+                lease_state,
+                break_in_progress: flags & LEASE_FLAG_BREAK_IN_PROGRESS != 0,
+                parent_lease_key: if flags & LEASE_FLAG_PARENT_LEASE_KEY_SET != 0 {
+                    Some(parent_lease_key)
+                } else {
+                    None
+                },
+                epoch,
+            }),
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_extended_attribute(data: &[u8]) -> IResult<&[u8], (u32, ExtendedAttribute)> {
+    do_parse!(data,
+        next_entry_offset: le_u32 >>
+        flags: le_u8 >>
+        name_length: le_u8 >>
+        value_length: le_u16 >>
+        name: map_res!(take!(name_length), |name| std::str::from_utf8(name).map(str::to_owned)) >>
+        take!(1) >> /* null terminator */
+        value: take!(value_length) >>
+        ((next_entry_offset, ExtendedAttribute {
+            flags,
+            name,
+            value,
+        }))
+    )
+}
+
+fn parse_extended_attributes(data: &[u8]) -> IResult<&[u8], Vec<ExtendedAttribute>> {
+    let mut attributes = Vec::new();
+    let mut cur = data;
+    while !cur.is_empty() {
+        let (remaining, (next, attribute)) = parse_extended_attribute(cur)?;
+        attributes.push(attribute);
+        if next == 0 {
+            return Ok((remaining, attributes));
+        }
+        if (next as usize) < cur.len() - remaining.len() {
+            return Err(Err::Error(error_position!(cur, ErrorKind::Verify)));
+        }
+        cur = take!(cur, next)?.0;
+    }
+    Ok((cur, attributes))
+}
+
+/// Every attribute but the last is padded to 4 bytes.
+#[allow(clippy::cast_possible_truncation)]
+fn write_extended_attributes(out: &mut Vec<u8>, attributes: &[ExtendedAttribute]) {
+    for (i, attribute) in attributes.iter().enumerate() {
+        let start = out.len();
+        out.extend_from_slice(&[0; 4]); /* next entry offset */
+        out.push(attribute.flags);
+        out.push(attribute.name.len() as u8);
+        out.extend_from_slice(&(attribute.value.len() as u16).to_le_bytes());
+        out.extend_from_slice(attribute.name.as_bytes());
+        out.push(0); /* null terminator */
+        out.extend_from_slice(attribute.value);
+        if i + 1 < attributes.len() {
+            out.resize(out.len() + (4 - (out.len() - start) % 4) % 4, 0);
+            let next = (out.len() - start) as u32;
+            out[start..start + 4].copy_from_slice(&next.to_le_bytes());
+        }
+    }
+}
+
+#[rustfmt::skip]
+fn parse_apple_server_query(data: &[u8]) -> IResult<&[u8], CreateContext> {
+    do_parse!(data,
+        verify!(le_u32, |x| x == APPLE_SERVER_QUERY) >>
+        take!(4) >> /* reserved */
+        request_bitmap: map_opt!(le_u64, AppleQueryBitmap::from_bits) >>
+        client_capabilities: le_u64 >>
+        (CreateContext::AppleServerQuery(AppleServerQuery {
+            request_bitmap,
+            client_capabilities,
+        }))
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_apple_server_query_response(data: &[u8]) -> IResult<&[u8], CreateContext> {
+    do_parse!(data,
+        verify!(le_u32, |x| x == APPLE_SERVER_QUERY) >>
+        take!(4) >> /* reserved */
+        reply_bitmap: map_opt!(le_u64, AppleQueryBitmap::from_bits) >>
+        server_capabilities: cond_with_error!(
+            reply_bitmap.contains(AppleQueryBitmap::SERVER_CAPABILITIES),
+            le_u64
+        ) >>
+        volume_capabilities: cond_with_error!(
+            reply_bitmap.contains(AppleQueryBitmap::VOLUME_CAPABILITIES),
+            le_u64
+        ) >>
+        model: cond_with_error!(
+            reply_bitmap.contains(AppleQueryBitmap::MODEL_INFO),
+            do_parse!(
+                take!(4) >> /* reserved */
+                length: le_u32 >>
+                model: map_res!(take!(length), utf16le_to_string) >>
+                (model)
+            )
+        ) >>
+        (CreateContext::AppleServerQueryResponse(AppleServerQueryResponse {
+            server_capabilities,
+            volume_capabilities,
+            model,
+        }))
+    )
+}
+
+#[rustfmt::skip]
+fn parse_context_header(data: &[u8]) -> IResult<&[u8], (u32, u16, u16, u16, u32)> {
+    do_parse!(data,
+        next: le_u32 >>
+        name_offset: verify!(le_u16, |x| x >= CONTEXT_HEADER_SIZE) >>
+        name_length: le_u16 >>
+        take!(2) >> /* reserved */
+        data_offset: le_u16 >>
+        data_length: le_u32 >>
+        ((next, name_offset, name_length, data_offset, data_length))
+    )
+}
+
+/// Parses the chain of contexts which are linked by their `Next` field.
+fn parse_contexts(data: &[u8], is_response: bool) -> IResult<&[u8], Vec<CreateContext>> {
+    let mut contexts = Vec::new();
+    let mut cur = data;
+    loop {
+        let (_, (next, name_offset, name_length, data_offset, data_length)) =
+            parse_context_header(cur)?;
+        let (_, name) = preceded!(cur, take!(name_offset), take!(name_length))?;
+        let (_, context_data) = preceded!(cur, take!(data_offset), take!(data_length))?;
+        let (_, context) = if is_response {
+            CreateContext::parse_response(context_data, name)?
+        } else {
+            CreateContext::parse_request(context_data, name)?
+        };
+        contexts.push(context);
+        if next == 0 {
+            return Ok((&cur[cur.len()..], contexts));
+        }
+        if next < u32::from(CONTEXT_HEADER_SIZE) {
+            return Err(Err::Error(error_position!(cur, ErrorKind::Verify)));
+        }
+        cur = take!(cur, next)?.0;
+    }
+}
+
+/// Writes the contexts each padded to 8 bytes.
+///
+/// `out` must be 8 byte aligned relative to the SMB2 header.
+#[allow(clippy::cast_possible_truncation)]
+fn write_contexts(out: &mut Vec<u8>, contexts: &[CreateContext]) {
+    for (i, context) in contexts.iter().enumerate() {
+        let start = out.len();
+        let name = context.name();
+        let data_offset = align8(usize::from(CONTEXT_HEADER_SIZE) + name.len()) as u16;
+        out.extend_from_slice(&[0; 4]); /* next */
+        out.extend_from_slice(&CONTEXT_HEADER_SIZE.to_le_bytes());
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&data_offset.to_le_bytes());
+        out.extend_from_slice(&[0; 4]); /* data length */
+        out.extend_from_slice(name);
+        pad8(out, start);
+        let data_start = out.len();
+        context.write_data(out);
+        let data_length = (out.len() - data_start) as u32;
+        out[start + 12..start + 16].copy_from_slice(&data_length.to_le_bytes());
+        pad8(out, start);
+        if i + 1 < contexts.len() {
+            let next = (out.len() - start) as u32;
+            out[start..start + 4].copy_from_slice(&next.to_le_bytes());
+        }
+    }
+}
+
 #[rustfmt::skip]
 #[allow(clippy::cyclomatic_complexity, clippy::cast_possible_truncation)]
 pub fn parse_request(data: &[u8], _dialect: Dialect) -> IResult<&[u8], Request> {
@@ -104,17 +772,29 @@ pub fn parse_request(data: &[u8], _dialect: Dialect) -> IResult<&[u8], Request>
         requested_oplock_level: map_opt!(le_u8, FromPrimitive::from_u8) >>
         impersonation_level: map_opt!(le_u32, FromPrimitive::from_u32) >>
         take!(16) >> // SmbCreateFlags ignore + reserved
-        desired_access: le_u32 >>
-        file_attributes: le_u32 >>
-        share_access: map_opt!(le_u32, |x| ShareAccess::from_bits(x as u8)) >>
+        desired_access: map_opt!(le_u32, AccessMask::from_bits) >>
+        file_attributes: map_opt!(le_u32, FileAttributes::from_bits) >>
+        share_access: map_opt!(le_u32, |x| u8::try_from(x).ok().and_then(ShareAccess::from_bits)) >>
         create_disposition: map_opt!(le_u32, FromPrimitive::from_u32) >>
-        create_options: le_u32 >>
+        create_options: map_opt!(le_u32, CreateOptions::from_bits) >>
         name_offset: verify!(le_u16, |offset| offset >= REQUEST_CONSTANT_SIZE) >>
         name_length: le_u16 >>
-        _create_context_offset: le_u32 >>
-        _create_context_length: le_u32 >>
+        context_offset: le_u32 >>
+        context_length: le_u32 >>
+        verify!(
+            value!(context_offset),
+            |offset| context_length == 0
+                || offset >= u32::from(name_offset) + u32::from(name_length)
+        ) >>
         take!(name_offset - REQUEST_CONSTANT_SIZE) >>
         name: map_res!(take!(name_length), utf16le_to_string) >>
+        contexts: cond_with_error!(
+            context_length > 0,
+            preceded!(
+                take!(context_offset - u32::from(name_offset) - u32::from(name_length)),
+                length_value!(value!(context_length), apply!(parse_contexts, false))
+            )
+        ) >>
         (Request {
            requested_oplock_level,
            impersonation_level,
@@ -124,6 +804,126 @@ pub fn parse_request(data: &[u8], _dialect: Dialect) -> IResult<&[u8], Request>
            create_disposition,
            create_options,
            name,
+           contexts: contexts.unwrap_or_default(),
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8], _dialect: Dialect) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        oplock_level: map_opt!(le_u8, FromPrimitive::from_u8) >>
+        flags: map_opt!(le_u8, Flags::from_bits) >>
+        create_action: map_opt!(le_u32, FromPrimitive::from_u32) >>
+        creation_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_access_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_write_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        change_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        allocation_size: le_u64 >>
+        end_of_file: le_u64 >>
+        file_attributes: map!(le_u32, FileAttributes::from_bits_truncate) >>
+        take!(4) >> /* reserved */
+        file_id: map!(take!(16), FileId::from_slice) >>
+        context_offset: le_u32 >>
+        context_length: le_u32 >>
+        verify!(
+            value!(context_offset),
+            |offset| context_length == 0 || offset >= u32::from(RESPONSE_CONSTANT_SIZE)
+        ) >>
+        contexts: cond_with_error!(
+            context_length > 0,
+            preceded!(
+                take!(context_offset - u32::from(RESPONSE_CONSTANT_SIZE)),
+                length_value!(value!(context_length), apply!(parse_contexts, true))
+            )
+        ) >>
+        (Response {
+            oplock_level,
+            flags,
+            create_action,
+            creation_time,
+            last_access_time,
+            last_write_time,
+            change_time,
+            allocation_size,
+            end_of_file,
+            file_attributes,
+            file_id,
+            contexts: contexts.unwrap_or_default(),
         })
     )
 }
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let start = out.len();
+        let name = string_to_utf16le(&self.name);
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.push(0); /* security flags */
+        out.push(self.requested_oplock_level as u8);
+        out.extend_from_slice(&(self.impersonation_level as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 16]); /* SmbCreateFlags + reserved */
+        out.extend_from_slice(&self.desired_access.bits().to_le_bytes());
+        out.extend_from_slice(&self.file_attributes.bits().to_le_bytes());
+        out.extend_from_slice(&u32::from(self.share_access.bits()).to_le_bytes());
+        out.extend_from_slice(&(self.create_disposition as u32).to_le_bytes());
+        out.extend_from_slice(&self.create_options.bits().to_le_bytes());
+        out.extend_from_slice(&REQUEST_CONSTANT_SIZE.to_le_bytes());
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        let context_pos = out.len();
+        out.extend_from_slice(&[0; 8]); /* create contexts offset + length */
+        if name.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        } else {
+            out.extend_from_slice(&name);
+        }
+        if !self.contexts.is_empty() {
+            pad8(out, start);
+            let contexts_start = out.len();
+            write_contexts(out, &self.contexts);
+            let offset =
+                (usize::from(crate::header::STRUCTURE_SIZE) + contexts_start - start) as u32;
+            let length = (out.len() - contexts_start) as u32;
+            out[context_pos..context_pos + 4].copy_from_slice(&offset.to_le_bytes());
+            out[context_pos + 4..context_pos + 8].copy_from_slice(&length.to_le_bytes());
+        }
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.push(self.oplock_level as u8);
+        out.push(self.flags.bits());
+        out.extend_from_slice(&(self.create_action as u32).to_le_bytes());
+        for time in &[
+            self.creation_time,
+            self.last_access_time,
+            self.last_write_time,
+            self.change_time,
+        ] {
+            out.extend_from_slice(&systemtime_to_filetime(*time).to_le_bytes());
+        }
+        out.extend_from_slice(&self.allocation_size.to_le_bytes());
+        out.extend_from_slice(&self.end_of_file.to_le_bytes());
+        out.extend_from_slice(&self.file_attributes.bits().to_le_bytes());
+        out.extend_from_slice(&[0; 4]); /* reserved */
+        out.extend_from_slice(&*self.file_id);
+        let context_pos = out.len();
+        out.extend_from_slice(&[0; 8]); /* create contexts offset + length */
+        if self.contexts.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        } else {
+            let contexts_start = out.len();
+            write_contexts(out, &self.contexts);
+            let offset = u32::from(RESPONSE_CONSTANT_SIZE);
+            let length = (out.len() - contexts_start) as u32;
+            out[context_pos..context_pos + 4].copy_from_slice(&offset.to_le_bytes());
+            out[context_pos + 4..context_pos + 8].copy_from_slice(&length.to_le_bytes());
+        }
+    }
+}
@@ -1,12 +1,343 @@
 use super::Command;
 
 use crate::ntstatus::NTStatus;
+use crate::{pad8, padding8, string_to_utf16le, utf16le_to_string, Dialect, Serialize};
+use nom::*;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const RESPONSE_STRUCTURE_SIZE: u16 = 9;
+const SYMLINK_ERROR_TAG: u32 = 0x4C4D_5953;
+const REPARSE_TAG_SYMLINK: u32 = 0xA000_000C;
+const SYMLINK_FIXED_SIZE: u32 = 24;
+const SYMLINK_REPARSE_FIXED_SIZE: u16 = 12;
+const SYMLINK_FLAG_RELATIVE: u32 = 0x01;
+const ERROR_ID_DEFAULT: u32 = 0x0000_0000;
+const ERROR_ID_SHARE_REDIRECT: u32 = 0x7264_5253;
+const SHARE_REDIRECT_STRUCTURE_SIZE: u32 = 0x30;
+const SHARE_REDIRECT_NOTIFICATION_TYPE: u32 = 3;
+const SHARE_REDIRECT_FIXED_SIZE: u32 = 24;
+const MOVE_DST_IPADDR_V4: u32 = 1;
+const MOVE_DST_IPADDR_V6: u32 = 2;
+const MOVE_DST_IPADDR_SIZE: u32 = 24;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-pub struct Response {
+pub struct Response<'a> {
     // This is the status from the header
     pub status: NTStatus,
     // This is the failed command
     pub command: Command,
-    // TODO: add remaining fields
+    pub error_data: ErrorData<'a>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum ErrorData<'a> {
+    // Used when ErrorContextCount is zero which is always the case before 3.1.1
+    Detail(Detail<'a>),
+    Contexts(Vec<Context<'a>>),
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Detail<'a> {
+    Empty,
+    SymbolicLink(SymbolicLink),
+    // Minimum buffer size required for STATUS_BUFFER_TOO_SMALL
+    BufferTooSmall(u32),
+    Unknown(&'a [u8]),
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Context<'a> {
+    Default(Detail<'a>),
+    ShareRedirect(ShareRedirect),
+    Unknown { error_id: u32, data: &'a [u8] },
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct SymbolicLink {
+    pub unparsed_path_length: u16,
+    pub substitute_name: String,
+    pub print_name: String,
+    pub relative: bool,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ShareRedirect {
+    pub ip_addr_move_list: Vec<IpAddr>,
+    pub resource_name: String,
+}
+
+/// Returns true if a response with this status carries an error body instead of
+/// the regular response structure of the command.
+pub fn is_error_response(command: Command, status: NTStatus, body: &[u8]) -> bool {
+    match status {
+        NTStatus::StatusSuccess => false,
+        NTStatus::StatusMoreProcessingRequired => command != Command::SessionSetup,
+        NTStatus::StatusBufferOverflow => {
+            !matches!(command, Command::Read | Command::Ioctl | Command::QueryInfo)
+        }
+        // a failed SRV_COPYCHUNK returns the server limits in a regular IOCTL response
+        NTStatus::StatusInvalidParameter if command == Command::Ioctl => {
+            body.get(..2) == Some(&RESPONSE_STRUCTURE_SIZE.to_le_bytes())
+        }
+        _ => true,
+    }
+}
+
+fn slice_name(buffer: &[u8], offset: u16, length: u16) -> Option<String> {
+    let start = usize::from(offset);
+    let name = buffer.get(start..start + usize::from(length))?;
+    utf16le_to_string(name).ok()
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_symbolic_link(data: &[u8]) -> IResult<&[u8], SymbolicLink> {
+    do_parse!(data,
+        symlink_length: verify!(le_u32, |x| x >= SYMLINK_FIXED_SIZE) >>
+        verify!(le_u32, |x| x == SYMLINK_ERROR_TAG) >>
+        verify!(le_u32, |x| x == REPARSE_TAG_SYMLINK) >>
+        reparse_data_length: verify!(
+            le_u16,
+            |x| x >= SYMLINK_REPARSE_FIXED_SIZE &&
+                u32::from(x - SYMLINK_REPARSE_FIXED_SIZE) == symlink_length - SYMLINK_FIXED_SIZE
+        ) >>
+        unparsed_path_length: le_u16 >>
+        substitute_name_offset: le_u16 >>
+        substitute_name_length: le_u16 >>
+        print_name_offset: le_u16 >>
+        print_name_length: le_u16 >>
+        flags: verify!(le_u32, |x| x & !SYMLINK_FLAG_RELATIVE == 0) >>
+        path_buffer: take!(reparse_data_length - SYMLINK_REPARSE_FIXED_SIZE) >>
+        substitute_name: expr_opt!(
+            slice_name(path_buffer, substitute_name_offset, substitute_name_length)
+        ) >>
+        print_name: expr_opt!(slice_name(path_buffer, print_name_offset, print_name_length)) >>
+        (SymbolicLink {
+            unparsed_path_length,
+            substitute_name,
+            print_name,
+            relative: flags & SYMLINK_FLAG_RELATIVE != 0,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_ip_addr(data: &[u8]) -> IResult<&[u8], IpAddr> {
+    do_parse!(data,
+        addr_type: le_u32 >>
+        take!(4) >> /* reserved */
+        addr: switch!(value!(addr_type),
+            MOVE_DST_IPADDR_V4 => do_parse!(
+                addr: map!(take!(4), |x| Ipv4Addr::new(x[0], x[1], x[2], x[3])) >>
+                take!(12) >> /* reserved */
+                (IpAddr::V4(addr))
+            ) |
+            MOVE_DST_IPADDR_V6 => map!(take!(16), |x| {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(x);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            })
+        ) >>
+        (addr)
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity, clippy::cast_possible_truncation)]
+fn parse_share_redirect(data: &[u8]) -> IResult<&[u8], ShareRedirect> {
+    do_parse!(data,
+        verify!(le_u32, |x| x == SHARE_REDIRECT_STRUCTURE_SIZE) >>
+        verify!(le_u32, |x| x == SHARE_REDIRECT_NOTIFICATION_TYPE) >>
+        resource_name_offset: le_u32 >>
+        resource_name_length: le_u32 >>
+        take!(2) >> /* reserved */
+        verify!(le_u16, |x| x == 0) >> /* TargetType */
+        ip_addr_count: le_u32 >>
+        ip_addr_move_list: count!(parse_ip_addr, ip_addr_count as usize) >>
+        verify!(
+            value!(resource_name_offset),
+            |offset| offset >= SHARE_REDIRECT_FIXED_SIZE + ip_addr_count * MOVE_DST_IPADDR_SIZE
+        ) >>
+        take!(resource_name_offset - SHARE_REDIRECT_FIXED_SIZE - ip_addr_count * MOVE_DST_IPADDR_SIZE) >>
+        resource_name: map_res!(take!(resource_name_length), utf16le_to_string) >>
+        (ShareRedirect {
+            ip_addr_move_list,
+            resource_name,
+        })
+    )
+}
+
+fn parse_detail(data: &[u8], status: NTStatus) -> IResult<&[u8], Detail> {
+    match status {
+        _ if data.is_empty() => Ok((data, Detail::Empty)),
+        NTStatus::StatusStoppedOnSymlink => map!(data, parse_symbolic_link, Detail::SymbolicLink),
+        NTStatus::StatusBufferTooSmall if data.len() == 4 => {
+            map!(data, le_u32, Detail::BufferTooSmall)
+        }
+        _ => map!(data, rest, Detail::Unknown),
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cast_possible_truncation)]
+fn parse_context(input: &[u8], error_data_len: usize, status: NTStatus) -> IResult<&[u8], Context> {
+    // contexts are 8 byte aligned relative to the start of the ErrorData
+    let padding = padding8(error_data_len - input.len());
+    do_parse!(input,
+        take!(padding) >>
+        data_length: le_u32 >>
+        error_id: le_u32 >>
+        context: length_value!(value!(data_length), switch!(value!(error_id),
+            ERROR_ID_DEFAULT => map!(apply!(parse_detail, status), Context::Default) |
+            ERROR_ID_SHARE_REDIRECT => map!(parse_share_redirect, Context::ShareRedirect) |
+            _ => map!(rest, |data| Context::Unknown { error_id, data })
+        )) >>
+        (context)
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8], command: Command, status: NTStatus) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        error_context_count: le_u8 >>
+        take!(1) >> /* reserved */
+        byte_count: le_u32 >>
+        error_data: flat_map!(take!(byte_count), switch!(value!(error_context_count > 0),
+            true => map!(
+                count!(
+                    apply!(parse_context, byte_count as usize, status),
+                    usize::from(error_context_count)
+                ),
+                ErrorData::Contexts
+            ) |
+            false => map!(apply!(parse_detail, status), ErrorData::Detail)
+        )) >>
+        (Response {
+            status,
+            command,
+            error_data,
+        })
+    )
+}
+
+impl Detail<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Detail::Empty => (),
+            Detail::SymbolicLink(symlink) => {
+                let substitute_name = string_to_utf16le(&symlink.substitute_name);
+                let print_name = string_to_utf16le(&symlink.print_name);
+                let path_length = substitute_name.len() + print_name.len();
+                let flags = if symlink.relative {
+                    SYMLINK_FLAG_RELATIVE
+                } else {
+                    0
+                };
+                out.extend_from_slice(&(SYMLINK_FIXED_SIZE + path_length as u32).to_le_bytes());
+                out.extend_from_slice(&SYMLINK_ERROR_TAG.to_le_bytes());
+                out.extend_from_slice(&REPARSE_TAG_SYMLINK.to_le_bytes());
+                out.extend_from_slice(
+                    &(SYMLINK_REPARSE_FIXED_SIZE + path_length as u16).to_le_bytes(),
+                );
+                out.extend_from_slice(&symlink.unparsed_path_length.to_le_bytes());
+                out.extend_from_slice(&0u16.to_le_bytes());
+                out.extend_from_slice(&(substitute_name.len() as u16).to_le_bytes());
+                out.extend_from_slice(&(substitute_name.len() as u16).to_le_bytes());
+                out.extend_from_slice(&(print_name.len() as u16).to_le_bytes());
+                out.extend_from_slice(&flags.to_le_bytes());
+                out.extend_from_slice(&substitute_name);
+                out.extend_from_slice(&print_name);
+            }
+            Detail::BufferTooSmall(size) => out.extend_from_slice(&size.to_le_bytes()),
+            Detail::Unknown(data) => out.extend_from_slice(data),
+        }
+    }
+}
+
+impl ShareRedirect {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>) {
+        let resource_name = string_to_utf16le(&self.resource_name);
+        let ip_addr_count = self.ip_addr_move_list.len() as u32;
+        let resource_name_offset = SHARE_REDIRECT_FIXED_SIZE + ip_addr_count * MOVE_DST_IPADDR_SIZE;
+        out.extend_from_slice(&SHARE_REDIRECT_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&SHARE_REDIRECT_NOTIFICATION_TYPE.to_le_bytes());
+        out.extend_from_slice(&resource_name_offset.to_le_bytes());
+        out.extend_from_slice(&(resource_name.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&0u16.to_le_bytes()); /* TargetType */
+        out.extend_from_slice(&ip_addr_count.to_le_bytes());
+        for addr in &self.ip_addr_move_list {
+            match addr {
+                IpAddr::V4(addr) => {
+                    out.extend_from_slice(&MOVE_DST_IPADDR_V4.to_le_bytes());
+                    out.extend_from_slice(&[0; 4]); /* reserved */
+                    out.extend_from_slice(&addr.octets());
+                    out.extend_from_slice(&[0; 12]); /* reserved */
+                }
+                IpAddr::V6(addr) => {
+                    out.extend_from_slice(&MOVE_DST_IPADDR_V6.to_le_bytes());
+                    out.extend_from_slice(&[0; 4]); /* reserved */
+                    out.extend_from_slice(&addr.octets());
+                }
+            }
+        }
+        out.extend_from_slice(&resource_name);
+    }
+}
+
+impl Context<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>) {
+        let header_pos = out.len();
+        let error_id = match self {
+            Context::Default(_) => ERROR_ID_DEFAULT,
+            Context::ShareRedirect(_) => ERROR_ID_SHARE_REDIRECT,
+            Context::Unknown { error_id, .. } => *error_id,
+        };
+        out.extend_from_slice(&[0; 4]); /* data length */
+        out.extend_from_slice(&error_id.to_le_bytes());
+        match self {
+            Context::Default(detail) => detail.write_to(out),
+            Context::ShareRedirect(redirect) => redirect.write_to(out),
+            Context::Unknown { data, .. } => out.extend_from_slice(data),
+        }
+        let data_length = (out.len() - header_pos - 8) as u32;
+        out[header_pos..header_pos + 4].copy_from_slice(&data_length.to_le_bytes());
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let error_context_count = match &self.error_data {
+            ErrorData::Detail(_) => 0,
+            ErrorData::Contexts(contexts) => contexts.len() as u8,
+        };
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.push(error_context_count);
+        out.push(0); /* reserved */
+        let byte_count_pos = out.len();
+        out.extend_from_slice(&[0; 4]); /* byte count */
+        let start = out.len();
+        match &self.error_data {
+            ErrorData::Detail(detail) => detail.write_to(out),
+            ErrorData::Contexts(contexts) => {
+                for (i, context) in contexts.iter().enumerate() {
+                    if i > 0 {
+                        pad8(out, start);
+                    }
+                    context.write_to(out);
+                }
+            }
+        }
+        let byte_count = (out.len() - start) as u32;
+        out[byte_count_pos..byte_count_pos + 4].copy_from_slice(&byte_count.to_le_bytes());
+        if byte_count == 0 {
+            out.push(0); /* ErrorData must be at least one byte */
+        }
+    }
 }
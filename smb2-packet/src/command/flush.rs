@@ -1,7 +1,8 @@
-use crate::FileId;
+use crate::{Dialect, FileId, Serialize};
 use nom::*;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 24;
+const RESPONSE_STRUCTURE_SIZE: u16 = 4;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Request {
@@ -20,3 +21,26 @@ pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
         })
     )
 }
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], ()> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        take!(2) >> /* reserved */
+        (())
+    )
+}
+
+impl Serialize for Request {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&[0; 6]); /* reserved */
+        out.extend_from_slice(&*self.file_id);
+    }
+}
+
+pub fn write_response(out: &mut Vec<u8>) {
+    out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+    out.extend_from_slice(&[0; 2]); /* reserved */
+}
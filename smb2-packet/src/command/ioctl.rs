@@ -0,0 +1,701 @@
+use super::negotiate::{self, Capabilities};
+use crate::{string_to_utf16le, utf16le_to_string, ClientGuid, Dialect, FileId, Serialize};
+use bitflags::bitflags;
+use nom::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const REQUEST_STRUCTURE_SIZE: u16 = 57;
+const REQUEST_CONSTANT_SIZE: u32 =
+    crate::header::STRUCTURE_SIZE as u32 + REQUEST_STRUCTURE_SIZE as u32 - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 49;
+const RESPONSE_CONSTANT_SIZE: u32 =
+    crate::header::STRUCTURE_SIZE as u32 + RESPONSE_STRUCTURE_SIZE as u32 - 1;
+const IS_FSCTL: u32 = 0x01;
+const RESUME_KEY_SIZE: usize = 24;
+const NETWORK_INTERFACE_INFO_SIZE: u32 = 152;
+const SOCKADDR_STORAGE_SIZE: usize = 128;
+const AF_INET: u16 = 0x02;
+const AF_INET6: u16 = 0x17;
+
+#[repr(u32)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum CtlCode {
+    DfsGetReferrals = 0x0006_0194,
+    GetReparsePoint = 0x0009_00A8,
+    SetSparse = 0x0009_00C4,
+    PipeTransceive = 0x0011_C017,
+    SrvRequestResumeKey = 0x0014_0078,
+    QueryNetworkInterfaceInfo = 0x0014_01FC,
+    ValidateNegotiateInfo = 0x0014_0204,
+    SrvCopychunk = 0x0014_40F2,
+    SrvCopychunkWrite = 0x0014_80F2,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Request<'a> {
+    pub file_id: FileId,
+    pub max_input_response: u32,
+    pub max_output_response: u32,
+    pub is_fsctl: bool,
+    pub input: Input<'a>,
+    /// `InputOffset` as sent. Clients disagree on the offset of an empty input:
+    /// Windows points it behind the fixed part while others send zero. It is only
+    /// written for an empty input, a non-empty one always follows the fixed part.
+    pub input_offset: u32,
+    /// `OutputOffset` as sent. Windows points it behind the input while others send
+    /// zero.
+    pub output_offset: u32,
+    /// Output buffer of the request, which clients should leave empty.
+    pub output: &'a [u8],
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Response<'a> {
+    pub file_id: FileId,
+    pub output: Output<'a>,
+}
+
+/// Input buffer of a request. The variant determines the `CtlCode`.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Input<'a> {
+    DfsGetReferrals(ReferralRequest),
+    GetReparsePoint,
+    /// The buffer is optional and sparse is assumed if it is missing
+    SetSparse(Option<bool>),
+    PipeTransceive(&'a [u8]),
+    SrvRequestResumeKey,
+    QueryNetworkInterfaceInfo,
+    ValidateNegotiateInfo(ValidateNegotiateInfoRequest),
+    SrvCopychunk(CopychunkCopy),
+    SrvCopychunkWrite(CopychunkCopy),
+    Unknown {
+        ctl_code: u32,
+        data: &'a [u8],
+    },
+}
+
+/// Output buffer of a response. The variant determines the `CtlCode`.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Output<'a> {
+    DfsGetReferrals(ReferralResponse<'a>),
+    GetReparsePoint(ReparseData<'a>),
+    SetSparse,
+    PipeTransceive(&'a [u8]),
+    SrvRequestResumeKey(ResumeKey<'a>),
+    QueryNetworkInterfaceInfo(Vec<NetworkInterfaceInfo>),
+    ValidateNegotiateInfo(ValidateNegotiateInfoResponse),
+    SrvCopychunk(CopychunkResponse),
+    SrvCopychunkWrite(CopychunkResponse),
+    Unknown { ctl_code: u32, data: &'a [u8] },
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ReferralRequest {
+    pub max_referral_level: u16,
+    pub file_name: String,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ReferralResponse<'a> {
+    pub path_consumed: u16,
+    pub number_of_referrals: u16,
+    pub header_flags: ReferralHeaderFlags,
+    /// Referral entries followed by the strings they point to
+    pub referral_entries: &'a [u8],
+}
+
+bitflags! {
+    pub struct ReferralHeaderFlags: u32 {
+        const REFERRAL_SERVERS = 0x01;
+        const STORAGE_SERVERS = 0x02;
+        const TARGET_FAILBACK = 0x04;
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ReparseData<'a> {
+    pub reparse_tag: u32,
+    pub data: &'a [u8],
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ResumeKey<'a> {
+    pub resume_key: [u8; RESUME_KEY_SIZE],
+    pub context: &'a [u8],
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct NetworkInterfaceInfo {
+    pub if_index: u32,
+    pub capability: InterfaceCapability,
+    /// Speed of the interface in bits per second
+    pub link_speed: u64,
+    pub address: IpAddr,
+}
+
+bitflags! {
+    pub struct InterfaceCapability: u32 {
+        const RSS_CAPABLE = 0x01;
+        const RDMA_CAPABLE = 0x02;
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ValidateNegotiateInfoRequest {
+    pub capabilities: Capabilities,
+    pub client_guid: ClientGuid,
+    pub signing_required: bool,
+    pub dialects: Vec<Dialect>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ValidateNegotiateInfoResponse {
+    pub capabilities: Capabilities,
+    pub server_guid: ClientGuid,
+    pub signing_required: bool,
+    pub dialect: Dialect,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct CopychunkCopy {
+    pub source_key: [u8; RESUME_KEY_SIZE],
+    pub chunks: Vec<Chunk>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    pub source_offset: u64,
+    pub target_offset: u64,
+    pub length: u32,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CopychunkResponse {
+    pub chunks_written: u32,
+    pub chunk_bytes_written: u32,
+    pub total_bytes_written: u32,
+}
+
+fn resume_key(data: &[u8]) -> [u8; RESUME_KEY_SIZE] {
+    let mut key = [0; RESUME_KEY_SIZE];
+    key.copy_from_slice(data);
+    key
+}
+
+fn strip_null(mut name: String) -> String {
+    if name.ends_with('\0') {
+        name.pop();
+    }
+    name
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_validate_negotiate_request(data: &[u8]) -> IResult<&[u8], ValidateNegotiateInfoRequest> {
+    do_parse!(data,
+        capabilities: map!(le_u32, Capabilities::from_bits_truncate) >>
+        client_guid: map!(take!(16), ClientGuid::from_slice) >>
+        security_mode: le_u16 >>
+        dialect_count: le_u16 >>
+        dialects: count!(negotiate::parse_dialect, usize::from(dialect_count)) >>
+        (ValidateNegotiateInfoRequest {
+            capabilities,
+            client_guid,
+            signing_required: (security_mode & negotiate::SIGNING_REQUIRED) != 0,
+            dialects,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_validate_negotiate_response(data: &[u8]) -> IResult<&[u8], ValidateNegotiateInfoResponse> {
+    do_parse!(data,
+        capabilities: map!(le_u32, Capabilities::from_bits_truncate) >>
+        server_guid: map!(take!(16), ClientGuid::from_slice) >>
+        security_mode: le_u16 >>
+        dialect: call!(negotiate::parse_dialect) >>
+        (ValidateNegotiateInfoResponse {
+            capabilities,
+            server_guid,
+            signing_required: (security_mode & negotiate::SIGNING_REQUIRED) != 0,
+            dialect,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_referral_request(data: &[u8]) -> IResult<&[u8], ReferralRequest> {
+    do_parse!(data,
+        max_referral_level: le_u16 >>
+        file_name: map!(map_res!(rest, utf16le_to_string), strip_null) >>
+        (ReferralRequest {
+            max_referral_level,
+            file_name,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_referral_response(data: &[u8]) -> IResult<&[u8], ReferralResponse> {
+    do_parse!(data,
+        path_consumed: le_u16 >>
+        number_of_referrals: le_u16 >>
+        header_flags: map_opt!(le_u32, ReferralHeaderFlags::from_bits) >>
+        referral_entries: rest >>
+        (ReferralResponse {
+            path_consumed,
+            number_of_referrals,
+            header_flags,
+            referral_entries,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_reparse_data(data: &[u8]) -> IResult<&[u8], ReparseData> {
+    do_parse!(data,
+        reparse_tag: le_u32 >>
+        data_length: le_u16 >>
+        take!(2) >> /* reserved */
+        data: take!(data_length) >>
+        (ReparseData {
+            reparse_tag,
+            data,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_resume_key(data: &[u8]) -> IResult<&[u8], ResumeKey> {
+    do_parse!(data,
+        resume_key: map!(take!(RESUME_KEY_SIZE), resume_key) >>
+        context_length: le_u32 >>
+        context: take!(context_length) >>
+        (ResumeKey {
+            resume_key,
+            context,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_chunk(data: &[u8]) -> IResult<&[u8], Chunk> {
+    do_parse!(data,
+        source_offset: le_u64 >>
+        target_offset: le_u64 >>
+        length: le_u32 >>
+        take!(4) >> /* reserved */
+        (Chunk {
+            source_offset,
+            target_offset,
+            length,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_copychunk_copy(data: &[u8]) -> IResult<&[u8], CopychunkCopy> {
+    do_parse!(data,
+        source_key: map!(take!(RESUME_KEY_SIZE), resume_key) >>
+        chunk_count: le_u32 >>
+        take!(4) >> /* reserved */
+        chunks: count!(parse_chunk, chunk_count as usize) >>
+        (CopychunkCopy {
+            source_key,
+            chunks,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_copychunk_response(data: &[u8]) -> IResult<&[u8], CopychunkResponse> {
+    do_parse!(data,
+        chunks_written: le_u32 >>
+        chunk_bytes_written: le_u32 >>
+        total_bytes_written: le_u32 >>
+        (CopychunkResponse {
+            chunks_written,
+            chunk_bytes_written,
+            total_bytes_written,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_sockaddr(data: &[u8]) -> IResult<&[u8], IpAddr> {
+    do_parse!(data,
+        family: le_u16 >>
+        take!(2) >> /* port */
+        address: switch!(value!(family),
+            AF_INET => map!(take!(4), |x| IpAddr::V4(Ipv4Addr::new(x[0], x[1], x[2], x[3]))) |
+            AF_INET6 => do_parse!(
+                take!(4) >> /* flow info */
+                addr: map!(take!(16), |x| {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(x);
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                }) >>
+                take!(4) >> /* scope id */
+                (addr)
+            )
+        ) >>
+        (address)
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_network_interface(data: &[u8]) -> IResult<&[u8], (u32, NetworkInterfaceInfo)> {
+    do_parse!(data,
+        next: verify!(le_u32, |x| x == 0 || x >= NETWORK_INTERFACE_INFO_SIZE) >>
+        if_index: le_u32 >>
+        capability: map_opt!(le_u32, InterfaceCapability::from_bits) >>
+        take!(4) >> /* reserved */
+        link_speed: le_u64 >>
+        address: flat_map!(take!(SOCKADDR_STORAGE_SIZE), parse_sockaddr) >>
+        ((next, NetworkInterfaceInfo {
+            if_index,
+            capability,
+            link_speed,
+            address,
+        }))
+    )
+}
+
+fn parse_network_interfaces(data: &[u8]) -> IResult<&[u8], Vec<NetworkInterfaceInfo>> {
+    let mut interfaces = Vec::new();
+    if data.is_empty() {
+        return Ok((data, interfaces));
+    }
+    let mut cur = data;
+    loop {
+        let (remaining, (next, interface)) = parse_network_interface(cur)?;
+        interfaces.push(interface);
+        if next == 0 {
+            return Ok((remaining, interfaces));
+        }
+        cur = take!(cur, next)?.0;
+    }
+}
+
+impl<'a> Input<'a> {
+    #[rustfmt::skip]
+    fn parse(data: &'a [u8], ctl_code: u32) -> IResult<&'a [u8], Self> {
+        match CtlCode::from_u32(ctl_code) {
+            Some(CtlCode::DfsGetReferrals) =>
+                map!(data, parse_referral_request, Input::DfsGetReferrals),
+            Some(CtlCode::GetReparsePoint) => Ok((data, Input::GetReparsePoint)),
+            Some(CtlCode::SetSparse) =>
+                map!(data, opt!(complete!(le_u8)), |x| Input::SetSparse(x.map(|x| x != 0))),
+            Some(CtlCode::PipeTransceive) => map!(data, rest, Input::PipeTransceive),
+            Some(CtlCode::SrvRequestResumeKey) => Ok((data, Input::SrvRequestResumeKey)),
+            Some(CtlCode::QueryNetworkInterfaceInfo) =>
+                Ok((data, Input::QueryNetworkInterfaceInfo)),
+            Some(CtlCode::ValidateNegotiateInfo) =>
+                map!(data, parse_validate_negotiate_request, Input::ValidateNegotiateInfo),
+            Some(CtlCode::SrvCopychunk) =>
+                map!(data, parse_copychunk_copy, Input::SrvCopychunk),
+            Some(CtlCode::SrvCopychunkWrite) =>
+                map!(data, parse_copychunk_copy, Input::SrvCopychunkWrite),
+            None => map!(data, rest, |d| Input::Unknown { ctl_code, data: d }),
+        }
+    }
+
+    pub fn ctl_code(&self) -> u32 {
+        let code = match self {
+            Input::DfsGetReferrals(_) => CtlCode::DfsGetReferrals,
+            Input::GetReparsePoint => CtlCode::GetReparsePoint,
+            Input::SetSparse(_) => CtlCode::SetSparse,
+            Input::PipeTransceive(_) => CtlCode::PipeTransceive,
+            Input::SrvRequestResumeKey => CtlCode::SrvRequestResumeKey,
+            Input::QueryNetworkInterfaceInfo => CtlCode::QueryNetworkInterfaceInfo,
+            Input::ValidateNegotiateInfo(_) => CtlCode::ValidateNegotiateInfo,
+            Input::SrvCopychunk(_) => CtlCode::SrvCopychunk,
+            Input::SrvCopychunkWrite(_) => CtlCode::SrvCopychunkWrite,
+            Input::Unknown { ctl_code, .. } => return *ctl_code,
+        };
+        code as u32
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_data(&self, out: &mut Vec<u8>) {
+        match self {
+            Input::DfsGetReferrals(request) => {
+                out.extend_from_slice(&request.max_referral_level.to_le_bytes());
+                out.extend_from_slice(&string_to_utf16le(&request.file_name));
+                out.extend_from_slice(&[0; 2]); /* null terminator */
+            }
+            Input::SetSparse(sparse) => {
+                if let Some(sparse) = sparse {
+                    out.push(u8::from(*sparse));
+                }
+            }
+            Input::PipeTransceive(data) | Input::Unknown { data, .. } => {
+                out.extend_from_slice(data);
+            }
+            Input::ValidateNegotiateInfo(request) => {
+                out.extend_from_slice(&request.capabilities.bits().to_le_bytes());
+                out.extend_from_slice(&*request.client_guid);
+                out.extend_from_slice(
+                    &negotiate::security_mode(request.signing_required).to_le_bytes(),
+                );
+                out.extend_from_slice(&(request.dialects.len() as u16).to_le_bytes());
+                for dialect in &request.dialects {
+                    out.extend_from_slice(&(*dialect as u16).to_le_bytes());
+                }
+            }
+            Input::SrvCopychunk(copy) | Input::SrvCopychunkWrite(copy) => {
+                out.extend_from_slice(&copy.source_key);
+                out.extend_from_slice(&(copy.chunks.len() as u32).to_le_bytes());
+                out.extend_from_slice(&[0; 4]); /* reserved */
+                for chunk in &copy.chunks {
+                    out.extend_from_slice(&chunk.source_offset.to_le_bytes());
+                    out.extend_from_slice(&chunk.target_offset.to_le_bytes());
+                    out.extend_from_slice(&chunk.length.to_le_bytes());
+                    out.extend_from_slice(&[0; 4]); /* reserved */
+                }
+            }
+            Input::GetReparsePoint
+            | Input::SrvRequestResumeKey
+            | Input::QueryNetworkInterfaceInfo => (),
+        }
+    }
+}
+
+impl<'a> Output<'a> {
+    #[rustfmt::skip]
+    fn parse(data: &'a [u8], ctl_code: u32) -> IResult<&'a [u8], Self> {
+        match CtlCode::from_u32(ctl_code) {
+            Some(CtlCode::DfsGetReferrals) =>
+                map!(data, parse_referral_response, Output::DfsGetReferrals),
+            Some(CtlCode::GetReparsePoint) =>
+                map!(data, parse_reparse_data, Output::GetReparsePoint),
+            Some(CtlCode::SetSparse) => Ok((data, Output::SetSparse)),
+            Some(CtlCode::PipeTransceive) => map!(data, rest, Output::PipeTransceive),
+            Some(CtlCode::SrvRequestResumeKey) =>
+                map!(data, parse_resume_key, Output::SrvRequestResumeKey),
+            Some(CtlCode::QueryNetworkInterfaceInfo) =>
+                map!(data, parse_network_interfaces, Output::QueryNetworkInterfaceInfo),
+            Some(CtlCode::ValidateNegotiateInfo) =>
+                map!(data, parse_validate_negotiate_response, Output::ValidateNegotiateInfo),
+            Some(CtlCode::SrvCopychunk) =>
+                map!(data, parse_copychunk_response, Output::SrvCopychunk),
+            Some(CtlCode::SrvCopychunkWrite) =>
+                map!(data, parse_copychunk_response, Output::SrvCopychunkWrite),
+            None => map!(data, rest, |d| Output::Unknown { ctl_code, data: d }),
+        }
+    }
+
+    pub fn ctl_code(&self) -> u32 {
+        let code = match self {
+            Output::DfsGetReferrals(_) => CtlCode::DfsGetReferrals,
+            Output::GetReparsePoint(_) => CtlCode::GetReparsePoint,
+            Output::SetSparse => CtlCode::SetSparse,
+            Output::PipeTransceive(_) => CtlCode::PipeTransceive,
+            Output::SrvRequestResumeKey(_) => CtlCode::SrvRequestResumeKey,
+            Output::QueryNetworkInterfaceInfo(_) => CtlCode::QueryNetworkInterfaceInfo,
+            Output::ValidateNegotiateInfo(_) => CtlCode::ValidateNegotiateInfo,
+            Output::SrvCopychunk(_) => CtlCode::SrvCopychunk,
+            Output::SrvCopychunkWrite(_) => CtlCode::SrvCopychunkWrite,
+            Output::Unknown { ctl_code, .. } => return *ctl_code,
+        };
+        code as u32
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_data(&self, out: &mut Vec<u8>) {
+        match self {
+            Output::DfsGetReferrals(response) => {
+                out.extend_from_slice(&response.path_consumed.to_le_bytes());
+                out.extend_from_slice(&response.number_of_referrals.to_le_bytes());
+                out.extend_from_slice(&response.header_flags.bits().to_le_bytes());
+                out.extend_from_slice(response.referral_entries);
+            }
+            Output::GetReparsePoint(reparse) => {
+                out.extend_from_slice(&reparse.reparse_tag.to_le_bytes());
+                out.extend_from_slice(&(reparse.data.len() as u16).to_le_bytes());
+                out.extend_from_slice(&[0; 2]); /* reserved */
+                out.extend_from_slice(reparse.data);
+            }
+            Output::PipeTransceive(data) | Output::Unknown { data, .. } => {
+                out.extend_from_slice(data);
+            }
+            Output::SrvRequestResumeKey(key) => {
+                out.extend_from_slice(&key.resume_key);
+                out.extend_from_slice(&(key.context.len() as u32).to_le_bytes());
+                out.extend_from_slice(key.context);
+            }
+            Output::QueryNetworkInterfaceInfo(interfaces) => {
+                for (i, interface) in interfaces.iter().enumerate() {
+                    let next = if i + 1 == interfaces.len() {
+                        0
+                    } else {
+                        NETWORK_INTERFACE_INFO_SIZE
+                    };
+                    out.extend_from_slice(&next.to_le_bytes());
+                    out.extend_from_slice(&interface.if_index.to_le_bytes());
+                    out.extend_from_slice(&interface.capability.bits().to_le_bytes());
+                    out.extend_from_slice(&[0; 4]); /* reserved */
+                    out.extend_from_slice(&interface.link_speed.to_le_bytes());
+                    write_sockaddr(out, interface.address);
+                }
+            }
+            Output::ValidateNegotiateInfo(response) => {
+                out.extend_from_slice(&response.capabilities.bits().to_le_bytes());
+                out.extend_from_slice(&*response.server_guid);
+                out.extend_from_slice(
+                    &negotiate::security_mode(response.signing_required).to_le_bytes(),
+                );
+                out.extend_from_slice(&(response.dialect as u16).to_le_bytes());
+            }
+            Output::SrvCopychunk(response) | Output::SrvCopychunkWrite(response) => {
+                out.extend_from_slice(&response.chunks_written.to_le_bytes());
+                out.extend_from_slice(&response.chunk_bytes_written.to_le_bytes());
+                out.extend_from_slice(&response.total_bytes_written.to_le_bytes());
+            }
+            Output::SetSparse => (),
+        }
+    }
+}
+
+fn write_sockaddr(out: &mut Vec<u8>, address: IpAddr) {
+    let start = out.len();
+    match address {
+        IpAddr::V4(addr) => {
+            out.extend_from_slice(&AF_INET.to_le_bytes());
+            out.extend_from_slice(&[0; 2]); /* port */
+            out.extend_from_slice(&addr.octets());
+        }
+        IpAddr::V6(addr) => {
+            out.extend_from_slice(&AF_INET6.to_le_bytes());
+            out.extend_from_slice(&[0; 6]); /* port + flow info */
+            out.extend_from_slice(&addr.octets());
+        }
+    }
+    out.resize(start + SOCKADDR_STORAGE_SIZE, 0);
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
+        take!(2) >> /* reserved */
+        ctl_code: le_u32 >>
+        file_id: map!(take!(16), FileId::from_slice) >>
+        input_offset: le_u32 >>
+        input_count: le_u32 >>
+        max_input_response: le_u32 >>
+        output_offset: le_u32 >>
+        output_count: le_u32 >>
+        max_output_response: le_u32 >>
+        flags: le_u32 >>
+        take!(4) >> /* reserved */
+        verify!(value!(input_offset), |offset| input_count == 0 || offset >= REQUEST_CONSTANT_SIZE) >>
+        input: preceded!(
+            take!(if input_count > 0 { input_offset - REQUEST_CONSTANT_SIZE } else { 0 }),
+            length_value!(value!(input_count), apply!(Input::parse, ctl_code))
+        ) >>
+        input_end: value!(if input_count > 0 { input_offset + input_count } else { REQUEST_CONSTANT_SIZE }) >>
+        verify!(value!(output_offset), |offset| output_count == 0 || offset >= input_end) >>
+        output: cond_with_error!(output_count > 0, preceded!(
+            take!(output_offset - input_end),
+            take!(output_count)
+        )) >>
+        (Request {
+            file_id,
+            max_input_response,
+            max_output_response,
+            is_fsctl: flags & IS_FSCTL != 0,
+            input,
+            input_offset,
+            output_offset,
+            output: output.unwrap_or_default(),
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        take!(2) >> /* reserved */
+        ctl_code: le_u32 >>
+        file_id: map!(take!(16), FileId::from_slice) >>
+        take!(8) >> /* input offset + count: servers do not return input */
+        output_offset: le_u32 >>
+        output_count: le_u32 >>
+        take!(8) >> /* flags + reserved */
+        verify!(value!(output_offset), |offset| output_count == 0 || offset >= RESPONSE_CONSTANT_SIZE) >>
+        output: preceded!(
+            take!(if output_count > 0 { output_offset - RESPONSE_CONSTANT_SIZE } else { 0 }),
+            length_value!(value!(output_count), apply!(Output::parse, ctl_code))
+        ) >>
+        (Response {
+            file_id,
+            output,
+        })
+    )
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut input = Vec::new();
+        self.input.write_data(&mut input);
+        let input_offset = if input.is_empty() {
+            self.input_offset
+        } else {
+            REQUEST_CONSTANT_SIZE
+        };
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&self.input.ctl_code().to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(&input_offset.to_le_bytes());
+        out.extend_from_slice(&(input.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.max_input_response.to_le_bytes());
+        out.extend_from_slice(&self.output_offset.to_le_bytes());
+        out.extend_from_slice(&(self.output.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.max_output_response.to_le_bytes());
+        out.extend_from_slice(&(if self.is_fsctl { IS_FSCTL } else { 0 }).to_le_bytes());
+        out.extend_from_slice(&[0; 4]); /* reserved */
+        out.extend_from_slice(&input);
+        if !self.output.is_empty() {
+            let input_end = REQUEST_CONSTANT_SIZE as usize + input.len();
+            let padding = (self.output_offset as usize).saturating_sub(input_end);
+            out.resize(out.len() + padding, 0);
+            out.extend_from_slice(self.output);
+        } else if input.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut output = Vec::new();
+        self.output.write_data(&mut output);
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&self.output.ctl_code().to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(&RESPONSE_CONSTANT_SIZE.to_le_bytes()); /* input offset */
+        out.extend_from_slice(&[0; 4]); /* input count */
+        out.extend_from_slice(&RESPONSE_CONSTANT_SIZE.to_le_bytes()); /* output offset */
+        out.extend_from_slice(&(output.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 8]); /* flags + reserved */
+        out.extend_from_slice(&output);
+        if output.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{Dialect, FileId, Serialize};
+use bitflags::bitflags;
+use nom::*;
+
+const REQUEST_STRUCTURE_SIZE: u16 = 48;
+const RESPONSE_STRUCTURE_SIZE: u16 = 4;
+const SEQUENCE_NUMBER_MASK: u32 = 0x0F;
+const SEQUENCE_INDEX_SHIFT: u32 = 4;
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Request {
+    /// Only transmitted for dialects newer than 2.0.2
+    pub lock_sequence: Option<LockSequence>,
+    pub file_id: FileId,
+    pub locks: Vec<Element>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LockSequence {
+    /// Only the lower 4 bits are transmitted
+    pub number: u8,
+    /// Only the lower 28 bits are transmitted
+    pub index: u32,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Element {
+    pub offset: u64,
+    pub length: u64,
+    pub flags: Flags,
+}
+
+bitflags! {
+    pub struct Flags: u32 {
+        const SHARED_LOCK = 0x01;
+        const EXCLUSIVE_LOCK = 0x02;
+        const UNLOCK = 0x04;
+        const FAIL_IMMEDIATELY = 0x10;
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn create_lock_sequence(value: u32) -> LockSequence {
+    LockSequence {
+        number: (value & SEQUENCE_NUMBER_MASK) as u8,
+        index: value >> SEQUENCE_INDEX_SHIFT,
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_element(data: &[u8]) -> IResult<&[u8], Element> {
+    do_parse!(data,
+        offset: le_u64 >>
+        length: le_u64 >>
+        flags: map_opt!(le_u32, Flags::from_bits) >>
+        take!(4) >> /* reserved */
+        (Element {
+            offset,
+            length,
+            flags,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_request(data: &[u8], dialect: Dialect) -> IResult<&[u8], Request> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
+        lock_count: verify!(le_u16, |x| x > 0) >>
+        lock_sequence: switch!(value!(dialect == Dialect::Smb2_0_2),
+            true => map!(take!(4), |_| None) |
+            false => map!(le_u32, |x| Some(create_lock_sequence(x)))
+        ) >>
+        file_id: map!(take!(16), FileId::from_slice) >>
+        locks: count!(parse_element, usize::from(lock_count)) >>
+        (Request {
+            lock_sequence,
+            file_id,
+            locks,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], ()> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        take!(2) >> /* reserved */
+        (())
+    )
+}
+
+impl Serialize for Request {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect) {
+        let lock_sequence = match self.lock_sequence {
+            /* reserved in 2.0.2 */
+            Some(sequence) if dialect != Dialect::Smb2_0_2 => {
+                (u32::from(sequence.number) & SEQUENCE_NUMBER_MASK)
+                    | (sequence.index << SEQUENCE_INDEX_SHIFT)
+            }
+            _ => 0,
+        };
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&(self.locks.len() as u16).to_le_bytes());
+        out.extend_from_slice(&lock_sequence.to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        for lock in &self.locks {
+            out.extend_from_slice(&lock.offset.to_le_bytes());
+            out.extend_from_slice(&lock.length.to_le_bytes());
+            out.extend_from_slice(&lock.flags.bits().to_le_bytes());
+            out.extend_from_slice(&[0; 4]); /* reserved */
+        }
+    }
+}
+
+pub fn write_response(out: &mut Vec<u8>) {
+    out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+    out.extend_from_slice(&[0; 2]); /* reserved */
+}
@@ -1,6 +1,7 @@
 use nom::*;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 4;
+const RESPONSE_STRUCTURE_SIZE: u16 = 4;
 
 #[rustfmt::skip]
 #[allow(clippy::cyclomatic_complexity)]
@@ -11,3 +12,23 @@ pub fn parse_request(data: &[u8]) -> IResult<&[u8], ()> {
         (())
     )
 }
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], ()> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        take!(2) >> /* reserved */
+        (())
+    )
+}
+
+pub fn write_request(out: &mut Vec<u8>) {
+    out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+    out.extend_from_slice(&[0; 2]); /* reserved */
+}
+
+pub fn write_response(out: &mut Vec<u8>) {
+    out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+    out.extend_from_slice(&[0; 2]); /* reserved */
+}
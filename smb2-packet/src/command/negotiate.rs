@@ -1,10 +1,18 @@
-use crate::{ClientGuid, Dialect};
+use crate::{
+    filetime_to_systemtime, pad8, string_to_utf16le, systemtime_to_filetime, utf16le_to_string,
+    ClientGuid, Dialect, Serialize,
+};
 use bitflags::bitflags;
 use nom::*;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 36;
+const RESPONSE_STRUCTURE_SIZE: u16 = 65;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
+const CONTEXT_HEADER_SIZE: usize = 8;
+const SIGNING_ENABLED: u16 = 0x01;
+pub(crate) const SIGNING_REQUIRED: u16 = 0x02;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Request<'a> {
@@ -23,6 +31,7 @@ pub struct Response<'a> {
     pub capabilities: Capabilities,
     pub max_transact_size: u32,
     pub max_read_size: u32,
+    pub max_write_size: u32,
     pub system_time: std::time::SystemTime,
     pub server_start_time: std::time::SystemTime,
     pub security_buffer: Option<&'a [u8]>,
@@ -30,7 +39,7 @@ pub struct Response<'a> {
 }
 
 bitflags! {
-    pub struct Capabilities: u8 {
+    pub struct Capabilities: u32 {
         const DFS = 0x01;
         const LEASING = 0x02;
         const LARGE_MTU = 0x04;
@@ -38,22 +47,67 @@ bitflags! {
         const PERSISTENT_HANDLES = 0x10;
         const DIRECTORY_LEASING = 0x20;
         const ENCRYPTION = 0x40;
+        const NOTIFICATIONS = 0x80;
     }
 }
 
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum HashAlgorithm {
     Sha512 = 0x01,
 }
 
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum Cipher {
     Aes128Ccm = 0x01,
     Aes128Gcm = 0x02,
+    Aes256Ccm = 0x03,
+    Aes256Gcm = 0x04,
+}
+
+#[repr(u8)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum CompressionAlgorithm {
+    None = 0x00,
+    Lznt1 = 0x01,
+    Lz77 = 0x02,
+    Lz77Huffman = 0x03,
+    PatternV1 = 0x04,
+    Lz4 = 0x05,
+}
+
+bitflags! {
+    pub struct CompressionFlags: u32 {
+        const CHAINED = 0x01;
+    }
+}
+
+bitflags! {
+    pub struct TransportCapabilities: u32 {
+        const ACCEPT_TRANSPORT_LEVEL_SECURITY = 0x01;
+    }
+}
+
+#[repr(u8)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum RdmaTransform {
+    None = 0x00,
+    Encryption = 0x01,
+    Signing = 0x02,
+}
+
+#[repr(u8)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum SigningAlgorithm {
+    HmacSha256 = 0x00,
+    AesCmac = 0x01,
+    AesGmac = 0x02,
 }
 
 #[cfg_attr(debug_assertions, derive(Debug))]
@@ -62,11 +116,34 @@ pub struct PreauthIntegrityCapabilities<'a> {
     pub salt: &'a [u8],
 }
 
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct CompressionCapabilities {
+    pub flags: CompressionFlags,
+    pub compression_algorithms: Vec<CompressionAlgorithm>,
+}
+
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub enum Context<'a> {
     PreauthIntegrityCapabilities(PreauthIntegrityCapabilities<'a>),
     EncryptionCapabilities(Vec<Cipher>),
-    Unknown(&'a [u8]),
+    CompressionCapabilities(CompressionCapabilities),
+    /// The server name the client connects to.
+    NetName(String),
+    TransportCapabilities(TransportCapabilities),
+    RdmaTransformCapabilities(Vec<RdmaTransform>),
+    SigningCapabilities(Vec<SigningAlgorithm>),
+    Unknown {
+        context_type: u16,
+        data: &'a [u8],
+    },
+}
+
+/// Parses `count` 16 bit ids and skips the ones that are unknown, so that a
+/// newer peer can still negotiate the values both sides know.
+fn parse_ids<T: FromPrimitive>(input: &[u8], count: u16) -> IResult<&[u8], Vec<T>> {
+    map!(input, count!(le_u16, usize::from(count)), |ids| {
+        ids.into_iter().filter_map(T::from_u16).collect()
+    })
 }
 
 impl<'a> Context<'a> {
@@ -76,11 +153,7 @@ impl<'a> Context<'a> {
             0x01 => do_parse!(data,
                 algo_count: le_u16 >>
                 salt_length: le_u16 >>
-                hash_algorithms:
-                    count!(
-                        map_opt!(le_u16, FromPrimitive::from_u16),
-                        usize::from(algo_count)
-                    ) >>
+                hash_algorithms: apply!(parse_ids, algo_count) >>
                 salt: take!(salt_length) >>
                 (Context::PreauthIntegrityCapabilities(PreauthIntegrityCapabilities {
                         hash_algorithms,
@@ -89,14 +162,95 @@ impl<'a> Context<'a> {
             ),
             0x02 => do_parse!(data,
                 cipher_count: le_u16 >>
-                ciphers:
-                    count!(
-                        map_opt!(le_u16, FromPrimitive::from_u16),
-                        usize::from(cipher_count)
-                    ) >>
+                ciphers: apply!(parse_ids, cipher_count) >>
                 (Context::EncryptionCapabilities(ciphers))
             ),
-            _ => map!(data, rest, |d| Context::Unknown(d)),
+            0x03 => do_parse!(data,
+                algo_count: le_u16 >>
+                take!(2) >> /* padding */
+                flags: map!(le_u32, CompressionFlags::from_bits_truncate) >>
+                compression_algorithms: apply!(parse_ids, algo_count) >>
+                (Context::CompressionCapabilities(CompressionCapabilities {
+                        flags,
+                        compression_algorithms,
+                }))
+            ),
+            0x05 => map!(data, map_res!(rest, utf16le_to_string), Context::NetName),
+            0x06 => map!(data,
+                map!(le_u32, TransportCapabilities::from_bits_truncate),
+                Context::TransportCapabilities
+            ),
+            0x07 => do_parse!(data,
+                transform_count: le_u16 >>
+                take!(6) >> /* reserved */
+                transforms: apply!(parse_ids, transform_count) >>
+                (Context::RdmaTransformCapabilities(transforms))
+            ),
+            0x08 => do_parse!(data,
+                algo_count: le_u16 >>
+                signing_algorithms: apply!(parse_ids, algo_count) >>
+                (Context::SigningCapabilities(signing_algorithms))
+            ),
+            _ => map!(data, rest, |d| Context::Unknown { context_type: ctype, data: d }),
+        }
+    }
+
+    fn context_type(&self) -> u16 {
+        match self {
+            Context::PreauthIntegrityCapabilities(_) => 0x01,
+            Context::EncryptionCapabilities(_) => 0x02,
+            Context::CompressionCapabilities(_) => 0x03,
+            Context::NetName(_) => 0x05,
+            Context::TransportCapabilities(_) => 0x06,
+            Context::RdmaTransformCapabilities(_) => 0x07,
+            Context::SigningCapabilities(_) => 0x08,
+            Context::Unknown { context_type, .. } => *context_type,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_data(&self, out: &mut Vec<u8>) {
+        match self {
+            Context::PreauthIntegrityCapabilities(caps) => {
+                out.extend_from_slice(&(caps.hash_algorithms.len() as u16).to_le_bytes());
+                out.extend_from_slice(&(caps.salt.len() as u16).to_le_bytes());
+                for algo in &caps.hash_algorithms {
+                    out.extend_from_slice(&u16::from(*algo as u8).to_le_bytes());
+                }
+                out.extend_from_slice(caps.salt);
+            }
+            Context::EncryptionCapabilities(ciphers) => {
+                out.extend_from_slice(&(ciphers.len() as u16).to_le_bytes());
+                for cipher in ciphers {
+                    out.extend_from_slice(&u16::from(*cipher as u8).to_le_bytes());
+                }
+            }
+            Context::CompressionCapabilities(caps) => {
+                out.extend_from_slice(&(caps.compression_algorithms.len() as u16).to_le_bytes());
+                out.extend_from_slice(&[0; 2]); /* padding */
+                out.extend_from_slice(&caps.flags.bits().to_le_bytes());
+                for algo in &caps.compression_algorithms {
+                    out.extend_from_slice(&u16::from(*algo as u8).to_le_bytes());
+                }
+            }
+            Context::NetName(name) => out.extend_from_slice(&string_to_utf16le(name)),
+            Context::TransportCapabilities(caps) => {
+                out.extend_from_slice(&caps.bits().to_le_bytes());
+            }
+            Context::RdmaTransformCapabilities(transforms) => {
+                out.extend_from_slice(&(transforms.len() as u16).to_le_bytes());
+                out.extend_from_slice(&[0; 6]); /* reserved */
+                for transform in transforms {
+                    out.extend_from_slice(&u16::from(*transform as u8).to_le_bytes());
+                }
+            }
+            Context::SigningCapabilities(algos) => {
+                out.extend_from_slice(&(algos.len() as u16).to_le_bytes());
+                for algo in algos {
+                    out.extend_from_slice(&u16::from(*algo as u8).to_le_bytes());
+                }
+            }
+            Context::Unknown { data, .. } => out.extend_from_slice(data),
         }
     }
 }
@@ -134,6 +288,14 @@ fn parse_negotiate_contexts(
     negot
 }
 
+/// Parses a dialect offered by a client, which can never be the wildcard
+/// of the SMB1 upgrade.
+pub(crate) fn parse_dialect(input: &[u8]) -> IResult<&[u8], Dialect> {
+    verify!(input, map_opt!(le_u16, Dialect::from_u16), |dialect| {
+        dialect != Dialect::Smb2Wildcard
+    })
+}
+
 #[rustfmt::skip]
 #[allow(clippy::cyclomatic_complexity)]
 #[allow(clippy::cast_possible_truncation)]
@@ -144,12 +306,12 @@ pub fn parse<'a>(data: &'a [u8]) -> nom::IResult<&'a [u8], Request> {
         dialect_count: verify!(le_u16, |x| x > 0) >>
         security_mode: le_u16 >>
         take!(2) >> /* reserved */
-        capabilities: map_opt!(le_u32, |x| Capabilities::from_bits(x as u8)) >>
+        capabilities: map!(le_u32, Capabilities::from_bits_truncate) >>
         client_guid: map!(take!(16), ClientGuid::from_slice) >>
         negot_context_offset: le_u32 >>
         negot_context_count: le_u16 >>
         take!(2) >> /* reserved */
-        dialects: count!(map_opt!(le_u16, FromPrimitive::from_u16), usize::from(dialect_count)) >>
+        dialects: count!(parse_dialect, usize::from(dialect_count)) >>
         negotiate_contexts:
             cond_with_error!(
                 dialects.contains(&Dialect::Smb3_1_1),
@@ -161,7 +323,7 @@ pub fn parse<'a>(data: &'a [u8]) -> nom::IResult<&'a [u8], Request> {
                 )
             ) >>
         (Request {
-            signing_required: (security_mode & 0x02) != 0,
+            signing_required: (security_mode & SIGNING_REQUIRED) != 0,
             capabilities,
             client_guid,
             dialects,
@@ -169,3 +331,147 @@ pub fn parse<'a>(data: &'a [u8]) -> nom::IResult<&'a [u8], Request> {
         })
     )
 }
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+#[allow(clippy::cast_possible_truncation)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    let packet_length = data.len() as u32 + u32::from(crate::header::STRUCTURE_SIZE);
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        security_mode: le_u16 >>
+        dialect: map_opt!(le_u16, FromPrimitive::from_u16) >>
+        negot_context_count: le_u16 >>
+        server_guid: map!(take!(16), ClientGuid::from_slice) >>
+        capabilities: map!(le_u32, Capabilities::from_bits_truncate) >>
+        max_transact_size: le_u32 >>
+        max_read_size: le_u32 >>
+        max_write_size: le_u32 >>
+        system_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        server_start_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        security_buffer_offset: verify!(
+            le_u16,
+            |offset| offset == 0 || offset >= RESPONSE_CONSTANT_SIZE
+        ) >>
+        security_buffer_length: verify!(
+            le_u16,
+            |length| length == 0 || security_buffer_offset != 0
+        ) >>
+        negot_context_offset: le_u32 >>
+        security_buffer: cond_with_error!(
+            security_buffer_offset != 0,
+            preceded!(
+                take!(security_buffer_offset - RESPONSE_CONSTANT_SIZE),
+                take!(security_buffer_length)
+            )
+        ) >>
+        negotiate_contexts:
+            cond_with_error!(
+                dialect == Dialect::Smb3_1_1 && negot_context_count > 0,
+                apply!(
+                    parse_negotiate_contexts,
+                    packet_length,
+                    negot_context_offset,
+                    negot_context_count
+                )
+            ) >>
+        (Response {
+            signing_required: (security_mode & SIGNING_REQUIRED) != 0,
+            dialect,
+            server_guid,
+            capabilities,
+            max_transact_size,
+            max_read_size,
+            max_write_size,
+            system_time,
+            server_start_time,
+            security_buffer,
+            negotiate_contexts: negotiate_contexts.unwrap_or_default(),
+        })
+    )
+}
+
+/// Writes the contexts each aligned to 8 bytes relative to `start` and returns the
+/// offset of the first one relative to the SMB2 header.
+#[allow(clippy::cast_possible_truncation)]
+fn write_negotiate_contexts(out: &mut Vec<u8>, start: usize, contexts: &[Context]) -> u32 {
+    pad8(out, start);
+    let offset = (usize::from(crate::header::STRUCTURE_SIZE) + out.len() - start) as u32;
+    for (i, context) in contexts.iter().enumerate() {
+        if i > 0 {
+            pad8(out, start);
+        }
+        let header_pos = out.len();
+        out.extend_from_slice(&context.context_type().to_le_bytes());
+        out.extend_from_slice(&[0; 6]); /* data length + reserved */
+        context.write_data(out);
+        let data_length = (out.len() - header_pos - CONTEXT_HEADER_SIZE) as u16;
+        out[header_pos + 2..header_pos + 4].copy_from_slice(&data_length.to_le_bytes());
+    }
+    offset
+}
+
+pub(crate) fn security_mode(signing_required: bool) -> u16 {
+    if signing_required {
+        SIGNING_ENABLED | SIGNING_REQUIRED
+    } else {
+        SIGNING_ENABLED
+    }
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let start = out.len();
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&(self.dialects.len() as u16).to_le_bytes());
+        out.extend_from_slice(&security_mode(self.signing_required).to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&self.capabilities.bits().to_le_bytes());
+        out.extend_from_slice(&*self.client_guid);
+        let context_pos = out.len();
+        out.extend_from_slice(&[0; 8]); /* contexts offset + count or ClientStartTime */
+        for dialect in &self.dialects {
+            out.extend_from_slice(&(*dialect as u16).to_le_bytes());
+        }
+        if !self.negotiate_contexts.is_empty() {
+            let offset = write_negotiate_contexts(out, start, &self.negotiate_contexts);
+            let count = self.negotiate_contexts.len() as u16;
+            out[context_pos..context_pos + 4].copy_from_slice(&offset.to_le_bytes());
+            out[context_pos + 4..context_pos + 6].copy_from_slice(&count.to_le_bytes());
+        }
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let start = out.len();
+        let security_buffer = self.security_buffer.unwrap_or_default();
+        let security_buffer_offset = if self.security_buffer.is_some() {
+            RESPONSE_CONSTANT_SIZE
+        } else {
+            0
+        };
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&security_mode(self.signing_required).to_le_bytes());
+        out.extend_from_slice(&(self.dialect as u16).to_le_bytes());
+        out.extend_from_slice(&(self.negotiate_contexts.len() as u16).to_le_bytes());
+        out.extend_from_slice(&*self.server_guid);
+        out.extend_from_slice(&self.capabilities.bits().to_le_bytes());
+        out.extend_from_slice(&self.max_transact_size.to_le_bytes());
+        out.extend_from_slice(&self.max_read_size.to_le_bytes());
+        out.extend_from_slice(&self.max_write_size.to_le_bytes());
+        out.extend_from_slice(&systemtime_to_filetime(self.system_time).to_le_bytes());
+        out.extend_from_slice(&systemtime_to_filetime(self.server_start_time).to_le_bytes());
+        out.extend_from_slice(&security_buffer_offset.to_le_bytes());
+        out.extend_from_slice(&(security_buffer.len() as u16).to_le_bytes());
+        let context_pos = out.len();
+        out.extend_from_slice(&[0; 4]); /* contexts offset */
+        out.extend_from_slice(security_buffer);
+        if !self.negotiate_contexts.is_empty() {
+            let offset = write_negotiate_contexts(out, start, &self.negotiate_contexts);
+            out[context_pos..context_pos + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use super::create::{LeaseKey, LeaseState, OplockLevel};
+use crate::{Dialect, FileId, Serialize};
+use nom::*;
+use num_traits::FromPrimitive;
+
+const OPLOCK_STRUCTURE_SIZE: u16 = 24;
+const LEASE_NOTIFICATION_STRUCTURE_SIZE: u16 = 44;
+const LEASE_ACKNOWLEDGMENT_STRUCTURE_SIZE: u16 = 36;
+const LEASE_FLAG_ACK_REQUIRED: u32 = 0x01;
+
+/// Message id the server uses for unsolicited break notifications.
+pub const NOTIFICATION_MESSAGE_ID: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+
+/// Sent by the client to acknowledge a break notification.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Request {
+    OplockAcknowledgment(OplockBreakAcknowledgment),
+    LeaseAcknowledgment(LeaseBreakAcknowledgment),
+}
+
+/// Oplock and lease break messages sent by the server.
+///
+/// Notifications are unsolicited and carry `NOTIFICATION_MESSAGE_ID` in
+/// their header while the acknowledgments answer a client request.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Response {
+    OplockNotification(OplockBreakNotification),
+    OplockAcknowledgment(OplockBreakAcknowledgment),
+    LeaseNotification(LeaseBreakNotification),
+    LeaseAcknowledgment(LeaseBreakAcknowledgment),
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct OplockBreakNotification {
+    /// The level the oplock is broken to
+    pub oplock_level: OplockLevel,
+    pub file_id: FileId,
+}
+
+/// Used by the client to acknowledge an oplock break and by the server to respond to it.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct OplockBreakAcknowledgment {
+    pub oplock_level: OplockLevel,
+    pub file_id: FileId,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct LeaseBreakNotification {
+    /// Only used for dialects 3.x and zero otherwise
+    pub new_epoch: u16,
+    pub ack_required: bool,
+    pub lease_key: LeaseKey,
+    pub current_lease_state: LeaseState,
+    pub new_lease_state: LeaseState,
+}
+
+/// Used by the client to acknowledge a lease break and by the server to respond to it.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct LeaseBreakAcknowledgment {
+    pub lease_key: LeaseKey,
+    pub lease_state: LeaseState,
+}
+
+#[rustfmt::skip]
+pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
+    switch!(data, peek!(le_u16),
+        OPLOCK_STRUCTURE_SIZE => map!(
+            parse_oplock_acknowledgment,
+            Request::OplockAcknowledgment
+        ) |
+        LEASE_ACKNOWLEDGMENT_STRUCTURE_SIZE => map!(
+            parse_lease_acknowledgment,
+            Request::LeaseAcknowledgment
+        )
+    )
+}
+
+/// Oplock break notifications and responses share their layout so `message_id`
+/// of the header is needed to tell them apart.
+#[rustfmt::skip]
+pub fn parse_response(data: &[u8], message_id: u64) -> IResult<&[u8], Response> {
+    switch!(data, peek!(le_u16),
+        OPLOCK_STRUCTURE_SIZE => switch!(value!(message_id == NOTIFICATION_MESSAGE_ID),
+            true => map!(parse_oplock_notification, Response::OplockNotification) |
+            false => map!(parse_oplock_acknowledgment, Response::OplockAcknowledgment)
+        ) |
+        LEASE_NOTIFICATION_STRUCTURE_SIZE => map!(
+            parse_lease_notification,
+            Response::LeaseNotification
+        ) |
+        LEASE_ACKNOWLEDGMENT_STRUCTURE_SIZE => map!(
+            parse_lease_acknowledgment,
+            Response::LeaseAcknowledgment
+        )
+    )
+}
+
+#[rustfmt::skip]
+fn parse_oplock(data: &[u8]) -> IResult<&[u8], (OplockLevel, FileId)> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == OPLOCK_STRUCTURE_SIZE) >>
+        oplock_level: map_opt!(le_u8, OplockLevel::from_u8) >>
+        take!(5) >> /* reserved */
+        file_id: map!(take!(16), FileId::from_slice) >>
+        ((oplock_level, file_id))
+    )
+}
+
+fn parse_oplock_notification(data: &[u8]) -> IResult<&[u8], OplockBreakNotification> {
+    map!(data, parse_oplock, |(oplock_level, file_id)| {
+        OplockBreakNotification {
+            oplock_level,
+            file_id,
+        }
+    })
+}
+
+fn parse_oplock_acknowledgment(data: &[u8]) -> IResult<&[u8], OplockBreakAcknowledgment> {
+    map!(data, parse_oplock, |(oplock_level, file_id)| {
+        OplockBreakAcknowledgment {
+            oplock_level,
+            file_id,
+        }
+    })
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_lease_notification(data: &[u8]) -> IResult<&[u8], LeaseBreakNotification> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == LEASE_NOTIFICATION_STRUCTURE_SIZE) >>
+        new_epoch: le_u16 >>
+        flags: verify!(le_u32, |x| x & !LEASE_FLAG_ACK_REQUIRED == 0) >>
+        lease_key: map!(take!(16), LeaseKey::from_slice) >>
+        current_lease_state: map_opt!(le_u32, LeaseState::from_bits) >>
+        new_lease_state: map_opt!(le_u32, LeaseState::from_bits) >>
+        take!(12) >> /* break reason, access mask hint and share mask hint are reserved */
+        (LeaseBreakNotification {
+            new_epoch,
+            ack_required: flags & LEASE_FLAG_ACK_REQUIRED != 0,
+            lease_key,
+            current_lease_state,
+            new_lease_state,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_lease_acknowledgment(data: &[u8]) -> IResult<&[u8], LeaseBreakAcknowledgment> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == LEASE_ACKNOWLEDGMENT_STRUCTURE_SIZE) >>
+        take!(6) >> /* reserved and flags */
+        lease_key: map!(take!(16), LeaseKey::from_slice) >>
+        lease_state: map_opt!(le_u32, LeaseState::from_bits) >>
+        take!(8) >> /* lease duration is reserved */
+        (LeaseBreakAcknowledgment {
+            lease_key,
+            lease_state,
+        })
+    )
+}
+
+fn write_oplock(out: &mut Vec<u8>, oplock_level: OplockLevel, file_id: &FileId) {
+    out.extend_from_slice(&OPLOCK_STRUCTURE_SIZE.to_le_bytes());
+    out.push(oplock_level as u8);
+    out.extend_from_slice(&[0; 5]); /* reserved */
+    out.extend_from_slice(&**file_id);
+}
+
+impl Serialize for OplockBreakNotification {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        write_oplock(out, self.oplock_level, &self.file_id);
+    }
+}
+
+impl Serialize for OplockBreakAcknowledgment {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        write_oplock(out, self.oplock_level, &self.file_id);
+    }
+}
+
+impl Serialize for LeaseBreakNotification {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let flags = if self.ack_required {
+            LEASE_FLAG_ACK_REQUIRED
+        } else {
+            0
+        };
+        out.extend_from_slice(&LEASE_NOTIFICATION_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&self.new_epoch.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&*self.lease_key);
+        out.extend_from_slice(&self.current_lease_state.bits().to_le_bytes());
+        out.extend_from_slice(&self.new_lease_state.bits().to_le_bytes());
+        out.extend_from_slice(&[0; 12]); /* break reason, access mask hint, share mask hint */
+    }
+}
+
+impl Serialize for LeaseBreakAcknowledgment {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&LEASE_ACKNOWLEDGMENT_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&[0; 6]); /* reserved and flags */
+        out.extend_from_slice(&*self.lease_key);
+        out.extend_from_slice(&self.lease_state.bits().to_le_bytes());
+        out.extend_from_slice(&[0; 8]); /* lease duration */
+    }
+}
+
+impl Serialize for Request {
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect) {
+        match self {
+            Request::OplockAcknowledgment(body) => body.write_to(out, dialect),
+            Request::LeaseAcknowledgment(body) => body.write_to(out, dialect),
+        }
+    }
+}
+
+impl Serialize for Response {
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect) {
+        match self {
+            Response::OplockNotification(body) => body.write_to(out, dialect),
+            Response::OplockAcknowledgment(body) => body.write_to(out, dialect),
+            Response::LeaseNotification(body) => body.write_to(out, dialect),
+            Response::LeaseAcknowledgment(body) => body.write_to(out, dialect),
+        }
+    }
+}
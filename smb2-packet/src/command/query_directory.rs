@@ -0,0 +1,501 @@
+use crate::{
+    filetime_to_systemtime, pad8, string_to_utf16le, systemtime_to_filetime, utf16le_to_string,
+    Dialect, FileAttributes, FileId, Serialize,
+};
+use bitflags::bitflags;
+use nom::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use std::ops::Deref;
+use std::time::SystemTime;
+
+const REQUEST_STRUCTURE_SIZE: u16 = 33;
+const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 9;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
+const SHORT_NAME_SIZE: usize = 24;
+
+#[repr(u8)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum FileInformationClass {
+    FileDirectoryInformation = 0x01,
+    FileFullDirectoryInformation = 0x02,
+    FileBothDirectoryInformation = 0x03,
+    FileNamesInformation = 0x0C,
+    FileIdBothDirectoryInformation = 0x25,
+    FileIdFullDirectoryInformation = 0x26,
+    FileIdExtdDirectoryInformation = 0x3C,
+}
+
+bitflags! {
+    pub struct Flags: u8 {
+        const RESTART_SCANS = 0x01;
+        const RETURN_SINGLE_ENTRY = 0x02;
+        const INDEX_SPECIFIED = 0x04;
+        const REOPEN = 0x10;
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Request {
+    pub file_information_class: FileInformationClass,
+    pub flags: Flags,
+    pub file_index: u32,
+    pub file_id: FileId,
+    pub file_name: String,
+    pub output_buffer_length: u32,
+}
+
+/// The output buffer can only be interpreted with the `FileInformationClass` of
+/// the request. Use `parse_file_information` to decode it.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Response<'a> {
+    pub output_buffer: &'a [u8],
+}
+
+/// Fields shared by all directory information classes but `FileNamesInformation`
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct DirectoryInformation {
+    pub file_index: u32,
+    pub creation_time: SystemTime,
+    pub last_access_time: SystemTime,
+    pub last_write_time: SystemTime,
+    pub change_time: SystemTime,
+    pub end_of_file: u64,
+    pub allocation_size: u64,
+    pub file_attributes: FileAttributes,
+    pub file_name: String,
+}
+
+/// A single entry of a query directory output buffer
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub enum FileInformation {
+    Directory(DirectoryInformation),
+    FullDirectory {
+        info: DirectoryInformation,
+        ea_size: u32,
+    },
+    IdFullDirectory {
+        info: DirectoryInformation,
+        ea_size: u32,
+        file_id: u64,
+    },
+    BothDirectory {
+        info: DirectoryInformation,
+        ea_size: u32,
+        short_name: ShortName,
+    },
+    IdBothDirectory {
+        info: DirectoryInformation,
+        ea_size: u32,
+        short_name: ShortName,
+        file_id: u64,
+    },
+    Names {
+        file_index: u32,
+        file_name: String,
+    },
+    IdExtdDirectory {
+        info: DirectoryInformation,
+        ea_size: u32,
+        reparse_point_tag: u32,
+        file_id: [u8; 16],
+    },
+}
+
+/// An 8.3 name which fits into the 12 UTF-16 code units reserved for it.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq, Clone)]
+pub struct ShortName {
+    name: String,
+}
+
+impl ShortName {
+    /// Returns `None` if `name` is longer than 12 UTF-16 code units.
+    pub fn new(name: &str) -> Option<Self> {
+        if name.encode_utf16().count() > SHORT_NAME_SIZE / 2 {
+            return None;
+        }
+        Some(Self { name: name.into() })
+    }
+}
+
+impl Deref for ShortName {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.name
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
+        file_information_class: map_opt!(le_u8, FileInformationClass::from_u8) >>
+        flags: map_opt!(le_u8, Flags::from_bits) >>
+        file_index: le_u32 >>
+        file_id: map!(take!(16), FileId::from_slice) >>
+        file_name_offset: le_u16 >>
+        file_name_length: le_u16 >>
+        output_buffer_length: le_u32 >>
+        verify!(
+            value!(file_name_offset),
+            |offset| file_name_length == 0 || offset >= REQUEST_CONSTANT_SIZE
+        ) >>
+        file_name: preceded!(
+            take!(if file_name_length > 0 { file_name_offset - REQUEST_CONSTANT_SIZE } else { 0 }),
+            map_res!(take!(file_name_length), utf16le_to_string)
+        ) >>
+        (Request {
+            file_information_class,
+            flags,
+            file_index,
+            file_id,
+            file_name,
+            output_buffer_length,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        output_buffer_offset: le_u16 >>
+        output_buffer_length: le_u32 >>
+        verify!(
+            value!(output_buffer_offset),
+            |offset| output_buffer_length == 0 || offset >= RESPONSE_CONSTANT_SIZE
+        ) >>
+        output_buffer: preceded!(
+            take!(if output_buffer_length > 0 {
+                output_buffer_offset - RESPONSE_CONSTANT_SIZE
+            } else {
+                0
+            }),
+            take!(output_buffer_length)
+        ) >>
+        (Response {
+            output_buffer,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_directory_information(data: &[u8]) -> IResult<&[u8], (DirectoryInformation, u32)> {
+    do_parse!(data,
+        file_index: le_u32 >>
+        creation_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_access_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_write_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        change_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        end_of_file: le_u64 >>
+        allocation_size: le_u64 >>
+        file_attributes: map!(le_u32, FileAttributes::from_bits_truncate) >>
+        file_name_length: le_u32 >>
+        (DirectoryInformation {
+            file_index,
+            creation_time,
+            last_access_time,
+            last_write_time,
+            change_time,
+            end_of_file,
+            allocation_size,
+            file_attributes,
+            file_name: String::new(),
+        }, file_name_length)
+    )
+}
+
+fn short_name(length: u8, buffer: &[u8]) -> Option<ShortName> {
+    buffer
+        .get(..usize::from(length))
+        .and_then(|name| utf16le_to_string(name).ok())
+        .map(|name| ShortName { name })
+}
+
+fn set_name(mut info: DirectoryInformation, file_name: String) -> DirectoryInformation {
+    info.file_name = file_name;
+    info
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_entry(data: &[u8], class: FileInformationClass) -> IResult<&[u8], FileInformation> {
+    match class {
+        FileInformationClass::FileDirectoryInformation => do_parse!(data,
+            fixed: parse_directory_information >>
+            file_name: map_res!(take!(fixed.1), utf16le_to_string) >>
+            (FileInformation::Directory(set_name(fixed.0, file_name)))
+        ),
+        FileInformationClass::FileFullDirectoryInformation => do_parse!(data,
+            fixed: parse_directory_information >>
+            ea_size: le_u32 >>
+            file_name: map_res!(take!(fixed.1), utf16le_to_string) >>
+            (FileInformation::FullDirectory {
+                info: set_name(fixed.0, file_name),
+                ea_size,
+            })
+        ),
+        FileInformationClass::FileIdFullDirectoryInformation => do_parse!(data,
+            fixed: parse_directory_information >>
+            ea_size: le_u32 >>
+            take!(4) >> /* reserved */
+            file_id: le_u64 >>
+            file_name: map_res!(take!(fixed.1), utf16le_to_string) >>
+            (FileInformation::IdFullDirectory {
+                info: set_name(fixed.0, file_name),
+                ea_size,
+                file_id,
+            })
+        ),
+        FileInformationClass::FileBothDirectoryInformation => do_parse!(data,
+            fixed: parse_directory_information >>
+            ea_size: le_u32 >>
+            short_name_length: le_u8 >>
+            take!(1) >> /* reserved */
+            short_name: map_opt!(take!(SHORT_NAME_SIZE), |x| short_name(short_name_length, x)) >>
+            file_name: map_res!(take!(fixed.1), utf16le_to_string) >>
+            (FileInformation::BothDirectory {
+                info: set_name(fixed.0, file_name),
+                ea_size,
+                short_name,
+            })
+        ),
+        FileInformationClass::FileIdBothDirectoryInformation => do_parse!(data,
+            fixed: parse_directory_information >>
+            ea_size: le_u32 >>
+            short_name_length: le_u8 >>
+            take!(1) >> /* reserved */
+            short_name: map_opt!(take!(SHORT_NAME_SIZE), |x| short_name(short_name_length, x)) >>
+            take!(2) >> /* reserved */
+            file_id: le_u64 >>
+            file_name: map_res!(take!(fixed.1), utf16le_to_string) >>
+            (FileInformation::IdBothDirectory {
+                info: set_name(fixed.0, file_name),
+                ea_size,
+                short_name,
+                file_id,
+            })
+        ),
+        FileInformationClass::FileNamesInformation => do_parse!(data,
+            file_index: le_u32 >>
+            file_name_length: le_u32 >>
+            file_name: map_res!(take!(file_name_length), utf16le_to_string) >>
+            (FileInformation::Names {
+                file_index,
+                file_name,
+            })
+        ),
+        FileInformationClass::FileIdExtdDirectoryInformation => do_parse!(data,
+            fixed: parse_directory_information >>
+            ea_size: le_u32 >>
+            reparse_point_tag: le_u32 >>
+            file_id: map!(take!(16), |x| {
+                let mut id = [0; 16];
+                id.copy_from_slice(x);
+                id
+            }) >>
+            file_name: map_res!(take!(fixed.1), utf16le_to_string) >>
+            (FileInformation::IdExtdDirectory {
+                info: set_name(fixed.0, file_name),
+                ea_size,
+                reparse_point_tag,
+                file_id,
+            })
+        ),
+    }
+}
+
+/// Decodes the output buffer of a query directory response.
+///
+/// The entries are chained by their `NextEntryOffset`.
+pub fn parse_file_information(
+    data: &[u8],
+    class: FileInformationClass,
+) -> IResult<&[u8], Vec<FileInformation>> {
+    let mut entries = Vec::new();
+    let mut cur = data;
+    loop {
+        let (remaining, next) = le_u32(cur)?;
+        let (remaining, entry) = parse_entry(remaining, class)?;
+        entries.push(entry);
+        if next == 0 {
+            return Ok((remaining, entries));
+        }
+        if (next as usize) < cur.len() - remaining.len() {
+            return Err(Err::Error(error_position!(cur, ErrorKind::Verify)));
+        }
+        cur = take!(cur, next)?.0;
+    }
+}
+
+impl FileInformation {
+    pub fn class(&self) -> FileInformationClass {
+        match self {
+            FileInformation::Directory(_) => FileInformationClass::FileDirectoryInformation,
+            FileInformation::FullDirectory { .. } => {
+                FileInformationClass::FileFullDirectoryInformation
+            }
+            FileInformation::IdFullDirectory { .. } => {
+                FileInformationClass::FileIdFullDirectoryInformation
+            }
+            FileInformation::BothDirectory { .. } => {
+                FileInformationClass::FileBothDirectoryInformation
+            }
+            FileInformation::IdBothDirectory { .. } => {
+                FileInformationClass::FileIdBothDirectoryInformation
+            }
+            FileInformation::Names { .. } => FileInformationClass::FileNamesInformation,
+            FileInformation::IdExtdDirectory { .. } => {
+                FileInformationClass::FileIdExtdDirectoryInformation
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>) {
+        let info = match self {
+            FileInformation::Names {
+                file_index,
+                file_name,
+            } => {
+                let name = string_to_utf16le(file_name);
+                out.extend_from_slice(&file_index.to_le_bytes());
+                out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                out.extend_from_slice(&name);
+                return;
+            }
+            FileInformation::Directory(info)
+            | FileInformation::FullDirectory { info, .. }
+            | FileInformation::IdFullDirectory { info, .. }
+            | FileInformation::BothDirectory { info, .. }
+            | FileInformation::IdBothDirectory { info, .. }
+            | FileInformation::IdExtdDirectory { info, .. } => info,
+        };
+        let name = string_to_utf16le(&info.file_name);
+        out.extend_from_slice(&info.file_index.to_le_bytes());
+        out.extend_from_slice(&systemtime_to_filetime(info.creation_time).to_le_bytes());
+        out.extend_from_slice(&systemtime_to_filetime(info.last_access_time).to_le_bytes());
+        out.extend_from_slice(&systemtime_to_filetime(info.last_write_time).to_le_bytes());
+        out.extend_from_slice(&systemtime_to_filetime(info.change_time).to_le_bytes());
+        out.extend_from_slice(&info.end_of_file.to_le_bytes());
+        out.extend_from_slice(&info.allocation_size.to_le_bytes());
+        out.extend_from_slice(&info.file_attributes.bits().to_le_bytes());
+        out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        match self {
+            FileInformation::FullDirectory { ea_size, .. } => {
+                out.extend_from_slice(&ea_size.to_le_bytes());
+            }
+            FileInformation::IdFullDirectory {
+                ea_size, file_id, ..
+            } => {
+                out.extend_from_slice(&ea_size.to_le_bytes());
+                out.extend_from_slice(&[0; 4]); /* reserved */
+                out.extend_from_slice(&file_id.to_le_bytes());
+            }
+            FileInformation::BothDirectory {
+                ea_size,
+                short_name,
+                ..
+            } => {
+                out.extend_from_slice(&ea_size.to_le_bytes());
+                write_short_name(out, short_name);
+            }
+            FileInformation::IdBothDirectory {
+                ea_size,
+                short_name,
+                file_id,
+                ..
+            } => {
+                out.extend_from_slice(&ea_size.to_le_bytes());
+                write_short_name(out, short_name);
+                out.extend_from_slice(&[0; 2]); /* reserved */
+                out.extend_from_slice(&file_id.to_le_bytes());
+            }
+            FileInformation::IdExtdDirectory {
+                ea_size,
+                reparse_point_tag,
+                file_id,
+                ..
+            } => {
+                out.extend_from_slice(&ea_size.to_le_bytes());
+                out.extend_from_slice(&reparse_point_tag.to_le_bytes());
+                out.extend_from_slice(file_id);
+            }
+            FileInformation::Directory(_) | FileInformation::Names { .. } => (),
+        }
+        out.extend_from_slice(&name);
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_short_name(out: &mut Vec<u8>, short_name: &ShortName) {
+    let mut name = string_to_utf16le(short_name);
+    out.push(name.len() as u8);
+    out.push(0); /* reserved */
+    name.resize(SHORT_NAME_SIZE, 0);
+    out.extend_from_slice(&name);
+}
+
+/// Encodes `entries` as output buffer of a query directory response.
+///
+/// Every entry but the last is padded to 8 bytes and linked to its successor
+/// with `NextEntryOffset`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn write_file_information(out: &mut Vec<u8>, entries: &[FileInformation]) {
+    for (i, entry) in entries.iter().enumerate() {
+        let start = out.len();
+        out.extend_from_slice(&[0; 4]); /* next entry offset */
+        entry.write_to(out);
+        if i + 1 < entries.len() {
+            pad8(out, start);
+            let next = (out.len() - start) as u32;
+            out[start..start + 4].copy_from_slice(&next.to_le_bytes());
+        }
+    }
+}
+
+impl Serialize for Request {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let file_name = string_to_utf16le(&self.file_name);
+        let file_name_offset = if file_name.is_empty() {
+            0
+        } else {
+            REQUEST_CONSTANT_SIZE
+        };
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.push(self.file_information_class as u8);
+        out.push(self.flags.bits());
+        out.extend_from_slice(&self.file_index.to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(&file_name_offset.to_le_bytes());
+        out.extend_from_slice(&(file_name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&self.output_buffer_length.to_le_bytes());
+        out.extend_from_slice(&file_name);
+        if file_name.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&RESPONSE_CONSTANT_SIZE.to_le_bytes());
+        out.extend_from_slice(&(self.output_buffer.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.output_buffer);
+        if self.output_buffer.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
@@ -0,0 +1,929 @@
+use crate::{
+    filetime_to_systemtime, string_to_utf16le, systemtime_to_filetime, utf16le_to_string,
+    AccessMask, Dialect, FileAttributes, FileId, Serialize,
+};
+use bitflags::bitflags;
+use nom::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use std::time::SystemTime;
+
+const REQUEST_STRUCTURE_SIZE: u16 = 41;
+const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 9;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
+const INFO_FILE: u8 = 0x01;
+const INFO_FILESYSTEM: u8 = 0x02;
+const INFO_SECURITY: u8 = 0x03;
+const INFO_QUOTA: u8 = 0x04;
+
+#[repr(u8)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum FileInfoClass {
+    FileBasicInformation = 0x04,
+    FileStandardInformation = 0x05,
+    FileInternalInformation = 0x06,
+    FileEaInformation = 0x07,
+    FileAccessInformation = 0x08,
+    FileNameInformation = 0x09,
+    FileRenameInformation = 0x0A,
+    FileLinkInformation = 0x0B,
+    FileDispositionInformation = 0x0D,
+    FilePositionInformation = 0x0E,
+    FileFullEaInformation = 0x0F,
+    FileModeInformation = 0x10,
+    FileAlignmentInformation = 0x11,
+    FileAllInformation = 0x12,
+    FileAllocationInformation = 0x13,
+    FileEndOfFileInformation = 0x14,
+    FileAlternateNameInformation = 0x15,
+    FileStreamInformation = 0x16,
+    FilePipeInformation = 0x17,
+    FilePipeLocalInformation = 0x18,
+    FilePipeRemoteInformation = 0x19,
+    FileCompressionInformation = 0x1C,
+    FileQuotaInformation = 0x20,
+    FileNetworkOpenInformation = 0x22,
+    FileAttributeTagInformation = 0x23,
+    FileValidDataLengthInformation = 0x27,
+    FileShortNameInformation = 0x28,
+    FileNormalizedNameInformation = 0x30,
+    FileIdInformation = 0x3B,
+    FileDispositionInformationEx = 0x40,
+    FileRenameInformationEx = 0x41,
+}
+
+#[repr(u8)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum FsInfoClass {
+    FileFsVolumeInformation = 0x01,
+    FileFsLabelInformation = 0x02,
+    FileFsSizeInformation = 0x03,
+    FileFsDeviceInformation = 0x04,
+    FileFsAttributeInformation = 0x05,
+    FileFsControlInformation = 0x06,
+    FileFsFullSizeInformation = 0x07,
+    FileFsObjectIdInformation = 0x08,
+    FileFsDriverPathInformation = 0x09,
+    FileFsVolumeFlagsInformation = 0x0A,
+    FileFsSectorSizeInformation = 0x0B,
+}
+
+/// `InfoType` together with the class of information for file and filesystem info
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum InfoClass {
+    File(FileInfoClass),
+    FileSystem(FsInfoClass),
+    Security,
+    Quota,
+    /// A server answers these with `STATUS_INVALID_INFO_CLASS`
+    Unknown {
+        info_type: u8,
+        class: u8,
+    },
+}
+
+bitflags! {
+    pub struct Flags: u32 {
+        const RESTART_SCAN = 0x01;
+        const RETURN_SINGLE_ENTRY = 0x02;
+        const INDEX_SPECIFIED = 0x04;
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Request<'a> {
+    pub info_class: InfoClass,
+    pub output_buffer_length: u32,
+    /// Only used for quota and extended attribute queries
+    pub input_buffer: &'a [u8],
+    /// `InputBufferOffset` as sent. Some clients send zero for an empty input
+    /// buffer, so it is only written for an empty one.
+    pub input_buffer_offset: u16,
+    /// Security information flags or the extended attribute index
+    pub additional_information: u32,
+    pub flags: Flags,
+    pub file_id: FileId,
+}
+
+/// The output buffer can only be interpreted with the `InfoClass` of the
+/// request. Use `parse_info` to decode it.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Response<'a> {
+    pub output_buffer: &'a [u8],
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Info<'a> {
+    File(FileInfo<'a>),
+    FileSystem(FsInfo<'a>),
+    /// Self-relative security descriptor
+    Security(&'a [u8]),
+    Quota(&'a [u8]),
+    Unknown {
+        info_type: u8,
+        class: u8,
+        data: &'a [u8],
+    },
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum FileInfo<'a> {
+    Basic(FileBasicInformation),
+    Standard(FileStandardInformation),
+    Internal {
+        index_number: u64,
+    },
+    Ea {
+        ea_size: u32,
+    },
+    Access {
+        access_flags: AccessMask,
+    },
+    Name {
+        file_name: String,
+    },
+    Rename(FileRenameInformation),
+    Disposition {
+        delete_pending: bool,
+    },
+    Position {
+        current_byte_offset: u64,
+    },
+    Mode {
+        mode: u32,
+    },
+    Alignment {
+        alignment_requirement: u32,
+    },
+    All(FileAllInformation),
+    Allocation {
+        allocation_size: u64,
+    },
+    EndOfFile {
+        end_of_file: u64,
+    },
+    NetworkOpen(FileNetworkOpenInformation),
+    DispositionEx {
+        flags: DispositionFlags,
+    },
+    /// Classes without a typed representation
+    Other {
+        class: FileInfoClass,
+        data: &'a [u8],
+    },
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum FsInfo<'a> {
+    Volume(FsVolumeInformation),
+    Size(FsSizeInformation),
+    Device(FsDeviceInformation),
+    Attribute(FsAttributeInformation),
+    FullSize(FsFullSizeInformation),
+    SectorSize(FsSectorSizeInformation),
+    /// Classes without a typed representation
+    Other {
+        class: FsInfoClass,
+        data: &'a [u8],
+    },
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileBasicInformation {
+    pub creation_time: FileTime,
+    pub last_access_time: FileTime,
+    pub last_write_time: FileTime,
+    pub change_time: FileTime,
+    pub file_attributes: FileAttributes,
+}
+
+/// A time of `FileBasicInformation` including the special values a client
+/// sends with `SET_INFO`.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FileTime {
+    /// Zero, the time is left as it is
+    Unchanged,
+    /// -1, the server stops updating the time for operations on the handle
+    StopUpdating,
+    /// -2, the server resumes updating the time for operations on the handle
+    ResumeUpdating,
+    Time(SystemTime),
+}
+
+impl FileTime {
+    const UNCHANGED: u64 = 0;
+    const STOP_UPDATING: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+    const RESUME_UPDATING: u64 = 0xFFFF_FFFF_FFFF_FFFE;
+
+    fn from_filetime(filetime: u64) -> Option<Self> {
+        match filetime {
+            Self::UNCHANGED => Some(FileTime::Unchanged),
+            Self::STOP_UPDATING => Some(FileTime::StopUpdating),
+            Self::RESUME_UPDATING => Some(FileTime::ResumeUpdating),
+            _ => filetime_to_systemtime(filetime).map(FileTime::Time),
+        }
+    }
+
+    fn to_filetime(self) -> u64 {
+        match self {
+            FileTime::Unchanged => Self::UNCHANGED,
+            FileTime::StopUpdating => Self::STOP_UPDATING,
+            FileTime::ResumeUpdating => Self::RESUME_UPDATING,
+            FileTime::Time(time) => systemtime_to_filetime(time),
+        }
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileStandardInformation {
+    pub allocation_size: u64,
+    pub end_of_file: u64,
+    pub number_of_links: u32,
+    pub delete_pending: bool,
+    pub directory: bool,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileNetworkOpenInformation {
+    pub creation_time: SystemTime,
+    pub last_access_time: SystemTime,
+    pub last_write_time: SystemTime,
+    pub change_time: SystemTime,
+    pub allocation_size: u64,
+    pub end_of_file: u64,
+    pub file_attributes: FileAttributes,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileAllInformation {
+    pub basic: FileBasicInformation,
+    pub standard: FileStandardInformation,
+    pub index_number: u64,
+    pub ea_size: u32,
+    pub access_flags: AccessMask,
+    pub current_byte_offset: u64,
+    pub mode: u32,
+    pub alignment_requirement: u32,
+    pub file_name: String,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileRenameInformation {
+    pub replace_if_exists: bool,
+    pub root_directory: u64,
+    pub file_name: String,
+}
+
+bitflags! {
+    pub struct DispositionFlags: u32 {
+        const DELETE = 0x01;
+        const POSIX_SEMANTICS = 0x02;
+        const FORCE_IMAGE_SECTION_CHECK = 0x04;
+        const ON_CLOSE = 0x08;
+        const IGNORE_READONLY_ATTRIBUTE = 0x10;
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FsVolumeInformation {
+    pub volume_creation_time: SystemTime,
+    pub volume_serial_number: u32,
+    pub supports_objects: bool,
+    pub volume_label: String,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FsSizeInformation {
+    pub total_allocation_units: u64,
+    pub available_allocation_units: u64,
+    pub sectors_per_allocation_unit: u32,
+    pub bytes_per_sector: u32,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FsDeviceInformation {
+    pub device_type: u32,
+    pub characteristics: u32,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FsAttributeInformation {
+    pub file_system_attributes: u32,
+    pub maximum_component_name_length: u32,
+    pub file_system_name: String,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FsFullSizeInformation {
+    pub total_allocation_units: u64,
+    pub caller_available_allocation_units: u64,
+    pub actual_available_allocation_units: u64,
+    pub sectors_per_allocation_unit: u32,
+    pub bytes_per_sector: u32,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct FsSectorSizeInformation {
+    pub logical_bytes_per_sector: u32,
+    pub physical_bytes_per_sector_for_atomicity: u32,
+    pub physical_bytes_per_sector_for_performance: u32,
+    pub effective_physical_bytes_per_sector_for_atomicity: u32,
+    pub flags: u32,
+    pub byte_offset_for_sector_alignment: u32,
+    pub byte_offset_for_partition_alignment: u32,
+}
+
+impl InfoClass {
+    pub(crate) fn from_wire(info_type: u8, class: u8) -> Self {
+        let known = match info_type {
+            INFO_FILE => FileInfoClass::from_u8(class).map(InfoClass::File),
+            INFO_FILESYSTEM => FsInfoClass::from_u8(class).map(InfoClass::FileSystem),
+            INFO_SECURITY if class == 0 => Some(InfoClass::Security),
+            INFO_QUOTA if class == 0 => Some(InfoClass::Quota),
+            _ => None,
+        };
+        known.unwrap_or(InfoClass::Unknown { info_type, class })
+    }
+
+    /// Returns `InfoType` and `FileInfoClass` as put on the wire
+    pub(crate) fn to_wire(self) -> [u8; 2] {
+        match self {
+            InfoClass::File(class) => [INFO_FILE, class as u8],
+            InfoClass::FileSystem(class) => [INFO_FILESYSTEM, class as u8],
+            InfoClass::Security => [INFO_SECURITY, 0],
+            InfoClass::Quota => [INFO_QUOTA, 0],
+            InfoClass::Unknown { info_type, class } => [info_type, class],
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
+        info_type: le_u8 >>
+        info_class: map!(le_u8, |x| InfoClass::from_wire(info_type, x)) >>
+        output_buffer_length: le_u32 >>
+        input_buffer_offset: le_u16 >>
+        take!(2) >> /* reserved */
+        input_buffer_length: le_u32 >>
+        additional_information: le_u32 >>
+        flags: map_opt!(le_u32, Flags::from_bits) >>
+        file_id: map!(take!(16), FileId::from_slice) >>
+        verify!(
+            value!(input_buffer_offset),
+            |offset| input_buffer_length == 0 || offset >= REQUEST_CONSTANT_SIZE
+        ) >>
+        input_buffer: preceded!(
+            take!(if input_buffer_length > 0 {
+                input_buffer_offset - REQUEST_CONSTANT_SIZE
+            } else {
+                0
+            }),
+            take!(input_buffer_length)
+        ) >>
+        (Request {
+            info_class,
+            output_buffer_length,
+            input_buffer,
+            input_buffer_offset,
+            additional_information,
+            flags,
+            file_id,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        output_buffer_offset: le_u16 >>
+        output_buffer_length: le_u32 >>
+        verify!(
+            value!(output_buffer_offset),
+            |offset| output_buffer_length == 0 || offset >= RESPONSE_CONSTANT_SIZE
+        ) >>
+        output_buffer: preceded!(
+            take!(if output_buffer_length > 0 {
+                output_buffer_offset - RESPONSE_CONSTANT_SIZE
+            } else {
+                0
+            }),
+            take!(output_buffer_length)
+        ) >>
+        (Response {
+            output_buffer,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_basic(data: &[u8]) -> IResult<&[u8], FileBasicInformation> {
+    do_parse!(data,
+        creation_time: map_opt!(le_u64, FileTime::from_filetime) >>
+        last_access_time: map_opt!(le_u64, FileTime::from_filetime) >>
+        last_write_time: map_opt!(le_u64, FileTime::from_filetime) >>
+        change_time: map_opt!(le_u64, FileTime::from_filetime) >>
+        file_attributes: map!(le_u32, FileAttributes::from_bits_truncate) >>
+        take!(4) >> /* reserved */
+        (FileBasicInformation {
+            creation_time,
+            last_access_time,
+            last_write_time,
+            change_time,
+            file_attributes,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_standard(data: &[u8]) -> IResult<&[u8], FileStandardInformation> {
+    do_parse!(data,
+        allocation_size: le_u64 >>
+        end_of_file: le_u64 >>
+        number_of_links: le_u32 >>
+        delete_pending: map!(le_u8, |x| x != 0) >>
+        directory: map!(le_u8, |x| x != 0) >>
+        take!(2) >> /* reserved */
+        (FileStandardInformation {
+            allocation_size,
+            end_of_file,
+            number_of_links,
+            delete_pending,
+            directory,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_network_open(data: &[u8]) -> IResult<&[u8], FileNetworkOpenInformation> {
+    do_parse!(data,
+        creation_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_access_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        last_write_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        change_time: map_opt!(le_u64, filetime_to_systemtime) >>
+        allocation_size: le_u64 >>
+        end_of_file: le_u64 >>
+        file_attributes: map!(le_u32, FileAttributes::from_bits_truncate) >>
+        take!(4) >> /* reserved */
+        (FileNetworkOpenInformation {
+            creation_time,
+            last_access_time,
+            last_write_time,
+            change_time,
+            allocation_size,
+            end_of_file,
+            file_attributes,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_all(data: &[u8]) -> IResult<&[u8], FileAllInformation> {
+    do_parse!(data,
+        basic: parse_basic >>
+        standard: parse_standard >>
+        index_number: le_u64 >>
+        ea_size: le_u32 >>
+        access_flags: map!(le_u32, AccessMask::from_bits_truncate) >>
+        current_byte_offset: le_u64 >>
+        mode: le_u32 >>
+        alignment_requirement: le_u32 >>
+        file_name: parse_file_name >>
+        (FileAllInformation {
+            basic,
+            standard,
+            index_number,
+            ea_size,
+            access_flags,
+            current_byte_offset,
+            mode,
+            alignment_requirement,
+            file_name,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_rename(data: &[u8]) -> IResult<&[u8], FileRenameInformation> {
+    do_parse!(data,
+        replace_if_exists: map!(le_u8, |x| x != 0) >>
+        take!(7) >> /* reserved */
+        root_directory: le_u64 >>
+        file_name: parse_file_name >>
+        (FileRenameInformation {
+            replace_if_exists,
+            root_directory,
+            file_name,
+        })
+    )
+}
+
+/// A 32 bit length followed by an UTF-16 name
+#[rustfmt::skip]
+fn parse_file_name(data: &[u8]) -> IResult<&[u8], String> {
+    do_parse!(data,
+        length: le_u32 >>
+        name: map_res!(take!(length), utf16le_to_string) >>
+        (name)
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_file_info(data: &[u8], class: FileInfoClass) -> IResult<&[u8], FileInfo> {
+    match class {
+        FileInfoClass::FileBasicInformation => map!(data, parse_basic, FileInfo::Basic),
+        FileInfoClass::FileStandardInformation => map!(data, parse_standard, FileInfo::Standard),
+        FileInfoClass::FileInternalInformation =>
+            map!(data, le_u64, |index_number| FileInfo::Internal { index_number }),
+        FileInfoClass::FileEaInformation =>
+            map!(data, le_u32, |ea_size| FileInfo::Ea { ea_size }),
+        FileInfoClass::FileAccessInformation =>
+            map!(data, map!(le_u32, AccessMask::from_bits_truncate), |access_flags| {
+                FileInfo::Access { access_flags }
+            }),
+        FileInfoClass::FileNameInformation =>
+            map!(data, parse_file_name, |file_name| FileInfo::Name { file_name }),
+        FileInfoClass::FileRenameInformation => map!(data, parse_rename, FileInfo::Rename),
+        FileInfoClass::FileDispositionInformation =>
+            map!(data, le_u8, |x| FileInfo::Disposition { delete_pending: x != 0 }),
+        FileInfoClass::FilePositionInformation =>
+            map!(data, le_u64, |current_byte_offset| FileInfo::Position { current_byte_offset }),
+        FileInfoClass::FileModeInformation =>
+            map!(data, le_u32, |mode| FileInfo::Mode { mode }),
+        FileInfoClass::FileAlignmentInformation =>
+            map!(data, le_u32, |alignment_requirement| FileInfo::Alignment {
+                alignment_requirement
+            }),
+        FileInfoClass::FileAllInformation => map!(data, parse_all, FileInfo::All),
+        FileInfoClass::FileAllocationInformation =>
+            map!(data, le_u64, |allocation_size| FileInfo::Allocation { allocation_size }),
+        FileInfoClass::FileEndOfFileInformation =>
+            map!(data, le_u64, |end_of_file| FileInfo::EndOfFile { end_of_file }),
+        FileInfoClass::FileNetworkOpenInformation =>
+            map!(data, parse_network_open, FileInfo::NetworkOpen),
+        FileInfoClass::FileDispositionInformationEx =>
+            map!(data, map_opt!(le_u32, DispositionFlags::from_bits), |flags| {
+                FileInfo::DispositionEx { flags }
+            }),
+        _ => map!(data, rest, |d| FileInfo::Other { class, data: d }),
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_fs_info(data: &[u8], class: FsInfoClass) -> IResult<&[u8], FsInfo> {
+    match class {
+        FsInfoClass::FileFsVolumeInformation => do_parse!(data,
+            volume_creation_time: map_opt!(le_u64, filetime_to_systemtime) >>
+            volume_serial_number: le_u32 >>
+            volume_label_length: le_u32 >>
+            supports_objects: map!(le_u8, |x| x != 0) >>
+            take!(1) >> /* reserved */
+            volume_label: map_res!(take!(volume_label_length), utf16le_to_string) >>
+            (FsInfo::Volume(FsVolumeInformation {
+                volume_creation_time,
+                volume_serial_number,
+                supports_objects,
+                volume_label,
+            }))
+        ),
+        FsInfoClass::FileFsSizeInformation => do_parse!(data,
+            total_allocation_units: le_u64 >>
+            available_allocation_units: le_u64 >>
+            sectors_per_allocation_unit: le_u32 >>
+            bytes_per_sector: le_u32 >>
+            (FsInfo::Size(FsSizeInformation {
+                total_allocation_units,
+                available_allocation_units,
+                sectors_per_allocation_unit,
+                bytes_per_sector,
+            }))
+        ),
+        FsInfoClass::FileFsDeviceInformation => do_parse!(data,
+            device_type: le_u32 >>
+            characteristics: le_u32 >>
+            (FsInfo::Device(FsDeviceInformation {
+                device_type,
+                characteristics,
+            }))
+        ),
+        FsInfoClass::FileFsAttributeInformation => do_parse!(data,
+            file_system_attributes: le_u32 >>
+            maximum_component_name_length: le_u32 >>
+            file_system_name: parse_file_name >>
+            (FsInfo::Attribute(FsAttributeInformation {
+                file_system_attributes,
+                maximum_component_name_length,
+                file_system_name,
+            }))
+        ),
+        FsInfoClass::FileFsFullSizeInformation => do_parse!(data,
+            total_allocation_units: le_u64 >>
+            caller_available_allocation_units: le_u64 >>
+            actual_available_allocation_units: le_u64 >>
+            sectors_per_allocation_unit: le_u32 >>
+            bytes_per_sector: le_u32 >>
+            (FsInfo::FullSize(FsFullSizeInformation {
+                total_allocation_units,
+                caller_available_allocation_units,
+                actual_available_allocation_units,
+                sectors_per_allocation_unit,
+                bytes_per_sector,
+            }))
+        ),
+        FsInfoClass::FileFsSectorSizeInformation => do_parse!(data,
+            logical_bytes_per_sector: le_u32 >>
+            physical_bytes_per_sector_for_atomicity: le_u32 >>
+            physical_bytes_per_sector_for_performance: le_u32 >>
+            effective_physical_bytes_per_sector_for_atomicity: le_u32 >>
+            flags: le_u32 >>
+            byte_offset_for_sector_alignment: le_u32 >>
+            byte_offset_for_partition_alignment: le_u32 >>
+            (FsInfo::SectorSize(FsSectorSizeInformation {
+                logical_bytes_per_sector,
+                physical_bytes_per_sector_for_atomicity,
+                physical_bytes_per_sector_for_performance,
+                effective_physical_bytes_per_sector_for_atomicity,
+                flags,
+                byte_offset_for_sector_alignment,
+                byte_offset_for_partition_alignment,
+            }))
+        ),
+        _ => map!(data, rest, |d| FsInfo::Other { class, data: d }),
+    }
+}
+
+/// Decodes an info buffer of the given class.
+///
+/// This is the output buffer of a query info response or the buffer of a set info request.
+pub fn parse_info(data: &[u8], info_class: InfoClass) -> IResult<&[u8], Info> {
+    match info_class {
+        InfoClass::File(class) => map!(data, apply!(parse_file_info, class), Info::File),
+        InfoClass::FileSystem(class) => map!(data, apply!(parse_fs_info, class), Info::FileSystem),
+        InfoClass::Security => map!(data, rest, Info::Security),
+        InfoClass::Quota => map!(data, rest, Info::Quota),
+        InfoClass::Unknown { info_type, class } => {
+            map!(data, rest, |data| Info::Unknown {
+                info_type,
+                class,
+                data
+            })
+        }
+    }
+}
+
+impl Info<'_> {
+    pub fn info_class(&self) -> InfoClass {
+        match self {
+            Info::File(info) => InfoClass::File(info.class()),
+            Info::FileSystem(info) => InfoClass::FileSystem(info.class()),
+            Info::Security(_) => InfoClass::Security,
+            Info::Quota(_) => InfoClass::Quota,
+            Info::Unknown {
+                info_type, class, ..
+            } => InfoClass::Unknown {
+                info_type: *info_type,
+                class: *class,
+            },
+        }
+    }
+}
+
+impl FileInfo<'_> {
+    pub fn class(&self) -> FileInfoClass {
+        match self {
+            FileInfo::Basic(_) => FileInfoClass::FileBasicInformation,
+            FileInfo::Standard(_) => FileInfoClass::FileStandardInformation,
+            FileInfo::Internal { .. } => FileInfoClass::FileInternalInformation,
+            FileInfo::Ea { .. } => FileInfoClass::FileEaInformation,
+            FileInfo::Access { .. } => FileInfoClass::FileAccessInformation,
+            FileInfo::Name { .. } => FileInfoClass::FileNameInformation,
+            FileInfo::Rename(_) => FileInfoClass::FileRenameInformation,
+            FileInfo::Disposition { .. } => FileInfoClass::FileDispositionInformation,
+            FileInfo::Position { .. } => FileInfoClass::FilePositionInformation,
+            FileInfo::Mode { .. } => FileInfoClass::FileModeInformation,
+            FileInfo::Alignment { .. } => FileInfoClass::FileAlignmentInformation,
+            FileInfo::All(_) => FileInfoClass::FileAllInformation,
+            FileInfo::Allocation { .. } => FileInfoClass::FileAllocationInformation,
+            FileInfo::EndOfFile { .. } => FileInfoClass::FileEndOfFileInformation,
+            FileInfo::NetworkOpen(_) => FileInfoClass::FileNetworkOpenInformation,
+            FileInfo::DispositionEx { .. } => FileInfoClass::FileDispositionInformationEx,
+            FileInfo::Other { class, .. } => *class,
+        }
+    }
+}
+
+impl FsInfo<'_> {
+    pub fn class(&self) -> FsInfoClass {
+        match self {
+            FsInfo::Volume(_) => FsInfoClass::FileFsVolumeInformation,
+            FsInfo::Size(_) => FsInfoClass::FileFsSizeInformation,
+            FsInfo::Device(_) => FsInfoClass::FileFsDeviceInformation,
+            FsInfo::Attribute(_) => FsInfoClass::FileFsAttributeInformation,
+            FsInfo::FullSize(_) => FsInfoClass::FileFsFullSizeInformation,
+            FsInfo::SectorSize(_) => FsInfoClass::FileFsSectorSizeInformation,
+            FsInfo::Other { class, .. } => *class,
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_file_name(out: &mut Vec<u8>, file_name: &str) {
+    let name = string_to_utf16le(file_name);
+    out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    out.extend_from_slice(&name);
+}
+
+fn write_basic(out: &mut Vec<u8>, info: &FileBasicInformation) {
+    out.extend_from_slice(&info.creation_time.to_filetime().to_le_bytes());
+    out.extend_from_slice(&info.last_access_time.to_filetime().to_le_bytes());
+    out.extend_from_slice(&info.last_write_time.to_filetime().to_le_bytes());
+    out.extend_from_slice(&info.change_time.to_filetime().to_le_bytes());
+    out.extend_from_slice(&info.file_attributes.bits().to_le_bytes());
+    out.extend_from_slice(&[0; 4]); /* reserved */
+}
+
+fn write_standard(out: &mut Vec<u8>, info: &FileStandardInformation) {
+    out.extend_from_slice(&info.allocation_size.to_le_bytes());
+    out.extend_from_slice(&info.end_of_file.to_le_bytes());
+    out.extend_from_slice(&info.number_of_links.to_le_bytes());
+    out.push(u8::from(info.delete_pending));
+    out.push(u8::from(info.directory));
+    out.extend_from_slice(&[0; 2]); /* reserved */
+}
+
+fn write_file_info(out: &mut Vec<u8>, info: &FileInfo) {
+    match info {
+        FileInfo::Basic(info) => write_basic(out, info),
+        FileInfo::Standard(info) => write_standard(out, info),
+        FileInfo::Internal { index_number } => out.extend_from_slice(&index_number.to_le_bytes()),
+        FileInfo::Ea { ea_size } => out.extend_from_slice(&ea_size.to_le_bytes()),
+        FileInfo::Access { access_flags } => {
+            out.extend_from_slice(&access_flags.bits().to_le_bytes());
+        }
+        FileInfo::Name { file_name } => write_file_name(out, file_name),
+        FileInfo::Rename(info) => {
+            out.push(u8::from(info.replace_if_exists));
+            out.extend_from_slice(&[0; 7]); /* reserved */
+            out.extend_from_slice(&info.root_directory.to_le_bytes());
+            write_file_name(out, &info.file_name);
+        }
+        FileInfo::Disposition { delete_pending } => out.push(u8::from(*delete_pending)),
+        FileInfo::Position {
+            current_byte_offset,
+        } => out.extend_from_slice(&current_byte_offset.to_le_bytes()),
+        FileInfo::Mode { mode } => out.extend_from_slice(&mode.to_le_bytes()),
+        FileInfo::Alignment {
+            alignment_requirement,
+        } => out.extend_from_slice(&alignment_requirement.to_le_bytes()),
+        FileInfo::All(info) => {
+            write_basic(out, &info.basic);
+            write_standard(out, &info.standard);
+            out.extend_from_slice(&info.index_number.to_le_bytes());
+            out.extend_from_slice(&info.ea_size.to_le_bytes());
+            out.extend_from_slice(&info.access_flags.bits().to_le_bytes());
+            out.extend_from_slice(&info.current_byte_offset.to_le_bytes());
+            out.extend_from_slice(&info.mode.to_le_bytes());
+            out.extend_from_slice(&info.alignment_requirement.to_le_bytes());
+            write_file_name(out, &info.file_name);
+        }
+        FileInfo::Allocation { allocation_size } => {
+            out.extend_from_slice(&allocation_size.to_le_bytes());
+        }
+        FileInfo::EndOfFile { end_of_file } => out.extend_from_slice(&end_of_file.to_le_bytes()),
+        FileInfo::NetworkOpen(info) => {
+            out.extend_from_slice(&systemtime_to_filetime(info.creation_time).to_le_bytes());
+            out.extend_from_slice(&systemtime_to_filetime(info.last_access_time).to_le_bytes());
+            out.extend_from_slice(&systemtime_to_filetime(info.last_write_time).to_le_bytes());
+            out.extend_from_slice(&systemtime_to_filetime(info.change_time).to_le_bytes());
+            out.extend_from_slice(&info.allocation_size.to_le_bytes());
+            out.extend_from_slice(&info.end_of_file.to_le_bytes());
+            out.extend_from_slice(&info.file_attributes.bits().to_le_bytes());
+            out.extend_from_slice(&[0; 4]); /* reserved */
+        }
+        FileInfo::DispositionEx { flags } => out.extend_from_slice(&flags.bits().to_le_bytes()),
+        FileInfo::Other { data, .. } => out.extend_from_slice(data),
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_fs_info(out: &mut Vec<u8>, info: &FsInfo) {
+    match info {
+        FsInfo::Volume(info) => {
+            let label = string_to_utf16le(&info.volume_label);
+            out.extend_from_slice(&systemtime_to_filetime(info.volume_creation_time).to_le_bytes());
+            out.extend_from_slice(&info.volume_serial_number.to_le_bytes());
+            out.extend_from_slice(&(label.len() as u32).to_le_bytes());
+            out.push(u8::from(info.supports_objects));
+            out.push(0); /* reserved */
+            out.extend_from_slice(&label);
+        }
+        FsInfo::Size(info) => {
+            out.extend_from_slice(&info.total_allocation_units.to_le_bytes());
+            out.extend_from_slice(&info.available_allocation_units.to_le_bytes());
+            out.extend_from_slice(&info.sectors_per_allocation_unit.to_le_bytes());
+            out.extend_from_slice(&info.bytes_per_sector.to_le_bytes());
+        }
+        FsInfo::Device(info) => {
+            out.extend_from_slice(&info.device_type.to_le_bytes());
+            out.extend_from_slice(&info.characteristics.to_le_bytes());
+        }
+        FsInfo::Attribute(info) => {
+            out.extend_from_slice(&info.file_system_attributes.to_le_bytes());
+            out.extend_from_slice(&info.maximum_component_name_length.to_le_bytes());
+            write_file_name(out, &info.file_system_name);
+        }
+        FsInfo::FullSize(info) => {
+            out.extend_from_slice(&info.total_allocation_units.to_le_bytes());
+            out.extend_from_slice(&info.caller_available_allocation_units.to_le_bytes());
+            out.extend_from_slice(&info.actual_available_allocation_units.to_le_bytes());
+            out.extend_from_slice(&info.sectors_per_allocation_unit.to_le_bytes());
+            out.extend_from_slice(&info.bytes_per_sector.to_le_bytes());
+        }
+        FsInfo::SectorSize(info) => {
+            out.extend_from_slice(&info.logical_bytes_per_sector.to_le_bytes());
+            out.extend_from_slice(&info.physical_bytes_per_sector_for_atomicity.to_le_bytes());
+            out.extend_from_slice(&info.physical_bytes_per_sector_for_performance.to_le_bytes());
+            out.extend_from_slice(
+                &info
+                    .effective_physical_bytes_per_sector_for_atomicity
+                    .to_le_bytes(),
+            );
+            out.extend_from_slice(&info.flags.to_le_bytes());
+            out.extend_from_slice(&info.byte_offset_for_sector_alignment.to_le_bytes());
+            out.extend_from_slice(&info.byte_offset_for_partition_alignment.to_le_bytes());
+        }
+        FsInfo::Other { data, .. } => out.extend_from_slice(data),
+    }
+}
+
+/// Encodes `info` as output buffer of a query info response or as buffer of a set info request.
+pub fn write_info(out: &mut Vec<u8>, info: &Info) {
+    match info {
+        Info::File(info) => write_file_info(out, info),
+        Info::FileSystem(info) => write_fs_info(out, info),
+        Info::Security(data) | Info::Quota(data) | Info::Unknown { data, .. } => {
+            out.extend_from_slice(data)
+        }
+    }
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&self.info_class.to_wire());
+        out.extend_from_slice(&self.output_buffer_length.to_le_bytes());
+        if self.input_buffer.is_empty() {
+            out.extend_from_slice(&self.input_buffer_offset.to_le_bytes());
+        } else {
+            out.extend_from_slice(&REQUEST_CONSTANT_SIZE.to_le_bytes());
+        }
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&(self.input_buffer.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.additional_information.to_le_bytes());
+        out.extend_from_slice(&self.flags.bits().to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(self.input_buffer);
+        if self.input_buffer.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&RESPONSE_CONSTANT_SIZE.to_le_bytes());
+        out.extend_from_slice(&(self.output_buffer.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.output_buffer);
+        if self.output_buffer.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
@@ -1,11 +1,13 @@
 use super::{Channel, ChannelType};
-use crate::{Dialect, FileId};
+use crate::{Dialect, FileId, Serialize};
 use bitflags::bitflags;
 use nom::*;
 use num_traits::FromPrimitive;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 49;
 const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 17;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Request<'a> {
@@ -42,7 +44,7 @@ pub fn parse_request(data: &[u8], dialect: Dialect) -> IResult<&[u8], Request> {
         offset: le_u64 >>
         file_id: map!(take!(16), FileId::from_slice) >>
         minimum_count: le_u32 >>
-        channel_type: switch!(value!(dialect >= Dialect::Smb3_1_1),
+        channel_type: switch!(value!(dialect.has_read_write_channel()),
             true => map_opt!(le_u32, ChannelType::from_u32) |
             false => map!(take!(4), |_| ChannelType::None)
         ) >>
@@ -72,3 +74,74 @@ pub fn parse_request(data: &[u8], dialect: Dialect) -> IResult<&[u8], Request> {
         })
     )
 }
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity, clippy::cast_possible_truncation)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        data_offset: le_u8 >>
+        take!(1) >> /* reserved */
+        data_length: le_u32 >>
+        verify!(
+            value!(data_offset),
+            |offset| data_length == 0 || u16::from(offset) >= RESPONSE_CONSTANT_SIZE
+        ) >>
+        data_remaining: le_u32 >>
+        take!(4) >> /* reserved */
+        data: cond_with_error!(
+            data_length > 0,
+            preceded!(
+                take!(u16::from(data_offset) - RESPONSE_CONSTANT_SIZE),
+                take!(data_length)
+            )
+        ) >>
+        (Response {
+            data_remaining,
+            data: data.unwrap_or_default(),
+        })
+    )
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut flags = Flags::empty();
+        flags.set(Flags::READ_UNBUFFERED, self.read_unbuffered);
+        let channel = self.channel.buffer();
+        let channel_offset = if channel.is_empty() {
+            0
+        } else {
+            REQUEST_CONSTANT_SIZE
+        };
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.push(self.padding);
+        out.push(flags.bits());
+        out.extend_from_slice(&self.length.to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(&self.minimum_count.to_le_bytes());
+        out.extend_from_slice(&(self.channel.channel_type() as u32).to_le_bytes());
+        out.extend_from_slice(&self.remaining_bytes.to_le_bytes());
+        out.extend_from_slice(&channel_offset.to_le_bytes());
+        out.extend_from_slice(&(channel.len() as u16).to_le_bytes());
+        if channel.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        } else {
+            out.extend_from_slice(channel);
+        }
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.push(RESPONSE_CONSTANT_SIZE as u8);
+        out.push(0); /* reserved */
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.data_remaining.to_le_bytes());
+        out.extend_from_slice(&[0; 4]); /* reserved */
+        out.extend_from_slice(self.data);
+    }
+}
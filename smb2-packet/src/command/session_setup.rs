@@ -1,22 +1,30 @@
-use crate::Dialect;
+use crate::{Dialect, Serialize};
 use bitflags::bitflags;
 use nom::*;
+use std::convert::TryFrom;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 25;
+const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 9;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
+const SIGNING_ENABLED: u8 = 0x01;
+const SIGNING_REQUIRED: u8 = 0x02;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Request<'a> {
-    flags: Flags,
-    signing_required: bool,
-    capabilities: Capabilities,
-    previous_session_id: u64,
-    security_buffer: &'a [u8],
+    pub flags: Flags,
+    pub signing_required: bool,
+    pub capabilities: Capabilities,
+    /// Reserved, clients send 0 and servers ignore it.
+    pub channel: u32,
+    pub previous_session_id: u64,
+    pub security_buffer: &'a [u8],
 }
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Response<'a> {
-    session_flags: SessionFlags,
-    security_buffer: &'a [u8],
+    pub session_flags: SessionFlags,
+    pub security_buffer: &'a [u8],
 }
 
 bitflags! {
@@ -43,14 +51,15 @@ bitflags! {
 #[allow(clippy::cyclomatic_complexity, clippy::cast_possible_truncation)]
 pub fn parse_request(data: &[u8], dialect: Dialect) -> nom::IResult<&[u8], Request> {
     /* for whatever reason the structure size is off by one */
-    let constant_size = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+    let constant_size = REQUEST_CONSTANT_SIZE;
     do_parse!(data,
         verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
         flags: map_opt!(le_u8, Flags::from_bits) >>
-        cond!(dialect >= Dialect::Smb3_0_0, verify!(value!(flags.is_empty()), |x| x)) >>
+        /* binding a session to another channel only exists since 3.0 */
+        cond_with_error!(!dialect.is_smb3(), verify!(value!(flags.is_empty()), |x| x)) >>
         security_mode: le_u8 >>
         capabilities: map!(le_u32, |x| Capabilities::from_bits_truncate(x as u8)) >>
-        take!(4) >> /* ignore Channel */
+        channel: le_u32 >>
         security_buffer_offset: verify!(le_u16, |offset| offset >= constant_size) >>
         security_buffer_length: le_u16 >>
         previous_session_id: le_u64 >>
@@ -58,10 +67,69 @@ pub fn parse_request(data: &[u8], dialect: Dialect) -> nom::IResult<&[u8], Reque
         security_buffer: take!(security_buffer_length) >>
         (Request {
             flags,
-            signing_required: (security_mode & 0x02) != 0,
+            signing_required: (security_mode & SIGNING_REQUIRED) != 0,
             capabilities,
+            channel,
             previous_session_id,
             security_buffer,
         })
     )
 }
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> nom::IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        session_flags: map_opt!(le_u16, |x| u8::try_from(x).ok().and_then(SessionFlags::from_bits)) >>
+        security_buffer_offset: verify!(
+            le_u16,
+            |offset| offset == 0 || offset >= RESPONSE_CONSTANT_SIZE
+        ) >>
+        security_buffer_length: verify!(
+            le_u16,
+            |length| length == 0 || security_buffer_offset != 0
+        ) >>
+        security_buffer: cond_with_error!(
+            security_buffer_offset != 0,
+            preceded!(
+                take!(security_buffer_offset - RESPONSE_CONSTANT_SIZE),
+                take!(security_buffer_length)
+            )
+        ) >>
+        (Response {
+            session_flags,
+            security_buffer: security_buffer.unwrap_or_default(),
+        })
+    )
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut security_mode = SIGNING_ENABLED;
+        if self.signing_required {
+            security_mode |= SIGNING_REQUIRED;
+        }
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.push(self.flags.bits());
+        out.push(security_mode);
+        out.extend_from_slice(&u32::from(self.capabilities.bits()).to_le_bytes());
+        out.extend_from_slice(&self.channel.to_le_bytes());
+        out.extend_from_slice(&REQUEST_CONSTANT_SIZE.to_le_bytes());
+        out.extend_from_slice(&(self.security_buffer.len() as u16).to_le_bytes());
+        out.extend_from_slice(&self.previous_session_id.to_le_bytes());
+        out.extend_from_slice(self.security_buffer);
+    }
+}
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&u16::from(self.session_flags.bits()).to_le_bytes());
+        out.extend_from_slice(&RESPONSE_CONSTANT_SIZE.to_le_bytes());
+        out.extend_from_slice(&(self.security_buffer.len() as u16).to_le_bytes());
+        out.extend_from_slice(self.security_buffer);
+    }
+}
@@ -0,0 +1,70 @@
+use super::query_info::{parse_info, write_info, Info, InfoClass};
+use crate::{Dialect, FileId, Serialize};
+use nom::*;
+
+const REQUEST_STRUCTURE_SIZE: u16 = 33;
+const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 2;
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Request<'a> {
+    /// Security information flags when setting a security descriptor
+    pub additional_information: u32,
+    pub file_id: FileId,
+    pub info: Info<'a>,
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
+        info_type: le_u8 >>
+        info_class: map!(le_u8, |x| InfoClass::from_wire(info_type, x)) >>
+        buffer_length: le_u32 >>
+        buffer_offset: verify!(le_u16, |offset| offset >= REQUEST_CONSTANT_SIZE) >>
+        take!(2) >> /* reserved */
+        additional_information: le_u32 >>
+        file_id: map!(take!(16), FileId::from_slice) >>
+        info: preceded!(
+            take!(buffer_offset - REQUEST_CONSTANT_SIZE),
+            length_value!(value!(buffer_length), apply!(parse_info, info_class))
+        ) >>
+        (Request {
+            additional_information,
+            file_id,
+            info,
+        })
+    )
+}
+
+#[rustfmt::skip]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], ()> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        (())
+    )
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut buffer = Vec::new();
+        write_info(&mut buffer, &self.info);
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&self.info.info_class().to_wire());
+        out.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+        out.extend_from_slice(&REQUEST_CONSTANT_SIZE.to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&self.additional_information.to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(&buffer);
+        if buffer.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
+
+pub fn write_response(out: &mut Vec<u8>) {
+    out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+}
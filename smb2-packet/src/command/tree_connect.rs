@@ -1,14 +1,28 @@
 use crate::utf16le_to_string;
+use crate::{pad8, string_to_utf16le, AccessMask, Dialect, Serialize};
 use bitflags::bitflags;
 use nom::*;
 use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use std::convert::TryFrom;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 9;
+const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 16;
+const CACHING_SHIFT: u32 = 4;
+const CACHING_MASK: u32 = 0x03;
+const EXTENSION_HEADER_SIZE: u16 = 16;
+const CONTEXT_HEADER_SIZE: usize = 8;
+const REMOTED_IDENTITY: u16 = 0x01;
+const REMOTED_IDENTITY_TICKET_TYPE: u16 = 0x01;
+const REMOTED_IDENTITY_HEADER_SIZE: usize = 28;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-pub struct Request {
+pub struct Request<'a> {
     pub flags: Flags,
     pub path: String,
+    /// Only transmitted when `Flags::EXTENSION_PRESENT` is set.
+    pub contexts: Vec<Context<'a>>,
 }
 
 #[cfg_attr(debug_assertions, derive(Debug))]
@@ -16,20 +30,59 @@ pub struct Response {
     pub share_type: ShareType,
     pub caching: Caching,
     pub share_flags: ShareFlags,
-    pub maxmimal_access: u32, // TODO: replace by proper type
+    pub capabilities: Capabilities,
+    pub maxmimal_access: AccessMask,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Context<'a> {
+    RemotedIdentity(Box<RemotedIdentity<'a>>),
+    Unknown { context_type: u16, data: &'a [u8] },
+}
+
+/// The identity a trusted client connects on behalf of.
+///
+/// Security descriptors, access control lists and claims are kept in their
+/// self-relative wire encoding.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct RemotedIdentity<'a> {
+    pub user: SidAttr<'a>,
+    pub user_name: String,
+    pub domain: String,
+    pub groups: Vec<SidAttr<'a>>,
+    pub restricted_groups: Vec<SidAttr<'a>>,
+    pub privileges: Vec<Privilege>,
+    pub primary_group: Vec<SidAttr<'a>>,
+    pub owner: &'a [u8],
+    pub default_dacl: &'a [u8],
+    pub device_groups: Vec<SidAttr<'a>>,
+    pub user_claims: &'a [u8],
+    pub device_claims: &'a [u8],
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct SidAttr<'a> {
+    pub sid: &'a [u8],
+    pub attributes: u32,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Privilege {
+    pub luid: u64,
+    pub attributes: u32,
 }
 
 bitflags! {
     pub struct Flags: u8 {
         const CLUSTER_RECONNECT = 0x01;
         const REDIRECT_TO_OWNER = 0x02;
-        const EXTENSION_RESENT = 0x04;
+        const EXTENSION_PRESENT = 0x04;
     }
 }
 
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum Caching {
     Manual = 0x00,
     Auto = 0x01,
@@ -43,17 +96,20 @@ bitflags! {
         const DFS_ROOT = 0x02;
         const RESTRICT_EXCLUSIVE_OPENS = 0x0000_0100;
         const FORCE_SHARED_DELETE = 0x0000_0200;
+        const ALLOW_NAMESPACE_CACHING = 0x0000_0400;
         const ACCESS_BASED_DIRECTORY_ENUM = 0x0000_0800;
         const FORCE_LEVELII_OPLOCK = 0x0000_1000;
         const ENABLE_HASH_V1 = 0x0000_2000;
         const ENABLE_HASH_V2 = 0x0000_4000;
         const SMB2_SHAREFLAG_ENCRYPT_DATA = 0x0000_8000;
         const IDENTITY_REMOTING = 0x0004_0000;
+        const COMPRESS_DATA = 0x0010_0000;
+        const ISOLATED_TRANSPORT = 0x0020_0000;
     }
 }
 
 bitflags! {
-    pub struct Capabilites: u16 {
+    pub struct Capabilities: u32 {
         const DFS = 0x08;
         const CONTINUOUS_AVAILABILITY = 0x10;
         const SCALEOUT = 0x20;
@@ -65,28 +121,329 @@ bitflags! {
 
 #[repr(u8)]
 #[cfg_attr(debug_assertions, derive(Debug))]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 pub enum ShareType {
     Disk = 0x01,
     Pipe = 0x02,
     Print = 0x03,
 }
 
+impl<'a> Context<'a> {
+    #[rustfmt::skip]
+    fn new(data: &'a [u8], ctype: u16) -> IResult<&[u8], Context> {
+        if ctype == REMOTED_IDENTITY {
+            map!(data, parse_remoted_identity, |i| Context::RemotedIdentity(Box::new(i)))
+        } else {
+            map!(data, rest, |d| Context::Unknown { context_type: ctype, data: d })
+        }
+    }
+
+    fn context_type(&self) -> u16 {
+        match self {
+            Context::RemotedIdentity(_) => REMOTED_IDENTITY,
+            Context::Unknown { context_type, .. } => *context_type,
+        }
+    }
+
+    fn write_data(&self, out: &mut Vec<u8>) {
+        match self {
+            Context::RemotedIdentity(identity) => write_remoted_identity(out, identity),
+            Context::Unknown { data, .. } => out.extend_from_slice(data),
+        }
+    }
+}
+
+#[rustfmt::skip]
+fn parse_blob(data: &[u8]) -> IResult<&[u8], &[u8]> {
+    length_data!(data, le_u16)
+}
+
+#[rustfmt::skip]
+fn parse_sid_attr(data: &[u8]) -> IResult<&[u8], SidAttr> {
+    do_parse!(data,
+        sid: parse_blob >>
+        attributes: le_u32 >>
+        (SidAttr {
+            sid,
+            attributes,
+        })
+    )
+}
+
+#[rustfmt::skip]
+fn parse_sid_array(data: &[u8]) -> IResult<&[u8], Vec<SidAttr>> {
+    length_count!(data, le_u16, parse_sid_attr)
+}
+
+#[rustfmt::skip]
+fn parse_privileges(data: &[u8]) -> IResult<&[u8], Vec<Privilege>> {
+    length_count!(data, le_u16, do_parse!(
+        verify!(le_u16, |x| x == 12) >>
+        luid: le_u64 >>
+        attributes: le_u32 >>
+        (Privilege {
+            luid,
+            attributes,
+        })
+    ))
+}
+
+/// Parses a null terminated UTF-16 string.
+fn parse_string(data: &[u8]) -> IResult<&[u8], String> {
+    let length = match data.chunks_exact(2).position(|c| c == [0, 0]) {
+        Some(chars) => chars * 2,
+        None => return Err(Err::Error(error_position!(data, ErrorKind::Eof))),
+    };
+    let (remaining, string) = map_res!(data, take!(length), utf16le_to_string)?;
+    Ok((&remaining[2..], string))
+}
+
+/// Runs `parser` on the part of `data` that starts at `offset`.
+fn parse_at<'a, O>(
+    data: &'a [u8],
+    offset: u16,
+    parser: fn(&'a [u8]) -> IResult<&'a [u8], O>,
+) -> Result<O, Err<&'a [u8]>> {
+    preceded!(data, take!(offset), call!(parser)).map(|(_, o)| o)
+}
+
+/// All members are located by offsets relative to the start of the structure.
+#[rustfmt::skip]
+fn parse_remoted_identity(data: &[u8]) -> IResult<&[u8], RemotedIdentity> {
+    let (_, (ticket_size, offsets)) = do_parse!(data,
+        verify!(le_u16, |x| x == REMOTED_IDENTITY_TICKET_TYPE) >>
+        ticket_size: verify!(le_u16, |x| usize::from(x) <= data.len()) >>
+        offsets: count!(le_u16, 12) >>
+        ((ticket_size, offsets))
+    )?;
+    let identity = RemotedIdentity {
+        user: parse_at(data, offsets[0], parse_sid_attr)?,
+        user_name: parse_at(data, offsets[1], parse_string)?,
+        domain: parse_at(data, offsets[2], parse_string)?,
+        groups: parse_at(data, offsets[3], parse_sid_array)?,
+        restricted_groups: parse_at(data, offsets[4], parse_sid_array)?,
+        privileges: parse_at(data, offsets[5], parse_privileges)?,
+        primary_group: parse_at(data, offsets[6], parse_sid_array)?,
+        owner: parse_at(data, offsets[7], parse_blob)?,
+        default_dacl: parse_at(data, offsets[8], parse_blob)?,
+        device_groups: parse_at(data, offsets[9], parse_sid_array)?,
+        user_claims: parse_at(data, offsets[10], parse_blob)?,
+        device_claims: parse_at(data, offsets[11], parse_blob)?,
+    };
+    Ok((&data[usize::from(ticket_size)..], identity))
+}
+
+#[rustfmt::skip]
+fn parse_context(input: &[u8], extension_len: usize) -> IResult<&[u8], Context> {
+    // pad to the next 8 byte aligned extension offset
+    let padding = crate::padding8(extension_len - input.len());
+    do_parse!(input,
+        take!(padding) >>
+        context_type: le_u16 >>
+        data_length: le_u16 >>
+        take!(4) >> /* reserved */
+        context: length_value!(value!(data_length), apply!(Context::new, context_type)) >>
+        (context)
+    )
+}
+
+/// Offsets inside the extension are relative to the start of the extension.
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_extension(data: &[u8], path_offset: u16, path_length: u16) -> IResult<&[u8], (String, Vec<Context>)> {
+    let extension_len = data.len();
+    do_parse!(data,
+        context_offset: le_u32 >>
+        context_count: le_u16 >>
+        take!(10) >> /* reserved */
+        verify!(value!(path_offset), |offset| offset >= EXTENSION_HEADER_SIZE) >>
+        take!(path_offset - EXTENSION_HEADER_SIZE) >> // padding
+        path: map_res!(take!(path_length), utf16le_to_string) >>
+        contexts: cond_with_error!(context_count > 0, do_parse!(
+            current_pos: value!(u32::from(path_offset) + u32::from(path_length)) >>
+            verify!(value!(context_offset), |offset| offset >= current_pos) >>
+            take!(context_offset - current_pos) >> /* optional padding */
+            contexts: count!(apply!(parse_context, extension_len), usize::from(context_count)) >>
+            (contexts)
+        )) >>
+        ((path, contexts.unwrap_or_default()))
+    )
+}
+
+/// When `Flags::EXTENSION_PRESENT` is set the path and the tree connect contexts are
+/// located inside the SMB2 `TREE_CONNECT` Request Extension.
 #[rustfmt::skip]
-#[allow(clippy::cyclomatic_complexity, clippy::cast_possible_truncation)]
+#[allow(clippy::cyclomatic_complexity)]
 pub fn parse_request(data: &[u8]) -> IResult<&[u8], Request> {
     /* is off by one */
-    let const_size = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+    let const_size = REQUEST_CONSTANT_SIZE;
     do_parse!(data,
         verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
-        flags: map_opt!(le_u16, |x| Flags::from_bits(x as u8)) >>
-        path_offset: verify!(le_u16, |offset| offset >= const_size) >>
+        flags: map_opt!(le_u16, |x| u8::try_from(x).ok().and_then(Flags::from_bits)) >>
+        path_offset: le_u16 >>
         path_length: le_u16 >>
-        take!(path_offset - const_size) >> // padding
-        path: map_res!(take!(path_length), utf16le_to_string) >>
+        body: switch!(value!(flags.contains(Flags::EXTENSION_PRESENT)),
+            true => apply!(parse_extension, path_offset, path_length) |
+            false => do_parse!(
+                verify!(value!(path_offset), |offset| offset >= const_size) >>
+                take!(path_offset - const_size) >> // padding
+                path: map_res!(take!(path_length), utf16le_to_string) >>
+                ((path, Vec::new()))
+            )
+        ) >>
         (Request {
             flags,
-            path,
+            path: body.0,
+            contexts: body.1,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        share_type: map_opt!(le_u8, FromPrimitive::from_u8) >>
+        take!(1) >> /* reserved */
+        share_flags: le_u32 >>
+        caching: map_opt!(value!((share_flags >> CACHING_SHIFT) & CACHING_MASK), FromPrimitive::from_u32) >>
+        share_flags: map!(value!(share_flags & !(CACHING_MASK << CACHING_SHIFT)), ShareFlags::from_bits_truncate) >>
+        capabilities: map!(le_u32, Capabilities::from_bits_truncate) >>
+        maxmimal_access: map!(le_u32, AccessMask::from_bits_truncate) >>
+        (Response {
+            share_type,
+            caching,
+            share_flags,
+            capabilities,
+            maxmimal_access,
         })
     )
 }
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_blob(out: &mut Vec<u8>, blob: &[u8]) {
+    out.extend_from_slice(&(blob.len() as u16).to_le_bytes());
+    out.extend_from_slice(blob);
+}
+
+fn write_sid_attr(out: &mut Vec<u8>, sid_attr: &SidAttr) {
+    write_blob(out, sid_attr.sid);
+    out.extend_from_slice(&sid_attr.attributes.to_le_bytes());
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_sid_array(out: &mut Vec<u8>, sid_attrs: &[SidAttr]) {
+    out.extend_from_slice(&(sid_attrs.len() as u16).to_le_bytes());
+    for sid_attr in sid_attrs {
+        write_sid_attr(out, sid_attr);
+    }
+}
+
+/// The members are written in the order of their offset fields.
+#[allow(clippy::cast_possible_truncation)]
+fn write_remoted_identity(out: &mut Vec<u8>, identity: &RemotedIdentity) {
+    let start = out.len();
+    out.extend_from_slice(&REMOTED_IDENTITY_TICKET_TYPE.to_le_bytes());
+    out.resize(start + REMOTED_IDENTITY_HEADER_SIZE, 0); /* ticket size + offsets */
+    let mut member = 0;
+    let mut set_offset = |out: &mut Vec<u8>| {
+        let offset = (out.len() - start) as u16;
+        let pos = start + 4 + member * 2;
+        out[pos..pos + 2].copy_from_slice(&offset.to_le_bytes());
+        member += 1;
+    };
+    set_offset(out);
+    write_sid_attr(out, &identity.user);
+    for string in &[&identity.user_name, &identity.domain] {
+        set_offset(out);
+        out.extend_from_slice(&string_to_utf16le(string));
+        out.extend_from_slice(&[0; 2]); /* null terminator */
+    }
+    for groups in &[&identity.groups, &identity.restricted_groups] {
+        set_offset(out);
+        write_sid_array(out, groups);
+    }
+    set_offset(out);
+    out.extend_from_slice(&(identity.privileges.len() as u16).to_le_bytes());
+    for privilege in &identity.privileges {
+        out.extend_from_slice(&12u16.to_le_bytes()); /* LUID_ATTR_DATA size */
+        out.extend_from_slice(&privilege.luid.to_le_bytes());
+        out.extend_from_slice(&privilege.attributes.to_le_bytes());
+    }
+    set_offset(out);
+    write_sid_array(out, &identity.primary_group);
+    for blob in &[identity.owner, identity.default_dacl] {
+        set_offset(out);
+        write_blob(out, blob);
+    }
+    set_offset(out);
+    write_sid_array(out, &identity.device_groups);
+    for blob in &[identity.user_claims, identity.device_claims] {
+        set_offset(out);
+        write_blob(out, blob);
+    }
+    let ticket_size = (out.len() - start) as u16;
+    out[start + 2..start + 4].copy_from_slice(&ticket_size.to_le_bytes());
+}
+
+/// Writes the contexts each aligned to 8 bytes relative to `start` and returns the
+/// offset of the first one relative to `start`.
+#[allow(clippy::cast_possible_truncation)]
+fn write_contexts(out: &mut Vec<u8>, start: usize, contexts: &[Context]) -> u32 {
+    pad8(out, start);
+    let offset = (out.len() - start) as u32;
+    for (i, context) in contexts.iter().enumerate() {
+        if i > 0 {
+            pad8(out, start);
+        }
+        let header_pos = out.len();
+        out.extend_from_slice(&context.context_type().to_le_bytes());
+        out.extend_from_slice(&[0; 6]); /* data length + reserved */
+        context.write_data(out);
+        let data_length = (out.len() - header_pos - CONTEXT_HEADER_SIZE) as u16;
+        out[header_pos + 2..header_pos + 4].copy_from_slice(&data_length.to_le_bytes());
+    }
+    offset
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let path = string_to_utf16le(&self.path);
+        let extension = self.flags.contains(Flags::EXTENSION_PRESENT);
+        let path_offset = if extension {
+            EXTENSION_HEADER_SIZE
+        } else {
+            REQUEST_CONSTANT_SIZE
+        };
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&u16::from(self.flags.bits()).to_le_bytes());
+        out.extend_from_slice(&path_offset.to_le_bytes());
+        out.extend_from_slice(&(path.len() as u16).to_le_bytes());
+        if !extension {
+            out.extend_from_slice(&path);
+            return;
+        }
+        let start = out.len();
+        out.extend_from_slice(&[0; EXTENSION_HEADER_SIZE as usize]); /* context offset + count + reserved */
+        out.extend_from_slice(&path);
+        if !self.contexts.is_empty() {
+            let offset = write_contexts(out, start, &self.contexts);
+            out[start..start + 4].copy_from_slice(&offset.to_le_bytes());
+            out[start + 4..start + 6].copy_from_slice(&(self.contexts.len() as u16).to_le_bytes());
+        }
+    }
+}
+
+impl Serialize for Response {
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let share_flags = self.share_flags.bits() | ((self.caching as u32) << CACHING_SHIFT);
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.push(self.share_type as u8);
+        out.push(0); /* reserved */
+        out.extend_from_slice(&share_flags.to_le_bytes());
+        out.extend_from_slice(&self.capabilities.bits().to_le_bytes());
+        out.extend_from_slice(&self.maxmimal_access.bits().to_le_bytes());
+    }
+}
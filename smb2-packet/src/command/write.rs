@@ -1,11 +1,14 @@
 use super::{Channel, ChannelType};
-use crate::{Dialect, FileId};
+use crate::{Dialect, FileId, Serialize};
 use bitflags::bitflags;
 use nom::*;
 use num_traits::FromPrimitive;
+use std::convert::TryFrom;
 
 const REQUEST_STRUCTURE_SIZE: u16 = 49;
 const REQUEST_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + REQUEST_STRUCTURE_SIZE - 1;
+const RESPONSE_STRUCTURE_SIZE: u16 = 17;
+const RESPONSE_CONSTANT_SIZE: u16 = crate::header::STRUCTURE_SIZE + RESPONSE_STRUCTURE_SIZE - 1;
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Request<'a> {
@@ -20,8 +23,9 @@ pub struct Request<'a> {
 
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Response<'a> {
-    pub data_remaining: u32,
-    pub data: &'a [u8],
+    pub count: u32,
+    pub remaining: u32,
+    pub channel_info: &'a [u8],
 }
 
 bitflags! {
@@ -36,34 +40,30 @@ struct Buffer {
     length: u32,
 }
 
-fn sort_buffers(
-    channel_offset: u16,
-    channel_length: u16,
-    data_offset: u16,
-    data_length: u32,
-) -> (Buffer, Buffer) {
-    if channel_offset > data_offset {
-        (
-            Buffer {
-                offset: channel_offset,
-                length: u32::from(channel_length),
-            },
+impl Buffer {
+    fn new(offset: u16, length: u32) -> Self {
+        // the offset of an empty buffer is meaningless and must not be checked
+        if length == 0 {
             Buffer {
-                offset: data_offset,
-                length: data_length,
-            },
-        )
+                offset: REQUEST_CONSTANT_SIZE,
+                length,
+            }
+        } else {
+            Buffer { offset, length }
+        }
+    }
+
+    fn end(&self) -> u32 {
+        u32::from(self.offset) + self.length
+    }
+}
+
+/// Orders the buffers by offset. The returned bool is true if data comes first.
+fn sort_buffers(channel: Buffer, data: Buffer) -> (Buffer, Buffer, bool) {
+    if channel.offset < data.offset || (channel.offset == data.offset && channel.length == 0) {
+        (channel, data, false)
     } else {
-        (
-            Buffer {
-                offset: data_offset,
-                length: data_length,
-            },
-            Buffer {
-                offset: channel_offset,
-                length: u32::from(channel_length),
-            },
-        )
+        (data, channel, true)
     }
 }
 
@@ -73,40 +73,31 @@ pub fn parse_request(data: &[u8], dialect: Dialect) -> IResult<&[u8], Request> {
     do_parse!(data,
         verify!(le_u16, |x| x == REQUEST_STRUCTURE_SIZE) >>
         data_offset: le_u16 >>
-        data_length: verify!(le_u32, |l| l > 0) >>
+        data_length: le_u32 >>
         offset: le_u64 >>
         file_id: map!(take!(16), FileId::from_slice) >>
-        channel_type: switch!(value!(dialect >= Dialect::Smb3_1_1),
+        channel_type: switch!(value!(dialect.has_read_write_channel()),
             true => map_opt!(le_u32, ChannelType::from_u32) |
             false => map!(take!(4), |_| ChannelType::None)
         ) >>
         remaining_bytes: le_u32 >>
-        channel_offset: map!(le_u16, |x| if channel_type == ChannelType::None { u16::max_value() } else { x }) >>
-        channel_length: verify!(le_u16, |x| channel_type == ChannelType::None || x > 0) >>
-        flags: map_opt!(le_u8, Flags::from_bits) >>
-        buffers: value!(sort_buffers(channel_offset, channel_length, data_offset, data_length)) >>
+        channel_offset: le_u16 >>
+        channel_length: map!(le_u16, |x| if channel_type == ChannelType::None { 0 } else { x }) >>
+        verify!(value!(channel_length), |x| channel_type == ChannelType::None || x > 0) >>
+        flags: map_opt!(le_u32, |x| u8::try_from(x).ok().and_then(Flags::from_bits)) >>
+        buffers: value!(sort_buffers(
+            Buffer::new(channel_offset, u32::from(channel_length)),
+            Buffer::new(data_offset, data_length)
+        )) >>
         verify!(value!(buffers.0.offset), |offset| offset >= REQUEST_CONSTANT_SIZE) >>
-        verify!(
-            value!(buffers.1.offset),
-            |offset|
-                channel_type == ChannelType::None ||
-                u32::from(offset) > (u32::from(REQUEST_CONSTANT_SIZE) + buffers.0.length)
-        ) >>
-        first_buffer: preceded!(take!(REQUEST_CONSTANT_SIZE - buffers.0.offset), take!(buffers.0.length)) >>
-        second_buffer: cond_with_error!(
-            channel_type != ChannelType::None,
-            preceded!(
-                take!(u32::from(REQUEST_CONSTANT_SIZE - buffers.1.offset) - buffers.0.length),
-                take!(buffers.1.length)
-            )
-        ) >>
-        data: switch!(value!(second_buffer.is_some() && data_offset > channel_offset),
-            true => value!(second_buffer.unwrap()) |
-            false => value!(first_buffer)
+        verify!(value!(buffers.1.offset), |offset| u32::from(offset) >= buffers.0.end()) >>
+        first_buffer: preceded!(
+            take!(buffers.0.offset - REQUEST_CONSTANT_SIZE),
+            take!(buffers.0.length)
         ) >>
-        channel: switch!(value!(second_buffer.is_some() && channel_offset > data_offset),
-            true => value!(super::create_channel(second_buffer.unwrap(), channel_type)) |
-            false => value!(super::create_channel(first_buffer, channel_type))
+        second_buffer: preceded!(
+            take!(u32::from(buffers.1.offset) - buffers.0.end()),
+            take!(buffers.1.length)
         ) >>
         (Request {
             write_through: flags.contains(Flags::WRITE_THROUGH),
@@ -114,8 +105,90 @@ pub fn parse_request(data: &[u8], dialect: Dialect) -> IResult<&[u8], Request> {
             remaining_bytes,
             offset,
             file_id,
-            data,
-            channel,
+            data: if buffers.2 { first_buffer } else { second_buffer },
+            channel: super::create_channel(
+                if buffers.2 { second_buffer } else { first_buffer },
+                channel_type
+            ),
+        })
+    )
+}
+
+impl Serialize for Request<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let mut flags = Flags::empty();
+        flags.set(Flags::WRITE_THROUGH, self.write_through);
+        flags.set(Flags::WRITE_UNBUFFERED, self.write_unbuffered);
+        let channel = self.channel.buffer();
+        // the channel goes first as the data may be larger than a 16 bit offset can skip
+        let channel_offset = if channel.is_empty() {
+            0
+        } else {
+            REQUEST_CONSTANT_SIZE
+        };
+        let data_offset = (usize::from(REQUEST_CONSTANT_SIZE) + channel.len()) as u16;
+        out.extend_from_slice(&REQUEST_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&data_offset.to_le_bytes());
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&*self.file_id);
+        out.extend_from_slice(&(self.channel.channel_type() as u32).to_le_bytes());
+        out.extend_from_slice(&self.remaining_bytes.to_le_bytes());
+        out.extend_from_slice(&channel_offset.to_le_bytes());
+        out.extend_from_slice(&(channel.len() as u16).to_le_bytes());
+        out.extend_from_slice(&u32::from(flags.bits()).to_le_bytes());
+        out.extend_from_slice(channel);
+        out.extend_from_slice(self.data);
+        if self.data.is_empty() && channel.is_empty() {
+            out.push(0); /* buffer must be at least one byte */
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_response(data: &[u8]) -> IResult<&[u8], Response> {
+    do_parse!(data,
+        verify!(le_u16, |x| x == RESPONSE_STRUCTURE_SIZE) >>
+        take!(2) >> /* reserved */
+        count: le_u32 >>
+        remaining: le_u32 >>
+        channel_info_offset: le_u16 >>
+        channel_info_length: le_u16 >>
+        verify!(
+            value!(channel_info_offset),
+            |offset| channel_info_length == 0 || offset >= RESPONSE_CONSTANT_SIZE
+        ) >>
+        channel_info: cond_with_error!(
+            channel_info_length > 0,
+            preceded!(
+                take!(channel_info_offset - RESPONSE_CONSTANT_SIZE),
+                take!(channel_info_length)
+            )
+        ) >>
+        (Response {
+            count,
+            remaining,
+            channel_info: channel_info.unwrap_or_default(),
         })
     )
 }
+
+impl Serialize for Response<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect) {
+        let channel_info_offset = if self.channel_info.is_empty() {
+            0
+        } else {
+            RESPONSE_CONSTANT_SIZE
+        };
+        out.extend_from_slice(&RESPONSE_STRUCTURE_SIZE.to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.extend_from_slice(&self.remaining.to_le_bytes());
+        out.extend_from_slice(&channel_info_offset.to_le_bytes());
+        out.extend_from_slice(&(self.channel_info.len() as u16).to_le_bytes());
+        out.extend_from_slice(self.channel_info);
+    }
+}
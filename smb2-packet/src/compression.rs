@@ -0,0 +1,332 @@
+//! SMB 3.1.1 compressed messages wrapped in an `SMB2_COMPRESSION_TRANSFORM_HEADER`.
+//!
+//! Every algorithm is behind the cargo feature of the same name. The algorithm modules
+//! only depend on `core` and `alloc`.
+
+use bitflags::bitflags;
+use nom::*;
+use num_traits::FromPrimitive;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::command::negotiate::CompressionAlgorithm;
+use crate::transport;
+
+#[cfg(feature = "lz77")]
+pub mod lz77;
+#[cfg(feature = "lz77-huffman")]
+pub mod lz77_huffman;
+#[cfg(feature = "lznt1")]
+pub mod lznt1;
+#[cfg(any(feature = "lz77", feature = "lz77-huffman", feature = "lznt1"))]
+mod matcher;
+#[cfg(feature = "pattern-v1")]
+pub mod pattern_v1;
+
+pub const PROTOCOL_ID: &[u8] = b"\xfcSMB";
+
+bitflags! {
+    pub struct Flags: u16 {
+        const CHAINED = 0x0001;
+    }
+}
+
+/// A message of which everything after `uncompressed` is compressed with a single algorithm.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Unchained<'a> {
+    /// Size of `compressed` once decompressed, `uncompressed` is not included.
+    pub original_compressed_segment_size: u32,
+    pub compression_algorithm: CompressionAlgorithm,
+    pub uncompressed: &'a [u8],
+    pub compressed: &'a [u8],
+}
+
+/// A message made of consecutive payloads, each with its own algorithm.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Chained<'a> {
+    pub original_compressed_segment_size: u32,
+    pub payloads: Vec<Payload<'a>>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Payload<'a> {
+    pub compression_algorithm: CompressionAlgorithm,
+    pub flags: Flags,
+    /// Only present for the LZ based algorithms.
+    pub original_payload_size: Option<u32>,
+    pub data: &'a [u8],
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Message<'a> {
+    Unchained(Unchained<'a>),
+    Chained(Chained<'a>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    /// The compressed data is corrupt or does not match the announced size.
+    InvalidData,
+    /// The uncompressed message exceeds the allowed size.
+    TooLarge,
+    /// The algorithm is unknown or its cargo feature is disabled.
+    UnsupportedAlgorithm(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidData => write!(f, "Invalid compressed data"),
+            Error::TooLarge => write!(f, "Uncompressed message exceeds the allowed size"),
+            Error::UnsupportedAlgorithm(a) => write!(f, "Unsupported compression algorithm {a}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl<'a> Message<'a> {
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(PROTOCOL_ID);
+        match self {
+            Message::Unchained(message) => {
+                out.extend_from_slice(&message.original_compressed_segment_size.to_le_bytes());
+                write_algorithm(out, message.compression_algorithm);
+                out.extend_from_slice(&Flags::empty().bits().to_le_bytes());
+                out.extend_from_slice(&(message.uncompressed.len() as u32).to_le_bytes());
+                out.extend_from_slice(message.uncompressed);
+                out.extend_from_slice(message.compressed);
+            }
+            Message::Chained(message) => {
+                out.extend_from_slice(&message.original_compressed_segment_size.to_le_bytes());
+                // the flags of the first payload tell the chained form apart
+                for (i, payload) in message.payloads.iter().enumerate() {
+                    let flags = if i == 0 {
+                        payload.flags | Flags::CHAINED
+                    } else {
+                        payload.flags
+                    };
+                    let size_length = if payload.original_payload_size.is_some() {
+                        4
+                    } else {
+                        0
+                    };
+                    write_algorithm(out, payload.compression_algorithm);
+                    out.extend_from_slice(&flags.bits().to_le_bytes());
+                    out.extend_from_slice(
+                        &((payload.data.len() + size_length) as u32).to_le_bytes(),
+                    );
+                    if let Some(size) = payload.original_payload_size {
+                        out.extend_from_slice(&size.to_le_bytes());
+                    }
+                    out.extend_from_slice(payload.data);
+                }
+            }
+        }
+    }
+
+    /// Returns the original message which can be passed to `Packet::parse`.
+    ///
+    /// Messages whose original size exceeds `max_size` are rejected before decompressing them.
+    pub fn decompress(&self, max_size: usize) -> Result<Vec<u8>, Error> {
+        let (original_size, mut out) = match self {
+            Message::Unchained(message) => {
+                let compressed_size = to_size(message.original_compressed_segment_size, max_size)?;
+                let original_size = compressed_size
+                    .checked_add(message.uncompressed.len())
+                    .filter(|&size| size <= max_size)
+                    .ok_or(Error::TooLarge)?;
+                let mut out = Vec::with_capacity(original_size);
+                out.extend_from_slice(message.uncompressed);
+                out.extend_from_slice(&decompress_payload(
+                    message.compression_algorithm,
+                    message.compressed,
+                    compressed_size,
+                )?);
+                (original_size, out)
+            }
+            Message::Chained(message) => {
+                let original_size = to_size(message.original_compressed_segment_size, max_size)?;
+                let mut out = Vec::with_capacity(original_size);
+                for payload in &message.payloads {
+                    let remaining = original_size - out.len();
+                    let data = match (payload.compression_algorithm, payload.original_payload_size)
+                    {
+                        (CompressionAlgorithm::None, None) => payload.data.to_vec(),
+                        #[cfg(feature = "pattern-v1")]
+                        (CompressionAlgorithm::PatternV1, None) => {
+                            pattern_v1::decompress(payload.data, remaining)?
+                        }
+                        (algorithm, Some(size)) => decompress_payload(
+                            algorithm,
+                            payload.data,
+                            to_size(size, remaining).map_err(|_| Error::InvalidData)?,
+                        )?,
+                        (algorithm, None) => {
+                            return Err(Error::UnsupportedAlgorithm(u16::from(algorithm as u8)))
+                        }
+                    };
+                    if data.len() > remaining {
+                        return Err(Error::InvalidData);
+                    }
+                    out.extend_from_slice(&data);
+                }
+                (original_size, out)
+            }
+        };
+        if out.len() == original_size {
+            out.shrink_to_fit();
+            Ok(out)
+        } else {
+            Err(Error::InvalidData)
+        }
+    }
+}
+
+fn to_size(size: u32, max_size: usize) -> Result<usize, Error> {
+    match usize::try_from(size) {
+        Ok(size) if size <= max_size => Ok(size),
+        _ => Err(Error::TooLarge),
+    }
+}
+
+fn decompress_payload(
+    algorithm: CompressionAlgorithm,
+    data: &[u8],
+    original_size: usize,
+) -> Result<Vec<u8>, Error> {
+    match algorithm {
+        CompressionAlgorithm::None if data.len() == original_size => Ok(data.to_vec()),
+        CompressionAlgorithm::None => Err(Error::InvalidData),
+        #[cfg(feature = "lznt1")]
+        CompressionAlgorithm::Lznt1 => lznt1::decompress(data, original_size),
+        #[cfg(feature = "lz77")]
+        CompressionAlgorithm::Lz77 => lz77::decompress(data, original_size),
+        #[cfg(feature = "lz77-huffman")]
+        CompressionAlgorithm::Lz77Huffman => lz77_huffman::decompress(data, original_size),
+        #[cfg(feature = "pattern-v1")]
+        CompressionAlgorithm::PatternV1 => pattern_v1::decompress(data, original_size),
+        other => Err(Error::UnsupportedAlgorithm(u16::from(other as u8))),
+    }
+}
+
+fn has_original_payload_size(algorithm: CompressionAlgorithm) -> bool {
+    match algorithm {
+        CompressionAlgorithm::Lznt1
+        | CompressionAlgorithm::Lz77
+        | CompressionAlgorithm::Lz77Huffman
+        | CompressionAlgorithm::Lz4 => true,
+        CompressionAlgorithm::None | CompressionAlgorithm::PatternV1 => false,
+    }
+}
+
+fn write_algorithm(out: &mut Vec<u8>, algorithm: CompressionAlgorithm) {
+    out.extend_from_slice(&u16::from(algorithm as u8).to_le_bytes());
+}
+
+named!(
+    parse_algorithm<CompressionAlgorithm>,
+    map_opt!(le_u16, FromPrimitive::from_u16)
+);
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_payload(input: &[u8]) -> IResult<&[u8], Payload> {
+    do_parse!(input,
+        compression_algorithm: parse_algorithm >>
+        flags: map_opt!(le_u16, Flags::from_bits) >>
+        has_size: value!(has_original_payload_size(compression_algorithm)) >>
+        length: verify!(le_u32, |length: u32| !has_size || length >= 4) >>
+        original_payload_size: cond!(has_size, le_u32) >>
+        data: take!(if has_size { length - 4 } else { length }) >>
+        (Payload {
+            compression_algorithm,
+            flags,
+            original_payload_size,
+            data,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_unchained(input: &[u8]) -> IResult<&[u8], Message> {
+    do_parse!(input,
+        original_compressed_segment_size: le_u32 >>
+        compression_algorithm: parse_algorithm >>
+        verify!(le_u16, |flags: u16| flags == 0) >>
+        offset: le_u32 >>
+        uncompressed: take!(offset) >>
+        compressed: rest >>
+        (Message::Unchained(Unchained {
+            original_compressed_segment_size,
+            compression_algorithm,
+            uncompressed,
+            compressed,
+        }))
+    )
+}
+
+#[rustfmt::skip]
+fn parse_chained(input: &[u8]) -> IResult<&[u8], Message> {
+    do_parse!(input,
+        original_compressed_segment_size: le_u32 >>
+        payloads: many1!(complete!(parse_payload)) >>
+        verify!(rest_len, |len: usize| len == 0) >>
+        (Message::Chained(Chained {
+            original_compressed_segment_size,
+            payloads,
+        }))
+    )
+}
+
+#[rustfmt::skip]
+fn parse_message(input: &[u8]) -> IResult<&[u8], Message> {
+    do_parse!(input,
+        tag!(PROTOCOL_ID) >>
+        /* the flags follow the size and the algorithm */
+        chained: peek!(preceded!(take!(6), map!(le_u16, |flags| flags & Flags::CHAINED.bits() != 0))) >>
+        message: switch!(value!(chained),
+            true => call!(parse_chained) |
+            false => call!(parse_unchained)
+        ) >>
+        (message)
+    )
+}
+
+/// Parses a session message that carries a compressed message.
+pub fn parse(input: &[u8]) -> IResult<&[u8], Message> {
+    match transport::get_payload(input) {
+        Ok((rem, out)) => complete!(out, parse_message).map(|(_, message)| (rem, message)),
+        Err(x) => Err(x),
+    }
+}
+
+#[cfg(any(feature = "lz77", feature = "lz77-huffman", feature = "lznt1"))]
+fn read_u16(input: &[u8], pos: usize) -> Result<u16, Error> {
+    match input.get(pos..pos + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(Error::InvalidData),
+    }
+}
+
+#[cfg(any(feature = "lz77", feature = "lz77-huffman", feature = "pattern-v1"))]
+fn read_u32(input: &[u8], pos: usize) -> Result<u32, Error> {
+    match input.get(pos..pos + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(Error::InvalidData),
+    }
+}
+
+#[cfg(any(feature = "lz77", feature = "lz77-huffman", feature = "lznt1"))]
+/// Appends `length` bytes starting `offset` bytes back, which may overlap the appended bytes.
+fn copy_match(out: &mut Vec<u8>, offset: usize, length: usize, limit: usize) -> Result<(), Error> {
+    if offset == 0 || offset > out.len() || out.len() + length > limit {
+        return Err(Error::InvalidData);
+    }
+    for _ in 0..length {
+        out.push(out[out.len() - offset]);
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Plain LZ77 as described in MS-XCA 2.3 and 2.4.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use super::matcher::Matcher;
+use super::{copy_match, read_u16, read_u32, Error};
+
+const MAX_OFFSET: usize = 1 << 13;
+/// Longer matches are split so that the length always fits the 16 bit encoding.
+const MAX_LENGTH: usize = 0xFFFF + 3;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() + input.len() / 8 + 8);
+    let mut matcher = Matcher::new(input.len());
+    let mut flags = FlagWriter::new(&mut out);
+    let mut nibble_pos = None;
+    let mut pos = 0;
+    while pos < input.len() {
+        let max_length = MAX_LENGTH.min(input.len() - pos);
+        if let Some((offset, length)) = matcher.find(input, pos, 0, MAX_OFFSET, max_length) {
+            write_match(&mut out, &mut nibble_pos, offset, length);
+            for i in pos..pos + length {
+                matcher.insert(input, i);
+            }
+            pos += length;
+            flags.push(&mut out, true);
+        } else {
+            out.push(input[pos]);
+            matcher.insert(input, pos);
+            pos += 1;
+            flags.push(&mut out, false);
+        }
+    }
+    flags.finish(&mut out);
+    out
+}
+
+/// Decompresses `input` which must yield exactly `original_size` bytes.
+///
+/// `original_size` is used to allocate the output and must be checked against a limit
+/// by the caller.
+pub fn decompress(input: &[u8], original_size: usize) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(original_size);
+    let mut pos = 0;
+    let mut flags = 0;
+    let mut flag_count = 0;
+    let mut nibble_pos = None;
+    loop {
+        if flag_count == 0 {
+            if pos == input.len() && out.len() == original_size {
+                break;
+            }
+            flags = read_u32(input, pos)?;
+            pos += 4;
+            flag_count = 32;
+        }
+        flag_count -= 1;
+        if flags & (1 << flag_count) == 0 {
+            let literal = *input.get(pos).ok_or(Error::InvalidData)?;
+            if out.len() == original_size {
+                return Err(Error::InvalidData);
+            }
+            out.push(literal);
+            pos += 1;
+            continue;
+        }
+        if pos == input.len() {
+            break;
+        }
+        let token = read_u16(input, pos)?;
+        pos += 2;
+        let offset = usize::from(token >> 3) + 1;
+        let mut length = usize::from(token & 7);
+        if length == 7 {
+            length = if let Some(nibble) = nibble_pos.take() {
+                usize::from(input[nibble] >> 4)
+            } else {
+                let nibble = *input.get(pos).ok_or(Error::InvalidData)?;
+                nibble_pos = Some(pos);
+                pos += 1;
+                usize::from(nibble & 0x0F)
+            };
+            if length == 15 {
+                length = usize::from(*input.get(pos).ok_or(Error::InvalidData)?);
+                pos += 1;
+                if length == 255 {
+                    length = usize::from(read_u16(input, pos)?);
+                    pos += 2;
+                    if length == 0 {
+                        length = usize::try_from(read_u32(input, pos)?)
+                            .map_err(|_| Error::InvalidData)?;
+                        pos += 4;
+                    }
+                    length = length.checked_sub(15 + 7).ok_or(Error::InvalidData)?;
+                }
+                length += 15;
+            }
+            length += 7;
+        }
+        copy_match(&mut out, offset, length + 3, original_size)?;
+    }
+    if out.len() == original_size {
+        Ok(out)
+    } else {
+        Err(Error::InvalidData)
+    }
+}
+
+/// Collects one flag bit per literal or match and writes them as 32 bit words in
+/// front of the data they describe.
+struct FlagWriter {
+    pos: usize,
+    flags: u32,
+    count: u32,
+}
+
+impl FlagWriter {
+    fn new(out: &mut Vec<u8>) -> Self {
+        let pos = out.len();
+        out.extend_from_slice(&[0; 4]);
+        Self {
+            pos,
+            flags: 0,
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, out: &mut Vec<u8>, is_match: bool) {
+        self.flags = self.flags << 1 | u32::from(is_match);
+        self.count += 1;
+        if self.count == 32 {
+            out[self.pos..self.pos + 4].copy_from_slice(&self.flags.to_le_bytes());
+            *self = Self::new(out);
+        }
+    }
+
+    /// Pads the last word with set bits which the decompressor takes as end marker.
+    fn finish(self, out: &mut [u8]) {
+        let pad = 32 - self.count;
+        let flags = self.flags.checked_shl(pad).unwrap_or(0) | u32::MAX >> self.count;
+        out[self.pos..self.pos + 4].copy_from_slice(&flags.to_le_bytes());
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_match(out: &mut Vec<u8>, nibble_pos: &mut Option<usize>, offset: usize, length: usize) {
+    let offset = ((offset - 1) << 3) as u16;
+    let length = length - 3;
+    if length < 7 {
+        out.extend_from_slice(&(offset | length as u16).to_le_bytes());
+        return;
+    }
+    out.extend_from_slice(&(offset | 7).to_le_bytes());
+    let length = length - 7;
+    let nibble = length.min(15) as u8;
+    if let Some(pos) = nibble_pos.take() {
+        out[pos] |= nibble << 4;
+    } else {
+        *nibble_pos = Some(out.len());
+        out.push(nibble);
+    }
+    if length < 15 {
+        return;
+    }
+    let length = length - 15;
+    if length < 255 {
+        out.push(length as u8);
+    } else {
+        out.push(255);
+        out.extend_from_slice(&((length + 15 + 7) as u16).to_le_bytes());
+    }
+}
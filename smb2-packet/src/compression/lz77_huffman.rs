@@ -0,0 +1,370 @@
+//! LZ77 with Huffman coding as described in MS-XCA 2.1 and 2.2.
+//!
+//! The data is split into blocks of 64 KiB uncompressed bytes. Every block starts with
+//! a table of 4 bit code lengths for its 512 symbols: 256 literals and 256 match symbols
+//! which combine the bit length of the offset with the lower bits of the length.
+
+use alloc::collections::BinaryHeap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::convert::TryFrom;
+
+use super::matcher::Matcher;
+use super::{copy_match, read_u16, read_u32, Error};
+
+const BLOCK_SIZE: usize = 1 << 16;
+const TABLE_SIZE: usize = 256;
+const SYMBOLS: usize = 512;
+const MAX_CODE_LENGTH: u8 = 15;
+const END_OF_STREAM: usize = 256;
+const MAX_OFFSET: usize = 0xFFFF;
+/// Longer matches are split so that the length always fits the 16 bit encoding.
+const MAX_LENGTH: usize = 0xFFFF + 3;
+
+#[derive(Clone, Copy)]
+enum Symbol {
+    Literal(u8),
+    Match { offset: usize, length: usize },
+    EndOfStream,
+}
+
+impl Symbol {
+    #[allow(clippy::cast_possible_truncation)]
+    fn index(self) -> usize {
+        match self {
+            Symbol::Literal(literal) => usize::from(literal),
+            Symbol::Match { offset, length } => {
+                let offset_bits = offset_bits(offset);
+                256 + (offset_bits << 4 | (length - 3).min(15))
+            }
+            Symbol::EndOfStream => END_OF_STREAM,
+        }
+    }
+}
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() + input.len() / 16 + TABLE_SIZE + 4);
+    let mut matcher = Matcher::new(input.len());
+    let mut symbols = Vec::new();
+    let mut pos = 0;
+    loop {
+        // matches stay within their block so that every block decodes to exactly 64 KiB
+        let block_end = input.len().min(pos + BLOCK_SIZE);
+        symbols.clear();
+        while pos < block_end {
+            let max_length = MAX_LENGTH.min(block_end - pos);
+            if let Some((offset, length)) = matcher.find(input, pos, 0, MAX_OFFSET, max_length) {
+                symbols.push(Symbol::Match { offset, length });
+                for i in pos..pos + length {
+                    matcher.insert(input, i);
+                }
+                pos += length;
+            } else {
+                symbols.push(Symbol::Literal(input[pos]));
+                matcher.insert(input, pos);
+                pos += 1;
+            }
+        }
+        let is_last = pos == input.len();
+        if is_last {
+            symbols.push(Symbol::EndOfStream);
+        }
+        write_block(&mut out, &symbols);
+        if is_last {
+            break;
+        }
+    }
+    out
+}
+
+/// Decompresses `input` which must yield exactly `original_size` bytes.
+///
+/// `original_size` is used to allocate the output and must be checked against a limit
+/// by the caller.
+pub fn decompress(input: &[u8], original_size: usize) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(original_size);
+    let mut pos = 0;
+    while out.len() < original_size {
+        let table = input.get(pos..pos + TABLE_SIZE).ok_or(Error::InvalidData)?;
+        let mut lengths = [0; SYMBOLS];
+        for (i, byte) in table.iter().enumerate() {
+            lengths[2 * i] = byte & 0x0F;
+            lengths[2 * i + 1] = byte >> 4;
+        }
+        let decoding_table = decoding_table(&lengths)?;
+        let mut reader = BitReader::new(input, pos + TABLE_SIZE);
+        let block_end = out.len() + BLOCK_SIZE;
+        while out.len() < block_end && out.len() < original_size {
+            let symbol = usize::from(decoding_table[reader.peek(MAX_CODE_LENGTH) as usize]);
+            reader.consume(lengths[symbol]);
+            if symbol < 256 {
+                #[allow(clippy::cast_possible_truncation)]
+                out.push(symbol as u8);
+                continue;
+            }
+            let symbol = symbol - 256;
+            let mut length = symbol & 0x0F;
+            #[allow(clippy::cast_possible_truncation)]
+            let offset_bits = (symbol >> 4) as u8;
+            if length == 15 {
+                length = usize::from(reader.byte()?);
+                if length == 255 {
+                    length = usize::from(reader.u16()?);
+                    if length == 0 {
+                        length = usize::try_from(reader.u32()?).map_err(|_| Error::InvalidData)?;
+                    }
+                    length = length.checked_sub(15).ok_or(Error::InvalidData)?;
+                }
+                length += 15;
+            }
+            let offset = reader.peek(offset_bits) as usize + (1 << offset_bits);
+            reader.consume(offset_bits);
+            copy_match(&mut out, offset, length + 3, original_size)?;
+        }
+        pos = reader.pos;
+    }
+    Ok(out)
+}
+
+fn offset_bits(offset: usize) -> usize {
+    (usize::BITS - 1 - offset.leading_zeros()) as usize
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_block(out: &mut Vec<u8>, symbols: &[Symbol]) {
+    let mut frequencies = [0_u32; SYMBOLS];
+    for symbol in symbols {
+        frequencies[symbol.index()] += 1;
+    }
+    let lengths = code_lengths(&frequencies);
+    for pair in lengths.chunks(2) {
+        out.push(pair[0] | pair[1] << 4);
+    }
+    let codes = canonical_codes(&lengths);
+
+    let mut writer = BitWriter::new(out);
+    for symbol in symbols {
+        let index = symbol.index();
+        writer.write(out, u32::from(codes[index]), lengths[index]);
+        if let Symbol::Match { offset, length } = *symbol {
+            let length = length - 3;
+            if length >= 15 {
+                if length - 15 < 255 {
+                    out.push((length - 15) as u8);
+                } else {
+                    out.push(255);
+                    out.extend_from_slice(&(length as u16).to_le_bytes());
+                }
+            }
+            let offset_bits = offset_bits(offset);
+            writer.write(out, (offset - (1 << offset_bits)) as u32, offset_bits as u8);
+        }
+    }
+    writer.finish(out);
+}
+
+/// Huffman code lengths limited to `MAX_CODE_LENGTH` bits.
+fn code_lengths(frequencies: &[u32; SYMBOLS]) -> [u8; SYMBOLS] {
+    let mut frequencies = *frequencies;
+    // a complete code needs at least two symbols
+    let missing = 2_usize.saturating_sub(frequencies.iter().filter(|f| **f > 0).count());
+    for frequency in frequencies.iter_mut().filter(|f| **f == 0).take(missing) {
+        *frequency = 1;
+    }
+    loop {
+        let lengths = huffman_lengths(&frequencies);
+        if lengths.iter().all(|l| *l <= MAX_CODE_LENGTH) {
+            return lengths;
+        }
+        // flatten the distribution until the tree is shallow enough
+        for frequency in frequencies.iter_mut().filter(|f| **f > 0) {
+            *frequency = (*frequency >> 1) | 1;
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn huffman_lengths(frequencies: &[u32; SYMBOLS]) -> [u8; SYMBOLS] {
+    // leaves are the symbols, inner nodes are appended
+    let mut parents = vec![usize::MAX; SYMBOLS];
+    let mut heap: BinaryHeap<_> = frequencies
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| **f > 0)
+        .map(|(symbol, f)| Reverse((u64::from(*f), symbol)))
+        .collect();
+    while heap.len() > 1 {
+        let Reverse((a, first)) = heap.pop().unwrap();
+        let Reverse((b, second)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[first] = node;
+        parents[second] = node;
+        heap.push(Reverse((a + b, node)));
+    }
+    let mut lengths = [0; SYMBOLS];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        if frequencies[symbol] == 0 {
+            continue;
+        }
+        let mut node = symbol;
+        let mut depth = 0_usize;
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            depth += 1;
+        }
+        *length = depth.min(usize::from(u8::MAX)) as u8;
+    }
+    lengths
+}
+
+/// Codes are assigned in the order of their length and then their symbol.
+fn canonical_codes(lengths: &[u8; SYMBOLS]) -> [u16; SYMBOLS] {
+    let mut codes = [0; SYMBOLS];
+    let mut code = 0;
+    for length in 1..=MAX_CODE_LENGTH {
+        for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == length) {
+            codes[symbol] = code;
+            code += 1;
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+/// Maps the next 15 bits of the stream to the symbol whose code they start with.
+fn decoding_table(lengths: &[u8; SYMBOLS]) -> Result<Vec<u16>, Error> {
+    let size = 1 << MAX_CODE_LENGTH;
+    let mut table = vec![0; size];
+    let mut next = 0;
+    for length in 1..=MAX_CODE_LENGTH {
+        for (symbol, _) in lengths.iter().enumerate().filter(|(_, l)| **l == length) {
+            let count = 1 << (MAX_CODE_LENGTH - length);
+            let entries = table
+                .get_mut(next..next + count)
+                .ok_or(Error::InvalidData)?;
+            #[allow(clippy::cast_possible_truncation)]
+            entries.fill(symbol as u16);
+            next += count;
+        }
+    }
+    if next == size {
+        Ok(table)
+    } else {
+        Err(Error::InvalidData)
+    }
+}
+
+/// Writes the bit stream as 16 bit words.
+///
+/// Two words are reserved ahead of the bytes written directly to the output because
+/// the decompressor reads that far ahead.
+struct BitWriter {
+    next: usize,
+    after_next: usize,
+    bits: u32,
+    free: u8,
+}
+
+impl BitWriter {
+    fn new(out: &mut Vec<u8>) -> Self {
+        let next = out.len();
+        out.extend_from_slice(&[0; 4]);
+        Self {
+            next,
+            after_next: next + 2,
+            bits: 0,
+            free: 16,
+        }
+    }
+
+    fn write(&mut self, out: &mut Vec<u8>, value: u32, count: u8) {
+        if count <= self.free {
+            self.bits = self.bits << count | value;
+            self.free -= count;
+            return;
+        }
+        let rest = count - self.free;
+        self.bits = self.bits << self.free | value >> rest;
+        self.store(out);
+        self.next = self.after_next;
+        self.after_next = out.len();
+        out.extend_from_slice(&[0; 2]);
+        self.bits = value & ((1 << rest) - 1);
+        self.free = 16 - rest;
+    }
+
+    fn finish(mut self, out: &mut [u8]) {
+        self.bits <<= self.free;
+        self.store(out);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn store(&self, out: &mut [u8]) {
+        out[self.next..self.next + 2].copy_from_slice(&(self.bits as u16).to_le_bytes());
+    }
+}
+
+struct BitReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+    bits: u32,
+    extra: i8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(input: &'a [u8], pos: usize) -> Self {
+        let mut reader = Self {
+            input,
+            pos,
+            bits: 0,
+            extra: 16,
+        };
+        reader.bits = u32::from(reader.word()) << 16 | u32::from(reader.word());
+        reader
+    }
+
+    /// The next 16 bits of the stream. Reads past the end yield zeros.
+    fn word(&mut self) -> u16 {
+        let word = read_u16(self.input, self.pos).unwrap_or(0);
+        self.pos += 2;
+        word
+    }
+
+    fn peek(&self, count: u8) -> u32 {
+        if count == 0 {
+            0
+        } else {
+            self.bits >> (32 - count)
+        }
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn consume(&mut self, count: u8) {
+        self.bits <<= count;
+        self.extra -= count as i8;
+        if self.extra < 0 {
+            self.bits |= u32::from(self.word()) << -self.extra;
+            self.extra += 16;
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.input.get(self.pos).ok_or(Error::InvalidData)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let value = read_u16(self.input, self.pos)?;
+        self.pos += 2;
+        Ok(value)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let value = read_u32(self.input, self.pos)?;
+        self.pos += 4;
+        Ok(value)
+    }
+}
@@ -0,0 +1,163 @@
+//! LZNT1 as described in MS-XCA 2.5.
+
+use alloc::vec::Vec;
+
+use super::matcher::Matcher;
+use super::{copy_match, read_u16, Error};
+
+const CHUNK_SIZE: usize = 4096;
+const COMPRESSED: u16 = 0x8000;
+const SIGNATURE: u16 = 0x3000;
+const SIZE_MASK: u16 = 0x0FFF;
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() + input.len() / CHUNK_SIZE * 2 + 2);
+    let mut matcher = Matcher::new(input.len());
+    let mut data = Vec::with_capacity(CHUNK_SIZE);
+    for start in (0..input.len()).step_by(CHUNK_SIZE) {
+        let end = input.len().min(start + CHUNK_SIZE);
+        data.clear();
+        compress_chunk(input, start, end, &mut matcher, &mut data);
+        // a chunk is stored as is unless compression saves space
+        if data.len() < end - start {
+            out.extend_from_slice(
+                &(COMPRESSED | SIGNATURE | (data.len() - 1) as u16).to_le_bytes(),
+            );
+            out.extend_from_slice(&data);
+        } else {
+            out.extend_from_slice(&(SIGNATURE | (end - start - 1) as u16).to_le_bytes());
+            out.extend_from_slice(&input[start..end]);
+        }
+    }
+    out
+}
+
+/// Decompresses `input` which must yield exactly `original_size` bytes.
+///
+/// `original_size` is used to allocate the output and must be checked against a limit
+/// by the caller.
+pub fn decompress(input: &[u8], original_size: usize) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(original_size);
+    let mut pos = 0;
+    // the stream may be terminated by a zero header or just end
+    while pos + 2 <= input.len() {
+        let header = read_u16(input, pos)?;
+        if header == 0 {
+            break;
+        }
+        let size = usize::from(header & SIZE_MASK) + 1;
+        let chunk = input
+            .get(pos + 2..pos + 2 + size)
+            .ok_or(Error::InvalidData)?;
+        pos += 2 + size;
+        if header & COMPRESSED == 0 {
+            if out.len() + size > original_size {
+                return Err(Error::InvalidData);
+            }
+            out.extend_from_slice(chunk);
+        } else {
+            decompress_chunk(chunk, &mut out, original_size)?;
+        }
+    }
+    if out.len() == original_size {
+        Ok(out)
+    } else {
+        Err(Error::InvalidData)
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn compress_chunk(
+    input: &[u8],
+    start: usize,
+    end: usize,
+    matcher: &mut Matcher,
+    out: &mut Vec<u8>,
+) {
+    let mut flag_pos = 0;
+    let mut flag_count = 0;
+    let mut pos = start;
+    while pos < end {
+        if flag_count % 8 == 0 {
+            flag_pos = out.len();
+            out.push(0);
+        }
+        let chunk_pos = pos - start;
+        let found = if chunk_pos == 0 {
+            None
+        } else {
+            let (offset_shift, length_mask) = split(chunk_pos);
+            let max_length = (usize::from(length_mask) + 3).min(end - pos);
+            matcher
+                .find(input, pos, start, chunk_pos, max_length)
+                .map(|m| (m, offset_shift))
+        };
+        if let Some(((offset, length), offset_shift)) = found {
+            let token = ((offset - 1) << offset_shift | (length - 3)) as u16;
+            out.extend_from_slice(&token.to_le_bytes());
+            out[flag_pos] |= 1 << (flag_count % 8);
+            for i in pos..pos + length {
+                matcher.insert(input, i);
+            }
+            pos += length;
+        } else {
+            out.push(input[pos]);
+            matcher.insert(input, pos);
+            pos += 1;
+        }
+        flag_count += 1;
+    }
+}
+
+fn decompress_chunk(chunk: &[u8], out: &mut Vec<u8>, original_size: usize) -> Result<(), Error> {
+    let start = out.len();
+    let limit = original_size.min(start + CHUNK_SIZE);
+    let mut pos = 0;
+    while pos < chunk.len() {
+        let flags = chunk[pos];
+        pos += 1;
+        for bit in 0..8 {
+            if pos == chunk.len() {
+                break;
+            }
+            if flags & (1 << bit) == 0 {
+                if out.len() == limit {
+                    return Err(Error::InvalidData);
+                }
+                out.push(chunk[pos]);
+                pos += 1;
+                continue;
+            }
+            let token = read_u16(chunk, pos)?;
+            pos += 2;
+            let chunk_pos = out.len() - start;
+            if chunk_pos == 0 {
+                return Err(Error::InvalidData);
+            }
+            let (offset_shift, length_mask) = split(chunk_pos);
+            let offset = usize::from(token >> offset_shift) + 1;
+            let length = usize::from(token & length_mask) + 3;
+            if offset > chunk_pos {
+                return Err(Error::InvalidData);
+            }
+            copy_match(out, offset, length, limit)?;
+        }
+    }
+    Ok(())
+}
+
+/// The split of a copy token between offset and length depends on the position in the chunk.
+///
+/// Returns the shift of the offset and the mask of the length.
+fn split(chunk_pos: usize) -> (u16, u16) {
+    let mut offset_shift = 12;
+    let mut length_mask = 0x0FFF;
+    let mut i = chunk_pos - 1;
+    while i >= 0x10 {
+        offset_shift -= 1;
+        length_mask >>= 1;
+        i >>= 1;
+    }
+    (offset_shift, length_mask)
+}
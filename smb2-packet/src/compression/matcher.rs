@@ -0,0 +1,81 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub(super) const MIN_MATCH: usize = 3;
+
+const HASH_BITS: u32 = 13;
+const MAX_CHAIN: usize = 32;
+const NONE: usize = usize::MAX;
+
+/// Finds earlier occurrences of the data at a position through chains of 3 byte hashes.
+pub(super) struct Matcher {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl Matcher {
+    pub(super) fn new(len: usize) -> Self {
+        Self {
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; len],
+        }
+    }
+
+    /// Makes `pos` available as match candidate for later positions.
+    pub(super) fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + MIN_MATCH > data.len() {
+            return;
+        }
+        let hash = hash(data, pos);
+        self.prev[pos] = self.head[hash];
+        self.head[hash] = pos;
+    }
+
+    /// Returns offset and length of the longest match for `pos`.
+    ///
+    /// Matches start no earlier than `window_start`, at most `max_offset` bytes back and
+    /// are at most `max_length` bytes long.
+    pub(super) fn find(
+        &self,
+        data: &[u8],
+        pos: usize,
+        window_start: usize,
+        max_offset: usize,
+        max_length: usize,
+    ) -> Option<(usize, usize)> {
+        if max_length < MIN_MATCH || pos + MIN_MATCH > data.len() {
+            return None;
+        }
+        let wanted = &data[pos..pos + max_length];
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash(data, pos)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == NONE || candidate < window_start || pos - candidate > max_offset {
+                break;
+            }
+            let length = data[candidate..]
+                .iter()
+                .zip(wanted)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best.1 {
+                best = (pos - candidate, length);
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+        }
+        if best.1 >= MIN_MATCH {
+            Some(best)
+        } else {
+            None
+        }
+    }
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let value =
+        u32::from(data[pos]) << 16 | u32::from(data[pos + 1]) << 8 | u32::from(data[pos + 2]);
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
@@ -0,0 +1,36 @@
+//! `Pattern_V1` which replaces a run of a single repeated byte in chained compression.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use super::{read_u32, Error};
+
+pub const PAYLOAD_SIZE: usize = 8;
+
+/// Returns the payload for `input` if it consists of a single repeated byte.
+pub fn compress(input: &[u8]) -> Option<[u8; PAYLOAD_SIZE]> {
+    let (&pattern, rest) = input.split_first()?;
+    if rest.iter().any(|b| *b != pattern) {
+        return None;
+    }
+    let repetitions = u32::try_from(input.len()).ok()?;
+    let mut payload = [0; PAYLOAD_SIZE];
+    payload[0] = pattern;
+    payload[4..].copy_from_slice(&repetitions.to_le_bytes());
+    Some(payload)
+}
+
+/// Expands the payload which must not exceed `max_size` bytes.
+///
+/// The size is carried by the payload itself and checked against `max_size` before allocating.
+pub fn decompress(input: &[u8], max_size: usize) -> Result<Vec<u8>, Error> {
+    if input.len() != PAYLOAD_SIZE {
+        return Err(Error::InvalidData);
+    }
+    let repetitions = usize::try_from(read_u32(input, 4)?).map_err(|_| Error::InvalidData)?;
+    if repetitions > max_size {
+        return Err(Error::InvalidData);
+    }
+    Ok(vec![input[0]; repetitions])
+}
@@ -17,6 +17,7 @@ pub struct Request {
     pub channel_sequence: Option<u16>,
     pub flags: Flags,
     pub message_id: u64,
+    pub process_id: Option<u32>,
     pub sync_type: SyncType,
     pub session_id: u64,
     pub signature: Signature,
@@ -29,13 +30,14 @@ pub struct Response {
     pub status: NTStatus,
     pub flags: Flags,
     pub message_id: u64,
+    pub process_id: Option<u32>,
     pub sync_type: SyncType,
     pub session_id: u64,
     pub signature: Signature,
 }
 
 #[repr(u8)]
-#[derive(FromPrimitive, PartialEq, Eq)]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub enum Command {
     Negotiate = 0x00,
@@ -110,6 +112,7 @@ where
         status: Option<NTStatus>,
         flags: Flags,
         message_id: u64,
+        process_id: Option<u32>,
         sync_type: SyncType,
         session_id: u64,
         signature: Signature,
@@ -117,6 +120,15 @@ where
 
     fn get_status(&self) -> Option<NTStatus>;
 
+    fn get_message_id(&self) -> u64;
+
+    /// Writes the 64 byte header for a message carrying `command`.
+    ///
+    /// `next_command` is the offset of the next message in a compound chain
+    /// or zero if this is the last one. The `SERVER_TO_REDIR` and `ASYNC_COMMAND`
+    /// flags are derived from the header type and its `SyncType`.
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect, command: Command, next_command: u32);
+
     #[allow(clippy::cyclomatic_complexity)]
     #[rustfmt::skip]
     fn parse<'a>(input: &'a [u8], dialect: Dialect) -> IResult<&'a [u8], ParseResult<Self>>
@@ -146,7 +158,7 @@ where
             ) >>
             next_command: le_u32 >>
             message_id: le_u64 >>
-            cond_with_error!(!flags.contains(Flags::ASYNC_COMMAND), take!(4)) >>
+            process_id: cond_with_error!(!flags.contains(Flags::ASYNC_COMMAND), le_u32) >>
             tree_id: cond_with_error!(!flags.contains(Flags::ASYNC_COMMAND), le_u32) >>
             async_id: cond_with_error!(flags.contains(Flags::ASYNC_COMMAND), le_u64) >>
             session_id: le_u64 >>
@@ -157,12 +169,13 @@ where
             ) >>
             (
                 ParseResult::<Self>::new( Self::new (
-                        if dialect > Dialect::Smb2_0_2 { Some(credit_charge) } else { None },
+                        if dialect.has_credit_charge() { Some(credit_charge) } else { None },
                         credit_req_grant,
                         channel_sequence,
                         status,
                         flags,
                         message_id,
+                        process_id,
                         {
                             if let Some(tree_id) = tree_id {
                                 SyncType::Sync { tree_id }
@@ -200,6 +213,7 @@ impl Header for Request {
         _status: Option<NTStatus>,
         flags: Flags,
         message_id: u64,
+        process_id: Option<u32>,
         sync_type: SyncType,
         session_id: u64,
         signature: Signature,
@@ -210,6 +224,7 @@ impl Header for Request {
             channel_sequence,
             flags,
             message_id,
+            process_id,
             sync_type,
             session_id,
             signature,
@@ -219,6 +234,31 @@ impl Header for Request {
     fn get_status(&self) -> Option<NTStatus> {
         None
     }
+
+    fn get_message_id(&self) -> u64 {
+        self.message_id
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect, command: Command, next_command: u32) {
+        let mut status = [0; 4];
+        if has_channel_sequence(dialect, Self::IS_RESPONSE) {
+            status[..2].copy_from_slice(&self.channel_sequence.unwrap_or(0).to_le_bytes());
+        }
+        write_header(
+            out,
+            self.credit_charge,
+            status,
+            command,
+            self.credit_request,
+            self.flags - Flags::SERVER_TO_REDIR,
+            next_command,
+            self.message_id,
+            self.process_id,
+            &self.sync_type,
+            self.session_id,
+            &self.signature,
+        );
+    }
 }
 
 impl Header for Response {
@@ -231,6 +271,7 @@ impl Header for Response {
         status: Option<NTStatus>,
         flags: Flags,
         message_id: u64,
+        process_id: Option<u32>,
         sync_type: SyncType,
         session_id: u64,
         signature: Signature,
@@ -241,6 +282,7 @@ impl Header for Response {
             status: status.unwrap(),
             flags,
             message_id,
+            process_id,
             sync_type,
             session_id,
             signature,
@@ -250,6 +292,27 @@ impl Header for Response {
     fn get_status(&self) -> Option<NTStatus> {
         Some(self.status)
     }
+
+    fn get_message_id(&self) -> u64 {
+        self.message_id
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>, _dialect: Dialect, command: Command, next_command: u32) {
+        write_header(
+            out,
+            self.credit_charge,
+            (self.status as u32).to_le_bytes(),
+            command,
+            self.credit_response,
+            self.flags | Flags::SERVER_TO_REDIR,
+            next_command,
+            self.message_id,
+            self.process_id,
+            &self.sync_type,
+            self.session_id,
+            &self.signature,
+        );
+    }
 }
 
 impl<'a, T> ParseResult<'a, T>
@@ -265,6 +328,45 @@ where
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+fn write_header(
+    out: &mut Vec<u8>,
+    credit_charge: Option<u16>,
+    status: [u8; 4],
+    command: Command,
+    credit_req_resp: u16,
+    flags: Flags,
+    next_command: u32,
+    message_id: u64,
+    process_id: Option<u32>,
+    sync_type: &SyncType,
+    session_id: u64,
+    signature: &Signature,
+) {
+    let flags = match sync_type {
+        SyncType::Async { .. } => flags | Flags::ASYNC_COMMAND,
+        SyncType::Sync { .. } => flags - Flags::ASYNC_COMMAND,
+    };
+    out.extend_from_slice(b"\xfeSMB");
+    out.extend_from_slice(&STRUCTURE_SIZE.to_le_bytes());
+    out.extend_from_slice(&credit_charge.unwrap_or(0).to_le_bytes());
+    out.extend_from_slice(&status);
+    out.extend_from_slice(&u16::from(command as u8).to_le_bytes());
+    out.extend_from_slice(&credit_req_resp.to_le_bytes());
+    out.extend_from_slice(&flags.bits().to_le_bytes());
+    out.extend_from_slice(&next_command.to_le_bytes());
+    out.extend_from_slice(&message_id.to_le_bytes());
+    match sync_type {
+        SyncType::Async { async_id } => out.extend_from_slice(&async_id.to_le_bytes()),
+        SyncType::Sync { tree_id } => {
+            out.extend_from_slice(&process_id.unwrap_or(0).to_le_bytes());
+            out.extend_from_slice(&tree_id.to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&session_id.to_le_bytes());
+    out.extend_from_slice(&signature.0);
+}
+
 fn copy_sig(input: &[u8]) -> Signature {
     let mut ret = [0; SIG_SIZE];
     ret.copy_from_slice(input);
@@ -276,10 +378,7 @@ fn has_channel_sequence(dialect: Dialect, is_response: bool) -> bool {
         return false;
     }
 
-    match dialect {
-        Dialect::Smb3_0_0 | Dialect::Smb3_0_2 | Dialect::Smb3_1_1 => true,
-        _ => false,
-    }
+    dialect.is_smb3()
 }
 
 fn value<F, O>(f: F, data: &[u8]) -> O
@@ -0,0 +1,157 @@
+//! Key derivation for SMB 3.x sessions.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::command::negotiate::{Cipher, HashAlgorithm};
+use crate::signing::KEY_SIZE;
+use crate::Dialect;
+
+pub const HASH_SIZE: usize = 64;
+
+const PRF_SIZE: usize = 32;
+
+/// SP800-108 KDF in counter mode with HMAC-SHA256 as PRF.
+///
+/// Fills `out` with key material. `label` and `context` are used as is, which means
+/// the terminating null byte that SMB includes in its labels must be part of them.
+pub fn derive(key: &[u8], label: &[u8], context: &[u8], out: &mut [u8]) {
+    #[allow(clippy::cast_possible_truncation)]
+    let length = (out.len() * 8) as u32;
+    for (i, chunk) in out.chunks_mut(PRF_SIZE).enumerate() {
+        #[allow(clippy::cast_possible_truncation)]
+        let counter = i as u32 + 1;
+        let mut mac = hmac_sha256(key);
+        mac.update(&counter.to_be_bytes());
+        mac.update(label);
+        mac.update(&[0]);
+        mac.update(context);
+        mac.update(&length.to_be_bytes());
+        chunk.copy_from_slice(&mac.finalize().into_bytes()[..chunk.len()]);
+    }
+}
+
+fn hmac_sha256(key: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size")
+}
+
+/// The SMB 3.1.1 preauth integrity hash chained over the NEGOTIATE and `SESSION_SETUP` exchange.
+///
+/// The connection feeds the NEGOTIATE request and response. Every session starts with a
+/// clone of that value and adds its `SESSION_SETUP` requests and responses except the final
+/// successful response.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct PreauthHash {
+    value: [u8; HASH_SIZE],
+}
+
+impl PreauthHash {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha512 => Self {
+                value: [0; HASH_SIZE],
+            },
+        }
+    }
+
+    /// Chains `message` into the hash.
+    ///
+    /// `message` is the complete SMB2 message without the transport header. For a
+    /// compound chain this is the whole chain.
+    pub fn update(&mut self, message: &[u8]) {
+        let mut hasher = Sha512::new();
+        hasher.update(self.value);
+        hasher.update(message);
+        self.value.copy_from_slice(&hasher.finalize());
+    }
+
+    pub fn value(&self) -> &[u8; HASH_SIZE] {
+        &self.value
+    }
+}
+
+/// The keys of an SMB 3.x session.
+///
+/// Encryption and decryption are named from the server's point of view. A client
+/// encrypts with the `decryption_key` and decrypts with the `encryption_key`.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKeys {
+    pub signing_key: [u8; KEY_SIZE],
+    /// Protects messages sent by the server. 32 bytes for the AES-256 ciphers.
+    pub encryption_key: Vec<u8>,
+    /// Protects messages sent by the client. 32 bytes for the AES-256 ciphers.
+    pub decryption_key: Vec<u8>,
+    pub application_key: [u8; KEY_SIZE],
+}
+
+impl SessionKeys {
+    /// Derives the keys from the `session_key` established by authentication.
+    ///
+    /// `cipher` is the cipher negotiated with SMB 3.1.1 and `Aes128Ccm` for SMB 3.0 and 3.0.2.
+    /// `preauth_hash` is the final hash of the session and required for SMB 3.1.1.
+    /// Returns `None` for SMB 2.x which signs with the session key directly or if the
+    /// hash is missing.
+    pub fn derive(
+        session_key: &[u8],
+        dialect: Dialect,
+        cipher: Cipher,
+        preauth_hash: Option<&PreauthHash>,
+    ) -> Option<Self> {
+        let (signing, application, server_out, server_in) = match dialect {
+            Dialect::Smb2_0_2 | Dialect::Smb2_1_0 | Dialect::Smb2Wildcard => return None,
+            Dialect::Smb3_0_0 | Dialect::Smb3_0_2 => (
+                (&b"SMB2AESCMAC\0"[..], &b"SmbSign\0"[..]),
+                (&b"SMB2APP\0"[..], &b"SmbRpc\0"[..]),
+                (&b"SMB2AESCCM\0"[..], &b"ServerOut\0"[..]),
+                (&b"SMB2AESCCM\0"[..], &b"ServerIn \0"[..]),
+            ),
+            Dialect::Smb3_1_1 => {
+                let hash = &preauth_hash?.value[..];
+                (
+                    (&b"SMBSigningKey\0"[..], hash),
+                    (&b"SMBAppKey\0"[..], hash),
+                    (&b"SMBS2CCipherKey\0"[..], hash),
+                    (&b"SMBC2SCipherKey\0"[..], hash),
+                )
+            }
+        };
+        // shorter keys are padded with zeros and longer ones are truncated
+        let mut key = [0; KEY_SIZE];
+        let len = session_key.len().min(KEY_SIZE);
+        key[..len].copy_from_slice(&session_key[..len]);
+
+        let (cipher_key, cipher_key_size): (&[u8], usize) = match cipher {
+            Cipher::Aes128Ccm | Cipher::Aes128Gcm => (&key, KEY_SIZE),
+            Cipher::Aes256Ccm | Cipher::Aes256Gcm => (session_key, 32),
+        };
+
+        let mut keys = Self {
+            signing_key: [0; KEY_SIZE],
+            encryption_key: vec![0; cipher_key_size],
+            decryption_key: vec![0; cipher_key_size],
+            application_key: [0; KEY_SIZE],
+        };
+        derive(&key, signing.0, signing.1, &mut keys.signing_key);
+        derive(
+            &key,
+            application.0,
+            application.1,
+            &mut keys.application_key,
+        );
+        derive(
+            cipher_key,
+            server_out.0,
+            server_out.1,
+            &mut keys.encryption_key,
+        );
+        derive(
+            cipher_key,
+            server_in.0,
+            server_in.1,
+            &mut keys.decryption_key,
+        );
+        Some(keys)
+    }
+}
@@ -2,32 +2,80 @@
 #![warn(clippy::pedantic)]
 #![deny(clippy::correctness)]
 
+extern crate alloc;
+
 pub mod command;
+pub mod compression;
 pub mod header;
+pub mod keys;
+#[cfg(feature = "ntlm")]
+pub mod ntlm;
 pub mod ntstatus;
+pub mod signing;
 pub mod smb1;
-mod transport;
+#[cfg(feature = "spnego")]
+pub mod spnego;
+pub mod transform;
+pub mod transport;
 
 use crate::command::{Body, RequestBody, ResponseBody};
 use crate::header::Header;
 use crate::header::Request as RequestHeader;
 use crate::header::Response as ResponseHeader;
+use bitflags::bitflags;
 use num_derive::FromPrimitive;
 use std::convert::TryInto;
 use std::ops::Deref;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of 100ns intervals between 1601-01-01 (FILETIME epoch) and the unix epoch
+const FILETIME_UNIX_EPOCH: u64 = 116_444_736_000_000_000;
+const FILETIME_PER_SEC: u64 = 10_000_000;
 
+/// The dialects are deliberately not ordered since the wildcard would sort between
+/// SMB 2.1 and 3.0. Ask for the features of a dialect instead.
 #[repr(u16)]
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive)]
+#[derive(Clone, Copy, PartialEq, Eq, FromPrimitive)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 #[allow(clippy::pub_enum_variant_names)]
 pub enum Dialect {
     Smb2_0_2 = 0x0202,
     Smb2_1_0 = 0x0210,
+    /// `SMB 2.???` which a server selects when upgrading from an SMB1 NEGOTIATE.
+    ///
+    /// Only valid in the NEGOTIATE response. The client follows up with an SMB2
+    /// NEGOTIATE request that offers the actual dialects, so dialect lists never
+    /// contain it. It is not a dialect to run a connection with and has none of the
+    /// features of the later dialects.
+    Smb2Wildcard = 0x02FF,
     Smb3_0_0 = 0x0300,
     Smb3_0_2 = 0x0302,
     Smb3_1_1 = 0x0311,
 }
 
+impl Dialect {
+    /// Whether the header carries a `CreditCharge`, which SMB 2.0.2 reserves.
+    pub fn has_credit_charge(self) -> bool {
+        match self {
+            Dialect::Smb2_0_2 | Dialect::Smb2Wildcard => false,
+            Dialect::Smb2_1_0 | Dialect::Smb3_0_0 | Dialect::Smb3_0_2 | Dialect::Smb3_1_1 => true,
+        }
+    }
+
+    /// Whether this is one of the SMB 3.x dialects.
+    pub fn is_smb3(self) -> bool {
+        match self {
+            Dialect::Smb2_0_2 | Dialect::Smb2_1_0 | Dialect::Smb2Wildcard => false,
+            Dialect::Smb3_0_0 | Dialect::Smb3_0_2 | Dialect::Smb3_1_1 => true,
+        }
+    }
+
+    /// Whether the `Channel` of READ and WRITE requests is parsed.
+    pub fn has_read_write_channel(self) -> bool {
+        self == Dialect::Smb3_1_1
+    }
+}
+
 #[cfg_attr(debug_assertions, derive(Debug))]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct FileId {
@@ -41,6 +89,20 @@ impl FileId {
     }
 }
 
+impl From<[u8; 16]> for FileId {
+    fn from(data: [u8; 16]) -> Self {
+        Self { data }
+    }
+}
+
+impl Deref for FileId {
+    type Target = [u8; 16];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
 #[cfg_attr(debug_assertions, derive(Debug))]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct ClientGuid {
@@ -54,6 +116,12 @@ impl ClientGuid {
     }
 }
 
+impl From<[u8; 16]> for ClientGuid {
+    fn from(data: [u8; 16]) -> Self {
+        Self { data }
+    }
+}
+
 impl Deref for ClientGuid {
     type Target = [u8; 16];
 
@@ -62,6 +130,62 @@ impl Deref for ClientGuid {
     }
 }
 
+bitflags! {
+    /// Access rights to files, pipes, printers and directories.
+    ///
+    /// The directory specific rights alias the file rights with the same value.
+    pub struct AccessMask: u32 {
+        const FILE_READ_DATA = 0x0000_0001;
+        const FILE_WRITE_DATA = 0x0000_0002;
+        const FILE_APPEND_DATA = 0x0000_0004;
+        const FILE_READ_EA = 0x0000_0008;
+        const FILE_WRITE_EA = 0x0000_0010;
+        const FILE_EXECUTE = 0x0000_0020;
+        const FILE_DELETE_CHILD = 0x0000_0040;
+        const FILE_READ_ATTRIBUTES = 0x0000_0080;
+        const FILE_WRITE_ATTRIBUTES = 0x0000_0100;
+        const FILE_LIST_DIRECTORY = Self::FILE_READ_DATA.bits;
+        const FILE_ADD_FILE = Self::FILE_WRITE_DATA.bits;
+        const FILE_ADD_SUBDIRECTORY = Self::FILE_APPEND_DATA.bits;
+        const FILE_TRAVERSE = Self::FILE_EXECUTE.bits;
+        const DELETE = 0x0001_0000;
+        const READ_CONTROL = 0x0002_0000;
+        const WRITE_DAC = 0x0004_0000;
+        const WRITE_OWNER = 0x0008_0000;
+        const SYNCHRONIZE = 0x0010_0000;
+        const ACCESS_SYSTEM_SECURITY = 0x0100_0000;
+        const MAXIMUM_ALLOWED = 0x0200_0000;
+        const GENERIC_ALL = 0x1000_0000;
+        const GENERIC_EXECUTE = 0x2000_0000;
+        const GENERIC_WRITE = 0x4000_0000;
+        const GENERIC_READ = 0x8000_0000;
+    }
+}
+
+bitflags! {
+    pub struct FileAttributes: u32 {
+        const READONLY = 0x0000_0001;
+        const HIDDEN = 0x0000_0002;
+        const SYSTEM = 0x0000_0004;
+        const DIRECTORY = 0x0000_0010;
+        const ARCHIVE = 0x0000_0020;
+        const NORMAL = 0x0000_0080;
+        const TEMPORARY = 0x0000_0100;
+        const SPARSE_FILE = 0x0000_0200;
+        const REPARSE_POINT = 0x0000_0400;
+        const COMPRESSED = 0x0000_0800;
+        const OFFLINE = 0x0000_1000;
+        const NOT_CONTENT_INDEXED = 0x0000_2000;
+        const ENCRYPTED = 0x0000_4000;
+        const INTEGRITY_STREAM = 0x0000_8000;
+        const NO_SCRUB_DATA = 0x0002_0000;
+        const RECALL_ON_OPEN = 0x0004_0000;
+        const PINNED = 0x0008_0000;
+        const UNPINNED = 0x0010_0000;
+        const RECALL_ON_DATA_ACCESS = 0x0040_0000;
+    }
+}
+
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct Request<'a> {
     pub header: RequestHeader,
@@ -84,6 +208,19 @@ where
     }
 }
 
+/// Serializes a compound chain of messages including the transport framing.
+///
+/// This is the inverse of `parse`: All messages passed are put into a single
+/// transport message and linked together with `NextCommand`.
+pub fn serialize<'a, T>(messages: &[T], dialect: Dialect) -> Vec<u8>
+where
+    T: Packet<'a>,
+{
+    let mut out = Vec::new();
+    transport::write_payload(&mut out, |out| T::serialize(messages, dialect, out));
+    out
+}
+
 pub fn parse_smb1_nego_request(input: &[u8]) -> nom::IResult<&[u8], smb1::NegotiateRequest> {
     match transport::get_payload(input) {
         Ok((rem, out)) => parse_smb1_nego_request_complete(out).map(|i| (rem, i)),
@@ -91,6 +228,13 @@ pub fn parse_smb1_nego_request(input: &[u8]) -> nom::IResult<&[u8], smb1::Negoti
     }
 }
 
+/// Serializes the SMB1 error response including the transport framing.
+pub fn serialize_smb1_nego_error_response(response: &smb1::NegotiateErrorResponse) -> Vec<u8> {
+    let mut out = Vec::new();
+    transport::write_payload(&mut out, |out| response.write_to(out));
+    out
+}
+
 fn parse_smb1_nego_request_complete(
     input: &[u8],
 ) -> Result<smb1::NegotiateRequest, nom::Err<&[u8]>> {
@@ -116,6 +260,10 @@ where
 
     fn new(header: Self::Header, body: Self::Body) -> Self;
 
+    fn header(&self) -> &Self::Header;
+
+    fn body(&self) -> &Self::Body;
+
     fn parse(input: &'a [u8], dialect: Dialect) -> Result<Vec<Self>, nom::Err<&[u8]>> {
         use nom::{apply, complete};
         let mut result = Vec::new();
@@ -124,11 +272,18 @@ where
             match complete!(cur, apply!(Self::Header::parse, dialect)) {
                 Ok((remainder, output)) => {
                     let status = output.header.get_status();
+                    let message_id = output.header.get_message_id();
                     result.push(Self::new(
                         output.header,
                         complete!(
                             output.body,
-                            apply!(Self::Body::parse, dialect, output.command, status)
+                            apply!(
+                                Self::Body::parse,
+                                dialect,
+                                output.command,
+                                status,
+                                message_id
+                            )
                         )?,
                     ));
                     if remainder.is_empty() {
@@ -141,6 +296,41 @@ where
         }
         Ok(result)
     }
+
+    /// Writes `messages` as compound chain without any transport framing.
+    ///
+    /// Every message but the last is padded to the next 8 byte boundary.
+    #[allow(clippy::cast_possible_truncation)]
+    fn serialize(messages: &[Self], dialect: Dialect, out: &mut Vec<u8>) {
+        let mut body = Vec::new();
+        for (i, message) in messages.iter().enumerate() {
+            let start = out.len();
+            body.clear();
+            message.body().write_to(&mut body, dialect);
+            let is_last = i + 1 == messages.len();
+            let next_command = if is_last {
+                0
+            } else {
+                align8(usize::from(header::STRUCTURE_SIZE) + body.len()) as u32
+            };
+            message
+                .header()
+                .write_to(out, dialect, message.body().command(), next_command);
+            out.extend_from_slice(&body);
+            if !is_last {
+                pad8(out, start);
+            }
+        }
+    }
+}
+
+/// Types that can be written in their wire representation.
+///
+/// Offsets contained in the written data are relative to the beginning of the
+/// SMB2 header. Therefore `out` is expected to be positioned directly after the header
+/// when calling `write_to` on a message body.
+pub trait Serialize {
+    fn write_to(&self, out: &mut Vec<u8>, dialect: Dialect);
 }
 
 impl<'a> Packet<'a> for Request<'a> {
@@ -150,6 +340,14 @@ impl<'a> Packet<'a> for Request<'a> {
     fn new(header: Self::Header, body: Self::Body) -> Self {
         Request { header, body }
     }
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+
+    fn body(&self) -> &Self::Body {
+        &self.body
+    }
 }
 
 impl<'a> Packet<'a> for Response<'a> {
@@ -159,6 +357,14 @@ impl<'a> Packet<'a> for Response<'a> {
     fn new(header: Self::Header, body: Self::Body) -> Self {
         Response { header, body }
     }
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+
+    fn body(&self) -> &Self::Body {
+        &self.body
+    }
 }
 
 #[allow(clippy::cast_ptr_alignment)]
@@ -176,3 +382,57 @@ fn utf16le_to_string(data: &[u8]) -> Result<String, String> {
 
     String::from_utf16(&buffer).map_err(|err| err.to_string())
 }
+
+fn string_to_utf16le(string: &str) -> Vec<u8> {
+    string.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn filetime_to_systemtime(filetime: u64) -> Option<SystemTime> {
+    let (later, diff) = if filetime >= FILETIME_UNIX_EPOCH {
+        (true, filetime - FILETIME_UNIX_EPOCH)
+    } else {
+        (false, FILETIME_UNIX_EPOCH - filetime)
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let duration = Duration::new(
+        diff / FILETIME_PER_SEC,
+        (diff % FILETIME_PER_SEC) as u32 * 100,
+    );
+    if later {
+        UNIX_EPOCH.checked_add(duration)
+    } else {
+        UNIX_EPOCH.checked_sub(duration)
+    }
+}
+
+fn systemtime_to_filetime(time: SystemTime) -> u64 {
+    let to_filetime = |duration: Duration| {
+        duration
+            .as_secs()
+            .saturating_mul(FILETIME_PER_SEC)
+            .saturating_add(u64::from(duration.subsec_nanos() / 100))
+    };
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => FILETIME_UNIX_EPOCH.saturating_add(to_filetime(duration)),
+        Err(err) => FILETIME_UNIX_EPOCH.saturating_sub(to_filetime(err.duration())),
+    }
+}
+
+/// Number of bytes needed to pad `offset` to the next 8 byte boundary
+fn padding8(offset: usize) -> usize {
+    (8 - (offset % 8)) % 8
+}
+
+fn align8(offset: usize) -> usize {
+    offset + padding8(offset)
+}
+
+/// Pads `out` with zeros so that the data written since `start` is a multiple of 8
+fn pad8(out: &mut Vec<u8>, start: usize) {
+    out.resize(out.len() + padding8(out.len() - start), 0);
+}
+
+/// Compares without leaking how many bytes matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
@@ -0,0 +1,651 @@
+//! NTLMSSP messages as carried in the `SESSION_SETUP` security buffer and NTLM v2
+//! authentication on the server side as described in MS-NLMP.
+//!
+//! A server answers the client's NEGOTIATE message with a `Challenge` and hands the
+//! three messages of the exchange to `authenticate` once the AUTHENTICATE message
+//! arrives. The session key it returns is the key SMB signs with and derives the
+//! SMB 3.x keys from.
+//!
+//! Windows clients wrap the messages in SPNEGO tokens, see `crate::spnego`.
+
+use bitflags::bitflags;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use nom::*;
+use rc4::consts::U16;
+use rc4::{KeyInit, Rc4, StreamCipher};
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::{
+    constant_time_eq, filetime_to_systemtime, string_to_utf16le, systemtime_to_filetime,
+    utf16le_to_string,
+};
+
+pub const SIGNATURE: &[u8] = b"NTLMSSP\0";
+pub const CHALLENGE_SIZE: usize = 8;
+pub const KEY_SIZE: usize = 16;
+pub const MIC_SIZE: usize = 16;
+/// `NTLMSSP_REVISION_W2K3`, the only revision in use.
+pub const REVISION: u8 = 0x0F;
+
+type HmacMd5 = Hmac<Md5>;
+
+const NEGOTIATE: u32 = 1;
+const CHALLENGE: u32 = 2;
+const AUTHENTICATE: u32 = 3;
+const CHALLENGE_PAYLOAD_OFFSET: usize = 56;
+const AUTHENTICATE_VERSION_END: usize = 72;
+const AUTHENTICATE_MIC_END: usize = AUTHENTICATE_VERSION_END + MIC_SIZE;
+const NTLM_RESPONSE_SIZE: usize = 24;
+const NTLMV2_RESPONSE_TYPE: u8 = 0x01;
+
+bitflags! {
+    pub struct NegotiateFlags: u32 {
+        const UNICODE = 0x0000_0001;
+        const OEM = 0x0000_0002;
+        const REQUEST_TARGET = 0x0000_0004;
+        const SIGN = 0x0000_0010;
+        const SEAL = 0x0000_0020;
+        const DATAGRAM = 0x0000_0040;
+        const LM_KEY = 0x0000_0080;
+        const NTLM = 0x0000_0200;
+        const ANONYMOUS = 0x0000_0800;
+        const OEM_DOMAIN_SUPPLIED = 0x0000_1000;
+        const OEM_WORKSTATION_SUPPLIED = 0x0000_2000;
+        const ALWAYS_SIGN = 0x0000_8000;
+        const TARGET_TYPE_DOMAIN = 0x0001_0000;
+        const TARGET_TYPE_SERVER = 0x0002_0000;
+        const EXTENDED_SESSIONSECURITY = 0x0008_0000;
+        const IDENTIFY = 0x0010_0000;
+        const REQUEST_NON_NT_SESSION_KEY = 0x0040_0000;
+        const TARGET_INFO = 0x0080_0000;
+        const VERSION = 0x0200_0000;
+        const NEGOTIATE_128 = 0x2000_0000;
+        const KEY_EXCH = 0x4000_0000;
+        const NEGOTIATE_56 = 0x8000_0000;
+    }
+}
+
+bitflags! {
+    pub struct AvFlags: u32 {
+        const CONSTRAINED = 0x0000_0001;
+        const MIC_PRESENT = 0x0000_0002;
+        const UNTRUSTED_SPN_SOURCE = 0x0000_0004;
+    }
+}
+
+/// The operating system version, for debugging only.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub build: u16,
+    pub revision: u8,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq)]
+pub enum AvPair<'a> {
+    NbComputerName(String),
+    NbDomainName(String),
+    DnsComputerName(String),
+    DnsDomainName(String),
+    DnsTreeName(String),
+    Flags(AvFlags),
+    Timestamp(SystemTime),
+    SingleHost(&'a [u8]),
+    TargetName(String),
+    ChannelBindings(&'a [u8]),
+    Other { id: u16, value: &'a [u8] },
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Negotiate<'a> {
+    pub flags: NegotiateFlags,
+    /// OEM encoded, only set along with `OEM_DOMAIN_SUPPLIED`.
+    pub domain_name: &'a [u8],
+    /// OEM encoded, only set along with `OEM_WORKSTATION_SUPPLIED`.
+    pub workstation: &'a [u8],
+    pub version: Option<Version>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Challenge<'a> {
+    pub flags: NegotiateFlags,
+    pub target_name: String,
+    pub server_challenge: [u8; CHALLENGE_SIZE],
+    pub target_info: Vec<AvPair<'a>>,
+    pub version: Option<Version>,
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Authenticate<'a> {
+    pub flags: NegotiateFlags,
+    pub lm_challenge_response: &'a [u8],
+    pub nt_challenge_response: &'a [u8],
+    pub domain_name: String,
+    pub user_name: String,
+    pub workstation: String,
+    pub encrypted_random_session_key: &'a [u8],
+    pub version: Option<Version>,
+    pub mic: Option<[u8; MIC_SIZE]>,
+}
+
+/// The `NtChallengeResponse` of an NTLM v2 AUTHENTICATE message.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Ntlmv2Response<'a> {
+    pub nt_proof_str: [u8; 16],
+    pub timestamp: SystemTime,
+    pub client_challenge: [u8; CHALLENGE_SIZE],
+    pub av_pairs: Vec<AvPair<'a>>,
+    /// Everything after `nt_proof_str` which is what the proof is computed over.
+    pub client_blob: &'a [u8],
+}
+
+/// The outcome of a successful authentication.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct Authenticated {
+    pub user_name: String,
+    pub domain_name: String,
+    pub session_base_key: [u8; KEY_SIZE],
+    /// The exported session key, which is the session key of the SMB session.
+    pub session_key: [u8; KEY_SIZE],
+}
+
+/// Looks up the credentials of the users a server accepts.
+pub trait PasswordHashProvider {
+    /// Returns the NT hash, the MD4 digest of the UTF-16LE password, or `None` for an
+    /// unknown user.
+    ///
+    /// `domain_name` is the domain as sent by the client and may be empty.
+    fn nt_hash(&self, user_name: &str, domain_name: &str) -> Option<[u8; KEY_SIZE]>;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    Malformed,
+    /// The client sent an LM or NTLM v1 response instead of an NTLM v2 response.
+    UnsupportedResponse,
+    /// The client asks for an anonymous session, which the server may grant or not.
+    Anonymous,
+    UnknownUser,
+    /// The response was not computed with the password of the user.
+    InvalidResponse,
+    /// The MIC is announced but missing or does not match the messages.
+    InvalidMic,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Malformed => write!(f, "Malformed NTLMSSP message"),
+            Error::UnsupportedResponse => write!(f, "Only NTLMv2 responses are supported"),
+            Error::Anonymous => write!(f, "Anonymous authentication"),
+            Error::UnknownUser => write!(f, "Unknown user"),
+            Error::InvalidResponse => write!(f, "Invalid NTLMv2 response"),
+            Error::InvalidMic => write!(f, "Invalid message integrity code"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl NegotiateFlags {
+    /// The flags a server answers the `requested` flags with.
+    pub fn challenge(requested: Self) -> Self {
+        let mut flags = requested
+            & (Self::UNICODE
+                | Self::SIGN
+                | Self::SEAL
+                | Self::ALWAYS_SIGN
+                | Self::EXTENDED_SESSIONSECURITY
+                | Self::IDENTIFY
+                | Self::NEGOTIATE_128
+                | Self::KEY_EXCH
+                | Self::NEGOTIATE_56);
+        if !flags.contains(Self::UNICODE) {
+            flags |= requested & Self::OEM;
+        }
+        if requested.contains(Self::REQUEST_TARGET) {
+            flags |= Self::REQUEST_TARGET | Self::TARGET_TYPE_SERVER;
+        }
+        flags | Self::NTLM | Self::TARGET_INFO
+    }
+}
+
+impl Version {
+    fn write_to(self, out: &mut Vec<u8>) {
+        out.push(self.major);
+        out.push(self.minor);
+        out.extend_from_slice(&self.build.to_le_bytes());
+        out.extend_from_slice(&[0; 3]); /* reserved */
+        out.push(self.revision);
+    }
+}
+
+impl<'a> AvPair<'a> {
+    #[rustfmt::skip]
+    fn new(data: &'a [u8], id: u16) -> IResult<&[u8], AvPair> {
+        match id {
+            0x01 => map!(data, map_res!(rest, utf16le_to_string), AvPair::NbComputerName),
+            0x02 => map!(data, map_res!(rest, utf16le_to_string), AvPair::NbDomainName),
+            0x03 => map!(data, map_res!(rest, utf16le_to_string), AvPair::DnsComputerName),
+            0x04 => map!(data, map_res!(rest, utf16le_to_string), AvPair::DnsDomainName),
+            0x05 => map!(data, map_res!(rest, utf16le_to_string), AvPair::DnsTreeName),
+            0x06 => map!(data,
+                map!(le_u32, AvFlags::from_bits_truncate),
+                AvPair::Flags
+            ),
+            0x07 => map!(data,
+                map_opt!(le_u64, filetime_to_systemtime),
+                AvPair::Timestamp
+            ),
+            0x08 => map!(data, rest, AvPair::SingleHost),
+            0x09 => map!(data, map_res!(rest, utf16le_to_string), AvPair::TargetName),
+            0x0A => map!(data, rest, AvPair::ChannelBindings),
+            _ => map!(data, rest, |value| AvPair::Other { id, value }),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>) {
+        let (id, value) = match self {
+            AvPair::NbComputerName(name) => (0x01, string_to_utf16le(name)),
+            AvPair::NbDomainName(name) => (0x02, string_to_utf16le(name)),
+            AvPair::DnsComputerName(name) => (0x03, string_to_utf16le(name)),
+            AvPair::DnsDomainName(name) => (0x04, string_to_utf16le(name)),
+            AvPair::DnsTreeName(name) => (0x05, string_to_utf16le(name)),
+            AvPair::Flags(flags) => (0x06, flags.bits().to_le_bytes().to_vec()),
+            AvPair::Timestamp(time) => (0x07, systemtime_to_filetime(*time).to_le_bytes().to_vec()),
+            AvPair::SingleHost(value) => (0x08, value.to_vec()),
+            AvPair::TargetName(name) => (0x09, string_to_utf16le(name)),
+            AvPair::ChannelBindings(value) => (0x0A, value.to_vec()),
+            AvPair::Other { id, value } => (*id, value.to_vec()),
+        };
+        out.extend_from_slice(&id.to_le_bytes());
+        out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        out.extend_from_slice(&value);
+    }
+}
+
+impl Challenge<'_> {
+    /// Serializes the message. `TARGET_INFO` is always set and `VERSION` is set
+    /// according to `version`.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let mut flags = self.flags | NegotiateFlags::TARGET_INFO;
+        flags.set(NegotiateFlags::VERSION, self.version.is_some());
+        let target_name = encode(&self.target_name, flags);
+        let mut target_info = Vec::new();
+        write_av_pairs(&mut target_info, &self.target_info);
+
+        out.extend_from_slice(SIGNATURE);
+        out.extend_from_slice(&CHALLENGE.to_le_bytes());
+        write_field(out, target_name.len(), CHALLENGE_PAYLOAD_OFFSET);
+        out.extend_from_slice(&flags.bits().to_le_bytes());
+        out.extend_from_slice(&self.server_challenge);
+        out.extend_from_slice(&[0; 8]); /* reserved */
+        write_field(
+            out,
+            target_info.len(),
+            CHALLENGE_PAYLOAD_OFFSET + target_name.len(),
+        );
+        match self.version {
+            Some(version) => version.write_to(out),
+            None => out.extend_from_slice(&[0; 8]),
+        }
+        out.extend_from_slice(&target_name);
+        out.extend_from_slice(&target_info);
+    }
+}
+
+/// The location of a variable length field in the payload of a message.
+#[derive(Clone, Copy)]
+struct Field {
+    length: u16,
+    offset: usize,
+}
+
+impl Field {
+    fn get(self, message: &[u8]) -> Option<&[u8]> {
+        if self.length == 0 {
+            return Some(&[]);
+        }
+        message.get(self.offset..self.offset.checked_add(usize::from(self.length))?)
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_field(out: &mut Vec<u8>, length: usize, offset: usize) {
+    out.extend_from_slice(&(length as u16).to_le_bytes());
+    out.extend_from_slice(&(length as u16).to_le_bytes());
+    out.extend_from_slice(&(offset as u32).to_le_bytes());
+}
+
+fn write_av_pairs(out: &mut Vec<u8>, pairs: &[AvPair]) {
+    for pair in pairs {
+        pair.write_to(out);
+    }
+    out.extend_from_slice(&[0; 4]); /* MsvAvEOL */
+}
+
+fn encode(string: &str, flags: NegotiateFlags) -> Vec<u8> {
+    if flags.contains(NegotiateFlags::UNICODE) {
+        string_to_utf16le(string)
+    } else {
+        string.as_bytes().to_vec()
+    }
+}
+
+fn decode(data: &[u8], flags: NegotiateFlags) -> Option<String> {
+    if flags.contains(NegotiateFlags::UNICODE) {
+        utf16le_to_string(data).ok()
+    } else {
+        String::from_utf8(data.to_vec()).ok()
+    }
+}
+
+/// Uppercases each UTF-16 code unit on its own like Windows does. A code unit
+/// whose uppercase form is not a single code unit, e.g. "ß", is kept.
+fn uppercase_utf16le(string: &str) -> Vec<u8> {
+    string
+        .encode_utf16()
+        .map(|unit| {
+            let mut upper = char::from_u32(u32::from(unit)).map(char::to_uppercase);
+            match upper.as_mut().map(|chars| (chars.next(), chars.next())) {
+                Some((Some(c), None)) => u16::try_from(u32::from(c)).unwrap_or(unit),
+                _ => unit,
+            }
+        })
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+fn to_array<const N: usize>(data: &[u8]) -> [u8; N] {
+    let mut out = [0; N];
+    out.copy_from_slice(data);
+    out
+}
+
+#[rustfmt::skip]
+named!(
+    parse_field<Field>,
+    do_parse!(
+        length: le_u16 >>
+        le_u16 >> /* maximum length */
+        offset: map!(le_u32, |offset| usize::try_from(offset).unwrap_or(usize::MAX)) >>
+        (Field { length, offset })
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_version<Version>,
+    do_parse!(
+        major: le_u8 >>
+        minor: le_u8 >>
+        build: le_u16 >>
+        take!(3) >> /* reserved */
+        revision: le_u8 >>
+        (Version { major, minor, build, revision })
+    )
+);
+
+#[rustfmt::skip]
+fn parse_av_pair(input: &[u8]) -> IResult<&[u8], AvPair> {
+    do_parse!(input,
+        id: verify!(le_u16, |id| id != 0) >>
+        length: le_u16 >>
+        pair: length_value!(value!(length), apply!(AvPair::new, id)) >>
+        (pair)
+    )
+}
+
+/// Parses AV pairs up to and including the terminating `MsvAvEOL`.
+pub fn parse_av_pairs(input: &[u8]) -> IResult<&[u8], Vec<AvPair>> {
+    map!(input, many_till!(parse_av_pair, tag!(&[0; 4][..])), |(
+        pairs,
+        _,
+    )| {
+        pairs
+    })
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_negotiate(input: &[u8]) -> IResult<&[u8], Negotiate> {
+    do_parse!(input,
+        tag!(SIGNATURE) >>
+        verify!(le_u32, |x| x == NEGOTIATE) >>
+        flags: map!(le_u32, NegotiateFlags::from_bits_truncate) >>
+        domain_name_field: parse_field >>
+        workstation_field: parse_field >>
+        version: cond!(flags.contains(NegotiateFlags::VERSION), parse_version) >>
+        domain_name: expr_opt!(domain_name_field.get(input)) >>
+        workstation: expr_opt!(workstation_field.get(input)) >>
+        rest >>
+        (Negotiate {
+            flags,
+            domain_name,
+            workstation,
+            version,
+        })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_challenge(input: &[u8]) -> IResult<&[u8], Challenge> {
+    do_parse!(input,
+        tag!(SIGNATURE) >>
+        verify!(le_u32, |x| x == CHALLENGE) >>
+        target_name_field: parse_field >>
+        flags: map!(le_u32, NegotiateFlags::from_bits_truncate) >>
+        server_challenge: map!(take!(CHALLENGE_SIZE), to_array) >>
+        take!(8) >> /* reserved */
+        target_info_field: parse_field >>
+        version: cond!(flags.contains(NegotiateFlags::VERSION), parse_version) >>
+        target_name: expr_opt!(target_name_field.get(input).and_then(|x| decode(x, flags))) >>
+        target_info: expr_opt!(target_info_field.get(input)) >>
+        target_info: cond!(!target_info.is_empty(), call!(parse_av_pairs_in, target_info)) >>
+        rest >>
+        (Challenge {
+            flags,
+            target_name,
+            server_challenge,
+            target_info: target_info.unwrap_or_default(),
+            version,
+        })
+    )
+}
+
+/// Parses the AV pairs of `data` without consuming any input.
+fn parse_av_pairs_in<'a>(input: &'a [u8], data: &'a [u8]) -> IResult<&'a [u8], Vec<AvPair<'a>>> {
+    match parse_av_pairs(data) {
+        Ok((_, pairs)) => Ok((input, pairs)),
+        Err(_) => Err(Err::Error(error_position!(input, ErrorKind::Custom(0)))),
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_authenticate(input: &[u8]) -> IResult<&[u8], Authenticate> {
+    do_parse!(input,
+        tag!(SIGNATURE) >>
+        verify!(le_u32, |x| x == AUTHENTICATE) >>
+        lm_field: parse_field >>
+        nt_field: parse_field >>
+        domain_name_field: parse_field >>
+        user_name_field: parse_field >>
+        workstation_field: parse_field >>
+        session_key_field: parse_field >>
+        flags: map!(le_u32, NegotiateFlags::from_bits_truncate) >>
+        /* version and MIC are only present if the payload starts after them */
+        payload_offset: value!(payload_offset(input, &[
+            lm_field,
+            nt_field,
+            domain_name_field,
+            user_name_field,
+            workstation_field,
+            session_key_field,
+        ])) >>
+        version: cond!(payload_offset >= AUTHENTICATE_VERSION_END, parse_version) >>
+        mic: cond!(payload_offset >= AUTHENTICATE_MIC_END, map!(take!(MIC_SIZE), to_array)) >>
+        lm_challenge_response: expr_opt!(lm_field.get(input)) >>
+        nt_challenge_response: expr_opt!(nt_field.get(input)) >>
+        domain_name: expr_opt!(domain_name_field.get(input).and_then(|x| decode(x, flags))) >>
+        user_name: expr_opt!(user_name_field.get(input).and_then(|x| decode(x, flags))) >>
+        workstation: expr_opt!(workstation_field.get(input).and_then(|x| decode(x, flags))) >>
+        encrypted_random_session_key: expr_opt!(session_key_field.get(input)) >>
+        rest >>
+        (Authenticate {
+            flags,
+            lm_challenge_response,
+            nt_challenge_response,
+            domain_name,
+            user_name,
+            workstation,
+            encrypted_random_session_key,
+            version: version.filter(|_| flags.contains(NegotiateFlags::VERSION)),
+            mic,
+        })
+    )
+}
+
+fn payload_offset(input: &[u8], fields: &[Field]) -> usize {
+    fields
+        .iter()
+        .filter(|field| field.length > 0)
+        .map(|field| field.offset)
+        .min()
+        .unwrap_or(input.len())
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+pub fn parse_ntlmv2_response(input: &[u8]) -> IResult<&[u8], Ntlmv2Response> {
+    do_parse!(input,
+        nt_proof_str: map!(take!(16), to_array) >>
+        client_blob: peek!(rest) >>
+        verify!(le_u8, |x| x == NTLMV2_RESPONSE_TYPE) >>
+        le_u8 >> /* highest response type */
+        take!(6) >> /* reserved */
+        timestamp: map_opt!(le_u64, filetime_to_systemtime) >>
+        client_challenge: map!(take!(CHALLENGE_SIZE), to_array) >>
+        take!(4) >> /* reserved */
+        av_pairs: parse_av_pairs >>
+        rest >> /* padding */
+        (Ntlmv2Response {
+            nt_proof_str,
+            timestamp,
+            client_challenge,
+            av_pairs,
+            client_blob,
+        })
+    )
+}
+
+/// Validates the NTLM v2 response of an AUTHENTICATE message.
+///
+/// `negotiate`, `challenge` and `authenticate` are the messages of the exchange as sent
+/// over the wire. All of them are needed to check the MIC. The timestamp of the
+/// response is not checked against the clock.
+pub fn authenticate<P: PasswordHashProvider + ?Sized>(
+    provider: &P,
+    negotiate: &[u8],
+    challenge: &[u8],
+    authenticate: &[u8],
+) -> Result<Authenticated, Error> {
+    let (_, challenge_message) = parse_challenge(challenge).map_err(|_| Error::Malformed)?;
+    let (_, message) = parse_authenticate(authenticate).map_err(|_| Error::Malformed)?;
+    if message.user_name.is_empty() && message.nt_challenge_response.is_empty() {
+        return Err(Error::Anonymous);
+    }
+    if message.nt_challenge_response.len() <= NTLM_RESPONSE_SIZE {
+        return Err(Error::UnsupportedResponse);
+    }
+    let (_, response) =
+        parse_ntlmv2_response(message.nt_challenge_response).map_err(|_| Error::Malformed)?;
+
+    let nt_hash = provider
+        .nt_hash(&message.user_name, &message.domain_name)
+        .ok_or(Error::UnknownUser)?;
+    let response_key = new_hmac(&nt_hash)
+        .chain_update(uppercase_utf16le(&message.user_name))
+        .chain_update(string_to_utf16le(&message.domain_name))
+        .finalize()
+        .into_bytes();
+    let nt_proof_str = new_hmac(&response_key)
+        .chain_update(challenge_message.server_challenge)
+        .chain_update(response.client_blob)
+        .finalize()
+        .into_bytes();
+    if !constant_time_eq(&nt_proof_str, &response.nt_proof_str) {
+        return Err(Error::InvalidResponse);
+    }
+
+    // NTLMv2 uses the session base key as key exchange key
+    let session_base_key: [u8; KEY_SIZE] = new_hmac(&response_key)
+        .chain_update(nt_proof_str)
+        .finalize()
+        .into_bytes()
+        .into();
+    let session_key = if message.flags.contains(NegotiateFlags::KEY_EXCH) {
+        if message.encrypted_random_session_key.len() != KEY_SIZE {
+            return Err(Error::Malformed);
+        }
+        let mut session_key = to_array(message.encrypted_random_session_key);
+        Rc4::<U16>::new(&session_base_key.into()).apply_keystream(&mut session_key);
+        session_key
+    } else {
+        session_base_key
+    };
+
+    let mic_present = response
+        .av_pairs
+        .iter()
+        .any(|pair| matches!(pair, AvPair::Flags(flags) if flags.contains(AvFlags::MIC_PRESENT)));
+    if mic_present {
+        let expected = mic(&session_key, negotiate, challenge, authenticate);
+        match message.mic {
+            Some(actual) if constant_time_eq(&expected, &actual) => (),
+            _ => return Err(Error::InvalidMic),
+        }
+    }
+
+    Ok(Authenticated {
+        user_name: message.user_name,
+        domain_name: message.domain_name,
+        session_base_key,
+        session_key,
+    })
+}
+
+/// Computes the MIC over the messages of an exchange with the MIC field of
+/// `authenticate` taken as zero.
+pub fn mic(
+    session_key: &[u8; KEY_SIZE],
+    negotiate: &[u8],
+    challenge: &[u8],
+    authenticate: &[u8],
+) -> [u8; MIC_SIZE] {
+    let mut authenticate = authenticate.to_vec();
+    if let Some(mic) = authenticate.get_mut(AUTHENTICATE_VERSION_END..AUTHENTICATE_MIC_END) {
+        mic.fill(0);
+    }
+    new_hmac(session_key)
+        .chain_update(negotiate)
+        .chain_update(challenge)
+        .chain_update(authenticate)
+        .finalize()
+        .into_bytes()
+        .into()
+}
+
+fn new_hmac(key: &[u8]) -> HmacMd5 {
+    <HmacMd5 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size")
+}
@@ -66,7 +66,7 @@ pub enum Facility {
 }
 
 #[repr(u32)]
-#[derive(FromPrimitive, Clone, Copy)]
+#[derive(FromPrimitive, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 #[cfg_attr(test, derive(EnumIter))]
 pub enum NTStatus {
@@ -0,0 +1,240 @@
+//! Computing and checking message signatures.
+//!
+//! All functions operate on serialized messages including the transport header
+//! as returned by `serialize` or `transport::Framer`. Every message of a compound
+//! chain is signed individually.
+
+use aes::Aes128;
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use cmac::{Cmac, Mac};
+use hmac::Hmac;
+use sha2::Sha256;
+use std::convert::TryInto;
+use std::fmt;
+use std::ops::Range;
+
+use crate::command::negotiate::SigningAlgorithm;
+use crate::command::oplock_break::NOTIFICATION_MESSAGE_ID;
+use crate::header::{self, Command, Flags, SIG_SIZE};
+use crate::ntstatus::NTStatus;
+use crate::{constant_time_eq, Dialect};
+
+pub const KEY_SIZE: usize = 16;
+
+const TRANSPORT_HEADER_SIZE: usize = 4;
+const PROTOCOL_ID: &[u8] = b"\xfeSMB";
+const STATUS_OFFSET: usize = 8;
+const COMMAND_OFFSET: usize = 12;
+const FLAGS_OFFSET: usize = 16;
+const NEXT_COMMAND_OFFSET: usize = 20;
+const MESSAGE_ID_OFFSET: usize = 24;
+const SIGNATURE_OFFSET: usize = 48;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum Algorithm {
+    HmacSha256,
+    AesCmac,
+    AesGmac,
+}
+
+impl Algorithm {
+    /// Selects the algorithm used by a connection with `dialect`.
+    ///
+    /// `negotiated` is the algorithm the server picked in the signing capabilities
+    /// context of an SMB 3.1.1 NEGOTIATE response. It is ignored for older dialects.
+    pub fn select(dialect: Dialect, negotiated: Option<SigningAlgorithm>) -> Self {
+        match (dialect, negotiated) {
+            (Dialect::Smb2_0_2 | Dialect::Smb2_1_0 | Dialect::Smb2Wildcard, _) => {
+                Algorithm::HmacSha256
+            }
+            (Dialect::Smb3_1_1, Some(SigningAlgorithm::HmacSha256)) => Algorithm::HmacSha256,
+            (Dialect::Smb3_1_1, Some(SigningAlgorithm::AesGmac)) => Algorithm::AesGmac,
+            _ => Algorithm::AesCmac,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The data is not a complete session message of SMB2 messages.
+    Malformed,
+    /// The `SIGNED` flag of the message is not set.
+    NotSigned { message_id: u64 },
+    /// The signature of the message does not match its content.
+    InvalidSignature { message_id: u64 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Malformed => write!(f, "Malformed SMB2 message"),
+            Error::NotSigned { message_id } => write!(f, "Message {message_id} is not signed"),
+            Error::InvalidSignature { message_id } => {
+                write!(f, "Message {message_id} has an invalid signature")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct Signer {
+    key: [u8; KEY_SIZE],
+    algorithm: Algorithm,
+}
+
+impl Signer {
+    /// `key` is the session key for SMB 2.x and the derived signing key for SMB 3.x.
+    pub fn new(key: [u8; KEY_SIZE], algorithm: Algorithm) -> Self {
+        Self { key, algorithm }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Sets the `SIGNED` flag and writes the signature of every message in `data`.
+    pub fn sign(&self, data: &mut [u8]) -> Result<(), Error> {
+        for range in messages(data)? {
+            let message = &mut data[range];
+            let flags = read_u32(message, FLAGS_OFFSET) | Flags::SIGNED.bits();
+            message[FLAGS_OFFSET..FLAGS_OFFSET + 4].copy_from_slice(&flags.to_le_bytes());
+            let signature = self.compute(message);
+            message[SIGNATURE_OFFSET..SIGNATURE_OFFSET + SIG_SIZE].copy_from_slice(&signature);
+        }
+        Ok(())
+    }
+
+    /// Checks that every message in `data` is signed with a valid signature.
+    ///
+    /// Oplock and lease break notifications and interim responses are not signed
+    /// by the server and are skipped.
+    pub fn verify(&self, data: &[u8]) -> Result<(), Error> {
+        for range in messages(data)? {
+            let message = &data[range];
+            let message_id = read_u64(message, MESSAGE_ID_OFFSET);
+            if is_break_notification(message) || is_interim_response(message) {
+                continue;
+            }
+            if read_u32(message, FLAGS_OFFSET) & Flags::SIGNED.bits() == 0 {
+                return Err(Error::NotSigned { message_id });
+            }
+            let expected = self.compute(message);
+            let actual = &message[SIGNATURE_OFFSET..SIGNATURE_OFFSET + SIG_SIZE];
+            if !constant_time_eq(&expected, actual) {
+                return Err(Error::InvalidSignature { message_id });
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the signature of `message` as if its signature field was zero.
+    fn compute(&self, message: &[u8]) -> [u8; SIG_SIZE] {
+        let head = &message[..SIGNATURE_OFFSET];
+        let tail = &message[SIGNATURE_OFFSET + SIG_SIZE..];
+        let mut signature = [0; SIG_SIZE];
+        match self.algorithm {
+            Algorithm::HmacSha256 => {
+                let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+                    .expect("HMAC accepts keys of any size");
+                mac.update(head);
+                mac.update(&[0; SIG_SIZE]);
+                mac.update(tail);
+                signature.copy_from_slice(&mac.finalize().into_bytes()[..SIG_SIZE]);
+            }
+            Algorithm::AesCmac => {
+                let mut mac = <Cmac<Aes128> as Mac>::new(&self.key.into());
+                mac.update(head);
+                mac.update(&[0; SIG_SIZE]);
+                mac.update(tail);
+                signature.copy_from_slice(&mac.finalize().into_bytes());
+            }
+            Algorithm::AesGmac => {
+                let mut aad = message.to_vec();
+                aad[SIGNATURE_OFFSET..SIGNATURE_OFFSET + SIG_SIZE].copy_from_slice(&[0; SIG_SIZE]);
+                let tag = Aes128Gcm::new(&self.key.into())
+                    .encrypt_in_place_detached(
+                        Nonce::from_slice(&gmac_nonce(message)),
+                        &aad,
+                        &mut [],
+                    )
+                    .expect("Empty plaintext cannot exceed the GCM limits");
+                signature.copy_from_slice(&tag);
+            }
+        }
+        signature
+    }
+}
+
+/// The nonce is the message id followed by a role bit and a bit that marks CANCEL requests.
+fn gmac_nonce(message: &[u8]) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&message[MESSAGE_ID_OFFSET..MESSAGE_ID_OFFSET + 8]);
+    let is_response = read_u32(message, FLAGS_OFFSET) & Flags::SERVER_TO_REDIR.bits() != 0;
+    let command = read_u16(message, COMMAND_OFFSET);
+    if is_response {
+        nonce[8] |= 0x01;
+    } else if command == u16::from(Command::Cancel as u8) {
+        nonce[8] |= 0x02;
+    }
+    nonce
+}
+
+/// An unsolicited `OPLOCK_BREAK` sent by the server with the reserved message id.
+fn is_break_notification(message: &[u8]) -> bool {
+    read_u32(message, FLAGS_OFFSET) & Flags::SERVER_TO_REDIR.bits() != 0
+        && read_u16(message, COMMAND_OFFSET) == u16::from(Command::OplockBreak as u8)
+        && read_u64(message, MESSAGE_ID_OFFSET) == NOTIFICATION_MESSAGE_ID
+}
+
+/// An async response with `STATUS_PENDING` that announces the final response.
+fn is_interim_response(message: &[u8]) -> bool {
+    let flags = Flags::SERVER_TO_REDIR | Flags::ASYNC_COMMAND;
+    read_u32(message, FLAGS_OFFSET) & flags.bits() == flags.bits()
+        && read_u32(message, STATUS_OFFSET) == NTStatus::StatusPending as u32
+}
+
+/// Splits a session message into the ranges of its compounded SMB2 messages.
+fn messages(data: &[u8]) -> Result<Vec<Range<usize>>, Error> {
+    if data.len() < TRANSPORT_HEADER_SIZE || data[0] != 0 {
+        return Err(Error::Malformed);
+    }
+    let length = u32::from_be_bytes([0, data[1], data[2], data[3]]) as usize;
+    if length != data.len() - TRANSPORT_HEADER_SIZE {
+        return Err(Error::Malformed);
+    }
+    let header_size = usize::from(header::STRUCTURE_SIZE);
+    let mut ranges = Vec::new();
+    let mut start = TRANSPORT_HEADER_SIZE;
+    while start < data.len() {
+        let message = &data[start..];
+        if message.len() < header_size || &message[..PROTOCOL_ID.len()] != PROTOCOL_ID {
+            return Err(Error::Malformed);
+        }
+        let end = match read_u32(message, NEXT_COMMAND_OFFSET) as usize {
+            0 => data.len(),
+            next if next >= header_size && next < message.len() => start + next,
+            _ => return Err(Error::Malformed),
+        };
+        ranges.push(start..end);
+        start = end;
+    }
+    if ranges.is_empty() {
+        return Err(Error::Malformed);
+    }
+    Ok(ranges)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
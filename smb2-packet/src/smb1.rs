@@ -1,8 +1,17 @@
+use crate::command::{negotiate, ResponseBody};
+use crate::header::{
+    Flags as Smb2Flags, Response as Smb2Header, Signature as Smb2Signature, SyncType,
+};
+use crate::ntstatus::NTStatus;
+use crate::{Dialect, Response};
 use bitflags::bitflags;
 use nom::*;
 use std::ops::Deref;
 
 pub const SIG_SIZE: usize = 8;
+const NEGOTIATE: u8 = 0x72;
+/// `DialectIndex` of a NEGOTIATE response that selects none of the offered dialects.
+const NO_DIALECT: u16 = 0xFFFF;
 
 bitflags! {
     pub struct Flags: u8 {
@@ -58,6 +67,17 @@ pub enum DialectLevel {
     Smb2Plus,
 }
 
+impl DialectLevel {
+    /// The dialect of the SMB2 NEGOTIATE response that upgrades the connection.
+    pub fn dialect(&self) -> Option<Dialect> {
+        match self {
+            DialectLevel::NotSupported => None,
+            DialectLevel::Smb2 => Some(Dialect::Smb2_0_2),
+            DialectLevel::Smb2Plus => Some(Dialect::Smb2Wildcard),
+        }
+    }
+}
+
 impl<'a> From<&'a [u8]> for DialectLevel {
     fn from(bytes: &'a [u8]) -> Self {
         match bytes {
@@ -86,6 +106,84 @@ pub struct NegotiateRequest {
     pub level: DialectLevel,
 }
 
+/// The response to a NEGOTIATE request that offers no SMB2 dialect.
+///
+/// It selects none of the offered dialects so the client gives up on the connection.
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct NegotiateErrorResponse {
+    pub header: Header,
+}
+
+impl NegotiateRequest {
+    /// Builds the SMB2 NEGOTIATE response that answers this request.
+    ///
+    /// The dialect of `body` is replaced by the one selected from the offered dialects
+    /// and its negotiate contexts are dropped.
+    /// Returns `None` for `DialectLevel::NotSupported` where `error_response` must be
+    /// sent instead. The response has to be serialized with `Dialect::Smb2_0_2` since
+    /// no dialect has been negotiated yet.
+    pub fn upgrade_response<'a>(&self, mut body: negotiate::Response<'a>) -> Option<Response<'a>> {
+        body.dialect = self.level.dialect()?;
+        body.negotiate_contexts.clear();
+        Some(Response {
+            header: Smb2Header {
+                credit_charge: None,
+                credit_response: 1,
+                status: NTStatus::StatusSuccess,
+                flags: Smb2Flags::SERVER_TO_REDIR,
+                message_id: 0,
+                process_id: Some(self.header.pid),
+                sync_type: SyncType::Sync { tree_id: 0 },
+                session_id: 0,
+                signature: Smb2Signature::empty(),
+            },
+            body: ResponseBody::Negotiate(body),
+        })
+    }
+
+    pub fn error_response(&self) -> NegotiateErrorResponse {
+        NegotiateErrorResponse {
+            header: Header {
+                status: 0,
+                flags: self.header.flags | Flags::REPLY,
+                flags2: self.header.flags2,
+                tid: self.header.tid,
+                pid: self.header.pid,
+                uid: self.header.uid,
+                mid: self.header.mid,
+                signature: Signature::empty(),
+            },
+        }
+    }
+}
+
+impl Header {
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_to(&self, out: &mut Vec<u8>, command: u8) {
+        out.extend_from_slice(b"\xffSMB");
+        out.push(command);
+        out.extend_from_slice(&self.status.to_le_bytes());
+        out.push(self.flags.bits());
+        out.extend_from_slice(&self.flags2.bits().to_le_bytes());
+        out.extend_from_slice(&((self.pid >> 16) as u16).to_le_bytes());
+        out.extend_from_slice(&self.signature.0);
+        out.extend_from_slice(&[0; 2]); /* reserved */
+        out.extend_from_slice(&self.tid.to_le_bytes());
+        out.extend_from_slice(&(self.pid as u16).to_le_bytes());
+        out.extend_from_slice(&self.uid.to_le_bytes());
+        out.extend_from_slice(&self.mid.to_le_bytes());
+    }
+}
+
+impl NegotiateErrorResponse {
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        self.header.write_to(out, NEGOTIATE);
+        out.push(1); /* word count */
+        out.extend_from_slice(&NO_DIALECT.to_le_bytes());
+        out.extend_from_slice(&[0; 2]); /* byte count */
+    }
+}
+
 fn copy_sig(input: &[u8]) -> Signature {
     let mut ret = [0; SIG_SIZE];
     ret.copy_from_slice(input);
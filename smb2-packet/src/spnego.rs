@@ -0,0 +1,234 @@
+//! The SPNEGO tokens of RFC 4178 which wrap the NTLMSSP messages in the
+//! `SESSION_SETUP` security buffer of Windows clients.
+//!
+//! Only the fields needed to get at the mechanism token are supported. Fields of
+//! `NegTokenInit` behind the mechanism token, like the `negHints` of MS-SPNG, are
+//! skipped when parsing and never written.
+
+use nom::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+/// The DER encoded object identifier of SPNEGO, 1.3.6.1.5.5.2.
+pub const SPNEGO_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+/// The DER encoded object identifier of NTLMSSP, 1.3.6.1.4.1.311.2.2.10.
+pub const NTLMSSP_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a];
+
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const OCTET_STRING: u8 = 0x04;
+const ENUMERATED: u8 = 0x0a;
+const SEQUENCE: u8 = 0x30;
+/// `[APPLICATION 0]` of the GSS-API `InitialContextToken`.
+const APPLICATION_0: u8 = 0x60;
+const CONTEXT_0: u8 = 0xa0;
+const CONTEXT_1: u8 = 0xa1;
+const CONTEXT_2: u8 = 0xa2;
+const CONTEXT_3: u8 = 0xa3;
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq)]
+pub enum Token<'a> {
+    Init(NegTokenInit<'a>),
+    Resp(NegTokenResp<'a>),
+}
+
+/// The first token of an exchange, wrapped in a GSS-API `InitialContextToken`.
+///
+/// The server sends one in the NEGOTIATE response to announce its mechanisms, the
+/// client in its first `SESSION_SETUP` request along with the first token of
+/// the mechanism it picked.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq)]
+pub struct NegTokenInit<'a> {
+    /// DER encoded object identifiers, most preferred first.
+    pub mech_types: Vec<&'a [u8]>,
+    pub mech_token: Option<&'a [u8]>,
+}
+
+/// Any token of an exchange after the first one.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq)]
+pub struct NegTokenResp<'a> {
+    pub neg_state: Option<NegState>,
+    /// DER encoded object identifier, only in the first token of the server.
+    pub supported_mech: Option<&'a [u8]>,
+    pub response_token: Option<&'a [u8]>,
+    pub mech_list_mic: Option<&'a [u8]>,
+}
+
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub enum NegState {
+    AcceptCompleted = 0,
+    AcceptIncomplete = 1,
+    Reject = 2,
+    RequestMic = 3,
+}
+
+impl NegTokenInit<'_> {
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let mut mech_types = Vec::new();
+        for mech in &self.mech_types {
+            write_element(&mut mech_types, OBJECT_IDENTIFIER, mech);
+        }
+        let mut fields = Vec::new();
+        write_nested(&mut fields, CONTEXT_0, SEQUENCE, &mech_types);
+        if let Some(token) = self.mech_token {
+            write_nested(&mut fields, CONTEXT_2, OCTET_STRING, token);
+        }
+        let mut token = Vec::new();
+        write_element(&mut token, OBJECT_IDENTIFIER, SPNEGO_OID);
+        write_nested(&mut token, CONTEXT_0, SEQUENCE, &fields);
+        write_element(out, APPLICATION_0, &token);
+    }
+}
+
+impl NegTokenResp<'_> {
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let mut fields = Vec::new();
+        if let Some(state) = self.neg_state {
+            write_nested(&mut fields, CONTEXT_0, ENUMERATED, &[state as u8]);
+        }
+        if let Some(mech) = self.supported_mech {
+            write_nested(&mut fields, CONTEXT_1, OBJECT_IDENTIFIER, mech);
+        }
+        if let Some(token) = self.response_token {
+            write_nested(&mut fields, CONTEXT_2, OCTET_STRING, token);
+        }
+        if let Some(mic) = self.mech_list_mic {
+            write_nested(&mut fields, CONTEXT_3, OCTET_STRING, mic);
+        }
+        write_nested(out, CONTEXT_1, SEQUENCE, &fields);
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_length(out: &mut Vec<u8>, length: usize) {
+    if length < 0x80 {
+        out.push(length as u8);
+    } else {
+        let bytes = length.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+fn write_element(out: &mut Vec<u8>, tag: u8, contents: &[u8]) {
+    out.push(tag);
+    write_length(out, contents.len());
+    out.extend_from_slice(contents);
+}
+
+/// Writes `contents` as the `inner` element of an explicitly tagged `outer` element.
+fn write_nested(out: &mut Vec<u8>, outer: u8, inner: u8, contents: &[u8]) {
+    let mut element = Vec::new();
+    write_element(&mut element, inner, contents);
+    write_element(out, outer, &element);
+}
+
+/// Parses a definite length of at most four bytes.
+fn parse_length(input: &[u8]) -> IResult<&[u8], usize> {
+    let (rest, first) = be_u8(input)?;
+    if first < 0x80 {
+        return Ok((rest, usize::from(first)));
+    }
+    let count = usize::from(first & 0x7f);
+    if count == 0 || count > 4 {
+        return Err(Err::Error(error_position!(input, ErrorKind::Custom(0))));
+    }
+    let (rest, bytes) = take!(rest, count)?;
+    let length = bytes
+        .iter()
+        .fold(0, |length, &byte| length << 8 | usize::from(byte));
+    Ok((rest, length))
+}
+
+/// Parses an element with `tag` and returns its contents.
+#[rustfmt::skip]
+fn parse_element(input: &[u8], tag: u8) -> IResult<&[u8], &[u8]> {
+    do_parse!(input,
+        verify!(be_u8, |x| x == tag) >>
+        length: parse_length >>
+        contents: take!(length) >>
+        (contents)
+    )
+}
+
+/// Parses an element with `tag` and applies `parser` to its contents which must
+/// consume all of them.
+fn parse_nested<'a, T, F>(input: &'a [u8], tag: u8, parser: F) -> IResult<&'a [u8], T>
+where
+    F: Fn(&'a [u8]) -> IResult<&'a [u8], T>,
+{
+    let (rest, contents) = parse_element(input, tag)?;
+    match parser(contents) {
+        Ok(([], value)) => Ok((rest, value)),
+        _ => Err(Err::Error(error_position!(input, ErrorKind::Custom(0)))),
+    }
+}
+
+/// Parses the explicitly tagged `[tag] OCTET STRING` of a token.
+fn parse_octet_string(input: &[u8], tag: u8) -> IResult<&[u8], &[u8]> {
+    parse_nested(input, tag, |data| parse_element(data, OCTET_STRING))
+}
+
+fn parse_mech_types(input: &[u8]) -> IResult<&[u8], Vec<&[u8]>> {
+    many1!(input, complete!(apply!(parse_element, OBJECT_IDENTIFIER)))
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_init_fields(input: &[u8]) -> IResult<&[u8], NegTokenInit> {
+    do_parse!(input,
+        mech_types: call!(parse_nested, CONTEXT_0, |data| {
+            parse_nested(data, SEQUENCE, parse_mech_types)
+        }) >>
+        opt!(complete!(apply!(parse_element, CONTEXT_1))) >> /* reqFlags */
+        mech_token: opt!(complete!(apply!(parse_octet_string, CONTEXT_2))) >>
+        rest >>
+        (NegTokenInit { mech_types, mech_token })
+    )
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_resp_fields(input: &[u8]) -> IResult<&[u8], NegTokenResp> {
+    do_parse!(input,
+        neg_state: opt!(complete!(map_opt!(
+            call!(parse_nested, CONTEXT_0, |data| parse_nested(data, ENUMERATED, be_u8)),
+            NegState::from_u8
+        ))) >>
+        supported_mech: opt!(complete!(call!(parse_nested, CONTEXT_1, |data| {
+            parse_element(data, OBJECT_IDENTIFIER)
+        }))) >>
+        response_token: opt!(complete!(apply!(parse_octet_string, CONTEXT_2))) >>
+        mech_list_mic: opt!(complete!(apply!(parse_octet_string, CONTEXT_3))) >>
+        (NegTokenResp { neg_state, supported_mech, response_token, mech_list_mic })
+    )
+}
+
+#[rustfmt::skip]
+pub fn parse_neg_token_init(input: &[u8]) -> IResult<&[u8], NegTokenInit> {
+    parse_nested(input, APPLICATION_0, |data| do_parse!(data,
+        verify!(apply!(parse_element, OBJECT_IDENTIFIER), |oid| oid == SPNEGO_OID) >>
+        init: call!(parse_nested, CONTEXT_0, |data| {
+            parse_nested(data, SEQUENCE, parse_init_fields)
+        }) >>
+        (init)
+    ))
+}
+
+pub fn parse_neg_token_resp(input: &[u8]) -> IResult<&[u8], NegTokenResp> {
+    parse_nested(input, CONTEXT_1, |data| {
+        parse_nested(data, SEQUENCE, parse_resp_fields)
+    })
+}
+
+/// Parses either token, e.g. the security buffer of a `SESSION_SETUP` request.
+pub fn parse(input: &[u8]) -> IResult<&[u8], Token> {
+    alt!(
+        input,
+        map!(parse_neg_token_init, Token::Init) | map!(parse_neg_token_resp, Token::Resp)
+    )
+}
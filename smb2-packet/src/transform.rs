@@ -0,0 +1,234 @@
+//! Encrypted messages wrapped in an `SMB2_TRANSFORM_HEADER`.
+
+use aes::{Aes128, Aes256};
+use aes_gcm::aead::consts::{U11, U16};
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use bitflags::bitflags;
+use ccm::Ccm;
+use nom::*;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::command::negotiate::Cipher;
+use crate::header::SIG_SIZE;
+use crate::transport;
+
+pub const PROTOCOL_ID: &[u8] = b"\xfdSMB";
+pub const HEADER_SIZE: usize = 52;
+pub const NONCE_SIZE: usize = 16;
+
+/// Everything after the signature is authenticated but not encrypted.
+const AAD_OFFSET: usize = 20;
+
+type Aes128Ccm = Ccm<Aes128, U16, U11>;
+type Aes256Ccm = Ccm<Aes256, U16, U11>;
+
+bitflags! {
+    /// Called `EncryptionAlgorithm` in SMB 3.0 and 3.0.2 with the same value for AES-128-CCM.
+    pub struct Flags: u16 {
+        const ENCRYPTED = 0x0001;
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq)]
+pub struct Header {
+    /// The authentication tag of the encrypted message.
+    pub signature: [u8; SIG_SIZE],
+    /// Only the first `nonce_size` bytes are used, the rest is zero.
+    pub nonce: [u8; NONCE_SIZE],
+    pub original_message_size: u32,
+    pub flags: Flags,
+    pub session_id: u64,
+}
+
+impl Header {
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(PROTOCOL_ID);
+        out.extend_from_slice(&self.signature);
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.original_message_size.to_le_bytes());
+        out.extend_from_slice(&[0; 2]);
+        out.extend_from_slice(&self.flags.bits().to_le_bytes());
+        out.extend_from_slice(&self.session_id.to_le_bytes());
+    }
+}
+
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct Message<'a> {
+    pub header: Header,
+    /// The encrypted compound chain.
+    pub data: &'a [u8],
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidKeySize,
+    InvalidNonceSize,
+    MessageTooLarge,
+    /// The header does not describe a message encrypted by one of the known ciphers.
+    Malformed,
+    /// The message was altered or encrypted with a different key.
+    DecryptionFailed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidKeySize => write!(f, "Key size does not match the cipher"),
+            Error::InvalidNonceSize => write!(f, "Nonce size does not match the cipher"),
+            Error::MessageTooLarge => write!(f, "Message does not fit into a session message"),
+            Error::Malformed => write!(f, "Malformed transform header"),
+            Error::DecryptionFailed => write!(f, "Message could not be decrypted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Number of nonce bytes used by `cipher`.
+pub fn nonce_size(cipher: Cipher) -> usize {
+    match cipher {
+        Cipher::Aes128Ccm | Cipher::Aes256Ccm => 11,
+        Cipher::Aes128Gcm | Cipher::Aes256Gcm => 12,
+    }
+}
+
+#[rustfmt::skip]
+#[allow(clippy::cyclomatic_complexity)]
+fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
+    do_parse!(input,
+        tag!(PROTOCOL_ID) >>
+        signature: take!(SIG_SIZE) >>
+        nonce: take!(NONCE_SIZE) >>
+        original_message_size: le_u32 >>
+        take!(2) >>
+        flags: map_opt!(le_u16, Flags::from_bits) >>
+        session_id: le_u64 >>
+        ({
+            let mut header = Header {
+                signature: [0; SIG_SIZE],
+                nonce: [0; NONCE_SIZE],
+                original_message_size,
+                flags,
+                session_id,
+            };
+            header.signature.copy_from_slice(signature);
+            header.nonce.copy_from_slice(nonce);
+            header
+        })
+    )
+}
+
+/// Parses a session message that carries an encrypted message.
+pub fn parse(input: &[u8]) -> IResult<&[u8], Message> {
+    match transport::get_payload(input) {
+        Ok((rem, out)) => complete!(
+            out,
+            do_parse!(header: parse_header >> data: rest >> (Message { header, data }))
+        )
+        .map(|(_, message)| (rem, message)),
+        Err(x) => Err(x),
+    }
+}
+
+/// Encrypts the compound chain `message` for the session `session_id`.
+///
+/// Returns the session message including the transport header. `nonce` must be
+/// `nonce_size` bytes long and never be reused with the same key.
+pub fn encrypt(
+    key: &[u8],
+    cipher: Cipher,
+    nonce: &[u8],
+    session_id: u64,
+    message: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if nonce.len() != nonce_size(cipher) {
+        return Err(Error::InvalidNonceSize);
+    }
+    if HEADER_SIZE + message.len() > transport::MAX_PAYLOAD_SIZE {
+        return Err(Error::MessageTooLarge);
+    }
+    let mut header = Header {
+        signature: [0; SIG_SIZE],
+        nonce: [0; NONCE_SIZE],
+        original_message_size: u32::try_from(message.len()).map_err(|_| Error::MessageTooLarge)?,
+        flags: Flags::ENCRYPTED,
+        session_id,
+    };
+    header.nonce[..nonce.len()].copy_from_slice(nonce);
+    let mut aad = Vec::with_capacity(HEADER_SIZE);
+    header.write_to(&mut aad);
+
+    let mut data = message.to_vec();
+    let tag = match cipher {
+        Cipher::Aes128Ccm => seal::<Aes128Ccm>(key, nonce, &aad[AAD_OFFSET..], &mut data),
+        Cipher::Aes128Gcm => seal::<Aes128Gcm>(key, nonce, &aad[AAD_OFFSET..], &mut data),
+        Cipher::Aes256Ccm => seal::<Aes256Ccm>(key, nonce, &aad[AAD_OFFSET..], &mut data),
+        Cipher::Aes256Gcm => seal::<Aes256Gcm>(key, nonce, &aad[AAD_OFFSET..], &mut data),
+    }?;
+    header.signature.copy_from_slice(&tag);
+
+    let mut out = Vec::with_capacity(4 + HEADER_SIZE + data.len());
+    transport::write_payload(&mut out, |out| {
+        header.write_to(out);
+        out.extend_from_slice(&data);
+    });
+    Ok(out)
+}
+
+/// Decrypts `message` and returns the compound chain it carries.
+///
+/// The result can be passed to `Packet::parse` directly.
+pub fn decrypt(key: &[u8], cipher: Cipher, message: &Message) -> Result<Vec<u8>, Error> {
+    let header = &message.header;
+    let original_size =
+        usize::try_from(header.original_message_size).map_err(|_| Error::Malformed)?;
+    if !header.flags.contains(Flags::ENCRYPTED) || original_size != message.data.len() {
+        return Err(Error::Malformed);
+    }
+    let mut aad = Vec::with_capacity(HEADER_SIZE);
+    header.write_to(&mut aad);
+    let nonce = &header.nonce[..nonce_size(cipher)];
+    let aad = &aad[AAD_OFFSET..];
+    let tag = &header.signature;
+
+    let mut data = message.data.to_vec();
+    match cipher {
+        Cipher::Aes128Ccm => open::<Aes128Ccm>(key, nonce, aad, &mut data, tag),
+        Cipher::Aes128Gcm => open::<Aes128Gcm>(key, nonce, aad, &mut data, tag),
+        Cipher::Aes256Ccm => open::<Aes256Ccm>(key, nonce, aad, &mut data, tag),
+        Cipher::Aes256Gcm => open::<Aes256Gcm>(key, nonce, aad, &mut data, tag),
+    }?;
+    Ok(data)
+}
+
+fn seal<C>(key: &[u8], nonce: &[u8], aad: &[u8], data: &mut [u8]) -> Result<[u8; SIG_SIZE], Error>
+where
+    C: AeadInPlace + KeyInit,
+{
+    let tag = C::new_from_slice(key)
+        .map_err(|_| Error::InvalidKeySize)?
+        .encrypt_in_place_detached(GenericArray::from_slice(nonce), aad, data)
+        .map_err(|_| Error::MessageTooLarge)?;
+    let mut signature = [0; SIG_SIZE];
+    signature.copy_from_slice(&tag);
+    Ok(signature)
+}
+
+fn open<C>(key: &[u8], nonce: &[u8], aad: &[u8], data: &mut [u8], tag: &[u8]) -> Result<(), Error>
+where
+    C: AeadInPlace + KeyInit,
+{
+    C::new_from_slice(key)
+        .map_err(|_| Error::InvalidKeySize)?
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(nonce),
+            aad,
+            data,
+            GenericArray::from_slice(tag),
+        )
+        .map_err(|_| Error::DecryptionFailed)
+}
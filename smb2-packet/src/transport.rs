@@ -1,3 +1,375 @@
 use nom::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use std::fmt;
+use std::net::Ipv4Addr;
+
+pub(crate) const MAX_PAYLOAD_SIZE: usize = 0x00FF_FFFF;
 
 named!(pub get_payload, preceded!(tag!(b"\x00"), length_bytes!(be_u24)));
+
+/// Frames the data written by `write` as session message.
+///
+/// # Panics
+///
+/// If the written payload does not fit into the 24 bit length field.
+pub fn write_payload<F>(out: &mut Vec<u8>, write: F)
+where
+    F: FnOnce(&mut Vec<u8>),
+{
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    write(out);
+    let len = out.len() - start - 4;
+    assert!(
+        len <= MAX_PAYLOAD_SIZE,
+        "Payload exceeds the maximum session message size"
+    );
+    #[allow(clippy::cast_possible_truncation)]
+    out[start + 1..start + 4].copy_from_slice(&(len as u32).to_be_bytes()[1..]);
+}
+
+const HEADER_SIZE: usize = 4;
+const SESSION_MESSAGE: u8 = 0x00;
+const SESSION_REQUEST: u8 = 0x81;
+const POSITIVE_SESSION_RESPONSE: u8 = 0x82;
+const NEGATIVE_SESSION_RESPONSE: u8 = 0x83;
+const RETARGET_SESSION_RESPONSE: u8 = 0x84;
+const SESSION_KEEP_ALIVE: u8 = 0x85;
+const NAME_SIZE: usize = 16;
+const ENCODED_NAME_SIZE: u8 = 0x20;
+const MAX_LABEL_SIZE: usize = 63;
+
+/// A `NetBIOS` name in the form used by the session service on port 139.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq, Clone)]
+pub struct NetbiosName {
+    name: String,
+    suffix: u8,
+    scope: String,
+}
+
+impl NetbiosName {
+    /// `name` is padded with spaces to 15 bytes on the wire and `suffix` is the 16th
+    /// byte denoting the service, e.g. 0x20 for the file server service. `scope` are
+    /// the labels of the scope joined by dots, empty for the default scope.
+    ///
+    /// Returns `None` if `name` is longer than 15 bytes or a label of `scope` is
+    /// empty or longer than 63 bytes.
+    pub fn new(name: &str, suffix: u8, scope: &str) -> Option<Self> {
+        let valid_label = |label: &str| !label.is_empty() && label.len() <= MAX_LABEL_SIZE;
+        if name.len() >= NAME_SIZE || !(scope.is_empty() || scope.split('.').all(valid_label)) {
+            return None;
+        }
+        Some(Self {
+            name: name.into(),
+            suffix,
+            scope: scope.into(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn suffix(&self) -> u8 {
+        self.suffix
+    }
+
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+}
+
+#[repr(u8)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum SessionError {
+    NotListeningOnCalledName = 0x80,
+    NotListeningForCallingName = 0x81,
+    CalledNameNotPresent = 0x82,
+    InsufficientResources = 0x83,
+    Unspecified = 0x8F,
+}
+
+/// The RFC 1002 session service packets other than the session message.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq)]
+pub enum SessionPacket {
+    Request {
+        called: NetbiosName,
+        calling: NetbiosName,
+    },
+    PositiveResponse,
+    NegativeResponse(SessionError),
+    RetargetResponse {
+        address: Ipv4Addr,
+        port: u16,
+    },
+    KeepAlive,
+}
+
+/// Undoes the first level encoding that maps every nibble to the letters A to P.
+fn decode_name(encoded: &[u8]) -> Option<(String, u8)> {
+    let mut name = [0; NAME_SIZE];
+    for (byte, pair) in name.iter_mut().zip(encoded.chunks_exact(2)) {
+        let nibble = |c: u8| c.checked_sub(b'A').filter(|n| *n < 0x10);
+        *byte = nibble(pair[0])? << 4 | nibble(pair[1])?;
+    }
+    let (suffix, name) = name.split_last()?;
+    let name = std::str::from_utf8(name).ok()?.trim_end_matches(' ');
+    Some((name.to_owned(), *suffix))
+}
+
+#[rustfmt::skip]
+fn parse_netbios_name(input: &[u8]) -> IResult<&[u8], NetbiosName> {
+    do_parse!(input,
+        verify!(le_u8, |x| x == ENCODED_NAME_SIZE) >>
+        name: map_opt!(take!(ENCODED_NAME_SIZE), decode_name) >>
+        labels: many_till!(
+            map_res!(length_data!(le_u8), std::str::from_utf8),
+            tag!(b"\x00")
+        ) >>
+        name: expr_opt!(NetbiosName::new(&name.0, name.1, &labels.0.join("."))) >>
+        (name)
+    )
+}
+
+#[rustfmt::skip]
+fn parse_trailer(input: &[u8], packet_type: u8) -> IResult<&[u8], SessionPacket> {
+    match packet_type {
+        SESSION_REQUEST => do_parse!(input,
+            called: parse_netbios_name >>
+            calling: parse_netbios_name >>
+            (SessionPacket::Request { called, calling })
+        ),
+        POSITIVE_SESSION_RESPONSE => Ok((input, SessionPacket::PositiveResponse)),
+        NEGATIVE_SESSION_RESPONSE => map!(input,
+            map_opt!(le_u8, FromPrimitive::from_u8),
+            SessionPacket::NegativeResponse
+        ),
+        RETARGET_SESSION_RESPONSE => do_parse!(input,
+            address: be_u32 >>
+            port: be_u16 >>
+            (SessionPacket::RetargetResponse { address: address.into(), port })
+        ),
+        SESSION_KEEP_ALIVE => Ok((input, SessionPacket::KeepAlive)),
+        _ => Err(Err::Error(error_position!(input, ErrorKind::Switch))),
+    }
+}
+
+/// Parses a session service packet that is not a session message.
+#[rustfmt::skip]
+pub fn parse_session_packet(input: &[u8]) -> IResult<&[u8], SessionPacket> {
+    do_parse!(input,
+        packet_type: verify!(le_u8, |x| x != SESSION_MESSAGE) >>
+        packet: length_value!(
+            preceded!(take!(1), be_u16),
+            complete!(apply!(parse_trailer, packet_type))
+        ) >>
+        (packet)
+    )
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_netbios_name(out: &mut Vec<u8>, name: &NetbiosName) {
+    let mut bytes = [b' '; NAME_SIZE];
+    bytes[..name.name.len()].copy_from_slice(name.name.as_bytes());
+    bytes[NAME_SIZE - 1] = name.suffix;
+    out.push(ENCODED_NAME_SIZE);
+    for byte in &bytes {
+        out.push(b'A' + (byte >> 4));
+        out.push(b'A' + (byte & 0x0f));
+    }
+    for label in name.scope.split('.').filter(|l| !l.is_empty()) {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+impl SessionPacket {
+    fn packet_type(&self) -> u8 {
+        match self {
+            SessionPacket::Request { .. } => SESSION_REQUEST,
+            SessionPacket::PositiveResponse => POSITIVE_SESSION_RESPONSE,
+            SessionPacket::NegativeResponse(_) => NEGATIVE_SESSION_RESPONSE,
+            SessionPacket::RetargetResponse { .. } => RETARGET_SESSION_RESPONSE,
+            SessionPacket::KeepAlive => SESSION_KEEP_ALIVE,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.push(self.packet_type());
+        out.extend_from_slice(&[0; 3]); /* flags + length */
+        match self {
+            SessionPacket::Request { called, calling } => {
+                write_netbios_name(out, called);
+                write_netbios_name(out, calling);
+            }
+            SessionPacket::NegativeResponse(error) => out.push(*error as u8),
+            SessionPacket::RetargetResponse { address, port } => {
+                out.extend_from_slice(&address.octets());
+                out.extend_from_slice(&port.to_be_bytes());
+            }
+            SessionPacket::PositiveResponse | SessionPacket::KeepAlive => (),
+        }
+        let len = (out.len() - start - HEADER_SIZE) as u16;
+        out[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The length announced by the transport header exceeds the configured maximum.
+    MessageTooLarge {
+        length: usize,
+        max: usize,
+    },
+    UnknownMessageType(u8),
+    UnknownProtocol([u8; 4]),
+    InvalidSessionPacket(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MessageTooLarge { length, max } => write!(
+                f,
+                "Message of {length} bytes exceeds the maximum of {max} bytes"
+            ),
+            Error::UnknownMessageType(t) => write!(f, "Unknown message type {t:#04x}"),
+            Error::UnknownProtocol(p) => write!(f, "Unknown protocol id {p:02x?}"),
+            Error::InvalidSessionPacket(t) => write!(f, "Invalid session packet of type {t:#04x}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A complete session message including its transport header.
+///
+/// The transport header is kept so that the data can be passed to `parse` or
+/// `parse_smb1_nego_request` as is.
+#[cfg_attr(debug_assertions, derive(Debug))]
+#[derive(PartialEq, Eq)]
+pub enum Message {
+    Smb2(Vec<u8>),
+    Smb1(Vec<u8>),
+    /// A message wrapped in a transform header which is passed to `transform::parse`.
+    Encrypted(Vec<u8>),
+    /// A message wrapped in a compression transform header which is passed to `compression::parse`.
+    Compressed(Vec<u8>),
+    /// Session establishment on port 139. Keepalives are dropped by the framer.
+    Session(SessionPacket),
+}
+
+/// Reassembles session messages from a stream of arbitrarily sized chunks.
+pub struct Framer {
+    buffer: Vec<u8>,
+    max_message_size: usize,
+}
+
+impl Framer {
+    /// Creates a framer that rejects messages whose payload exceeds `max_message_size`.
+    ///
+    /// The maximum is capped at the largest length the 24 bit length field can express.
+    pub fn new(max_message_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_message_size: max_message_size.min(MAX_PAYLOAD_SIZE),
+        }
+    }
+
+    /// Appends received data.
+    ///
+    /// The transport headers are checked as soon as they are received so that an oversized
+    /// message is rejected before its payload is buffered. On error nothing of `data` is
+    /// kept and the stream must be considered broken.
+    pub fn push(&mut self, data: &[u8]) -> Result<(), Error> {
+        let start = self.buffer.len();
+        self.buffer.extend_from_slice(data);
+        let mut pos = 0;
+        while let Some(length) = self.payload_length(pos) {
+            let end = match length {
+                Ok(length) => pos + HEADER_SIZE + length,
+                Err(e) => {
+                    self.buffer.truncate(start);
+                    return Err(e);
+                }
+            };
+            if end > self.buffer.len() {
+                break;
+            }
+            pos = end;
+        }
+        Ok(())
+    }
+
+    /// Number of bytes that are at least missing to complete the next message.
+    ///
+    /// Returns zero if the next message is complete and can be processed by `next_message`.
+    pub fn needed(&self) -> usize {
+        match self.payload_length(0) {
+            Some(Ok(length)) => (HEADER_SIZE + length).saturating_sub(self.buffer.len()),
+            Some(Err(_)) => 0,
+            None => HEADER_SIZE - self.buffer.len(),
+        }
+    }
+
+    /// Takes the next complete message out of the buffer.
+    pub fn next_message(&mut self) -> Result<Option<Message>, Error> {
+        loop {
+            let length = match self.payload_length(0) {
+                Some(length) => HEADER_SIZE + length?,
+                None => return Ok(None),
+            };
+            if length > self.buffer.len() {
+                return Ok(None);
+            }
+            let message: Vec<u8> = self.buffer.drain(..length).collect();
+            if message[0] == SESSION_MESSAGE {
+                return Self::classify(message).map(Some);
+            }
+            match parse_session_packet(&message) {
+                Ok((_, SessionPacket::KeepAlive)) => {}
+                Ok((_, packet)) => return Ok(Some(Message::Session(packet))),
+                Err(_) => return Err(Error::InvalidSessionPacket(message[0])),
+            }
+        }
+    }
+
+    fn classify(message: Vec<u8>) -> Result<Message, Error> {
+        let length = message.len();
+        let mut protocol = [0; 4];
+        let available = (length - HEADER_SIZE).min(protocol.len());
+        protocol[..available].copy_from_slice(&message[HEADER_SIZE..HEADER_SIZE + available]);
+        match &protocol {
+            b"\xfeSMB" => Ok(Message::Smb2(message)),
+            b"\xffSMB" => Ok(Message::Smb1(message)),
+            b"\xfdSMB" => Ok(Message::Encrypted(message)),
+            b"\xfcSMB" => Ok(Message::Compressed(message)),
+            _ => Err(Error::UnknownProtocol(protocol)),
+        }
+    }
+
+    /// Length of the payload of the message whose transport header starts at `pos`.
+    fn payload_length(&self, pos: usize) -> Option<Result<usize, Error>> {
+        let header = self.buffer.get(pos..pos + HEADER_SIZE)?;
+        match header[0] {
+            SESSION_MESSAGE | SESSION_REQUEST..=SESSION_KEEP_ALIVE => (),
+            other => return Some(Err(Error::UnknownMessageType(other))),
+        }
+        let length =
+            usize::from(header[1]) << 16 | usize::from(header[2]) << 8 | usize::from(header[3]);
+        if length > self.max_message_size {
+            return Some(Err(Error::MessageTooLarge {
+                length,
+                max: self.max_message_size,
+            }));
+        }
+        Some(Ok(length))
+    }
+}
@@ -5,10 +5,15 @@
 #[allow(dead_code)]
 mod common;
 
-use crate::common::{parse_pcap_requests, parse_pcap_responses};
+use crate::common::{
+    parse_pcap_requests, parse_pcap_responses, request_header, roundtrip_messages, CANCEL_REQUESTS,
+    ECHO_REQUEST, ECHO_RESPONSE,
+};
+use smb2_packet::command::error::{Detail, ErrorData};
 use smb2_packet::command::{RequestBody, ResponseBody};
 use smb2_packet::header::{Command, Flags, Signature, SyncType};
-use smb2_packet::Dialect;
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{Dialect, FileAttributes, Request, Response};
 use std::ops::Deref;
 
 #[test]
@@ -41,6 +46,8 @@ fn all_responses_just_parse() {
 
 #[test]
 fn header1() {
+    use smb2_packet::command::query_info::{FileInfoClass, InfoClass};
+
     let mut buffer = Vec::new();
     let request = &parse_pcap_requests("header1", &mut buffer, Dialect::Smb3_1_1).unwrap()[0];
     let header = &request.header;
@@ -55,8 +62,15 @@ fn header1() {
     assert_eq!(header.signature, Signature::empty());
 
     match &request.body {
-        RequestBody::NotImplemented { command, .. } => assert_eq!(*command, Command::QueryInfo),
-        _ => panic!("Expected not implemented!"),
+        RequestBody::QueryInfo(body) => {
+            assert_eq!(
+                body.info_class,
+                InfoClass::File(FileInfoClass::FileAllInformation)
+            );
+            assert_eq!(body.output_buffer_length, 0x68);
+            assert!(body.input_buffer.is_empty());
+        }
+        _ => panic!("Expected query info!"),
     };
 }
 
@@ -80,8 +94,15 @@ fn header2() {
     assert_eq!(header.signature, Signature::empty());
 
     match &request.body {
-        ResponseBody::NotImplemented { command, .. } => assert_eq!(*command, Command::Read),
-        _ => panic!("Expected not implemented!"),
+        ResponseBody::Error(error) => {
+            assert_eq!(error.command, Command::Read);
+            assert_eq!(error.status, NTStatus::StatusEndOfFile);
+            match error.error_data {
+                ErrorData::Detail(Detail::Empty) => (),
+                _ => panic!("Expected empty error data!"),
+            }
+        }
+        _ => panic!("Expected error response!"),
     };
 }
 
@@ -175,9 +196,883 @@ fn negotiate_with_context_request() {
     } else {
         panic!("Second context is EncryptionCapabilities")
     };
-    if let Context::Unknown(x) = body.negotiate_contexts[2] {
-        assert_eq!(x, [0; 8]);
+    if let Context::Unknown { context_type, data } = body.negotiate_contexts[2] {
+        assert_eq!(context_type, 0x100);
+        assert_eq!(data, [0; 8]);
     } else {
         panic!("Third context is Unknown")
     };
 }
+
+#[test]
+fn close_response_flags() {
+    use crate::common::response_header;
+    use smb2_packet::{parse, serialize, Response};
+
+    let mut buffer = Vec::new();
+    let responses = parse_pcap_responses("all_responses", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let close = responses
+        .into_iter()
+        .find_map(|response| match response.body {
+            ResponseBody::Close(body) => Some(body),
+            _ => None,
+        })
+        .unwrap();
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 7),
+        body: ResponseBody::Close(close),
+    };
+    let mut serialized = serialize(&[response], Dialect::Smb3_1_1);
+    assert!(parse::<Response>(&serialized, Dialect::Smb3_1_1).is_ok());
+    // the flags are 16 bits wide but only the low byte is defined
+    serialized[4 + 64 + 2..4 + 64 + 4].copy_from_slice(&[0x01, 0x01]);
+    assert!(parse::<Response>(&serialized, Dialect::Smb3_1_1).is_err());
+}
+
+#[test]
+fn negotiate_response() {
+    use smb2_packet::command::negotiate::*;
+
+    let mut buffer = Vec::new();
+    let response =
+        &parse_pcap_responses("negotiate_response", &mut buffer, Dialect::Smb3_0_2).unwrap()[0];
+    let server_guid = *b"homebsd\0\0\0\0\0\0\0\0\0";
+    let body = match &response.body {
+        ResponseBody::Negotiate(msg) => msg,
+        _ => panic!("Expected negotiate response!"),
+    };
+
+    assert_eq!(response.header.status, NTStatus::StatusSuccess);
+    assert!(!body.signing_required);
+    assert_eq!(body.dialect, Dialect::Smb3_0_2);
+    assert_eq!(*body.server_guid, server_guid);
+    assert_eq!(
+        body.capabilities,
+        Capabilities::DFS
+            | Capabilities::LEASING
+            | Capabilities::LARGE_MTU
+            | Capabilities::ENCRYPTION
+    );
+    assert_eq!(body.max_transact_size, 0x0080_0000);
+    assert_eq!(body.max_read_size, 0x0080_0000);
+    assert_eq!(body.max_write_size, 0x0080_0000);
+    assert_eq!(body.security_buffer.map(<[u8]>::len), Some(0x4a));
+    assert_eq!(body.negotiate_contexts.len(), 0);
+}
+
+#[test]
+fn write_request() {
+    use smb2_packet::command::Channel;
+
+    let mut buffer = Vec::new();
+    let requests = parse_pcap_requests("all_requests", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let writes: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.body {
+            RequestBody::Write(body) => Some(body),
+            _ => None,
+        })
+        .collect();
+    let file_id = [
+        0xed, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x89, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff,
+        0xff,
+    ];
+
+    assert_eq!(writes.len(), 22);
+    let body = writes[0];
+    assert_eq!(*body.file_id, file_id);
+    assert_eq!(body.offset, 0);
+    assert_eq!(body.remaining_bytes, 0);
+    assert!(!body.write_through);
+    assert!(!body.write_unbuffered);
+    assert!(matches!(body.channel, Channel::None));
+    assert_eq!(body.data.len(), 0x200);
+    assert_eq!(body.data[..8], [0, 1, 2, 3, 4, 5, 6, 7]);
+}
+
+#[test]
+fn write_response() {
+    let mut buffer = Vec::new();
+    let responses = parse_pcap_responses("all_responses", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let writes: Vec<_> = responses
+        .iter()
+        .filter_map(|response| match &response.body {
+            ResponseBody::Write(body) => Some(body),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(writes.len(), 17);
+    assert_eq!(writes[0].count, 0x0c25);
+    assert_eq!(writes[0].remaining, 0);
+    assert!(writes[0].channel_info.is_empty());
+    assert_eq!(writes[1].count, 0x1000);
+}
+
+#[test]
+fn write_request_large_with_channel() {
+    use crate::common::request_header;
+    use smb2_packet::command::write::Request as WriteRequest;
+    use smb2_packet::command::Channel;
+    use smb2_packet::{parse, serialize, FileId, Request};
+
+    let data = vec![0x5a; 128 * 1024];
+    let descriptor = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+    let request = Request {
+        header: request_header(7),
+        body: RequestBody::Write(WriteRequest {
+            file_id: FileId::from([0xab; 16]),
+            offset: 0x0002_0000,
+            remaining_bytes: 0,
+            write_unbuffered: false,
+            write_through: true,
+            channel: Channel::RdmaV1(&descriptor),
+            data: &data,
+        }),
+    };
+    let buffer = serialize(&[request], Dialect::Smb3_1_1);
+    let (rem, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&requests, Dialect::Smb3_1_1), buffer);
+
+    let body = match &requests[0].body {
+        RequestBody::Write(body) => body,
+        _ => panic!("Expected write request!"),
+    };
+    assert!(body.write_through);
+    assert_eq!(body.data.len(), data.len());
+    match body.channel {
+        Channel::RdmaV1(channel) => assert_eq!(channel, descriptor),
+        _ => panic!("Expected RDMA channel!"),
+    }
+}
+
+#[test]
+fn ioctl_request() {
+    use smb2_packet::command::ioctl::*;
+
+    let mut buffer = Vec::new();
+    let requests = parse_pcap_requests("all_requests", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let ioctls: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.body {
+            RequestBody::Ioctl(body) => Some(body),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(ioctls.len(), 5);
+    assert!(ioctls.iter().all(|body| body.is_fsctl));
+    assert!(matches!(ioctls[0].input, Input::QueryNetworkInterfaceInfo));
+    assert_eq!(ioctls[0].max_output_response, 0x4000);
+    assert_eq!((ioctls[0].input_offset, ioctls[0].output_offset), (0, 0));
+    match &ioctls[1].input {
+        Input::DfsGetReferrals(referral) => {
+            assert_eq!(referral.max_referral_level, 3);
+            assert_eq!(referral.file_name, "\\127.0.0.1\\Lager");
+        }
+        _ => panic!("Expected dfs referral request!"),
+    }
+    assert_eq!(ioctls[1].max_output_response, 0x4000);
+    assert_eq!(ioctls[2].output_offset, 0x78);
+    match ioctls[3].input {
+        Input::Unknown { ctl_code, data } => {
+            assert_eq!(ctl_code, 0x0009_01af);
+            assert_eq!(data, [3, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        _ => panic!("Expected unknown ioctl!"),
+    }
+}
+
+#[test]
+fn ioctl_response() {
+    use smb2_packet::command::ioctl::*;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    let mut buffer = Vec::new();
+    let responses = parse_pcap_responses("all_responses", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let body = responses
+        .iter()
+        .find_map(|response| match &response.body {
+            ResponseBody::Ioctl(body) => Some(body),
+            _ => None,
+        })
+        .unwrap();
+    let interfaces = match &body.output {
+        Output::QueryNetworkInterfaceInfo(interfaces) => interfaces,
+        _ => panic!("Expected network interface info!"),
+    };
+
+    assert_eq!(interfaces.len(), 2);
+    assert_eq!(interfaces[0].if_index, 4);
+    assert_eq!(interfaces[0].capability, InterfaceCapability::empty());
+    assert_eq!(interfaces[0].link_speed, 10_000_000_000);
+    assert_eq!(
+        interfaces[0].address,
+        IpAddr::V4(Ipv4Addr::new(192, 168, 88, 1))
+    );
+    assert_eq!(interfaces[1].if_index, 2);
+    assert_eq!(interfaces[1].link_speed, 1_000_000_000);
+    assert_eq!(
+        interfaces[1].address,
+        IpAddr::V6(Ipv6Addr::new(
+            0x2003, 0x00ea, 0x6f0d, 0x9c00, 0x922b, 0x34ff, 0xfe5a, 0x3638
+        ))
+    );
+}
+
+#[test]
+fn ioctl_empty_network_interfaces() {
+    use crate::common::response_header;
+    use smb2_packet::command::ioctl::{Output, Response as IoctlResponse};
+    use smb2_packet::{parse, serialize, FileId, Response};
+
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 7),
+        body: ResponseBody::Ioctl(IoctlResponse {
+            file_id: FileId::from([0xff; 16]),
+            output: Output::QueryNetworkInterfaceInfo(Vec::new()),
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&responses, Dialect::Smb3_1_1), buffer);
+    match &responses[0].body {
+        ResponseBody::Ioctl(body) => {
+            assert!(matches!(&body.output, Output::QueryNetworkInterfaceInfo(i) if i.is_empty()));
+        }
+        _ => panic!("Expected ioctl response!"),
+    }
+}
+
+#[test]
+fn ioctl_validate_negotiate_unknown_capabilities_truncated() {
+    use crate::common::request_header;
+    use smb2_packet::command::ioctl::{
+        Input, Request as IoctlRequest, ValidateNegotiateInfoRequest,
+    };
+    use smb2_packet::command::negotiate::Capabilities;
+    use smb2_packet::{parse, serialize, ClientGuid, FileId, Request};
+
+    let request = Request {
+        header: request_header(7),
+        body: RequestBody::Ioctl(IoctlRequest {
+            file_id: FileId::from([0xff; 16]),
+            max_input_response: 0,
+            max_output_response: 24,
+            is_fsctl: true,
+            input: Input::ValidateNegotiateInfo(ValidateNegotiateInfoRequest {
+                capabilities: Capabilities::LEASING,
+                client_guid: ClientGuid::from([0x11; 16]),
+                signing_required: true,
+                dialects: vec![Dialect::Smb3_0_0, Dialect::Smb3_0_2],
+            }),
+            input_offset: 0x78,
+            output_offset: 0x78,
+            output: &[],
+        }),
+    };
+    let mut buffer = serialize(&[request], Dialect::Smb3_0_2);
+    // the input buffer directly follows the 56 byte request structure
+    let capabilities = 4 + 64 + 56;
+    assert_eq!(&buffer[capabilities..capabilities + 4], &[0x02, 0, 0, 0]);
+    // like NEGOTIATE, unknown capabilities of a newer client are ignored
+    buffer[capabilities + 1] = 0x01;
+    let (_, requests) = parse::<Request>(&buffer, Dialect::Smb3_0_2).unwrap();
+    match &requests[0].body {
+        RequestBody::Ioctl(IoctlRequest {
+            input: Input::ValidateNegotiateInfo(info),
+            ..
+        }) => assert_eq!(info.capabilities, Capabilities::LEASING),
+        _ => panic!("Expected validate negotiate request!"),
+    }
+}
+
+#[test]
+fn ioctl_request_output_kept() {
+    use crate::common::roundtrip_request;
+    use smb2_packet::command::ioctl::{Input, Request as IoctlRequest};
+    use smb2_packet::{parse, FileId, Request};
+
+    let body = IoctlRequest {
+        file_id: FileId::from([0xff; 16]),
+        max_input_response: 0,
+        max_output_response: 0x4000,
+        is_fsctl: true,
+        input: Input::QueryNetworkInterfaceInfo,
+        input_offset: 0x78,
+        output_offset: 0x7c,
+        output: &[1, 2, 3],
+    };
+    // clients should not send an output buffer but servers accept it
+    let buffer = roundtrip_request(7, RequestBody::Ioctl(body), Dialect::Smb3_1_1);
+    assert_eq!(&buffer[4 + 64 + 40..4 + 64 + 44], &[3, 0, 0, 0]);
+    assert_eq!(&buffer[4 + 0x78..], &[0, 0, 0, 0, 1, 2, 3]);
+    let (_, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    match &requests[0].body {
+        RequestBody::Ioctl(body) => assert_eq!(body.output, [1, 2, 3]),
+        _ => panic!("Expected ioctl request!"),
+    }
+}
+
+#[test]
+fn query_directory_request() {
+    use smb2_packet::command::query_directory::*;
+
+    let mut buffer = Vec::new();
+    let requests = parse_pcap_requests("all_requests", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let queries: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.body {
+            RequestBody::QueryDirectory(body) => Some(body),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(queries.len(), 78);
+    let body = queries[0];
+    assert_eq!(
+        body.file_information_class,
+        FileInformationClass::FileDirectoryInformation
+    );
+    assert_eq!(
+        body.flags,
+        Flags::RESTART_SCANS | Flags::RETURN_SINGLE_ENTRY
+    );
+    assert_eq!(body.file_index, 0);
+    assert_eq!(body.file_name, "roattr.dat");
+    assert_eq!(body.output_buffer_length, 0x148);
+    assert!(queries.iter().any(|body| body.file_information_class
+        == FileInformationClass::FileIdFullDirectoryInformation
+        && body.file_name == "*"));
+}
+
+#[test]
+fn query_directory_response() {
+    use smb2_packet::command::query_directory::*;
+
+    let mut buffer = Vec::new();
+    let responses = parse_pcap_responses("all_responses", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let body = responses
+        .iter()
+        .find_map(|response| match &response.body {
+            ResponseBody::QueryDirectory(body) => Some(body),
+            _ => None,
+        })
+        .unwrap();
+    let (_, entries) = parse_file_information(
+        body.output_buffer,
+        FileInformationClass::FileIdFullDirectoryInformation,
+    )
+    .unwrap();
+
+    match &entries[0] {
+        FileInformation::IdFullDirectory { info, file_id, .. } => {
+            assert_eq!(info.file_name, ".");
+            assert_eq!(info.file_attributes, FileAttributes::DIRECTORY);
+            assert_eq!(*file_id, 0x0010_c984);
+        }
+        _ => panic!("Expected id full directory information!"),
+    }
+    match &entries[1] {
+        FileInformation::IdFullDirectory { info, .. } => assert_eq!(info.file_name, ".."),
+        _ => panic!("Expected id full directory information!"),
+    }
+
+    let mut serialized = Vec::new();
+    write_file_information(&mut serialized, &entries);
+    assert_eq!(serialized, body.output_buffer);
+}
+
+#[test]
+fn query_directory_short_name() {
+    use smb2_packet::command::query_directory::*;
+    use std::time::UNIX_EPOCH;
+
+    let short_name = ShortName::new("ALONGF~1.TXT").unwrap();
+    let entry = FileInformation::BothDirectory {
+        info: DirectoryInformation {
+            file_index: 0,
+            creation_time: UNIX_EPOCH,
+            last_access_time: UNIX_EPOCH,
+            last_write_time: UNIX_EPOCH,
+            change_time: UNIX_EPOCH,
+            end_of_file: 0,
+            allocation_size: 0,
+            file_attributes: FileAttributes::NORMAL,
+            file_name: "a long file name.txt".into(),
+        },
+        ea_size: 0,
+        short_name: short_name.clone(),
+    };
+    let mut serialized = Vec::new();
+    write_file_information(&mut serialized, &[entry]);
+    let (_, entries) = parse_file_information(
+        &serialized,
+        FileInformationClass::FileBothDirectoryInformation,
+    )
+    .unwrap();
+    match &entries[0] {
+        FileInformation::BothDirectory {
+            short_name: name, ..
+        } => assert_eq!(*name, short_name),
+        _ => panic!("Expected both directory information!"),
+    }
+
+    // short names longer than 12 UTF-16 code units can not be put on the wire
+    assert!(ShortName::new("ALONGF~1.TXT.BAK").is_none());
+    assert!(ShortName::new("ALONGF~1.TX\u{1f600}").is_none());
+    assert_eq!(
+        &*ShortName::new("LONGF~1.TX\u{1f600}").unwrap(),
+        "LONGF~1.TX\u{1f600}"
+    );
+}
+
+#[test]
+fn set_info_request() {
+    use smb2_packet::command::query_info::*;
+
+    let mut buffer = Vec::new();
+    let requests = parse_pcap_requests("all_requests", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let infos: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.body {
+            RequestBody::SetInfo(body) => Some(&body.info),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(infos.len(), 58);
+    match infos[0] {
+        Info::File(FileInfo::Basic(basic)) => {
+            assert_eq!(basic.creation_time, basic.last_write_time);
+            assert_eq!(basic.file_attributes, FileAttributes::NORMAL);
+        }
+        _ => panic!("Expected basic information!"),
+    }
+    assert!(infos.iter().any(|info| matches!(
+        info,
+        Info::File(FileInfo::Disposition {
+            delete_pending: true
+        })
+    )));
+}
+
+#[test]
+fn set_info_basic_special_times() {
+    use crate::common::request_header;
+    use smb2_packet::command::query_info::*;
+    use smb2_packet::command::set_info::Request as SetInfoRequest;
+    use smb2_packet::{parse, serialize, FileId, Request};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let last_write_time = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let request = Request {
+        header: request_header(7),
+        body: RequestBody::SetInfo(SetInfoRequest {
+            additional_information: 0,
+            file_id: FileId::from([0xab; 16]),
+            info: Info::File(FileInfo::Basic(FileBasicInformation {
+                creation_time: FileTime::Unchanged,
+                last_access_time: FileTime::StopUpdating,
+                last_write_time: FileTime::Time(last_write_time),
+                change_time: FileTime::ResumeUpdating,
+                file_attributes: FileAttributes::empty(),
+            })),
+        }),
+    };
+    let buffer = serialize(&[request], Dialect::Smb3_1_1);
+    let info = 4 + 64 + 32;
+    assert_eq!(&buffer[info..info + 8], &[0; 8]);
+    assert_eq!(&buffer[info + 8..info + 16], &[0xff; 8]);
+    assert_eq!(
+        &buffer[info + 24..info + 32],
+        &[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+    );
+
+    let (rem, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&requests, Dialect::Smb3_1_1), buffer);
+    match &requests[0].body {
+        RequestBody::SetInfo(SetInfoRequest {
+            info: Info::File(FileInfo::Basic(basic)),
+            ..
+        }) => {
+            assert_eq!(basic.creation_time, FileTime::Unchanged);
+            assert_eq!(basic.last_access_time, FileTime::StopUpdating);
+            assert_eq!(basic.last_write_time, FileTime::Time(last_write_time));
+            assert_eq!(basic.change_time, FileTime::ResumeUpdating);
+        }
+        _ => panic!("Expected set info request!"),
+    }
+}
+
+#[test]
+fn unknown_info_class() {
+    use crate::common::request_header;
+    use smb2_packet::command::query_info::{Flags, Info, InfoClass, Request as QueryInfoRequest};
+    use smb2_packet::command::set_info::Request as SetInfoRequest;
+    use smb2_packet::{parse, serialize, FileId, Request};
+
+    // FileCaseSensitiveInformation
+    let class = InfoClass::Unknown {
+        info_type: 1,
+        class: 0x47,
+    };
+    let query = Request {
+        header: request_header(7),
+        body: RequestBody::QueryInfo(QueryInfoRequest {
+            info_class: class,
+            output_buffer_length: 4,
+            input_buffer: &[],
+            input_buffer_offset: 0,
+            additional_information: 0,
+            flags: Flags::empty(),
+            file_id: FileId::from([0xab; 16]),
+        }),
+    };
+    let set = Request {
+        header: request_header(8),
+        body: RequestBody::SetInfo(SetInfoRequest {
+            additional_information: 0,
+            file_id: FileId::from([0xab; 16]),
+            info: Info::Unknown {
+                info_type: 1,
+                class: 0x47,
+                data: &[1, 0, 0, 0],
+            },
+        }),
+    };
+    let buffer = serialize(&[query, set], Dialect::Smb3_1_1);
+    let (rem, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&requests, Dialect::Smb3_1_1), buffer);
+    match &requests[0].body {
+        RequestBody::QueryInfo(body) => {
+            assert_eq!(body.info_class, class);
+            assert_eq!(body.input_buffer_offset, 0);
+        }
+        _ => panic!("Expected query info request!"),
+    }
+    match &requests[1].body {
+        RequestBody::SetInfo(SetInfoRequest {
+            info: Info::Unknown { data, .. },
+            ..
+        }) => assert_eq!(*data, &[1, 0, 0, 0]),
+        _ => panic!("Expected unknown set info request!"),
+    }
+}
+
+#[test]
+fn query_info_response() {
+    use smb2_packet::command::query_info::*;
+
+    let mut buffer = Vec::new();
+    let responses = parse_pcap_responses("all_responses", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let buffers: Vec<_> = responses
+        .iter()
+        .filter_map(|response| match &response.body {
+            ResponseBody::QueryInfo(body) => Some(body.output_buffer),
+            _ => None,
+        })
+        .collect();
+    let attribute_class = InfoClass::FileSystem(FsInfoClass::FileFsAttributeInformation);
+    let all_class = InfoClass::File(FileInfoClass::FileAllInformation);
+
+    assert_eq!(buffers.len(), 60);
+    let (_, info) = parse_info(buffers[0], attribute_class).unwrap();
+    match &info {
+        Info::FileSystem(FsInfo::Attribute(attribute)) => {
+            assert_eq!(attribute.file_system_attributes, 0x0001_006f);
+            assert_eq!(attribute.maximum_component_name_length, 255);
+            assert_eq!(attribute.file_system_name, "NTFS");
+        }
+        _ => panic!("Expected attribute information!"),
+    }
+    let mut serialized = Vec::new();
+    write_info(&mut serialized, &info);
+    assert_eq!(serialized, buffers[0]);
+
+    let all = buffers.iter().find(|buffer| buffer.len() == 102).unwrap();
+    let (_, info) = parse_info(all, all_class).unwrap();
+    match &info {
+        Info::File(FileInfo::All(all)) => {
+            assert_eq!(all.basic.file_attributes, FileAttributes::DIRECTORY);
+            assert!(all.standard.directory);
+            assert_eq!(all.file_name, "\\");
+        }
+        _ => panic!("Expected all information!"),
+    }
+    let mut serialized = Vec::new();
+    write_info(&mut serialized, &info);
+    assert_eq!(&serialized, all);
+}
+
+#[test]
+fn echo_request() {
+    let requests: Vec<Request> = roundtrip_messages(&ECHO_REQUEST, Dialect::Smb3_1_1);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].header.message_id, 0x2a);
+    match requests[0].body {
+        RequestBody::Echo => (),
+        _ => panic!("Expected echo request!"),
+    }
+}
+
+#[test]
+fn echo_response() {
+    let responses: Vec<Response> = roundtrip_messages(&ECHO_RESPONSE, Dialect::Smb3_1_1);
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].header.message_id, 0x2a);
+    assert_eq!(responses[0].header.status, NTStatus::StatusSuccess);
+    match responses[0].body {
+        ResponseBody::Echo => (),
+        _ => panic!("Expected echo response!"),
+    }
+}
+
+#[test]
+fn cancel_request() {
+    let requests: Vec<Request> = roundtrip_messages(&CANCEL_REQUESTS, Dialect::Smb3_1_1);
+    assert_eq!(requests.len(), 2);
+
+    // canceling a request which is still synchronous
+    let sync = &requests[0];
+    assert_eq!(sync.header.message_id, 0x31);
+    assert_eq!(sync.header.sync_type, SyncType::Sync { tree_id: 5 });
+    assert_eq!(sync.header.credit_request, 0);
+
+    // canceling a request after the interim response
+    let async_ = &requests[1];
+    assert!(async_.header.flags.contains(Flags::ASYNC_COMMAND));
+    assert_eq!(async_.header.message_id, 0);
+    assert_eq!(async_.header.process_id, None);
+    assert_eq!(async_.header.sync_type, SyncType::Async { async_id: 0x21 });
+
+    for request in &requests {
+        match request.body {
+            RequestBody::Cancel => (),
+            _ => panic!("Expected cancel request!"),
+        }
+    }
+}
+
+#[test]
+fn cancel_matches_target() {
+    use smb2_packet::command::cancel::cancels;
+
+    let requests: Vec<Request> = roundtrip_messages(&CANCEL_REQUESTS, Dialect::Smb3_1_1);
+    let (sync, async_) = (&requests[0].header, &requests[1].header);
+    let target = request_header(0x31);
+    let other = request_header(0x32);
+
+    assert!(cancels(sync, &target, None));
+    assert!(cancels(sync, &target, Some(0x21)));
+    assert!(!cancels(sync, &other, None));
+
+    /* an asynchronous cancel only matches once the interim response assigned the id */
+    assert!(!cancels(async_, &target, None));
+    assert!(cancels(async_, &target, Some(0x21)));
+    assert!(cancels(async_, &other, Some(0x21)));
+    assert!(!cancels(async_, &target, Some(0x22)));
+}
+
+#[test]
+fn create_request_contexts() {
+    use smb2_packet::command::create::{CreateContext, LeaseState};
+
+    let mut buffer = Vec::new();
+    let requests = parse_pcap_requests("all_requests", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let contexts: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.body {
+            RequestBody::Create(body) if !body.contexts.is_empty() => Some(&body.contexts),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(contexts.len(), 189);
+    assert!(contexts.iter().all(|c| c.len() == 1));
+
+    let count = |f: &dyn Fn(&CreateContext) -> bool| contexts.iter().filter(|c| f(&c[0])).count();
+    assert_eq!(
+        count(&|c| matches!(c, CreateContext::MaximalAccessRequest { timestamp: None })),
+        154
+    );
+    assert_eq!(
+        count(&|c| matches!(c, CreateContext::DurableHandleRequest)),
+        34
+    );
+
+    let lease = contexts
+        .iter()
+        .find_map(|c| match &c[0] {
+            CreateContext::LeaseV2(lease) => Some(lease),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(
+        *lease.lease_key,
+        [
+            0x00, 0x78, 0x08, 0x2a, 0x08, 0x8d, 0xff, 0xff, 0x00, 0x16, 0x7a, 0x07, 0x08, 0x8d,
+            0xff, 0xff
+        ]
+    );
+    assert_eq!(lease.lease_state, LeaseState::READ_CACHING);
+    assert!(!lease.break_in_progress);
+    assert!(lease.parent_lease_key.is_none());
+    assert_eq!(lease.epoch, 0);
+}
+
+#[test]
+fn create_response() {
+    use smb2_packet::command::create::{Action, Flags, OplockLevel};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let mut buffer = Vec::new();
+    let responses = parse_pcap_responses("all_responses", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let creates: Vec<_> = responses
+        .iter()
+        .filter_map(|response| match &response.body {
+            ResponseBody::Create(body) => Some((response.header.message_id, body)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(creates.len(), 58);
+    assert!(creates.iter().all(|(_, body)| body.contexts.is_empty()));
+
+    let (message_id, body) = creates[0];
+    assert_eq!(message_id, 5);
+    assert_eq!(body.oplock_level, OplockLevel::No);
+    assert_eq!(body.flags, Flags::empty());
+    assert_eq!(body.create_action, Action::Opened);
+    assert_eq!(
+        body.creation_time,
+        UNIX_EPOCH + Duration::new(1_555_620_945, 756_251_400)
+    );
+    assert_eq!(body.last_access_time, body.creation_time);
+    assert_eq!(
+        body.last_write_time,
+        UNIX_EPOCH + Duration::new(1_555_664_596, 557_527_900)
+    );
+    assert_eq!(body.change_time, body.last_write_time);
+    assert_eq!(body.allocation_size, 0);
+    assert_eq!(body.end_of_file, 0);
+    assert_eq!(body.file_attributes, FileAttributes::DIRECTORY);
+    assert_eq!(
+        *body.file_id,
+        [0x8f, 0x5d, 0xd7, 0x2d, 0, 0, 0, 0, 0x51, 0xa4, 0xa8, 0x7f, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn session_setup_request() {
+    use smb2_packet::command::session_setup::{Capabilities, Flags};
+
+    let mut buffer = Vec::new();
+    let requests = parse_pcap_requests("all_requests", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let bodies: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.body {
+            RequestBody::SessionSetup(body) => Some(body),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(bodies.len(), 4);
+    for body in &bodies {
+        assert_eq!(body.flags, Flags::empty());
+        assert!(!body.signing_required);
+        assert_eq!(body.capabilities, Capabilities::empty());
+        assert_eq!(body.channel, 0);
+        assert_eq!(body.previous_session_id, 0);
+        assert_eq!(&body.security_buffer[..8], b"NTLMSSP\0");
+    }
+    assert_eq!(bodies[0].security_buffer.len(), 32);
+    assert_eq!(bodies[1].security_buffer.len(), 268);
+}
+
+#[test]
+fn session_setup_request_binding() {
+    use crate::common::request_header;
+    use smb2_packet::command::session_setup::{Capabilities, Flags, Request as SessionRequest};
+    use smb2_packet::{parse, serialize, Request};
+
+    let request = Request {
+        header: request_header(4),
+        body: RequestBody::SessionSetup(SessionRequest {
+            flags: Flags::BINDING,
+            signing_required: true,
+            capabilities: Capabilities::empty(),
+            channel: 0,
+            previous_session_id: 0,
+            security_buffer: b"NTLMSSP\0",
+        }),
+    };
+    let buffer = serialize(&[request], Dialect::Smb3_0_2);
+    let (_, requests) = parse::<Request>(&buffer, Dialect::Smb3_0_2).unwrap();
+    match &requests[0].body {
+        RequestBody::SessionSetup(body) => assert_eq!(body.flags, Flags::BINDING),
+        _ => panic!("Expected session setup request!"),
+    }
+
+    // there are no channels to bind before 3.0
+    assert!(parse::<Request>(&buffer, Dialect::Smb2_1_0).is_err());
+}
+
+#[test]
+fn session_setup_response() {
+    use smb2_packet::command::session_setup::SessionFlags;
+
+    let mut buffer = Vec::new();
+    let responses = parse_pcap_responses("all_responses", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let bodies: Vec<_> = responses
+        .iter()
+        .filter_map(|response| match &response.body {
+            ResponseBody::SessionSetup(body) => Some((response.header.status, body)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(bodies.len(), 4);
+
+    let (status, challenge) = bodies[0];
+    assert_eq!(status, NTStatus::StatusMoreProcessingRequired);
+    assert_eq!(challenge.session_flags, SessionFlags::empty());
+    assert_eq!(challenge.security_buffer.len(), 194);
+    assert_eq!(&challenge.security_buffer[..8], b"NTLMSSP\0");
+
+    let (status, done) = bodies[1];
+    assert_eq!(status, NTStatus::StatusSuccess);
+    assert_eq!(done.session_flags, SessionFlags::empty());
+    assert!(done.security_buffer.is_empty());
+
+    let (status, guest) = bodies[3];
+    assert_eq!(status, NTStatus::StatusSuccess);
+    assert_eq!(guest.session_flags, SessionFlags::IS_GUEST);
+}
+
+#[test]
+fn session_setup_response_without_buffer_offset() {
+    use crate::common::response_header;
+    use smb2_packet::command::session_setup::{Response as SessionResponse, SessionFlags};
+    use smb2_packet::{parse, serialize, Response};
+
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 2),
+        body: ResponseBody::SessionSetup(SessionResponse {
+            session_flags: SessionFlags::empty(),
+            security_buffer: &[],
+        }),
+    };
+    let mut buffer = serialize(&[response], Dialect::Smb3_1_1);
+    // security buffer offset
+    buffer[4 + 64 + 4] = 0;
+    let (_, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    match &responses[0].body {
+        ResponseBody::SessionSetup(body) => assert!(body.security_buffer.is_empty()),
+        _ => panic!("Expected session setup response!"),
+    }
+
+    // a buffer needs an offset
+    buffer[4 + 64 + 6] = 1;
+    assert!(parse::<Response>(&buffer, Dialect::Smb3_1_1).is_err());
+}
@@ -0,0 +1,155 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{response_header, roundtrip_request};
+use smb2_packet::command::change_notify::{
+    Action, CompletionFilter, FileNotifyInformation, Request as NotifyRequest,
+    Response as NotifyResponse,
+};
+use smb2_packet::command::error::{Detail, ErrorData, Response as ErrorResponse};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::header::{Command, Flags, SyncType};
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{parse, serialize, Dialect, FileId, Request, Response};
+
+const FILE_ID: [u8; 16] = [
+    0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x11, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+];
+const ASYNC_ID: u64 = 0x0000_0000_0000_0042;
+
+fn async_header(status: NTStatus) -> smb2_packet::header::Response {
+    let mut header = response_header(status, 7);
+    header.flags |= Flags::ASYNC_COMMAND;
+    header.process_id = None;
+    header.sync_type = SyncType::Async { async_id: ASYNC_ID };
+    header
+}
+
+fn notify_request() -> Vec<u8> {
+    let body = RequestBody::ChangeNotify(NotifyRequest {
+        watch_tree: true,
+        output_buffer_length: 0x800,
+        file_id: FileId::from(FILE_ID),
+        completion_filter: CompletionFilter::FILE_NAME
+            | CompletionFilter::DIR_NAME
+            | CompletionFilter::LAST_WRITE,
+    });
+    roundtrip_request(7, body, Dialect::Smb3_1_1)
+}
+
+#[test]
+fn request() {
+    let buffer = notify_request();
+    assert_eq!(buffer.len(), 4 + 64 + 32);
+
+    let (rem, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    let body = match &requests[0].body {
+        RequestBody::ChangeNotify(body) => body,
+        _ => panic!("Expected change notify request!"),
+    };
+    assert!(body.watch_tree);
+    assert_eq!(body.output_buffer_length, 0x800);
+    assert_eq!(*body.file_id, FILE_ID);
+    assert_eq!(
+        body.completion_filter,
+        CompletionFilter::FILE_NAME | CompletionFilter::DIR_NAME | CompletionFilter::LAST_WRITE
+    );
+}
+
+#[test]
+fn request_reserved_filter_rejected() {
+    let mut buffer = notify_request();
+    // completion filter
+    buffer[4 + 64 + 24 + 1] |= 0x10;
+    assert!(parse::<Request>(&buffer, Dialect::Smb3_1_1).is_err());
+}
+
+#[test]
+fn interim_response() {
+    let response = Response {
+        header: async_header(NTStatus::StatusPending),
+        body: ResponseBody::Error(ErrorResponse {
+            status: NTStatus::StatusPending,
+            command: Command::ChangeNotify,
+            error_data: ErrorData::Detail(Detail::Empty),
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    let response = &responses[0];
+    assert_eq!(
+        response.header.sync_type,
+        SyncType::Async { async_id: ASYNC_ID }
+    );
+    assert_eq!(response.header.process_id, None);
+    assert_eq!(response.header.status, NTStatus::StatusPending);
+    match &response.body {
+        ResponseBody::Error(body) => assert_eq!(body.command, Command::ChangeNotify),
+        _ => panic!("Expected error response!"),
+    }
+}
+
+#[test]
+fn response() {
+    let changes = vec![
+        FileNotifyInformation {
+            action: Action::RenamedOldName,
+            file_name: "a.txt".into(),
+        },
+        FileNotifyInformation {
+            action: Action::RenamedNewName,
+            file_name: "dir\\b.txt".into(),
+        },
+        FileNotifyInformation {
+            action: Action::Removed,
+            file_name: "c".into(),
+        },
+    ];
+    let response = Response {
+        header: async_header(NTStatus::StatusSuccess),
+        body: ResponseBody::ChangeNotify(NotifyResponse {
+            changes: changes.clone(),
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    // entries are 12 bytes plus name, all but the last aligned to 4 bytes
+    assert_eq!(buffer.len(), 4 + 64 + 8 + 24 + 32 + 14);
+    // next entry offset of the first and second entry
+    assert_eq!(buffer[4 + 64 + 8], 24);
+    assert_eq!(buffer[4 + 64 + 8 + 24], 32);
+
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    let response = &responses[0];
+    assert_eq!(
+        response.header.sync_type,
+        SyncType::Async { async_id: ASYNC_ID }
+    );
+    match &response.body {
+        ResponseBody::ChangeNotify(body) => assert_eq!(body.changes, changes),
+        _ => panic!("Expected change notify response!"),
+    }
+}
+
+#[test]
+fn response_empty() {
+    let response = Response {
+        header: async_header(NTStatus::StatusSuccess),
+        body: ResponseBody::ChangeNotify(NotifyResponse { changes: vec![] }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    assert_eq!(buffer.len(), 4 + 64 + 9);
+
+    let (_, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    match &responses[0].body {
+        ResponseBody::ChangeNotify(body) => assert!(body.changes.is_empty()),
+        _ => panic!("Expected change notify response!"),
+    }
+}
@@ -6,10 +6,57 @@ use pnet_packet::ipv4::Ipv4Packet;
 use pnet_packet::ipv6::Ipv6Packet;
 use pnet_packet::tcp::TcpPacket;
 use pnet_packet::Packet;
+use smb2_packet::command::RequestBody;
+use smb2_packet::header::{Flags, Signature, SyncType};
+use smb2_packet::ntstatus::NTStatus;
 use smb2_packet::smb1::NegotiateRequest as V1NegotRequest;
-use smb2_packet::{parse, parse_smb1_nego_request, Dialect, Request, Response};
+use smb2_packet::Packet as SmbPacket;
+use smb2_packet::{parse, parse_smb1_nego_request, serialize, Dialect, Request, Response};
 use std::path::PathBuf;
 
+/// An unsigned ECHO request with message id 0x2a in its session message.
+#[rustfmt::skip]
+pub const ECHO_REQUEST: [u8; 4 + 64 + 4] = [
+    0x00, 0x00, 0x00, 0x44,
+    0xfe, 0x53, 0x4d, 0x42, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x00, 0x01, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xfe, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00,
+];
+
+/// The ECHO response to `ECHO_REQUEST` in its session message.
+#[rustfmt::skip]
+pub const ECHO_RESPONSE: [u8; 4 + 64 + 4] = [
+    0x00, 0x00, 0x00, 0x44,
+    0xfe, 0x53, 0x4d, 0x42, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x00, 0x01, 0x00,
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xfe, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00,
+];
+
+/// Two unsigned CANCEL requests, each in its own session message.
+///
+/// The first cancels the synchronous request with message id 0x31 in tree 5,
+/// the second the request which got the async id 0x21 in an interim response.
+/// Neither asks for credits.
+#[rustfmt::skip]
+pub const CANCEL_REQUESTS: [u8; 2 * (4 + 64 + 4)] = [
+    0x00, 0x00, 0x00, 0x44,
+    0xfe, 0x53, 0x4d, 0x42, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x31, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xfe, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x44,
+    0xfe, 0x53, 0x4d, 0x42, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00,
+    0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x04, 0x00, 0x00, 0x00,
+];
+
 enum IPPacket<'a> {
     V4(Ipv4Packet<'a>),
     V6(Ipv6Packet<'a>),
@@ -65,10 +112,171 @@ pub fn parse_pcap_smb1nego(
     parse_pcap(name, buffer, request_smb1_nego)
 }
 
+pub fn response_header(status: NTStatus, message_id: u64) -> smb2_packet::header::Response {
+    smb2_packet::header::Response {
+        credit_charge: Some(1),
+        credit_response: 1,
+        status,
+        flags: Flags::SERVER_TO_REDIR,
+        message_id,
+        process_id: Some(0xfeff),
+        sync_type: SyncType::Sync { tree_id: 1 },
+        session_id: 0x0000_0400_0000_0005,
+        signature: Signature::empty(),
+    }
+}
+
+pub fn request_header(message_id: u64) -> smb2_packet::header::Request {
+    smb2_packet::header::Request {
+        credit_charge: Some(1),
+        credit_request: 1,
+        channel_sequence: Some(0),
+        flags: Flags::empty(),
+        message_id,
+        process_id: Some(0xfeff),
+        sync_type: SyncType::Sync { tree_id: 1 },
+        session_id: 0x0000_0400_0000_0005,
+        signature: Signature::empty(),
+    }
+}
+
+/// Serializes `body` as a single request with `request_header(message_id)`.
+pub fn serialize_request(message_id: u64, body: RequestBody, dialect: Dialect) -> Vec<u8> {
+    let request = Request {
+        header: request_header(message_id),
+        body,
+    };
+    serialize(&[request], dialect)
+}
+
+/// Like `serialize_request` but also checks that parsing and serializing the
+/// request again yields the same bytes.
+pub fn roundtrip_request(message_id: u64, body: RequestBody, dialect: Dialect) -> Vec<u8> {
+    let buffer = serialize_request(message_id, body, dialect);
+    let (rem, requests) = parse::<Request>(&buffer, dialect).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&requests, dialect), buffer);
+    buffer
+}
+
+/// Parses all session messages in `data` and checks that serializing each of them
+/// yields the same bytes again.
+pub fn roundtrip_messages<'a, T: SmbPacket<'a>>(data: &'a [u8], dialect: Dialect) -> Vec<T> {
+    let mut messages = Vec::new();
+    let mut ptr = data;
+    while !ptr.is_empty() {
+        let (remaining, mut parsed) = parse::<T>(ptr, dialect).unwrap();
+        assert_eq!(
+            serialize(&parsed, dialect),
+            &ptr[..ptr.len() - remaining.len()]
+        );
+        messages.append(&mut parsed);
+        ptr = remaining;
+    }
+    messages
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+pub fn unhex(data: &str) -> Vec<u8> {
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&data[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Parses every session message in the capture and checks that serializing the
+/// parsed messages yields the exact same bytes again.
+///
+/// Messages for which `skip` returns true are only parsed.
+pub fn roundtrip_pcap<'a, T, F>(name: &str, buffer: &'a mut Vec<u8>, dialect: Dialect, skip: F)
+where
+    T: SmbPacket<'a>,
+    F: Fn(&T) -> bool,
+{
+    roundtrip_pcap_masked(name, buffer, dialect, skip, |_| {});
+}
+
+/// Same as `roundtrip_pcap` but the original bytes of each compound are passed
+/// through `mask` before the comparison, e.g. to zero uninitialized padding.
+pub fn roundtrip_pcap_masked<'a, T, F, M>(
+    name: &str,
+    buffer: &'a mut Vec<u8>,
+    dialect: Dialect,
+    skip: F,
+    mask: M,
+) where
+    T: SmbPacket<'a>,
+    F: Fn(&T) -> bool,
+    M: Fn(&mut [u8]),
+{
+    load_pcap(name, buffer);
+    let mut ptr: &'a [u8] = buffer;
+    let mut at_byte: usize = 0;
+    while !ptr.is_empty() {
+        let (remaining, messages) = parse::<T>(ptr, dialect).unwrap();
+        let bytes_read = ptr.len() - remaining.len();
+        if !messages.iter().any(&skip) {
+            let mut original = ptr[4..bytes_read].to_vec();
+            mask(&mut original);
+            let original = original.as_slice();
+            let serialized = serialize(&messages, dialect);
+            let serialized = &serialized[4..];
+            /* some clients leave out the padding byte of an empty buffer at the very end */
+            let serialized = match serialized.split_last() {
+                Some((0, rest)) if rest.len() == original.len() => rest,
+                _ => serialized,
+            };
+            /* some clients pad the last message which is not preserved by parsing */
+            let (original, padding) = original.split_at(serialized.len().min(original.len()));
+            assert!(
+                original == serialized && padding.iter().all(|x| *x == 0),
+                "Roundtrip failed for message at byte 0x{:08X}:\n{:02x?}\n{:02x?}",
+                at_byte,
+                original,
+                serialized,
+            );
+        }
+        ptr = remaining;
+        at_byte += bytes_read;
+    }
+}
+
 fn parse_pcap<'a, F, T>(name: &str, buffer: &'a mut Vec<u8>, func: F) -> Result<Vec<T>, String>
 where
     F: Fn(&'a [u8]) -> IResult<&'a [u8], Vec<T>>,
 {
+    load_pcap(name, buffer);
+    let mut requests = Vec::new();
+    let mut ptr = buffer.as_slice();
+    let mut at_byte: usize = 0;
+    while !ptr.is_empty() {
+        match func(ptr) {
+            Ok((remaining, mut messages)) => {
+                let bytes_read = ptr.len() - remaining.len();
+                ptr = &ptr[bytes_read..];
+                requests.append(&mut messages);
+                at_byte += bytes_read;
+            }
+            Err(err) => {
+                let msg = format!(
+                    "Error parsing at byte 0x{:08X} with value 0x{:02X}: {:x?}",
+                    at_byte, ptr[0], err,
+                );
+                return Err(msg);
+            }
+        }
+    }
+    Ok(requests)
+}
+
+/// Appends the tcp payload of all packets in the capture to `buffer`
+fn load_pcap(name: &str, buffer: &mut Vec<u8>) {
     use std::fs::File;
 
     let mut path: PathBuf = TEST_DIR.clone();
@@ -77,7 +285,6 @@ where
 
     let file = File::open(path).unwrap();
     let mut pcap = Capture::new(file).unwrap();
-    let mut requests = Vec::new();
 
     while let Some(record) = pcap.next() {
         let record = record.unwrap();
@@ -112,25 +319,4 @@ where
 
         buffer.extend_from_slice(tcp_segment.payload());
     }
-
-    let mut ptr = buffer.as_slice();
-    let mut at_byte: usize = 0;
-    while !ptr.is_empty() {
-        match func(ptr) {
-            Ok((remaining, mut messages)) => {
-                let bytes_read = ptr.len() - remaining.len();
-                ptr = &ptr[bytes_read..];
-                requests.append(&mut messages);
-                at_byte += bytes_read;
-            }
-            Err(err) => {
-                let msg = format!(
-                    "Error parsing at byte 0x{:08X} with value 0x{:02X}: {:x?}",
-                    at_byte, ptr[0], err,
-                );
-                return Err(msg);
-            }
-        }
-    }
-    Ok(requests)
 }
@@ -0,0 +1,355 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::request_header;
+use smb2_packet::command::negotiate::CompressionAlgorithm;
+use smb2_packet::command::RequestBody;
+use smb2_packet::compression::{parse, Chained, Error, Flags, Message, Payload, Unchained};
+use smb2_packet::transport::{self, Framer};
+use smb2_packet::{serialize, Dialect, Packet, Request};
+
+/// Text with repetitions mixed with runs and noise from a linear congruential generator.
+#[allow(clippy::cast_possible_truncation)]
+fn sample(len: usize) -> Vec<u8> {
+    const WORDS: [&[u8]; 6] = [
+        b"SMB2 ",
+        b"compression ",
+        b"chained ",
+        b"abc",
+        b"\x00\x00\x00\x00",
+        b"the ",
+    ];
+    let mut out = Vec::with_capacity(len);
+    let mut state: u32 = 0x1234_5678;
+    while out.len() < len {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let choice = (state >> 16) as usize;
+        match choice % 8 {
+            0..=5 => out.extend_from_slice(WORDS[choice % 6]),
+            6 => out.resize(out.len() + choice % 300, (choice >> 8) as u8),
+            _ => out.push((choice >> 4) as u8),
+        }
+    }
+    out.truncate(len);
+    out
+}
+
+fn inputs() -> Vec<Vec<u8>> {
+    vec![
+        Vec::new(),
+        b"a".to_vec(),
+        b"abcdefghijklmnopqrstuvwxyz".to_vec(),
+        b"abc".repeat(100),
+        vec![0; 70_000],
+        sample(5000),
+        sample(200_000),
+        (0..=255).cycle().take(10_000).collect(),
+    ]
+}
+
+#[cfg(feature = "lz77")]
+#[test]
+fn lz77_examples() {
+    use smb2_packet::compression::lz77::{compress, decompress};
+
+    // the examples of MS-XCA
+    let text = b"abcdefghijklmnopqrstuvwxyz";
+    let mut expected = vec![0x3f, 0, 0, 0];
+    expected.extend_from_slice(text);
+    assert_eq!(compress(text), expected);
+    assert_eq!(decompress(&expected, text.len()).unwrap(), text.to_vec());
+
+    let repeated = b"abc".repeat(100);
+    let expected = [
+        0xff, 0xff, 0xff, 0x1f, 0x61, 0x62, 0x63, 0x17, 0x00, 0x0f, 0xff, 0x26, 0x01,
+    ];
+    assert_eq!(compress(&repeated), expected);
+    assert_eq!(decompress(&expected, 300).unwrap(), repeated);
+    assert_eq!(decompress(&expected, 299), Err(Error::InvalidData));
+    assert_eq!(decompress(&expected, 301), Err(Error::InvalidData));
+}
+
+#[cfg(feature = "lz77")]
+#[test]
+fn lz77_roundtrip() {
+    use smb2_packet::compression::lz77::{compress, decompress};
+
+    for input in inputs() {
+        let compressed = compress(&input);
+        assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+    }
+    assert!(compress(&sample(200_000)).len() < 100_000);
+    // match offset beyond the start of the output
+    assert_eq!(
+        decompress(&[0xff, 0xff, 0xff, 0x7f, 0x61, 0x08, 0x00], 4),
+        Err(Error::InvalidData)
+    );
+}
+
+#[cfg(feature = "lznt1")]
+#[test]
+fn lznt1_example() {
+    use smb2_packet::compression::lznt1::{compress, decompress};
+
+    // the example of MS-XCA, a single compressed chunk
+    let text =
+        b"F# F# G A A G F# E D D E F# F# E E F# F# G A A G F# E D D E F# E D D E E F# D E F# G F# \
+                 D E F# G F# E D E A F# F# G A A G F# E D D E F# E D D\0";
+    let expected = [
+        0x38, 0xb0, 0x88, 0x46, 0x23, 0x20, 0x00, 0x20, 0x47, 0x20, 0x41, 0x00, 0x10, 0xa2, 0x47,
+        0x01, 0xa0, 0x45, 0x20, 0x44, 0x00, 0x08, 0x45, 0x01, 0x50, 0x79, 0x00, 0xc0, 0x45, 0x20,
+        0x05, 0x24, 0x13, 0x88, 0x05, 0xb4, 0x02, 0x4a, 0x44, 0xef, 0x03, 0x58, 0x02, 0x8c, 0x09,
+        0x16, 0x01, 0x48, 0x45, 0x00, 0xbe, 0x00, 0x9e, 0x00, 0x04, 0x01, 0x18, 0x90, 0x00,
+    ];
+    assert_eq!(decompress(&expected, text.len()).unwrap(), text.to_vec());
+    // the matches found may differ from the ones of the example
+    let compressed = compress(text);
+    assert!(compressed.len() <= expected.len());
+    assert_eq!(decompress(&compressed, text.len()).unwrap(), text.to_vec());
+}
+
+#[cfg(feature = "lznt1")]
+#[test]
+fn lznt1_roundtrip() {
+    use smb2_packet::compression::lznt1::{compress, decompress};
+
+    // literal followed by a match of the 9 remaining bytes in a compressed chunk
+    let compressed = compress(b"aaaaaaaaaa");
+    assert_eq!(compressed, [0x03, 0xb0, 0x02, 0x61, 0x06, 0x00]);
+    assert_eq!(decompress(&compressed, 10).unwrap(), b"aaaaaaaaaa");
+    // stored chunk followed by the terminating zero header
+    assert_eq!(
+        decompress(&[0x02, 0x30, b'a', b'b', b'c', 0, 0], 3).unwrap(),
+        b"abc"
+    );
+
+    for input in inputs() {
+        let compressed = compress(&input);
+        assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+    }
+    assert!(compress(&sample(200_000)).len() < 100_000);
+    assert_eq!(
+        decompress(&[0x03, 0xb0, 0x02, 0x61, 0x16, 0x00], 10),
+        Err(Error::InvalidData)
+    );
+}
+
+#[cfg(feature = "lz77-huffman")]
+#[test]
+fn lz77_huffman_examples() {
+    use smb2_packet::compression::lz77_huffman::{compress, decompress};
+
+    // the examples of MS-XCA, a table of 256 bytes with the 4 bit code length of
+    // every symbol followed by the bit stream
+    let text = b"abcdefghijklmnopqrstuvwxyz";
+    let mut expected = vec![0; 256];
+    expected[0x30] = 0x50;
+    expected[0x31..0x3b].fill(0x55);
+    expected[0x3b..0x3e].copy_from_slice(&[0x45, 0x44, 0x04]);
+    expected[0x80] = 0x04;
+    expected.extend_from_slice(&[
+        0xd8, 0x52, 0x3e, 0xd7, 0x94, 0x11, 0x5b, 0xe9, 0x19, 0x5f, 0xf9, 0xd6, 0x7c, 0xdf, 0x8d,
+        0x04, 0x00, 0x00, 0x00, 0x00,
+    ]);
+    assert_eq!(compress(text), expected);
+    assert_eq!(decompress(&expected, text.len()).unwrap(), text.to_vec());
+
+    let repeated = b"abc".repeat(100);
+    let mut expected = vec![0; 256];
+    expected[0x30..0x32].copy_from_slice(&[0x30, 0x23]);
+    expected[0x80] = 0x02;
+    expected[0x8f] = 0x20;
+    expected.extend_from_slice(&[0xa8, 0xdc, 0x00, 0x00, 0xff, 0x26, 0x01]);
+    assert_eq!(compress(&repeated), expected);
+    assert_eq!(decompress(&expected, 300).unwrap(), repeated);
+}
+
+#[cfg(feature = "lz77-huffman")]
+#[test]
+fn lz77_huffman_roundtrip() {
+    use smb2_packet::compression::lz77_huffman::{compress, decompress};
+
+    for input in inputs() {
+        let compressed = compress(&input);
+        assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+    }
+    assert!(compress(&sample(200_000)).len() < 80_000);
+
+    let compressed = compress(&sample(1000));
+    assert_eq!(decompress(&compressed, 1001), Err(Error::InvalidData));
+    assert_eq!(
+        decompress(&compressed[..compressed.len() / 2], 1000),
+        Err(Error::InvalidData)
+    );
+    // a code length table that does not describe a complete code
+    let mut invalid = compressed;
+    invalid[0] = 0xff;
+    assert_eq!(decompress(&invalid, 1000), Err(Error::InvalidData));
+}
+
+#[cfg(feature = "pattern-v1")]
+#[test]
+fn pattern_v1() {
+    use smb2_packet::compression::pattern_v1::{compress, decompress};
+
+    let payload = compress(&[0x5a; 300]).unwrap();
+    assert_eq!(payload, [0x5a, 0, 0, 0, 0x2c, 0x01, 0, 0]);
+    assert_eq!(decompress(&payload, 300).unwrap(), vec![0x5a; 300]);
+    assert_eq!(decompress(&payload, 299), Err(Error::InvalidData));
+    assert_eq!(compress(b"aab"), None);
+    assert_eq!(compress(b""), None);
+}
+
+fn echo_requests() -> Vec<u8> {
+    let requests: Vec<_> = (1..=3)
+        .map(|id| Request {
+            header: request_header(id),
+            body: RequestBody::Echo,
+        })
+        .collect();
+    serialize(&requests, Dialect::Smb3_1_1)[4..].to_vec()
+}
+
+fn frame(message: &Message) -> Vec<u8> {
+    let mut out = Vec::new();
+    transport::write_payload(&mut out, |out| message.write_to(out));
+    out
+}
+
+#[cfg(feature = "lz77")]
+#[test]
+#[allow(clippy::cast_possible_truncation)]
+fn unchained() {
+    use smb2_packet::compression::lz77::compress;
+
+    let original = echo_requests();
+    let compressed = compress(&original[64..]);
+    let data = frame(&Message::Unchained(Unchained {
+        original_compressed_segment_size: (original.len() - 64) as u32,
+        compression_algorithm: CompressionAlgorithm::Lz77,
+        uncompressed: &original[..64],
+        compressed: &compressed,
+    }));
+    assert_eq!(&data[4..8], b"\xfcSMB");
+    // the segment size does not include the uncompressed prefix
+    assert_eq!(&data[8..12], &((original.len() - 64) as u32).to_le_bytes());
+    assert_eq!(&data[12..20], &[2, 0, 0, 0, 64, 0, 0, 0]);
+
+    let (rem, message) = parse(&data).unwrap();
+    assert!(rem.is_empty());
+    match &message {
+        Message::Unchained(message) => {
+            assert_eq!(message.compression_algorithm, CompressionAlgorithm::Lz77);
+            assert_eq!(message.uncompressed, &original[..64]);
+            assert_eq!(message.compressed, &compressed[..]);
+        }
+        Message::Chained(_) => panic!("Expected unchained message!"),
+    }
+    assert_eq!(frame(&message), data);
+    let decompressed = message.decompress(0x1_0000).unwrap();
+    assert_eq!(decompressed, original);
+    let requests = Request::parse(&decompressed, Dialect::Smb3_1_1).unwrap();
+    assert_eq!(requests.len(), 3);
+
+    assert_eq!(message.decompress(original.len() - 1), Err(Error::TooLarge));
+
+    // a segment size that counts the prefix does not match the compressed data
+    let wrong = Message::Unchained(Unchained {
+        original_compressed_segment_size: original.len() as u32,
+        compression_algorithm: CompressionAlgorithm::Lz77,
+        uncompressed: &original[..64],
+        compressed: &compressed,
+    });
+    assert_eq!(wrong.decompress(0x1_0000), Err(Error::InvalidData));
+}
+
+#[cfg(all(feature = "lznt1", feature = "pattern-v1"))]
+#[test]
+#[allow(clippy::cast_possible_truncation)]
+fn chained() {
+    use smb2_packet::compression::{lznt1, pattern_v1};
+
+    let mut original = echo_requests();
+    original.extend_from_slice(&[0; 1000]);
+    let compressed = lznt1::compress(&original[64..original.len() - 1000]);
+    let pattern = pattern_v1::compress(&[0; 1000]).unwrap();
+    let message = Message::Chained(Chained {
+        original_compressed_segment_size: original.len() as u32,
+        payloads: vec![
+            Payload {
+                compression_algorithm: CompressionAlgorithm::None,
+                flags: Flags::empty(),
+                original_payload_size: None,
+                data: &original[..64],
+            },
+            Payload {
+                compression_algorithm: CompressionAlgorithm::Lznt1,
+                flags: Flags::CHAINED,
+                original_payload_size: Some(original.len() as u32 - 64 - 1000),
+                data: &compressed,
+            },
+            Payload {
+                compression_algorithm: CompressionAlgorithm::PatternV1,
+                flags: Flags::CHAINED,
+                original_payload_size: None,
+                data: &pattern,
+            },
+        ],
+    });
+    let data = frame(&message);
+    // the first payload header marks the chained form
+    assert_eq!(&data[12..20], &[0, 0, 1, 0, 64, 0, 0, 0]);
+    // the length of the LZNT1 payload includes its original size
+    let second = 4 + 8 + 8 + 64;
+    assert_eq!(
+        &data[second + 4..second + 8],
+        &(compressed.len() as u32 + 4).to_le_bytes()
+    );
+
+    let (rem, parsed) = parse(&data).unwrap();
+    assert!(rem.is_empty());
+    match &parsed {
+        Message::Chained(message) => {
+            assert_eq!(message.payloads.len(), 3);
+            assert_eq!(message.payloads[0].flags, Flags::CHAINED);
+            assert_eq!(message.payloads[1].data, &compressed[..]);
+            assert_eq!(message.payloads[2].original_payload_size, None);
+        }
+        Message::Unchained(_) => panic!("Expected chained message!"),
+    }
+    assert_eq!(frame(&parsed), data);
+    assert_eq!(parsed.decompress(0x1_0000).unwrap(), original);
+
+    // truncated payload
+    let mut truncated = data.clone();
+    truncated.pop();
+    truncated[3] -= 1;
+    assert!(parse(&truncated).is_err());
+}
+
+#[test]
+fn unsupported_algorithm() {
+    let message = Message::Unchained(Unchained {
+        original_compressed_segment_size: 4,
+        compression_algorithm: CompressionAlgorithm::Lz4,
+        uncompressed: &[],
+        compressed: &[1, 2, 3, 4],
+    });
+    let data = frame(&message);
+    let (_, parsed) = parse(&data).unwrap();
+    assert_eq!(
+        parsed.decompress(0x1_0000),
+        Err(Error::UnsupportedAlgorithm(5))
+    );
+
+    let mut framer = Framer::new(0x1_0000);
+    framer.push(&data).unwrap();
+    assert_eq!(
+        framer.next_message().unwrap(),
+        Some(transport::Message::Compressed(data))
+    );
+}
@@ -0,0 +1,317 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{response_header, roundtrip_request};
+use smb2_packet::command::create::{
+    Action, AppleQueryBitmap, AppleServerQuery, AppleServerQueryResponse, CreateContext,
+    CreateOptions, Disposition, DurableHandleRequestV2, ExtendedAttribute, Flags,
+    ImpersonationLevel, Lease, LeaseKey, LeaseState, OplockLevel, Request as CreateRequest,
+    Response as CreateResponse, ShareAccess,
+};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{
+    parse, serialize, AccessMask, Dialect, FileAttributes, FileId, Request, Response,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const FILE_ID: [u8; 16] = [
+    0x0d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x31, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+];
+const LEASE_KEY: [u8; 16] = [
+    0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef,
+];
+const CREATE_GUID: [u8; 16] = [
+    0xa1, 0xa2, 0xa3, 0xa4, 0xb1, 0xb2, 0xc1, 0xc2, 0xd1, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8,
+];
+
+fn timestamp() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_563_456_789)
+}
+
+fn all_access() -> AccessMask {
+    AccessMask::from_bits(0x001f_01ff).unwrap()
+}
+
+fn create_request(name: &str, contexts: Vec<CreateContext>) -> Vec<u8> {
+    let body = RequestBody::Create(CreateRequest {
+        requested_oplock_level: OplockLevel::Lease,
+        impersonation_level: ImpersonationLevel::Impersonation,
+        desired_access: AccessMask::FILE_READ_DATA
+            | AccessMask::FILE_READ_EA
+            | AccessMask::FILE_READ_ATTRIBUTES
+            | AccessMask::READ_CONTROL
+            | AccessMask::SYNCHRONIZE,
+        file_attributes: FileAttributes::empty(),
+        share_access: ShareAccess::READ | ShareAccess::WRITE,
+        create_disposition: Disposition::Open,
+        create_options: CreateOptions::NON_DIRECTORY_FILE,
+        name: name.into(),
+        contexts,
+    });
+    roundtrip_request(11, body, Dialect::Smb3_1_1)
+}
+
+#[test]
+fn request_contexts() {
+    let security_descriptor = [0x01, 0x00, 0x04, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let buffer = create_request(
+        "dir\\file.txt",
+        vec![
+            CreateContext::MaximalAccessRequest {
+                timestamp: Some(timestamp()),
+            },
+            CreateContext::QueryOnDiskId,
+            CreateContext::DurableHandleRequestV2(DurableHandleRequestV2 {
+                timeout: 60_000,
+                persistent: true,
+                create_guid: CREATE_GUID,
+            }),
+            CreateContext::Lease(Lease {
+                lease_key: LeaseKey::from(LEASE_KEY),
+                lease_state: LeaseState::READ_CACHING | LeaseState::HANDLE_CACHING,
+                break_in_progress: false,
+            }),
+            CreateContext::Timewarp(timestamp()),
+            CreateContext::ExtendedAttributes(vec![
+                ExtendedAttribute {
+                    flags: 0,
+                    name: "user.a".into(),
+                    value: b"1",
+                },
+                ExtendedAttribute {
+                    flags: 0x80,
+                    name: "b".into(),
+                    value: b"value",
+                },
+            ]),
+            CreateContext::SecurityDescriptor(&security_descriptor),
+            CreateContext::AllocationSize(0x10_0000),
+            CreateContext::AppleServerQuery(AppleServerQuery {
+                request_bitmap: AppleQueryBitmap::all(),
+                client_capabilities: 0x07,
+            }),
+            CreateContext::Unknown {
+                name: b"SVHDXOpenDeviceContext",
+                data: &[1, 2, 3],
+            },
+        ],
+    );
+
+    let (_, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    let body = match &requests[0].body {
+        RequestBody::Create(body) => body,
+        _ => panic!("Expected create request!"),
+    };
+    assert_eq!(body.name, "dir\\file.txt");
+    assert_eq!(body.contexts.len(), 10);
+    match &body.contexts[0] {
+        CreateContext::MaximalAccessRequest { timestamp: t } => assert_eq!(*t, Some(timestamp())),
+        _ => panic!("Expected maximal access request!"),
+    }
+    match &body.contexts[2] {
+        CreateContext::DurableHandleRequestV2(durable) => {
+            assert_eq!(durable.timeout, 60_000);
+            assert!(durable.persistent);
+            assert_eq!(durable.create_guid, CREATE_GUID);
+        }
+        _ => panic!("Expected durable handle v2 request!"),
+    }
+    match &body.contexts[3] {
+        CreateContext::Lease(lease) => {
+            assert_eq!(*lease.lease_key, LEASE_KEY);
+            assert_eq!(
+                lease.lease_state,
+                LeaseState::READ_CACHING | LeaseState::HANDLE_CACHING
+            );
+        }
+        _ => panic!("Expected lease v1!"),
+    }
+    match &body.contexts[5] {
+        CreateContext::ExtendedAttributes(attributes) => {
+            assert_eq!(attributes.len(), 2);
+            assert_eq!(attributes[0].name, "user.a");
+            assert_eq!(attributes[1].flags, 0x80);
+            assert_eq!(attributes[1].value, b"value");
+        }
+        _ => panic!("Expected extended attributes!"),
+    }
+    match &body.contexts[6] {
+        CreateContext::SecurityDescriptor(sd) => assert_eq!(*sd, &security_descriptor[..]),
+        _ => panic!("Expected security descriptor!"),
+    }
+    match &body.contexts[8] {
+        CreateContext::AppleServerQuery(query) => {
+            assert_eq!(query.request_bitmap, AppleQueryBitmap::all());
+            assert_eq!(query.client_capabilities, 0x07);
+        }
+        _ => panic!("Expected apple server query!"),
+    }
+    match &body.contexts[9] {
+        CreateContext::Unknown { name, data } => {
+            assert_eq!(*name, b"SVHDXOpenDeviceContext");
+            assert_eq!(*data, [1, 2, 3]);
+        }
+        _ => panic!("Expected unknown context!"),
+    }
+}
+
+#[test]
+fn request_contexts_empty_name() {
+    let buffer = create_request("", vec![CreateContext::DurableHandleRequest]);
+    // contexts follow the single padding byte of the empty name aligned to 8 bytes
+    assert_eq!(
+        &buffer[4 + 64 + 48..4 + 64 + 56],
+        &[0x80, 0, 0, 0, 40, 0, 0, 0]
+    );
+}
+
+#[test]
+fn response_contexts() {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 11),
+        body: ResponseBody::Create(CreateResponse {
+            oplock_level: OplockLevel::Lease,
+            flags: Flags::empty(),
+            create_action: Action::Opened,
+            creation_time: timestamp(),
+            last_access_time: timestamp(),
+            last_write_time: timestamp(),
+            change_time: timestamp(),
+            allocation_size: 0x1000,
+            end_of_file: 0x0123,
+            file_attributes: FileAttributes::ARCHIVE,
+            file_id: FileId::from(FILE_ID),
+            contexts: vec![
+                CreateContext::MaximalAccessResponse {
+                    query_status: NTStatus::StatusSuccess,
+                    maximal_access: all_access(),
+                },
+                CreateContext::OnDiskId {
+                    disk_file_id: 0x0002_0000_0000_1234,
+                    volume_id: 0x4321,
+                },
+                CreateContext::DurableHandleResponseV2 {
+                    timeout: 60_000,
+                    persistent: false,
+                },
+                CreateContext::Lease(Lease {
+                    lease_key: LeaseKey::from(LEASE_KEY),
+                    lease_state: LeaseState::READ_CACHING,
+                    break_in_progress: true,
+                }),
+                CreateContext::AppleServerQueryResponse(AppleServerQueryResponse {
+                    server_capabilities: Some(0x07),
+                    volume_capabilities: None,
+                    model: Some("MacSamba".into()),
+                }),
+            ],
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&responses, Dialect::Smb3_1_1), buffer);
+
+    let body = match &responses[0].body {
+        ResponseBody::Create(body) => body,
+        _ => panic!("Expected create response!"),
+    };
+    assert_eq!(body.contexts.len(), 5);
+    match &body.contexts[0] {
+        CreateContext::MaximalAccessResponse {
+            query_status,
+            maximal_access,
+        } => {
+            assert_eq!(*query_status, NTStatus::StatusSuccess);
+            assert_eq!(*maximal_access, all_access());
+        }
+        _ => panic!("Expected maximal access response!"),
+    }
+    match &body.contexts[1] {
+        CreateContext::OnDiskId { volume_id, .. } => assert_eq!(*volume_id, 0x4321),
+        _ => panic!("Expected on disk id!"),
+    }
+    match &body.contexts[3] {
+        CreateContext::Lease(lease) => assert!(lease.break_in_progress),
+        _ => panic!("Expected lease v1!"),
+    }
+    match &body.contexts[4] {
+        CreateContext::AppleServerQueryResponse(apple) => {
+            assert_eq!(apple.server_capabilities, Some(0x07));
+            assert_eq!(apple.volume_capabilities, None);
+            assert_eq!(apple.model.as_ref().unwrap(), "MacSamba");
+        }
+        _ => panic!("Expected apple server query response!"),
+    }
+}
+
+#[test]
+fn request_reserved_options_rejected() {
+    let buffer = create_request("file.txt", Vec::new());
+    let mut reserved = buffer.clone();
+    // create options
+    reserved[4 + 64 + 40 + 2] |= 0x04;
+    assert!(parse::<Request>(&reserved, Dialect::Smb3_1_1).is_err());
+    let mut reserved = buffer.clone();
+    // file attributes
+    reserved[4 + 64 + 28] |= 0x40;
+    assert!(parse::<Request>(&reserved, Dialect::Smb3_1_1).is_err());
+    let mut reserved = buffer;
+    // share access beyond the low byte
+    reserved[4 + 64 + 32 + 1] |= 0x01;
+    assert!(parse::<Request>(&reserved, Dialect::Smb3_1_1).is_err());
+}
+
+#[test]
+fn response_reparse_point() {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 11),
+        body: ResponseBody::Create(CreateResponse {
+            oplock_level: OplockLevel::No,
+            flags: Flags::REPARSEPOINT,
+            create_action: Action::Created,
+            creation_time: timestamp(),
+            last_access_time: timestamp(),
+            last_write_time: timestamp() + Duration::from_secs(90),
+            change_time: timestamp(),
+            allocation_size: 0,
+            end_of_file: 0,
+            file_attributes: FileAttributes::REPARSE_POINT,
+            file_id: FileId::from(FILE_ID),
+            contexts: Vec::new(),
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    // no create contexts, so neither offset nor length are set and the
+    // empty buffer is a single padding byte
+    assert_eq!(buffer.len(), 4 + 64 + 89);
+    assert_eq!(&buffer[4 + 64 + 80..], &[0; 9]);
+
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    let body = match &responses[0].body {
+        ResponseBody::Create(body) => body,
+        _ => panic!("Expected create response!"),
+    };
+    assert_eq!(body.flags, Flags::REPARSEPOINT);
+    assert_eq!(body.create_action, Action::Created);
+    assert_eq!(body.last_write_time, timestamp() + Duration::from_secs(90));
+    assert_eq!(body.file_attributes, FileAttributes::REPARSE_POINT);
+    assert!(body.contexts.is_empty());
+
+    // unknown attribute bits are dropped instead of failing the whole packet
+    let mut unknown = buffer.clone();
+    unknown[4 + 64 + 56] |= 0x40;
+    let (_, responses) = parse::<Response>(&unknown, Dialect::Smb3_1_1).unwrap();
+    match &responses[0].body {
+        ResponseBody::Create(body) => {
+            assert_eq!(body.file_attributes, FileAttributes::REPARSE_POINT);
+        }
+        _ => panic!("Expected create response!"),
+    }
+}
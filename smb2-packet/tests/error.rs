@@ -0,0 +1,198 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::response_header;
+use smb2_packet::command::error::Response as ErrorResponse;
+use smb2_packet::command::error::{Context, Detail, ErrorData, ShareRedirect, SymbolicLink};
+use smb2_packet::command::ResponseBody;
+use smb2_packet::header::Command;
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{parse, serialize, Dialect, Response};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+fn serialize_error(
+    status: NTStatus,
+    command: Command,
+    error_data: ErrorData,
+    buffer: &mut Vec<u8>,
+) {
+    let response = Response {
+        header: response_header(status, 7),
+        body: ResponseBody::Error(ErrorResponse {
+            status,
+            command,
+            error_data,
+        }),
+    };
+    *buffer = serialize(&[response], Dialect::Smb3_1_1);
+}
+
+fn parse_error(buffer: &[u8]) -> ErrorResponse {
+    let (rem, mut responses) = parse::<Response>(buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(responses.len(), 1);
+    match responses.remove(0).body {
+        ResponseBody::Error(error) => error,
+        _ => panic!("Expected error response!"),
+    }
+}
+
+#[test]
+fn empty() {
+    let mut buffer = Vec::new();
+    serialize_error(
+        NTStatus::StatusAccessDenied,
+        Command::Create,
+        ErrorData::Detail(Detail::Empty),
+        &mut buffer,
+    );
+    // header + structure + one byte of ErrorData
+    assert_eq!(buffer.len(), 4 + 64 + 9);
+    let error = parse_error(&buffer);
+    assert_eq!(error.status, NTStatus::StatusAccessDenied);
+    assert_eq!(error.command, Command::Create);
+    match error.error_data {
+        ErrorData::Detail(Detail::Empty) => (),
+        _ => panic!("Expected empty error data!"),
+    }
+}
+
+#[test]
+fn buffer_too_small() {
+    let mut buffer = Vec::new();
+    serialize_error(
+        NTStatus::StatusBufferTooSmall,
+        Command::QueryInfo,
+        ErrorData::Detail(Detail::BufferTooSmall(0x68)),
+        &mut buffer,
+    );
+    assert_eq!(&buffer[4 + 64..], &[9, 0, 0, 0, 4, 0, 0, 0, 0x68, 0, 0, 0]);
+    match parse_error(&buffer).error_data {
+        ErrorData::Detail(Detail::BufferTooSmall(len)) => assert_eq!(len, 0x68),
+        _ => panic!("Expected buffer too small!"),
+    }
+}
+
+#[test]
+fn symbolic_link() {
+    let mut buffer = Vec::new();
+    serialize_error(
+        NTStatus::StatusStoppedOnSymlink,
+        Command::Create,
+        ErrorData::Detail(Detail::SymbolicLink(SymbolicLink {
+            unparsed_path_length: 8,
+            substitute_name: "..\\target".to_string(),
+            print_name: "..\\target".to_string(),
+            relative: true,
+        })),
+        &mut buffer,
+    );
+    let body = &buffer[4 + 64 + 8..];
+    // SymLinkLength excludes itself, ReparseDataLength starts at SubstituteNameOffset
+    assert_eq!(&body[..4], &(24u32 + 36).to_le_bytes());
+    assert_eq!(&body[4..8], b"SYML");
+    assert_eq!(&body[12..14], &(12u16 + 36).to_le_bytes());
+    match parse_error(&buffer).error_data {
+        ErrorData::Detail(Detail::SymbolicLink(symlink)) => {
+            assert_eq!(symlink.unparsed_path_length, 8);
+            assert_eq!(symlink.substitute_name, "..\\target");
+            assert_eq!(symlink.print_name, "..\\target");
+            assert!(symlink.relative);
+        }
+        _ => panic!("Expected symbolic link!"),
+    }
+}
+
+#[test]
+fn contexts() {
+    let mut buffer = Vec::new();
+    let v4 = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+    let v6 = IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+    serialize_error(
+        NTStatus::StatusBadNetworkName,
+        Command::TreeConnect,
+        ErrorData::Contexts(vec![
+            Context::ShareRedirect(ShareRedirect {
+                ip_addr_move_list: vec![v4, v6],
+                resource_name: "share".to_string(),
+            }),
+            Context::Unknown {
+                error_id: 0x1234_5678,
+                data: &[1, 2, 3],
+            },
+            Context::Default(Detail::Empty),
+        ]),
+        &mut buffer,
+    );
+    // second context starts 8 byte aligned: 8 + 24 + 2 * 24 + 10 = 90 -> 96
+    let error_data = &buffer[4 + 64 + 8..];
+    assert_eq!(&error_data[96 + 4..96 + 8], &0x1234_5678u32.to_le_bytes());
+    let contexts = match parse_error(&buffer).error_data {
+        ErrorData::Contexts(contexts) => contexts,
+        _ => panic!("Expected error contexts!"),
+    };
+    assert_eq!(contexts.len(), 3);
+    match &contexts[0] {
+        Context::ShareRedirect(redirect) => {
+            assert_eq!(redirect.ip_addr_move_list, [v4, v6]);
+            assert_eq!(redirect.resource_name, "share");
+        }
+        _ => panic!("Expected share redirect!"),
+    }
+    match contexts[1] {
+        Context::Unknown { error_id, data } => {
+            assert_eq!(error_id, 0x1234_5678);
+            assert_eq!(data, [1, 2, 3]);
+        }
+        _ => panic!("Expected unknown context!"),
+    }
+    match contexts[2] {
+        Context::Default(Detail::Empty) => (),
+        _ => panic!("Expected empty default context!"),
+    }
+}
+
+#[test]
+fn ioctl_invalid_parameter() {
+    use smb2_packet::command::ioctl::{CopychunkResponse, Output, Response as IoctlResponse};
+    use smb2_packet::FileId;
+
+    // SRV_COPYCHUNK answers with the server limits in a regular IOCTL response
+    let limits = CopychunkResponse {
+        chunks_written: 256,
+        chunk_bytes_written: 0x0010_0000,
+        total_bytes_written: 0x0100_0000,
+    };
+    let response = Response {
+        header: response_header(NTStatus::StatusInvalidParameter, 7),
+        body: ResponseBody::Ioctl(IoctlResponse {
+            file_id: FileId::from([0x11; 16]),
+            output: Output::SrvCopychunk(limits),
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    let (_, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    match &responses[0].body {
+        ResponseBody::Ioctl(IoctlResponse {
+            output: Output::SrvCopychunk(output),
+            ..
+        }) => assert_eq!(*output, limits),
+        _ => panic!("Expected copychunk response!"),
+    }
+
+    // any other IOCTL fails with an error body
+    let mut buffer = Vec::new();
+    serialize_error(
+        NTStatus::StatusInvalidParameter,
+        Command::Ioctl,
+        ErrorData::Detail(Detail::Empty),
+        &mut buffer,
+    );
+    let error = parse_error(&buffer);
+    assert_eq!(error.status, NTStatus::StatusInvalidParameter);
+    assert_eq!(error.command, Command::Ioctl);
+}
@@ -0,0 +1,136 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{hex, parse_pcap_requests};
+use smb2_packet::command::negotiate::{Cipher, HashAlgorithm};
+use smb2_packet::keys::{derive, PreauthHash, SessionKeys};
+use smb2_packet::Dialect;
+
+/// Feeds the captured SMB 3.1.1 NEGOTIATE request into a fresh hash.
+fn preauth_hash() -> PreauthHash {
+    let mut buffer = Vec::new();
+    parse_pcap_requests(
+        "negotiate_with_context_request",
+        &mut buffer,
+        Dialect::Smb3_1_1,
+    )
+    .unwrap();
+    let mut hash = PreauthHash::new(HashAlgorithm::Sha512);
+    assert_eq!(*hash.value(), [0; 64]);
+    hash.update(&buffer[4..]);
+    hash
+}
+
+// Unless noted otherwise the expected values were computed with the SHA-512 and
+// SP800-108 KDF implementations of the Python cryptography package.
+
+#[test]
+fn kdf() {
+    let key = [
+        0x7c, 0xd4, 0x51, 0x82, 0x5d, 0x04, 0x50, 0xd2, 0x35, 0x42, 0x4e, 0x44, 0xba, 0x6e, 0x78,
+        0xcc,
+    ];
+    // longer outputs use multiple PRF blocks
+    let mut out = [0; 40];
+    derive(&key, b"label\0", b"ctx", &mut out);
+    assert_eq!(
+        hex(&out),
+        "29821e0d7d989d2f140e7a677ef7b74150b65a2c0ab9037f2fe850cd8d70f3c35ea343ade2952e45"
+    );
+}
+
+/// Test vector for SMB 3.0 published by Microsoft.
+#[test]
+fn smb3_0_keys() {
+    let session_key = [
+        0x7c, 0xd4, 0x51, 0x82, 0x5d, 0x04, 0x50, 0xd2, 0x35, 0x42, 0x4e, 0x44, 0xba, 0x6e, 0x78,
+        0xcc,
+    ];
+    let keys =
+        SessionKeys::derive(&session_key, Dialect::Smb3_0_0, Cipher::Aes128Ccm, None).unwrap();
+    assert_eq!(hex(&keys.signing_key), "0b7e9c5cac36c0f6ea9ab275298cedce");
+    assert_eq!(
+        hex(&keys.encryption_key),
+        "b0f0427f7ceb416d1d9dcc0cd4f99447"
+    );
+    assert_eq!(
+        hex(&keys.decryption_key),
+        "fad27796665b313ebb578f388632b4f7"
+    );
+    assert_eq!(
+        hex(&keys.application_key),
+        "bb23a4575aa26c721af525af15a87b4f"
+    );
+
+    // the preauth hash is not used before SMB 3.1.1
+    let with_hash = SessionKeys::derive(
+        &session_key,
+        Dialect::Smb3_0_2,
+        Cipher::Aes128Ccm,
+        Some(&preauth_hash()),
+    );
+    assert_eq!(with_hash, Some(keys));
+    assert_eq!(
+        SessionKeys::derive(&session_key, Dialect::Smb2_1_0, Cipher::Aes128Ccm, None),
+        None
+    );
+}
+
+#[test]
+fn smb3_1_1_keys() {
+    let hash = preauth_hash();
+    assert_eq!(
+        hex(hash.value()),
+        "2842ca1aa03c5f1e5bd30acff00a8b72263a6300af180a66efd6f9d9d55de120\
+         ad3784967525bf4db53c689f609d13f376730aa5448fd4914a34874279d9e0fe"
+    );
+
+    let session_key: Vec<u8> = (0..32).collect();
+    assert_eq!(
+        SessionKeys::derive(&session_key, Dialect::Smb3_1_1, Cipher::Aes128Gcm, None),
+        None
+    );
+
+    let keys = SessionKeys::derive(
+        &session_key,
+        Dialect::Smb3_1_1,
+        Cipher::Aes128Gcm,
+        Some(&hash),
+    )
+    .unwrap();
+    assert_eq!(hex(&keys.signing_key), "9ee17233a170eb48d8bcb42d51294d21");
+    assert_eq!(
+        hex(&keys.application_key),
+        "0a96aea2a3f85e684667ab2bb0fa448b"
+    );
+    assert_eq!(
+        hex(&keys.encryption_key),
+        "93294d06b27b1f4dffec61ac3b23ee08"
+    );
+    assert_eq!(
+        hex(&keys.decryption_key),
+        "cf9ddfa5e6e9e0855e17699541ea3df0"
+    );
+
+    // AES-256 derives longer cipher keys from the full session key
+    let keys = SessionKeys::derive(
+        &session_key,
+        Dialect::Smb3_1_1,
+        Cipher::Aes256Gcm,
+        Some(&hash),
+    )
+    .unwrap();
+    assert_eq!(hex(&keys.signing_key), "9ee17233a170eb48d8bcb42d51294d21");
+    assert_eq!(
+        hex(&keys.encryption_key),
+        "b7b75a8ec9c5b8ad10442c6544bb0591a3a91bdda49419a767a05a6cfe7a7ff6"
+    );
+    assert_eq!(
+        hex(&keys.decryption_key),
+        "4f1adc1dc22ef95dcd83b5c288d31861c172ebf63bb31b9674ab39704b28a4fa"
+    );
+}
@@ -0,0 +1,110 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{response_header, serialize_request};
+use smb2_packet::command::lock::{Element, Flags, LockSequence, Request as LockRequest};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{parse, serialize, Dialect, FileId, Request, Response};
+
+const FILE_ID: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+];
+
+fn lock_request(dialect: Dialect) -> Vec<u8> {
+    let body = RequestBody::Lock(LockRequest {
+        lock_sequence: Some(LockSequence {
+            number: 0x0a,
+            index: 0x0123_4567,
+        }),
+        file_id: FileId::from(FILE_ID),
+        locks: vec![
+            Element {
+                offset: 0,
+                length: 0x200,
+                flags: Flags::EXCLUSIVE_LOCK | Flags::FAIL_IMMEDIATELY,
+            },
+            Element {
+                offset: 0x7fff_ffff_ffff_fff0,
+                length: 0x10,
+                flags: Flags::SHARED_LOCK,
+            },
+        ],
+    });
+    serialize_request(3, body, dialect)
+}
+
+#[test]
+fn request() {
+    let buffer = lock_request(Dialect::Smb3_1_1);
+    // header + structure + one additional element
+    assert_eq!(buffer.len(), 4 + 64 + 48 + 24);
+
+    let (rem, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    let body = match &requests[0].body {
+        RequestBody::Lock(body) => body,
+        _ => panic!("Expected lock request!"),
+    };
+    assert_eq!(
+        body.lock_sequence,
+        Some(LockSequence {
+            number: 0x0a,
+            index: 0x0123_4567,
+        })
+    );
+    assert_eq!(*body.file_id, FILE_ID);
+    assert_eq!(body.locks.len(), 2);
+    assert_eq!(body.locks[0].length, 0x200);
+    assert_eq!(
+        body.locks[0].flags,
+        Flags::EXCLUSIVE_LOCK | Flags::FAIL_IMMEDIATELY
+    );
+    assert_eq!(body.locks[1].offset, 0x7fff_ffff_ffff_fff0);
+    assert_eq!(body.locks[1].flags, Flags::SHARED_LOCK);
+}
+
+#[test]
+fn request_smb2_0_2_has_no_sequence() {
+    let buffer = lock_request(Dialect::Smb2_0_2);
+    let (_, requests) = parse::<Request>(&buffer, Dialect::Smb2_0_2).unwrap();
+    match &requests[0].body {
+        RequestBody::Lock(body) => assert_eq!(body.lock_sequence, None),
+        _ => panic!("Expected lock request!"),
+    }
+    // the sequence field is reserved and written as zero
+    assert_eq!(&buffer[4 + 64 + 4..4 + 64 + 8], &[0; 4]);
+    assert_eq!(
+        serialize(&requests, Dialect::Smb2_0_2)[4 + 64..],
+        buffer[4 + 64..]
+    );
+}
+
+#[test]
+fn request_reserved_flags_rejected() {
+    let mut buffer = lock_request(Dialect::Smb3_1_1);
+    // flags of the first element
+    buffer[4 + 64 + 24 + 16] |= 0x80;
+    assert!(parse::<Request>(&buffer, Dialect::Smb3_1_1).is_err());
+}
+
+#[test]
+fn response() {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 3),
+        body: ResponseBody::Lock,
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    assert_eq!(buffer.len(), 4 + 64 + 4);
+
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    match responses[0].body {
+        ResponseBody::Lock => (),
+        _ => panic!("Expected lock response!"),
+    }
+}
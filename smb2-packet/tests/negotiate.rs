@@ -0,0 +1,269 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{request_header, response_header};
+use smb2_packet::command::negotiate::{
+    Capabilities, Cipher, CompressionAlgorithm, CompressionCapabilities, CompressionFlags, Context,
+    HashAlgorithm, PreauthIntegrityCapabilities, RdmaTransform, Request as NegotiateRequest,
+    Response as NegotiateResponse, SigningAlgorithm, TransportCapabilities,
+};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{parse, serialize, ClientGuid, Dialect, Request, Response};
+use std::time::{Duration, UNIX_EPOCH};
+
+const BODY: usize = 4 + 64;
+const GUID: [u8; 16] = [
+    0x3f, 0x2b, 0x11, 0x07, 0x5e, 0x8a, 0x4c, 0x90, 0x91, 0x0f, 0xa7, 0x4e, 0x62, 0x20, 0xd1, 0x5c,
+];
+const SALT: [u8; 32] = [0x5a; 32];
+
+fn contexts() -> Vec<Context<'static>> {
+    vec![
+        Context::PreauthIntegrityCapabilities(PreauthIntegrityCapabilities {
+            hash_algorithms: vec![HashAlgorithm::Sha512],
+            salt: &SALT,
+        }),
+        Context::EncryptionCapabilities(vec![
+            Cipher::Aes256Gcm,
+            Cipher::Aes256Ccm,
+            Cipher::Aes128Gcm,
+            Cipher::Aes128Ccm,
+        ]),
+        Context::CompressionCapabilities(CompressionCapabilities {
+            flags: CompressionFlags::CHAINED,
+            compression_algorithms: vec![
+                CompressionAlgorithm::PatternV1,
+                CompressionAlgorithm::Lz77,
+                CompressionAlgorithm::Lz4,
+            ],
+        }),
+        Context::NetName("fileserver.example.com".into()),
+        Context::TransportCapabilities(TransportCapabilities::ACCEPT_TRANSPORT_LEVEL_SECURITY),
+        Context::RdmaTransformCapabilities(vec![RdmaTransform::Encryption, RdmaTransform::Signing]),
+        Context::SigningCapabilities(vec![
+            SigningAlgorithm::AesGmac,
+            SigningAlgorithm::AesCmac,
+            SigningAlgorithm::HmacSha256,
+        ]),
+    ]
+}
+
+fn check_contexts(contexts: &[Context]) {
+    assert_eq!(contexts.len(), 7);
+    match &contexts[1] {
+        Context::EncryptionCapabilities(ciphers) => assert_eq!(
+            *ciphers,
+            [
+                Cipher::Aes256Gcm,
+                Cipher::Aes256Ccm,
+                Cipher::Aes128Gcm,
+                Cipher::Aes128Ccm
+            ]
+        ),
+        _ => panic!("Expected encryption capabilities!"),
+    }
+    match &contexts[2] {
+        Context::CompressionCapabilities(caps) => {
+            assert_eq!(caps.flags, CompressionFlags::CHAINED);
+            assert_eq!(
+                caps.compression_algorithms,
+                [
+                    CompressionAlgorithm::PatternV1,
+                    CompressionAlgorithm::Lz77,
+                    CompressionAlgorithm::Lz4
+                ]
+            );
+        }
+        _ => panic!("Expected compression capabilities!"),
+    }
+    match &contexts[3] {
+        Context::NetName(name) => assert_eq!(name, "fileserver.example.com"),
+        _ => panic!("Expected net name!"),
+    }
+    match &contexts[4] {
+        Context::TransportCapabilities(caps) => {
+            assert_eq!(
+                *caps,
+                TransportCapabilities::ACCEPT_TRANSPORT_LEVEL_SECURITY
+            );
+        }
+        _ => panic!("Expected transport capabilities!"),
+    }
+    match &contexts[5] {
+        Context::RdmaTransformCapabilities(transforms) => {
+            assert_eq!(
+                *transforms,
+                [RdmaTransform::Encryption, RdmaTransform::Signing]
+            );
+        }
+        _ => panic!("Expected rdma transform capabilities!"),
+    }
+    match &contexts[6] {
+        Context::SigningCapabilities(algos) => assert_eq!(
+            *algos,
+            [
+                SigningAlgorithm::AesGmac,
+                SigningAlgorithm::AesCmac,
+                SigningAlgorithm::HmacSha256
+            ]
+        ),
+        _ => panic!("Expected signing capabilities!"),
+    }
+}
+
+#[test]
+fn request_contexts() {
+    let request = Request {
+        header: request_header(0),
+        body: RequestBody::Negotiate(NegotiateRequest {
+            signing_required: true,
+            capabilities: Capabilities::all(),
+            client_guid: ClientGuid::from(GUID),
+            dialects: vec![Dialect::Smb3_0_2, Dialect::Smb3_1_1],
+            negotiate_contexts: contexts(),
+        }),
+    };
+    let buffer = serialize(&[request], Dialect::Smb3_1_1);
+    let (rem, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&requests, Dialect::Smb3_1_1), buffer);
+
+    match &requests[0].body {
+        RequestBody::Negotiate(body) => {
+            assert_eq!(*body.client_guid, GUID);
+            check_contexts(&body.negotiate_contexts);
+        }
+        _ => panic!("Expected negotiate request!"),
+    }
+}
+
+#[test]
+fn response_contexts() {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 0),
+        body: ResponseBody::Negotiate(NegotiateResponse {
+            signing_required: false,
+            dialect: Dialect::Smb3_1_1,
+            server_guid: ClientGuid::from(GUID),
+            capabilities: Capabilities::DFS | Capabilities::LEASING | Capabilities::LARGE_MTU,
+            max_transact_size: 0x0080_0000,
+            max_read_size: 0x0080_0000,
+            max_write_size: 0x0080_0000,
+            system_time: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            server_start_time: UNIX_EPOCH,
+            security_buffer: Some(&[0x60, 0x48, 0x06, 0x06]),
+            negotiate_contexts: contexts(),
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    // negotiate context count
+    assert_eq!(&buffer[BODY + 6..BODY + 8], &[7, 0]);
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(serialize(&responses, Dialect::Smb3_1_1), buffer);
+
+    match &responses[0].body {
+        ResponseBody::Negotiate(body) => {
+            assert_eq!(body.dialect, Dialect::Smb3_1_1);
+            assert_eq!(*body.server_guid, GUID);
+            assert_eq!(body.security_buffer, Some(&[0x60, 0x48, 0x06, 0x06][..]));
+            check_contexts(&body.negotiate_contexts);
+        }
+        _ => panic!("Expected negotiate response!"),
+    }
+}
+
+#[test]
+fn response_unknown_capabilities_truncated() {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 0),
+        body: ResponseBody::Negotiate(NegotiateResponse {
+            signing_required: false,
+            dialect: Dialect::Smb3_1_1,
+            server_guid: ClientGuid::from(GUID),
+            capabilities: Capabilities::LEASING | Capabilities::NOTIFICATIONS,
+            max_transact_size: 0x0080_0000,
+            max_read_size: 0x0080_0000,
+            max_write_size: 0x0080_0000,
+            system_time: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+            server_start_time: UNIX_EPOCH,
+            security_buffer: None,
+            negotiate_contexts: contexts(),
+        }),
+    };
+    let mut buffer = serialize(&[response], Dialect::Smb3_1_1);
+    let capabilities = BODY + 24;
+    assert_eq!(&buffer[capabilities..capabilities + 4], &[0x82, 0, 0, 0]);
+    buffer[capabilities + 1] = 0x01;
+    buffer[capabilities + 3] = 0x40;
+    let (_, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    match &responses[0].body {
+        ResponseBody::Negotiate(body) => assert_eq!(
+            body.capabilities,
+            Capabilities::LEASING | Capabilities::NOTIFICATIONS
+        ),
+        _ => panic!("Expected negotiate response!"),
+    }
+}
+
+#[test]
+fn request_unknown_ids_skipped() {
+    // raw contexts that mix an unknown id or flag with a known one
+    let raw: [(u16, &[u8]); 6] = [
+        (0x01, &[2, 0, 0, 0, 0x02, 0, 0x01, 0]),
+        (0x02, &[2, 0, 0x05, 0, 0x02, 0]),
+        (0x03, &[2, 0, 0, 0, 0x03, 0, 0, 0, 0x06, 0, 0x02, 0]),
+        (0x06, &[0x05, 0, 0, 0]),
+        (0x07, &[2, 0, 0, 0, 0, 0, 0, 0, 0x03, 0, 0x01, 0]),
+        (0x08, &[2, 0, 0x03, 0, 0x01, 0]),
+    ];
+    let request = Request {
+        header: request_header(0),
+        body: RequestBody::Negotiate(NegotiateRequest {
+            signing_required: false,
+            capabilities: Capabilities::ENCRYPTION,
+            client_guid: ClientGuid::from(GUID),
+            dialects: vec![Dialect::Smb3_1_1],
+            negotiate_contexts: raw
+                .iter()
+                .map(|(context_type, data)| Context::Unknown {
+                    context_type: *context_type,
+                    data,
+                })
+                .collect(),
+        }),
+    };
+    let mut buffer = serialize(&[request], Dialect::Smb3_1_1);
+    // capabilities
+    buffer[BODY + 8 + 3] = 0x80;
+    let (_, requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    let body = match &requests[0].body {
+        RequestBody::Negotiate(body) => body,
+        _ => panic!("Expected negotiate request!"),
+    };
+    assert_eq!(body.capabilities, Capabilities::ENCRYPTION);
+    match &body.negotiate_contexts[..] {
+        [Context::PreauthIntegrityCapabilities(preauth), Context::EncryptionCapabilities(ciphers), Context::CompressionCapabilities(compression), Context::TransportCapabilities(transport), Context::RdmaTransformCapabilities(transforms), Context::SigningCapabilities(algos)] =>
+        {
+            assert_eq!(preauth.hash_algorithms, [HashAlgorithm::Sha512]);
+            assert_eq!(*ciphers, [Cipher::Aes128Gcm]);
+            assert_eq!(compression.flags, CompressionFlags::CHAINED);
+            assert_eq!(
+                compression.compression_algorithms,
+                [CompressionAlgorithm::Lz77]
+            );
+            assert_eq!(
+                *transport,
+                TransportCapabilities::ACCEPT_TRANSPORT_LEVEL_SECURITY
+            );
+            assert_eq!(*transforms, [RdmaTransform::Encryption]);
+            assert_eq!(*algos, [SigningAlgorithm::AesCmac]);
+        }
+        _ => panic!("Expected known contexts!"),
+    }
+}
@@ -0,0 +1,468 @@
+#![cfg(feature = "ntlm")]
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{hex, parse_pcap_requests, parse_pcap_responses};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::ntlm::{
+    authenticate, mic, parse_authenticate, parse_challenge, parse_negotiate, parse_ntlmv2_response,
+    AvFlags, AvPair, Challenge, Error, NegotiateFlags, PasswordHashProvider, Version, KEY_SIZE,
+    REVISION,
+};
+use smb2_packet::Dialect;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// NT hash of "Password", the password of the examples in MS-NLMP 4.2.
+const NT_HASH: [u8; KEY_SIZE] = [
+    0xa4, 0xf4, 0x9c, 0x40, 0x65, 0x10, 0xbd, 0xca, 0xb6, 0x82, 0x4e, 0xe7, 0xc3, 0x0f, 0xd8, 0x52,
+];
+const SERVER_CHALLENGE: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+/// The flags of the CHALLENGE message of the examples.
+const FLAGS: u32 = 0xe28a_8233;
+
+struct Users;
+
+impl PasswordHashProvider for Users {
+    fn nt_hash(&self, user_name: &str, _domain_name: &str) -> Option<[u8; KEY_SIZE]> {
+        if user_name.eq_ignore_ascii_case("user") {
+            Some(NT_HASH)
+        } else {
+            None
+        }
+    }
+}
+
+fn utf16(string: &str) -> Vec<u8> {
+    string.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn example_challenge() -> Challenge<'static> {
+    Challenge {
+        flags: NegotiateFlags::from_bits(FLAGS).unwrap(),
+        target_name: "Server".to_string(),
+        server_challenge: SERVER_CHALLENGE,
+        target_info: vec![
+            AvPair::NbDomainName("Domain".to_string()),
+            AvPair::NbComputerName("Server".to_string()),
+        ],
+        version: Some(Version {
+            major: 6,
+            minor: 0,
+            build: 6000,
+            revision: REVISION,
+        }),
+    }
+}
+
+/// The NTLM v2 response of the examples with the MIC announced if `with_mic` is set.
+fn nt_response(with_mic: bool) -> Vec<u8> {
+    let (proof, extra): (&str, &[u8]) = if with_mic {
+        (
+            "bd5a24700ce3f1862575689b9bde5f99",
+            &[6, 0, 4, 0, 2, 0, 0, 0],
+        )
+    } else {
+        ("68cd0ab851e51c96aabc927bebef6a1c", &[])
+    };
+    let mut out: Vec<_> = (0..16)
+        .map(|i| u8::from_str_radix(&proof[2 * i..2 * i + 2], 16).unwrap())
+        .collect();
+    out.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0]);
+    out.extend_from_slice(&[0; 8]); /* timestamp */
+    out.extend_from_slice(&[0xaa; 8]);
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&[2, 0, 12, 0]);
+    out.extend_from_slice(&utf16("Domain"));
+    out.extend_from_slice(&[1, 0, 12, 0]);
+    out.extend_from_slice(&utf16("Server"));
+    out.extend_from_slice(extra);
+    out.extend_from_slice(&[0; 4]);
+    if !with_mic {
+        out.extend_from_slice(&[0; 4]);
+    }
+    out
+}
+
+/// Builds an AUTHENTICATE message with version and, if `with_mic` is set, a zero MIC.
+#[allow(clippy::cast_possible_truncation)]
+fn authenticate_message(
+    flags: u32,
+    nt_challenge_response: &[u8],
+    user_name: &str,
+    session_key: &[u8],
+    with_mic: bool,
+) -> Vec<u8> {
+    let fields = [
+        vec![0; 24],
+        nt_challenge_response.to_vec(),
+        utf16("Domain"),
+        utf16(user_name),
+        utf16("COMPUTER"),
+        session_key.to_vec(),
+    ];
+    let mut offset = if with_mic { 88 } else { 72 };
+    let mut out = b"NTLMSSP\0\x03\0\0\0".to_vec();
+    for field in &fields {
+        out.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&[6, 0, 0x70, 0x17, 0, 0, 0, 0x0f]);
+    if with_mic {
+        out.extend_from_slice(&[0; 16]);
+    }
+    for field in &fields {
+        out.extend_from_slice(field);
+    }
+    out
+}
+
+fn negotiate_message() -> Vec<u8> {
+    let mut out = b"NTLMSSP\0\x01\0\0\0".to_vec();
+    out.extend_from_slice(&0xe208_b207_u32.to_le_bytes());
+    out.extend_from_slice(&[6, 0, 6, 0, 40, 0, 0, 0]);
+    out.extend_from_slice(&[8, 0, 8, 0, 46, 0, 0, 0]);
+    out.extend_from_slice(&[6, 1, 0xb1, 0x1d, 0, 0, 0, 0x0f]);
+    out.extend_from_slice(b"DOMAINCOMPUTER");
+    out
+}
+
+#[test]
+fn negotiate() {
+    let data = negotiate_message();
+    let (rem, negotiate) = parse_negotiate(&data).unwrap();
+    assert!(rem.is_empty());
+    assert!(negotiate.flags.contains(
+        NegotiateFlags::UNICODE
+            | NegotiateFlags::OEM_DOMAIN_SUPPLIED
+            | NegotiateFlags::OEM_WORKSTATION_SUPPLIED
+            | NegotiateFlags::KEY_EXCH
+    ));
+    assert_eq!(negotiate.domain_name, b"DOMAIN");
+    assert_eq!(negotiate.workstation, b"COMPUTER");
+    assert_eq!(
+        negotiate.version,
+        Some(Version {
+            major: 6,
+            minor: 1,
+            build: 7601,
+            revision: REVISION
+        })
+    );
+
+    let mut truncated = data.clone();
+    truncated.truncate(44);
+    assert!(parse_negotiate(&truncated).is_err());
+    assert!(parse_negotiate(&data[..30]).is_err());
+}
+
+#[test]
+fn challenge() {
+    // the CHALLENGE message of MS-NLMP 4.2.4.3
+    let mut data = Vec::new();
+    example_challenge().write_to(&mut data);
+    assert_eq!(
+        hex(&data),
+        "4e544c4d53535000020000000c000c003800000033828ae20123456789abcdef\
+         00000000000000002400240044000000060070170000000f5300650072007600\
+         6500720002000c0044006f006d00610069006e0001000c005300650072007600\
+         6500720000000000"
+    );
+
+    let (rem, challenge) = parse_challenge(&data).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(challenge.flags.bits(), FLAGS);
+    assert_eq!(challenge.target_name, "Server");
+    assert_eq!(challenge.server_challenge, SERVER_CHALLENGE);
+    assert_eq!(challenge.target_info, example_challenge().target_info);
+    assert_eq!(challenge.version, example_challenge().version);
+
+    // VERSION follows the version field and the target info gets a timestamp
+    let time = UNIX_EPOCH + Duration::from_secs(1_555_869_594);
+    let mut data = Vec::new();
+    Challenge {
+        flags: NegotiateFlags::UNICODE,
+        target_info: vec![AvPair::Timestamp(time)],
+        version: None,
+        ..example_challenge()
+    }
+    .write_to(&mut data);
+    let (_, challenge) = parse_challenge(&data).unwrap();
+    assert_eq!(
+        challenge.flags,
+        NegotiateFlags::UNICODE | NegotiateFlags::TARGET_INFO
+    );
+    assert_eq!(challenge.target_info, [AvPair::Timestamp(time)]);
+    assert_eq!(challenge.version, None);
+}
+
+#[test]
+fn challenge_flags() {
+    let requested = NegotiateFlags::from_bits_truncate(0xe208_b297);
+    assert_eq!(
+        NegotiateFlags::challenge(requested),
+        NegotiateFlags::UNICODE
+            | NegotiateFlags::REQUEST_TARGET
+            | NegotiateFlags::SIGN
+            | NegotiateFlags::NTLM
+            | NegotiateFlags::ALWAYS_SIGN
+            | NegotiateFlags::TARGET_TYPE_SERVER
+            | NegotiateFlags::EXTENDED_SESSIONSECURITY
+            | NegotiateFlags::TARGET_INFO
+            | NegotiateFlags::NEGOTIATE_128
+            | NegotiateFlags::KEY_EXCH
+            | NegotiateFlags::NEGOTIATE_56
+    );
+    assert_eq!(
+        NegotiateFlags::challenge(NegotiateFlags::OEM),
+        NegotiateFlags::OEM | NegotiateFlags::NTLM | NegotiateFlags::TARGET_INFO
+    );
+}
+
+#[test]
+fn ntlmv2() {
+    // the NTLMv2 example of MS-NLMP 4.2.4 with key exchange
+    let negotiate = negotiate_message();
+    let mut challenge = Vec::new();
+    example_challenge().write_to(&mut challenge);
+    let encrypted_session_key = [
+        0xc5, 0xda, 0xd2, 0x54, 0x4f, 0xc9, 0x79, 0x90, 0x94, 0xce, 0x1c, 0xe9, 0x0b, 0xc9, 0xd0,
+        0x3e,
+    ];
+    let message = authenticate_message(
+        FLAGS,
+        &nt_response(false),
+        "User",
+        &encrypted_session_key,
+        false,
+    );
+
+    let (rem, parsed) = parse_authenticate(&message).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(parsed.flags.bits(), FLAGS);
+    assert_eq!(parsed.lm_challenge_response, &[0; 24][..]);
+    assert_eq!(parsed.domain_name, "Domain");
+    assert_eq!(parsed.user_name, "User");
+    assert_eq!(parsed.workstation, "COMPUTER");
+    assert_eq!(parsed.encrypted_random_session_key, encrypted_session_key);
+    assert_eq!(parsed.version.unwrap().build, 6000);
+    assert_eq!(parsed.mic, None);
+
+    let (_, response) = parse_ntlmv2_response(parsed.nt_challenge_response).unwrap();
+    assert_eq!(
+        hex(&response.nt_proof_str),
+        "68cd0ab851e51c96aabc927bebef6a1c"
+    );
+    assert_eq!(response.client_challenge, [0xaa; 8]);
+    assert_eq!(response.client_blob, &nt_response(false)[16..]);
+    assert_eq!(response.av_pairs, example_challenge().target_info);
+
+    let authenticated = authenticate(&Users, &negotiate, &challenge, &message).unwrap();
+    assert_eq!(authenticated.user_name, "User");
+    assert_eq!(authenticated.domain_name, "Domain");
+    assert_eq!(
+        hex(&authenticated.session_base_key),
+        "8de40ccadbc14a82f15cb0ad0de95ca3"
+    );
+    assert_eq!(authenticated.session_key, [0x55; KEY_SIZE]);
+
+    // without key exchange the session base key is the session key
+    let flags = FLAGS & !NegotiateFlags::KEY_EXCH.bits();
+    let message = authenticate_message(flags, &nt_response(false), "User", &[], false);
+    let authenticated = authenticate(&Users, &negotiate, &challenge, &message).unwrap();
+    assert_eq!(authenticated.session_key, authenticated.session_base_key);
+}
+
+#[test]
+fn ntlmv2_user_name_case() {
+    struct AnyUser;
+
+    impl PasswordHashProvider for AnyUser {
+        fn nt_hash(&self, _user_name: &str, _domain_name: &str) -> Option<[u8; KEY_SIZE]> {
+            Some(NT_HASH)
+        }
+    }
+
+    // proofs computed with HMAC-MD5 of the Python standard library: the identity
+    // "STRAßE" keeps the "ß" as Windows uppercases one UTF-16 code unit at a time
+    let respond = |proof: &str| {
+        let mut response = nt_response(false);
+        for (i, byte) in response[..16].iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&proof[2 * i..2 * i + 2], 16).unwrap();
+        }
+        response
+    };
+    let negotiate = negotiate_message();
+    let mut challenge = Vec::new();
+    example_challenge().write_to(&mut challenge);
+    let flags = FLAGS & !NegotiateFlags::KEY_EXCH.bits();
+
+    let response = respond("9cb43493dc783f2ec912972f796a818a");
+    let message = authenticate_message(flags, &response, "Straße", &[], false);
+    let authenticated = authenticate(&AnyUser, &negotiate, &challenge, &message).unwrap();
+    assert_eq!(authenticated.user_name, "Straße");
+
+    // full case mapping would have turned the identity into "STRASSE"
+    let response = respond("6ee4b015d343bd5c00ed479495fe52b3");
+    let message = authenticate_message(flags, &response, "Straße", &[], false);
+    assert_eq!(
+        authenticate(&AnyUser, &negotiate, &challenge, &message),
+        Err(Error::InvalidResponse)
+    );
+}
+
+#[test]
+fn message_integrity_code() {
+    let negotiate = negotiate_message();
+    let mut challenge = Vec::new();
+    example_challenge().write_to(&mut challenge);
+    let flags = FLAGS & !NegotiateFlags::KEY_EXCH.bits();
+    let mut message = authenticate_message(flags, &nt_response(true), "User", &[], true);
+
+    let (_, parsed) = parse_authenticate(&message).unwrap();
+    assert_eq!(parsed.mic, Some([0; 16]));
+    let (_, response) = parse_ntlmv2_response(parsed.nt_challenge_response).unwrap();
+    assert_eq!(response.av_pairs[2], AvPair::Flags(AvFlags::MIC_PRESENT));
+    assert_eq!(
+        authenticate(&Users, &negotiate, &challenge, &message),
+        Err(Error::InvalidMic)
+    );
+
+    let session_key = [
+        0x83, 0xfd, 0x6a, 0x92, 0x7f, 0x61, 0x8a, 0x2c, 0xc5, 0x11, 0x3b, 0x60, 0xc7, 0xca, 0xb2,
+        0x42,
+    ];
+    let code = mic(&session_key, &negotiate, &challenge, &message);
+    message[72..88].copy_from_slice(&code);
+    assert_eq!(mic(&session_key, &negotiate, &challenge, &message), code);
+    let authenticated = authenticate(&Users, &negotiate, &challenge, &message).unwrap();
+    assert_eq!(authenticated.session_key, session_key);
+
+    // the MIC covers all messages
+    let mut other = negotiate.clone();
+    other[40] = b'X';
+    assert_eq!(
+        authenticate(&Users, &other, &challenge, &message),
+        Err(Error::InvalidMic)
+    );
+
+    // an announced MIC must be present
+    let message = authenticate_message(flags, &nt_response(true), "User", &[], false);
+    assert_eq!(
+        authenticate(&Users, &negotiate, &challenge, &message),
+        Err(Error::InvalidMic)
+    );
+}
+
+#[test]
+fn authenticate_failures() {
+    struct WrongPassword;
+
+    impl PasswordHashProvider for WrongPassword {
+        fn nt_hash(&self, _user_name: &str, _domain_name: &str) -> Option<[u8; KEY_SIZE]> {
+            Some([0; KEY_SIZE])
+        }
+    }
+
+    let negotiate = negotiate_message();
+    let mut challenge = Vec::new();
+    example_challenge().write_to(&mut challenge);
+    let check = |provider: &dyn PasswordHashProvider, message: &[u8]| {
+        authenticate(provider, &negotiate, &challenge, message)
+    };
+    let flags = FLAGS & !NegotiateFlags::KEY_EXCH.bits();
+
+    let message = authenticate_message(flags, &nt_response(false), "User", &[], false);
+    assert_eq!(check(&WrongPassword, &message), Err(Error::InvalidResponse));
+
+    let message = authenticate_message(flags, &nt_response(false), "Other", &[], false);
+    assert_eq!(check(&Users, &message), Err(Error::UnknownUser));
+
+    let mut response = nt_response(false);
+    response[20] ^= 1;
+    let message = authenticate_message(flags, &response, "User", &[], false);
+    assert_eq!(check(&Users, &message), Err(Error::InvalidResponse));
+
+    let message = authenticate_message(flags, &[0; 24], "User", &[], false);
+    assert_eq!(check(&Users, &message), Err(Error::UnsupportedResponse));
+
+    let message = authenticate_message(flags, &[], "", &[], false);
+    assert_eq!(check(&Users, &message), Err(Error::Anonymous));
+
+    let message = authenticate_message(FLAGS, &nt_response(false), "User", &[1; 5], false);
+    assert_eq!(check(&Users, &message), Err(Error::Malformed));
+
+    let mut message = authenticate_message(flags, &nt_response(false), "User", &[], false);
+    message.truncate(message.len() - 1);
+    assert_eq!(check(&Users, &message), Err(Error::Malformed));
+
+    assert_eq!(
+        authenticate(&Users, &negotiate, &negotiate, &message),
+        Err(Error::Malformed)
+    );
+}
+
+#[test]
+fn pcap() {
+    let mut buffer = Vec::new();
+    let requests = parse_pcap_requests("all_requests", &mut buffer, Dialect::Smb3_1_1).unwrap();
+    let buffers: Vec<_> = requests
+        .iter()
+        .filter_map(|request| match &request.body {
+            RequestBody::SessionSetup(body) => Some(body.security_buffer),
+            _ => None,
+        })
+        .collect();
+    let mut response_buffer = Vec::new();
+    let responses =
+        parse_pcap_responses("all_responses", &mut response_buffer, Dialect::Smb3_1_1).unwrap();
+    let challenges: Vec<_> = responses
+        .iter()
+        .filter_map(|response| match &response.body {
+            ResponseBody::SessionSetup(body) if !body.security_buffer.is_empty() => {
+                Some(body.security_buffer)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(buffers.len(), 4);
+    assert_eq!(challenges.len(), 2);
+
+    let (_, negotiate) = parse_negotiate(buffers[0]).unwrap();
+    assert!(negotiate.flags.contains(NegotiateFlags::KEY_EXCH));
+    assert_eq!(negotiate.version, None);
+
+    let (_, challenge) = parse_challenge(challenges[0]).unwrap();
+    assert_eq!(challenge.target_name, "HOMEBSD");
+    assert_eq!(challenge.target_info.len(), 5);
+    assert_eq!(
+        challenge.target_info[3],
+        AvPair::DnsComputerName("localhost.localdomain".to_string())
+    );
+
+    let (_, message) = parse_authenticate(buffers[1]).unwrap();
+    assert_eq!(message.user_name, "alexander");
+    assert_eq!(message.domain_name, "");
+    assert_eq!(message.encrypted_random_session_key.len(), 16);
+    assert_eq!(message.version, None);
+    assert_eq!(message.mic, None);
+    let (_, response) = parse_ntlmv2_response(message.nt_challenge_response).unwrap();
+    // the client echoes the target info of the server
+    assert_eq!(response.av_pairs, challenge.target_info);
+
+    assert_eq!(
+        authenticate(&Users, buffers[0], challenges[0], buffers[1]),
+        Err(Error::UnknownUser)
+    );
+
+    // the second session logs on as guest
+    let (_, message) = parse_authenticate(buffers[3]).unwrap();
+    assert_eq!(message.user_name, "");
+    assert_eq!(message.domain_name, "BUILDER");
+}
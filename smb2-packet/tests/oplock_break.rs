@@ -0,0 +1,167 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{request_header, response_header};
+use smb2_packet::command::create::{LeaseKey, LeaseState, OplockLevel};
+use smb2_packet::command::oplock_break::{
+    LeaseBreakAcknowledgment, LeaseBreakNotification, OplockBreakAcknowledgment,
+    OplockBreakNotification, Request as BreakRequest, Response as BreakResponse,
+    NOTIFICATION_MESSAGE_ID,
+};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{parse, serialize, Dialect, FileId, Request, Response};
+
+const FILE_ID: [u8; 16] = [
+    0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+];
+const LEASE_KEY: [u8; 16] = [
+    0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef,
+];
+
+fn parse_response(body: BreakResponse, message_id: u64) -> BreakResponse {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, message_id),
+        body: ResponseBody::OplockBreak(body),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    let (rem, mut responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    match responses.pop().unwrap().body {
+        ResponseBody::OplockBreak(body) => body,
+        _ => panic!("Expected oplock break response!"),
+    }
+}
+
+fn parse_request(body: BreakRequest) -> BreakRequest {
+    let request = Request {
+        header: request_header(9),
+        body: RequestBody::OplockBreak(body),
+    };
+    let buffer = serialize(&[request], Dialect::Smb3_1_1);
+    let (rem, mut requests) = parse::<Request>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    match requests.pop().unwrap().body {
+        RequestBody::OplockBreak(body) => body,
+        _ => panic!("Expected oplock break request!"),
+    }
+}
+
+#[test]
+fn oplock_notification() {
+    let body = BreakResponse::OplockNotification(OplockBreakNotification {
+        oplock_level: OplockLevel::II,
+        file_id: FileId::from(FILE_ID),
+    });
+    match parse_response(body, NOTIFICATION_MESSAGE_ID) {
+        BreakResponse::OplockNotification(body) => {
+            assert_eq!(body.oplock_level, OplockLevel::II);
+            assert_eq!(*body.file_id, FILE_ID);
+        }
+        _ => panic!("Expected oplock break notification!"),
+    }
+}
+
+#[test]
+fn oplock_acknowledgment() {
+    let body = BreakRequest::OplockAcknowledgment(OplockBreakAcknowledgment {
+        oplock_level: OplockLevel::No,
+        file_id: FileId::from(FILE_ID),
+    });
+    match parse_request(body) {
+        BreakRequest::OplockAcknowledgment(body) => {
+            assert_eq!(body.oplock_level, OplockLevel::No);
+            assert_eq!(*body.file_id, FILE_ID);
+        }
+        _ => panic!("Expected oplock break acknowledgment!"),
+    }
+}
+
+#[test]
+fn oplock_response() {
+    let body = BreakResponse::OplockAcknowledgment(OplockBreakAcknowledgment {
+        oplock_level: OplockLevel::No,
+        file_id: FileId::from(FILE_ID),
+    });
+    match parse_response(body, 9) {
+        BreakResponse::OplockAcknowledgment(body) => {
+            assert_eq!(body.oplock_level, OplockLevel::No);
+            assert_eq!(*body.file_id, FILE_ID);
+        }
+        _ => panic!("Expected oplock break response!"),
+    }
+}
+
+#[test]
+fn lease_notification() {
+    let body = BreakResponse::LeaseNotification(LeaseBreakNotification {
+        new_epoch: 3,
+        ack_required: true,
+        lease_key: LeaseKey::from(LEASE_KEY),
+        current_lease_state: LeaseState::READ_CACHING
+            | LeaseState::HANDLE_CACHING
+            | LeaseState::WRITE_CACHING,
+        new_lease_state: LeaseState::READ_CACHING,
+    });
+    match parse_response(body, NOTIFICATION_MESSAGE_ID) {
+        BreakResponse::LeaseNotification(body) => {
+            assert_eq!(body.new_epoch, 3);
+            assert!(body.ack_required);
+            assert_eq!(*body.lease_key, LEASE_KEY);
+            assert_eq!(body.current_lease_state, LeaseState::all());
+            assert_eq!(body.new_lease_state, LeaseState::READ_CACHING);
+        }
+        _ => panic!("Expected lease break notification!"),
+    }
+}
+
+#[test]
+fn lease_acknowledgment() {
+    let body = BreakRequest::LeaseAcknowledgment(LeaseBreakAcknowledgment {
+        lease_key: LeaseKey::from(LEASE_KEY),
+        lease_state: LeaseState::READ_CACHING,
+    });
+    match parse_request(body) {
+        BreakRequest::LeaseAcknowledgment(body) => {
+            assert_eq!(*body.lease_key, LEASE_KEY);
+            assert_eq!(body.lease_state, LeaseState::READ_CACHING);
+        }
+        _ => panic!("Expected lease break acknowledgment!"),
+    }
+}
+
+#[test]
+fn lease_response() {
+    let body = BreakResponse::LeaseAcknowledgment(LeaseBreakAcknowledgment {
+        lease_key: LeaseKey::from(LEASE_KEY),
+        lease_state: LeaseState::empty(),
+    });
+    match parse_response(body, 9) {
+        BreakResponse::LeaseAcknowledgment(body) => {
+            assert_eq!(*body.lease_key, LEASE_KEY);
+            assert_eq!(body.lease_state, LeaseState::empty());
+        }
+        _ => panic!("Expected lease break response!"),
+    }
+}
+
+#[test]
+fn lease_reserved_state_rejected() {
+    let request = Request {
+        header: request_header(9),
+        body: RequestBody::OplockBreak(BreakRequest::LeaseAcknowledgment(
+            LeaseBreakAcknowledgment {
+                lease_key: LeaseKey::from(LEASE_KEY),
+                lease_state: LeaseState::READ_CACHING,
+            },
+        )),
+    };
+    let mut buffer = serialize(&[request], Dialect::Smb3_1_1);
+    // lease state
+    buffer[4 + 64 + 24] |= 0x08;
+    assert!(parse::<Request>(&buffer, Dialect::Smb3_1_1).is_err());
+}
@@ -0,0 +1,103 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{roundtrip_pcap, roundtrip_pcap_masked};
+use smb2_packet::{Dialect, Request, Response};
+use std::convert::TryInto;
+
+fn le_u16(data: &[u8], pos: usize) -> usize {
+    usize::from(u16::from_le_bytes(data[pos..pos + 2].try_into().unwrap()))
+}
+
+fn le_u32(data: &[u8], pos: usize) -> usize {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()) as usize
+}
+
+/// Zeroes the padding behind the name of empty maximal access create contexts
+/// which the Linux client leaves uninitialized.
+fn zero_maximal_access_padding(data: &mut [u8]) {
+    let mut start = 0;
+    loop {
+        let next_command = le_u32(data, start + 20);
+        let is_create = le_u16(data, start + 12) == 0x05;
+        if is_create && le_u32(data, start + 64 + 52) > 0 {
+            let mut context = start + le_u32(data, start + 64 + 48);
+            loop {
+                let name_end = context + le_u16(data, context + 4) + le_u16(data, context + 6);
+                if &data[name_end - 4..name_end] == b"MxAc" && le_u32(data, context + 12) == 0 {
+                    let padding_end = context + le_u16(data, context + 10);
+                    data[name_end..padding_end].iter_mut().for_each(|x| *x = 0);
+                }
+                match le_u32(data, context) {
+                    0 => break,
+                    next => context += next,
+                }
+            }
+        }
+        match next_command {
+            0 => return,
+            next => start += next,
+        }
+    }
+}
+
+#[test]
+fn all_requests_roundtrip() {
+    let mut buffer = Vec::new();
+    roundtrip_pcap_masked::<Request, _, _>(
+        "all_requests",
+        &mut buffer,
+        Dialect::Smb3_1_1,
+        |_| false,
+        zero_maximal_access_padding,
+    );
+}
+
+#[test]
+fn all_responses_roundtrip() {
+    let mut buffer = Vec::new();
+    roundtrip_pcap::<Response, _>("all_responses", &mut buffer, Dialect::Smb3_1_1, |_| false);
+}
+
+#[test]
+fn header1_roundtrip() {
+    let mut buffer = Vec::new();
+    roundtrip_pcap::<Request, _>("header1", &mut buffer, Dialect::Smb3_1_1, |_| false);
+}
+
+#[test]
+fn header2_roundtrip() {
+    let mut buffer = Vec::new();
+    roundtrip_pcap::<Response, _>("header2", &mut buffer, Dialect::Smb3_1_1, |_| false);
+}
+
+#[test]
+fn negotiate_request_roundtrip() {
+    let mut buffer = Vec::new();
+    roundtrip_pcap::<Request, _>("negotiate_request", &mut buffer, Dialect::Smb2_0_2, |_| {
+        false
+    });
+}
+
+#[test]
+fn negotiate_with_context_request_roundtrip() {
+    let mut buffer = Vec::new();
+    roundtrip_pcap::<Request, _>(
+        "negotiate_with_context_request",
+        &mut buffer,
+        Dialect::Smb3_1_1,
+        |_| false,
+    );
+}
+
+#[test]
+fn negotiate_response_roundtrip() {
+    let mut buffer = Vec::new();
+    roundtrip_pcap::<Response, _>("negotiate_response", &mut buffer, Dialect::Smb3_0_2, |_| {
+        false
+    });
+}
@@ -0,0 +1,308 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{hex, request_header, response_header, CANCEL_REQUESTS};
+use smb2_packet::command::create::OplockLevel;
+use smb2_packet::command::error::Response as ErrorResponse;
+use smb2_packet::command::error::{Detail, ErrorData};
+use smb2_packet::command::negotiate::SigningAlgorithm;
+use smb2_packet::command::oplock_break::{
+    OplockBreakNotification, Response as BreakResponse, NOTIFICATION_MESSAGE_ID,
+};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::header::{Command, Flags, SyncType};
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::signing::{Algorithm, Error, Signer};
+use smb2_packet::{parse, serialize, Dialect, Request, Response};
+
+const KEY: [u8; 16] = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+];
+const SIGNATURE: std::ops::Range<usize> = 4 + 48..4 + 64;
+
+/// ECHO and CANCEL request compounded into one session message.
+fn requests() -> Vec<u8> {
+    let requests = [
+        Request {
+            header: request_header(1),
+            body: RequestBody::Echo,
+        },
+        Request {
+            header: request_header(2),
+            body: RequestBody::Cancel,
+        },
+    ];
+    serialize(&requests, Dialect::Smb3_1_1)
+}
+
+fn response() -> Vec<u8> {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 1),
+        body: ResponseBody::Echo,
+    };
+    serialize(&[response], Dialect::Smb3_1_1)
+}
+
+fn pending() -> ResponseBody<'static> {
+    ResponseBody::Error(ErrorResponse {
+        status: NTStatus::StatusPending,
+        command: Command::Read,
+        error_data: ErrorData::Detail(Detail::Empty),
+    })
+}
+
+fn signatures(data: &[u8]) -> Vec<&[u8]> {
+    let mut ret = vec![&data[SIGNATURE]];
+    if data.len() > 4 + 72 {
+        ret.push(&data[SIGNATURE.start + 72..SIGNATURE.end + 72]);
+    }
+    ret
+}
+
+fn check(algorithm: Algorithm, data: &mut [u8], expected: &[[u8; 16]]) {
+    let signer = Signer::new(KEY, algorithm);
+    signer.sign(data).unwrap();
+    assert_eq!(signatures(data), expected);
+    assert_eq!(signer.verify(data), Ok(()));
+}
+
+#[test]
+fn hmac_sha256() {
+    let mut data = requests();
+    check(
+        Algorithm::HmacSha256,
+        &mut data,
+        &[
+            [
+                0xc5, 0xa8, 0x44, 0x09, 0xf1, 0x27, 0xbd, 0x5d, 0x71, 0xa8, 0x77, 0x58, 0x06, 0x45,
+                0xe0, 0x8a,
+            ],
+            [
+                0xc6, 0xb9, 0xf3, 0xe2, 0xbd, 0x9d, 0x51, 0x8c, 0xb6, 0xd6, 0xd1, 0x8f, 0x11, 0x25,
+                0x63, 0x2b,
+            ],
+        ],
+    );
+    let (_, requests) = parse::<Request>(&data, Dialect::Smb2_1_0).unwrap();
+    assert!(requests
+        .iter()
+        .all(|r| r.header.flags.contains(Flags::SIGNED)));
+    assert_eq!(*requests[0].header.signature, data[SIGNATURE]);
+
+    check(
+        Algorithm::HmacSha256,
+        &mut response(),
+        &[[
+            0x04, 0x5c, 0x2b, 0x87, 0x4d, 0xf4, 0x3c, 0x9e, 0x13, 0xe9, 0x5c, 0xac, 0x1b, 0x0b,
+            0x97, 0x87,
+        ]],
+    );
+}
+
+#[test]
+fn aes_cmac() {
+    check(
+        Algorithm::AesCmac,
+        &mut requests(),
+        &[
+            [
+                0x94, 0x67, 0xde, 0xe3, 0xbd, 0xb5, 0x8e, 0x46, 0x4a, 0x1b, 0x32, 0xf5, 0x3f, 0x70,
+                0x2c, 0x37,
+            ],
+            [
+                0xf2, 0x79, 0xec, 0x01, 0x4a, 0xe9, 0x49, 0x01, 0x1c, 0x99, 0xac, 0x5b, 0x5d, 0x5b,
+                0xcb, 0x6c,
+            ],
+        ],
+    );
+    check(
+        Algorithm::AesCmac,
+        &mut response(),
+        &[[
+            0x64, 0xc7, 0x6e, 0xdb, 0xb7, 0x7f, 0x6a, 0x6b, 0x5e, 0xbe, 0xeb, 0x60, 0x72, 0xab,
+            0x71, 0x18,
+        ]],
+    );
+}
+
+#[test]
+fn aes_gmac() {
+    // the second request is a CANCEL which is marked in the nonce
+    check(
+        Algorithm::AesGmac,
+        &mut requests(),
+        &[
+            [
+                0x34, 0x6e, 0x04, 0xec, 0xbf, 0x3b, 0x91, 0x39, 0x47, 0xe8, 0xd4, 0xe7, 0xd8, 0x77,
+                0xb3, 0x2e,
+            ],
+            [
+                0xec, 0x78, 0xcc, 0x91, 0xf3, 0x85, 0x97, 0xfe, 0x36, 0x5f, 0xb3, 0xae, 0xa2, 0x80,
+                0xbe, 0xc8,
+            ],
+        ],
+    );
+    check(
+        Algorithm::AesGmac,
+        &mut response(),
+        &[[
+            0xc8, 0x0a, 0xee, 0xa8, 0xe9, 0x5a, 0x47, 0xce, 0xd4, 0xc7, 0x16, 0xd2, 0xb1, 0xb9,
+            0x0a, 0x4d,
+        ]],
+    );
+}
+
+/// Signatures of the synchronous and asynchronous CANCEL requests computed with the
+/// HMAC, CMAC and AES-GCM implementations of the Python cryptography package.
+#[test]
+fn cancel_signatures() {
+    let (sync, async_) = CANCEL_REQUESTS.split_at(4 + 0x44);
+    let sign = |algorithm, message: &[u8]| {
+        let signer = Signer::new(KEY, algorithm);
+        let mut data = message.to_vec();
+        signer.sign(&mut data).unwrap();
+        assert_eq!(signer.verify(&data), Ok(()));
+        hex(&data[SIGNATURE])
+    };
+
+    assert_eq!(
+        sign(Algorithm::HmacSha256, sync),
+        "4951bb14bc1cc26dd8233c10f2fcbfc8"
+    );
+    assert_eq!(
+        sign(Algorithm::HmacSha256, async_),
+        "b794e88198e7c046c0c29b01fe6c005c"
+    );
+    assert_eq!(
+        sign(Algorithm::AesCmac, sync),
+        "65e7e5c962a4ed8a7b7ff2496dc7ee8f"
+    );
+    assert_eq!(
+        sign(Algorithm::AesCmac, async_),
+        "ff191a510ba91dc0573254525bdfacc9"
+    );
+    // the nonce is the message id followed by the CANCEL bit
+    assert_eq!(
+        sign(Algorithm::AesGmac, sync),
+        "b7b247a79c332e7776949f40b653f6df"
+    );
+    assert_eq!(
+        sign(Algorithm::AesGmac, async_),
+        "2888b26cf88bb427765c5bac836d52ac"
+    );
+}
+
+#[test]
+fn verify_failures() {
+    let signer = Signer::new(KEY, Algorithm::AesCmac);
+    let mut data = requests();
+    signer.sign(&mut data).unwrap();
+
+    let mut tampered = data.clone();
+    // credit request of the second message
+    tampered[4 + 72 + 14] = 2;
+    assert_eq!(
+        signer.verify(&tampered),
+        Err(Error::InvalidSignature { message_id: 2 })
+    );
+
+    let other = Signer::new([0; 16], Algorithm::AesCmac);
+    assert_eq!(
+        other.verify(&data),
+        Err(Error::InvalidSignature { message_id: 1 })
+    );
+
+    let mut unsigned = data.clone();
+    unsigned[4 + 72 + 16] &= !0x08;
+    assert_eq!(
+        signer.verify(&unsigned),
+        Err(Error::NotSigned { message_id: 2 })
+    );
+
+    assert_eq!(
+        signer.verify(&requests()),
+        Err(Error::NotSigned { message_id: 1 })
+    );
+    assert_eq!(
+        signer.verify(&data[..data.len() - 1]),
+        Err(Error::Malformed)
+    );
+    assert_eq!(signer.verify(&[0, 0, 0, 0]), Err(Error::Malformed));
+}
+
+#[test]
+fn verify_skips_break_notification() {
+    let signer = Signer::new(KEY, Algorithm::AesCmac);
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, NOTIFICATION_MESSAGE_ID),
+        body: ResponseBody::OplockBreak(BreakResponse::OplockNotification(
+            OplockBreakNotification {
+                oplock_level: OplockLevel::II,
+                file_id: [0x11; 16].into(),
+            },
+        )),
+    };
+    let data = serialize(&[response], Dialect::Smb3_1_1);
+    assert_eq!(signer.verify(&data), Ok(()));
+
+    // only the server sends notifications
+    let request = Request {
+        header: request_header(NOTIFICATION_MESSAGE_ID),
+        body: RequestBody::Echo,
+    };
+    let data = serialize(&[request], Dialect::Smb3_1_1);
+    assert_eq!(
+        signer.verify(&data),
+        Err(Error::NotSigned {
+            message_id: NOTIFICATION_MESSAGE_ID
+        })
+    );
+}
+
+#[test]
+fn verify_skips_interim_response() {
+    let signer = Signer::new(KEY, Algorithm::AesCmac);
+    let mut header = response_header(NTStatus::StatusPending, 1);
+    header.sync_type = SyncType::Async { async_id: 8 };
+    let interim = Response {
+        header,
+        body: pending(),
+    };
+    let data = serialize(&[interim], Dialect::Smb3_1_1);
+    assert_eq!(signer.verify(&data), Ok(()));
+
+    // a synchronous response with STATUS_PENDING is not an interim response
+    let sync = Response {
+        header: response_header(NTStatus::StatusPending, 1),
+        body: pending(),
+    };
+    let data = serialize(&[sync], Dialect::Smb3_1_1);
+    assert_eq!(
+        signer.verify(&data),
+        Err(Error::NotSigned { message_id: 1 })
+    );
+}
+
+#[test]
+fn select_algorithm() {
+    assert_eq!(
+        Algorithm::select(Dialect::Smb2_1_0, Some(SigningAlgorithm::AesGmac)),
+        Algorithm::HmacSha256
+    );
+    assert_eq!(
+        Algorithm::select(Dialect::Smb3_0_2, None),
+        Algorithm::AesCmac
+    );
+    assert_eq!(
+        Algorithm::select(Dialect::Smb3_1_1, None),
+        Algorithm::AesCmac
+    );
+    assert_eq!(
+        Algorithm::select(Dialect::Smb3_1_1, Some(SigningAlgorithm::AesGmac)),
+        Algorithm::AesGmac
+    );
+}
@@ -6,7 +6,31 @@
 mod common;
 
 use crate::common::parse_pcap_smb1nego;
+use smb2_packet::command::negotiate::{Capabilities, Response as NegotiateResponse};
+use smb2_packet::command::ResponseBody;
+use smb2_packet::header::SyncType;
+use smb2_packet::ntstatus::NTStatus;
 use smb2_packet::smb1::{DialectLevel, Flags, Flags2, Signature};
+use smb2_packet::{
+    parse, serialize, serialize_smb1_nego_error_response, ClientGuid, Dialect, Response,
+};
+use std::time::{Duration, UNIX_EPOCH};
+
+fn negotiate_body() -> NegotiateResponse<'static> {
+    NegotiateResponse {
+        signing_required: false,
+        dialect: Dialect::Smb3_1_1,
+        server_guid: ClientGuid::from(*b"smb2-packet\0\0\0\0\0"),
+        capabilities: Capabilities::DFS,
+        max_transact_size: 0x0010_0000,
+        max_read_size: 0x0010_0000,
+        max_write_size: 0x0010_0000,
+        system_time: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        server_start_time: UNIX_EPOCH,
+        security_buffer: None,
+        negotiate_contexts: Vec::new(),
+    }
+}
 
 #[test]
 fn smb1_negot_req_smb2plus() {
@@ -68,3 +92,100 @@ fn smb1_negot_req_not_supported() {
     assert_eq!(header.mid, 0);
     assert_eq!(header.signature, Signature::empty())
 }
+
+#[test]
+fn smb1_negot_upgrade_smb2plus() {
+    let mut buffer = Vec::new();
+    let req = &parse_pcap_smb1nego("smb1_negot_req_smb2plus", &mut buffer).unwrap()[0];
+    let response = req.upgrade_response(negotiate_body()).unwrap();
+    let buffer = serialize(&[response], Dialect::Smb2_0_2);
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb2_0_2).unwrap();
+    assert!(rem.is_empty());
+
+    let header = &responses[0].header;
+    assert_eq!(header.status, NTStatus::StatusSuccess);
+    assert_eq!(header.message_id, 0);
+    assert_eq!(header.process_id, Some(65279));
+    assert_eq!(header.session_id, 0);
+    match header.sync_type {
+        SyncType::Sync { tree_id } => assert_eq!(tree_id, 0),
+        SyncType::Async { .. } => panic!("Expected sync response!"),
+    }
+    match &responses[0].body {
+        ResponseBody::Negotiate(body) => {
+            assert_eq!(body.dialect, Dialect::Smb2Wildcard);
+            assert_eq!(body.capabilities, Capabilities::DFS);
+        }
+        _ => panic!("Expected negotiate response!"),
+    }
+    // dialect revision
+    assert_eq!(&buffer[4 + 64 + 4..4 + 64 + 6], &[0xff, 0x02]);
+}
+
+#[test]
+fn smb1_negot_not_supported_response() {
+    let mut buffer = Vec::new();
+    let req = &parse_pcap_smb1nego("smb1_negot_req_not_supported", &mut buffer).unwrap()[0];
+    assert!(req.upgrade_response(negotiate_body()).is_none());
+
+    let response = req.error_response();
+    assert!(response.header.flags.contains(Flags::REPLY));
+    assert_eq!(response.header.mid, req.header.mid);
+    let buffer = serialize_smb1_nego_error_response(&response);
+    assert_eq!(&buffer[..4], &[0, 0, 0, 32 + 5]);
+    assert_eq!(&buffer[4..9], b"\xffSMB\x72");
+    // status
+    assert_eq!(&buffer[9..13], &[0; 4]);
+    assert_eq!(buffer[13], 0x98);
+    // pid high and low
+    assert_eq!(&buffer[4 + 12..4 + 14], &[0, 0]);
+    assert_eq!(&buffer[4 + 26..4 + 28], &[0xff, 0xfe]);
+    // word count, no dialect selected and byte count
+    assert_eq!(&buffer[4 + 32..], &[1, 0xff, 0xff, 0, 0]);
+}
+
+#[test]
+fn dialect_level_dialect() {
+    assert_eq!(DialectLevel::NotSupported.dialect(), None);
+    assert_eq!(DialectLevel::Smb2.dialect(), Some(Dialect::Smb2_0_2));
+    assert_eq!(
+        DialectLevel::Smb2Plus.dialect(),
+        Some(Dialect::Smb2Wildcard)
+    );
+}
+
+#[test]
+fn smb2_negot_req_wildcard_rejected() {
+    use crate::common::request_header;
+    use smb2_packet::command::negotiate::Request as NegotiateRequest;
+    use smb2_packet::command::RequestBody;
+    use smb2_packet::Request;
+
+    let request = |dialects| Request {
+        header: request_header(0),
+        body: RequestBody::Negotiate(NegotiateRequest {
+            signing_required: false,
+            capabilities: Capabilities::empty(),
+            client_guid: ClientGuid::from([0x11; 16]),
+            dialects,
+            negotiate_contexts: Vec::new(),
+        }),
+    };
+    let buffer = serialize(&[request(vec![Dialect::Smb2_1_0])], Dialect::Smb2_0_2);
+    assert!(parse::<Request>(&buffer, Dialect::Smb2_0_2).is_ok());
+    // the wildcard is only selected by the server
+    let buffer = serialize(&[request(vec![Dialect::Smb2Wildcard])], Dialect::Smb2_0_2);
+    assert!(parse::<Request>(&buffer, Dialect::Smb2_0_2).is_err());
+}
+
+#[test]
+fn wildcard_dialect_features() {
+    assert!(!Dialect::Smb2Wildcard.has_credit_charge());
+    assert!(!Dialect::Smb2Wildcard.is_smb3());
+    assert!(!Dialect::Smb2Wildcard.has_read_write_channel());
+    assert!(Dialect::Smb2_1_0.has_credit_charge());
+    assert!(!Dialect::Smb2_1_0.is_smb3());
+    assert!(Dialect::Smb3_0_0.is_smb3());
+    assert!(!Dialect::Smb3_0_2.has_read_write_channel());
+    assert!(Dialect::Smb3_1_1.has_read_write_channel());
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "spnego")]
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{hex, parse_pcap_responses};
+use smb2_packet::command::ResponseBody;
+use smb2_packet::spnego::{
+    parse, parse_neg_token_init, parse_neg_token_resp, NegState, NegTokenInit, NegTokenResp, Token,
+    NTLMSSP_OID,
+};
+use smb2_packet::Dialect;
+
+const NTLMSSP_NEGOTIATE: &[u8] = b"NTLMSSP\0\x01\0\0\0";
+
+#[test]
+fn negotiate_response_hints() {
+    let mut buffer = Vec::new();
+    let responses =
+        parse_pcap_responses("negotiate_response", &mut buffer, Dialect::Smb3_0_2).unwrap();
+    let security_buffer = match &responses[0].body {
+        ResponseBody::Negotiate(body) => body.security_buffer.unwrap(),
+        _ => panic!("Expected negotiate response!"),
+    };
+
+    // the negHints of MS-SPNG are skipped
+    let (rem, init) = parse_neg_token_init(security_buffer).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(init.mech_types, [NTLMSSP_OID]);
+    assert_eq!(init.mech_token, None);
+
+    let mut serialized = Vec::new();
+    init.write_to(&mut serialized);
+    assert_eq!(
+        hex(&serialized),
+        "601c06062b0601050502a0123010a00e300c060a2b06010401823702020a"
+    );
+}
+
+#[test]
+fn neg_token_init() {
+    let kerberos: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12, 0x01, 0x02, 0x02];
+    let init = NegTokenInit {
+        mech_types: vec![NTLMSSP_OID, kerberos],
+        mech_token: Some(NTLMSSP_NEGOTIATE),
+    };
+    let mut serialized = Vec::new();
+    init.write_to(&mut serialized);
+    assert_eq!(
+        hex(&serialized),
+        "603706062b0601050502a02d302ba0193017060a2b06010401823702020a06092a864886f712010202\
+         a20e040c4e544c4d5353500001000000"
+    );
+    assert_eq!(parse(&serialized).unwrap(), (&[][..], Token::Init(init)));
+
+    let trailing = [&serialized[..], &[0xff][..]].concat();
+    let (rem, _) = parse(&trailing).unwrap();
+    assert_eq!(rem, [0xff]);
+
+    let mut wrong_oid = serialized.clone();
+    wrong_oid[8] ^= 1;
+    assert!(parse_neg_token_init(&wrong_oid).is_err());
+    assert!(parse_neg_token_init(&serialized[..serialized.len() - 1]).is_err());
+}
+
+#[test]
+fn neg_token_resp() {
+    let challenge = NegTokenResp {
+        neg_state: Some(NegState::AcceptIncomplete),
+        supported_mech: Some(NTLMSSP_OID),
+        response_token: Some(NTLMSSP_NEGOTIATE),
+        mech_list_mic: None,
+    };
+    let mut serialized = Vec::new();
+    challenge.write_to(&mut serialized);
+    assert_eq!(
+        hex(&serialized),
+        "a1253023a0030a0101a10c060a2b06010401823702020aa20e040c4e544c4d5353500001000000"
+    );
+    assert_eq!(
+        parse(&serialized).unwrap(),
+        (&[][..], Token::Resp(challenge))
+    );
+
+    let completed = NegTokenResp {
+        neg_state: Some(NegState::AcceptCompleted),
+        supported_mech: None,
+        response_token: None,
+        mech_list_mic: Some(&[0x01; 16]),
+    };
+    let mut serialized = Vec::new();
+    completed.write_to(&mut serialized);
+    assert_eq!(
+        hex(&serialized),
+        "a11b3019a0030a0100a312041001010101010101010101010101010101"
+    );
+    let (_, parsed) = parse_neg_token_resp(&serialized).unwrap();
+    assert_eq!(parsed, completed);
+
+    // unknown states are rejected
+    serialized[8] = 4;
+    assert!(parse_neg_token_resp(&serialized).is_err());
+}
+
+#[test]
+fn long_lengths() {
+    let token = vec![0x5a; 0x1234];
+    let resp = NegTokenResp {
+        neg_state: None,
+        supported_mech: None,
+        response_token: Some(&token),
+        mech_list_mic: None,
+    };
+    let mut serialized = Vec::new();
+    resp.write_to(&mut serialized);
+    assert_eq!(hex(&serialized[..16]), "a18212403082123ca282123804821234");
+    assert_eq!(parse_neg_token_resp(&serialized).unwrap().1, resp);
+
+    // indefinite lengths are not DER
+    assert!(parse_neg_token_resp(&[0xa1, 0x80, 0x30, 0x00, 0x00, 0x00]).is_err());
+}
@@ -0,0 +1,224 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{hex, request_header, unhex};
+use smb2_packet::command::negotiate::Cipher;
+use smb2_packet::command::RequestBody;
+use smb2_packet::transform::{decrypt, encrypt, nonce_size, parse, Error, Flags};
+use smb2_packet::transport::{Framer, Message};
+use smb2_packet::{serialize, Dialect, Packet, Request};
+
+const SESSION_ID: u64 = 0x0000_0400_0000_0005;
+
+/// ECHO and CANCEL request compounded without the transport header.
+fn requests() -> Vec<u8> {
+    let requests = [
+        Request {
+            header: request_header(1),
+            body: RequestBody::Echo,
+        },
+        Request {
+            header: request_header(2),
+            body: RequestBody::Cancel,
+        },
+    ];
+    serialize(&requests, Dialect::Smb3_1_1)[4..].to_vec()
+}
+
+fn key(cipher: Cipher) -> Vec<u8> {
+    match cipher {
+        Cipher::Aes128Ccm | Cipher::Aes128Gcm => (0..16).collect(),
+        Cipher::Aes256Ccm | Cipher::Aes256Gcm => (0..32).collect(),
+    }
+}
+
+fn nonce(cipher: Cipher) -> Vec<u8> {
+    (0xa0..).take(nonce_size(cipher)).collect()
+}
+
+fn roundtrip(cipher: Cipher, signature: &str, data: &str) {
+    let plain = requests();
+    let encrypted = encrypt(&key(cipher), cipher, &nonce(cipher), SESSION_ID, &plain).unwrap();
+    assert_eq!(encrypted.len(), 4 + 52 + plain.len());
+
+    let (rem, message) = parse(&encrypted).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(hex(&message.header.signature), signature);
+    assert_eq!(
+        &message.header.nonce[..nonce_size(cipher)],
+        &nonce(cipher)[..]
+    );
+    assert!(message.header.nonce[nonce_size(cipher)..]
+        .iter()
+        .all(|b| *b == 0));
+    assert_eq!(message.header.original_message_size as usize, plain.len());
+    assert_eq!(message.header.flags, Flags::ENCRYPTED);
+    assert_eq!(message.header.session_id, SESSION_ID);
+    assert_eq!(hex(&message.data[..16]), data);
+
+    let decrypted = decrypt(&key(cipher), cipher, &message).unwrap();
+    assert_eq!(decrypted, plain);
+    let requests = Request::parse(&decrypted, Dialect::Smb3_1_1).unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].header.message_id, 2);
+}
+
+#[test]
+fn aes_128_ccm() {
+    roundtrip(
+        Cipher::Aes128Ccm,
+        "f6d1b805d240a07a0a1bc8c8eb0951d1",
+        "1ae90934aeababa6dca66b98e606aee7",
+    );
+}
+
+#[test]
+fn aes_128_gcm() {
+    roundtrip(
+        Cipher::Aes128Gcm,
+        "f4e68c191e8bf86c4207aa5a673bd6d8",
+        "54d575f93e89320a8a78b5004b12b160",
+    );
+}
+
+#[test]
+fn aes_256_ccm() {
+    roundtrip(
+        Cipher::Aes256Ccm,
+        "3fb23430b8356241dd580bc3a39da83f",
+        "66f20ecdb152f54ee8fb35f23be5b5e9",
+    );
+}
+
+#[test]
+fn aes_256_gcm() {
+    roundtrip(
+        Cipher::Aes256Gcm,
+        "deae8b37302ef6cff87c9a68573a3f4c",
+        "184b316f05cb03bf626587d30a7ac1de",
+    );
+}
+
+/// ECHO request of session 0x0000_0400_0000_0005 with message id 1.
+const ECHO_REQUEST: &str = "fe534d4240000000000000000d00010000000000000000000100000000000000\
+                            fffe00000100000005000000000400000000000000000000000000000000000004000000";
+
+/// Decrypts a message that was encrypted independently of this crate, with
+/// Python's `cryptography` package and the transform header laid out by hand.
+fn decrypt_external(cipher: Cipher, key: &str, message: &str) {
+    let key = unhex(key);
+    let encrypted = unhex(message);
+    let (rem, message) = parse(&encrypted).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(message.header.session_id, SESSION_ID);
+    let decrypted = decrypt(&key, cipher, &message).unwrap();
+    assert_eq!(hex(&decrypted), ECHO_REQUEST);
+    let requests = Request::parse(&decrypted, Dialect::Smb3_0_0).unwrap();
+    assert_eq!(requests[0].header.message_id, 1);
+    assert!(match requests[0].body {
+        RequestBody::Echo => true,
+        _ => false,
+    });
+}
+
+#[test]
+fn aes_128_ccm_external() {
+    decrypt_external(
+        Cipher::Aes128Ccm,
+        "1b6bcb8f5b3a1cc08a35db3d1e6fa5c4",
+        "00000078fd534d429e5641656790a1c2d1056ff9c6f81f5c6a4e8f1d2c3b5a7980e1f200000000\
+         0044000000000001000500000000040000a924c44b66289645e89adb66ea9767daeaff781e858eda\
+         13aa9f60af177736addcf12a6d614c5e7bd7ec33efba3799b99c169d81d4ce6afe7f6c359c84d770\
+         c890474e52",
+    );
+}
+
+#[test]
+fn aes_128_gcm_external() {
+    decrypt_external(
+        Cipher::Aes128Gcm,
+        "b0c7d1e2f3a4958675ef0d1c2b3a4958",
+        "00000078fd534d4219617b0122966da0e67bbf6123469d8e3c1d5e7f9a0b2c4d6e8f0a1b00000000\
+         4400000000000100050000000004000004f944cb2813d1652aa6d7ac19fb14780c2f89a6e474c19f\
+         06bdf9533e125eba20cd07cf21c2add829ee59b42a213f1dedc4ceca1d76a2cd97c0e974d162b817\
+         125e54e2",
+    );
+}
+
+#[test]
+fn decrypt_failures() {
+    let cipher = Cipher::Aes128Gcm;
+    let encrypted = encrypt(
+        &key(cipher),
+        cipher,
+        &nonce(cipher),
+        SESSION_ID,
+        &requests(),
+    )
+    .unwrap();
+
+    // the session id is authenticated
+    let mut tampered = encrypted.clone();
+    tampered[4 + 44] ^= 0x01;
+    let (_, message) = parse(&tampered).unwrap();
+    assert_eq!(
+        decrypt(&key(cipher), cipher, &message),
+        Err(Error::DecryptionFailed)
+    );
+
+    let mut tampered = encrypted.clone();
+    *tampered.last_mut().unwrap() ^= 0x01;
+    let (_, message) = parse(&tampered).unwrap();
+    assert_eq!(
+        decrypt(&key(cipher), cipher, &message),
+        Err(Error::DecryptionFailed)
+    );
+
+    let (_, message) = parse(&encrypted).unwrap();
+    assert_eq!(
+        decrypt(&key(Cipher::Aes256Gcm), cipher, &message),
+        Err(Error::InvalidKeySize)
+    );
+    assert_eq!(
+        encrypt(&key(cipher), cipher, &[0; 11], SESSION_ID, &requests()),
+        Err(Error::InvalidNonceSize)
+    );
+
+    // original message size does not match the encrypted data
+    let mut truncated = encrypted.clone();
+    truncated.pop();
+    truncated[3] -= 1;
+    let (_, message) = parse(&truncated).unwrap();
+    assert_eq!(
+        decrypt(&key(cipher), cipher, &message),
+        Err(Error::Malformed)
+    );
+
+    // reserved flags
+    let mut reserved = encrypted;
+    reserved[4 + 42] = 0x02;
+    assert!(parse(&reserved).is_err());
+}
+
+#[test]
+fn framer_encrypted() {
+    let cipher = Cipher::Aes128Ccm;
+    let encrypted = encrypt(
+        &key(cipher),
+        cipher,
+        &nonce(cipher),
+        SESSION_ID,
+        &requests(),
+    )
+    .unwrap();
+    let mut framer = Framer::new(0x1_0000);
+    framer.push(&encrypted).unwrap();
+    assert_eq!(
+        framer.next_message().unwrap(),
+        Some(Message::Encrypted(encrypted))
+    );
+}
@@ -0,0 +1,241 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::request_header;
+use smb2_packet::command::RequestBody;
+use smb2_packet::transport::{
+    parse_session_packet, Error, Framer, Message, NetbiosName, SessionError, SessionPacket,
+};
+use smb2_packet::{parse, parse_smb1_nego_request, serialize, Dialect, Request};
+use std::convert::TryFrom;
+use std::net::Ipv4Addr;
+
+fn echo(message_id: u64) -> Vec<u8> {
+    let request = Request {
+        header: request_header(message_id),
+        body: RequestBody::Echo,
+    };
+    serialize(&[request], Dialect::Smb3_1_1)
+}
+
+fn smb1_negotiate() -> Vec<u8> {
+    let mut message = vec![0, 0, 0, 0x2e];
+    message.extend_from_slice(b"\xffSMB\x72");
+    message.extend_from_slice(&[0; 4]); /* status */
+    message.push(0x18);
+    message.extend_from_slice(&[0x01, 0xc8]);
+    message.extend_from_slice(&[0; 12]); /* pid high, signature, reserved */
+    message.extend_from_slice(&[0xff, 0xff, 0xff, 0xfe, 0, 0, 0, 0]);
+    message.push(0); /* word count */
+    message.extend_from_slice(&[0x0b, 0]); /* byte count */
+    message.extend_from_slice(b"\x02SMB 2.002\x00");
+    message
+}
+
+#[test]
+fn byte_by_byte() {
+    let data = echo(7);
+    let mut framer = Framer::new(0x1_0000);
+    assert_eq!(framer.needed(), 4);
+    for (i, byte) in data.iter().enumerate() {
+        assert_eq!(framer.next_message(), Ok(None));
+        framer.push(&[*byte]).unwrap();
+        if i < 3 {
+            assert_eq!(framer.needed(), 3 - i);
+        } else {
+            assert_eq!(framer.needed(), data.len() - i - 1);
+        }
+    }
+    let message = match framer.next_message().unwrap().unwrap() {
+        Message::Smb2(message) => message,
+        _ => panic!("Expected SMB2 message!"),
+    };
+    assert_eq!(message, data);
+    let (rem, requests) = parse::<Request>(&message, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(requests[0].header.message_id, 7);
+    assert_eq!(framer.needed(), 4);
+    assert_eq!(framer.next_message(), Ok(None));
+}
+
+#[test]
+fn multiple_messages_in_one_chunk() {
+    let first = smb1_negotiate();
+    let second = echo(1);
+    let third = echo(2);
+    let mut data = first.clone();
+    data.extend_from_slice(&second);
+    data.extend_from_slice(&third[..10]);
+
+    let mut framer = Framer::new(0x1_0000);
+    framer.push(&data).unwrap();
+    assert_eq!(framer.needed(), 0);
+    match framer.next_message().unwrap().unwrap() {
+        Message::Smb1(message) => {
+            assert_eq!(message, first);
+            assert!(parse_smb1_nego_request(&message).is_ok());
+        }
+        _ => panic!("Expected SMB1 message!"),
+    }
+    assert_eq!(framer.next_message().unwrap(), Some(Message::Smb2(second)));
+    assert_eq!(framer.next_message().unwrap(), None);
+    assert_eq!(framer.needed(), third.len() - 10);
+    framer.push(&third[10..]).unwrap();
+    assert_eq!(framer.next_message().unwrap(), Some(Message::Smb2(third)));
+}
+
+#[test]
+fn message_too_large() {
+    let mut framer = Framer::new(64);
+    framer.push(&echo(1)[..2]).unwrap();
+    // rejected as soon as the length is known
+    assert_eq!(
+        framer.push(&[0, 68]),
+        Err(Error::MessageTooLarge {
+            length: 68,
+            max: 64
+        })
+    );
+    // the rejected chunk is not buffered
+    assert_eq!(framer.needed(), 2);
+
+    let mut framer = Framer::new(usize::MAX);
+    assert!(framer.push(&[0, 0xff, 0xff, 0xff]).is_ok());
+    assert_eq!(framer.needed(), 0x00ff_ffff);
+}
+
+#[test]
+fn unknown_message_type() {
+    let mut framer = Framer::new(0x1_0000);
+    assert_eq!(
+        framer.push(&[0x86, 0, 0, 0]),
+        Err(Error::UnknownMessageType(0x86))
+    );
+}
+
+#[test]
+fn unknown_protocol() {
+    let mut framer = Framer::new(0x1_0000);
+    framer.push(&[0, 0, 0, 4, 0xfa, b'S', b'M', b'B']).unwrap();
+    assert_eq!(
+        framer.next_message(),
+        Err(Error::UnknownProtocol([0xfa, b'S', b'M', b'B']))
+    );
+}
+
+fn session_request() -> SessionPacket {
+    SessionPacket::Request {
+        called: NetbiosName::new("FRED", 0x20, "").unwrap(),
+        calling: NetbiosName::new("CLIENT", 0x00, "NETBIOS.COM").unwrap(),
+    }
+}
+
+fn roundtrip_session_packet(packet: &SessionPacket) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    packet.write_to(&mut buffer);
+    let (rem, parsed) = parse_session_packet(&buffer).unwrap();
+    assert!(rem.is_empty());
+    assert_eq!(parsed, *packet);
+    buffer
+}
+
+#[test]
+fn session_request_names() {
+    let buffer = roundtrip_session_packet(&session_request());
+    assert_eq!(&buffer[..4], &[0x81, 0, 0, 34 + 46]);
+    // first level encoding of "FRED" padded with spaces as in RFC 1001
+    assert_eq!(&buffer[4..5], &[0x20]);
+    assert_eq!(&buffer[5..37], b"EGFCEFEECACACACACACACACACACACACA");
+    assert_eq!(buffer[37], 0);
+    // calling name with the scope labels
+    assert_eq!(&buffer[38 + 31..38 + 33], b"AA");
+    assert_eq!(&buffer[38 + 33..], b"\x07NETBIOS\x03COM\x00");
+}
+
+#[test]
+fn netbios_name_limits() {
+    assert!(NetbiosName::new("FIFTEEN_BYTES_X", 0x20, "").is_some());
+    assert!(NetbiosName::new("SIXTEEN_BYTES_XX", 0x20, "").is_none());
+    assert!(NetbiosName::new("FRED", 0x20, &"A".repeat(63)).is_some());
+    assert!(NetbiosName::new("FRED", 0x20, &"A".repeat(64)).is_none());
+    assert!(NetbiosName::new("FRED", 0x20, "NETBIOS..COM").is_none());
+    assert!(NetbiosName::new("FRED", 0x20, ".COM").is_none());
+
+    // the length byte of a label could announce more than the 63 bytes allowed
+    let mut buffer = roundtrip_session_packet(&session_request());
+    buffer.truncate(38 + 33);
+    buffer.push(64);
+    buffer.extend_from_slice(&[b'A'; 64]);
+    buffer.push(0);
+    let length = u16::try_from(buffer.len() - 4).unwrap();
+    buffer[2..4].copy_from_slice(&length.to_be_bytes());
+    assert!(parse_session_packet(&buffer).is_err());
+}
+
+#[test]
+fn session_responses() {
+    assert_eq!(
+        roundtrip_session_packet(&SessionPacket::PositiveResponse),
+        [0x82, 0, 0, 0]
+    );
+    assert_eq!(
+        roundtrip_session_packet(&SessionPacket::NegativeResponse(
+            SessionError::CalledNameNotPresent
+        )),
+        [0x83, 0, 0, 1, 0x82]
+    );
+    assert_eq!(
+        roundtrip_session_packet(&SessionPacket::RetargetResponse {
+            address: Ipv4Addr::new(192, 168, 1, 10),
+            port: 139,
+        }),
+        [0x84, 0, 0, 6, 192, 168, 1, 10, 0, 139]
+    );
+    assert_eq!(
+        roundtrip_session_packet(&SessionPacket::KeepAlive),
+        [0x85, 0, 0, 0]
+    );
+}
+
+#[test]
+fn invalid_session_packets() {
+    // unknown negative response error code
+    assert!(parse_session_packet(&[0x83, 0, 0, 1, 0x84]).is_err());
+    // encoded name with letters outside of A to P
+    let mut buffer = Vec::new();
+    session_request().write_to(&mut buffer);
+    buffer[5] = b'Q';
+    assert!(parse_session_packet(&buffer).is_err());
+
+    let mut framer = Framer::new(0x1_0000);
+    framer.push(&buffer).unwrap();
+    assert_eq!(
+        framer.next_message(),
+        Err(Error::InvalidSessionPacket(0x81))
+    );
+}
+
+#[test]
+fn framer_session_service() {
+    let mut data = Vec::new();
+    session_request().write_to(&mut data);
+    SessionPacket::KeepAlive.write_to(&mut data);
+    SessionPacket::KeepAlive.write_to(&mut data);
+    data.extend_from_slice(&echo(3));
+    SessionPacket::KeepAlive.write_to(&mut data);
+
+    let mut framer = Framer::new(0x1_0000);
+    framer.push(&data).unwrap();
+    assert_eq!(
+        framer.next_message().unwrap(),
+        Some(Message::Session(session_request()))
+    );
+    // keepalives are skipped
+    assert_eq!(framer.next_message().unwrap(), Some(Message::Smb2(echo(3))));
+    assert_eq!(framer.next_message().unwrap(), None);
+    assert_eq!(framer.needed(), 4);
+}
@@ -0,0 +1,208 @@
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![deny(clippy::correctness)]
+
+#[allow(dead_code)]
+mod common;
+
+use crate::common::{response_header, roundtrip_request};
+use smb2_packet::command::tree_connect::{
+    Caching, Capabilities, Context, Flags, Privilege, RemotedIdentity, Request as TreeRequest,
+    Response as TreeResponse, ShareFlags, ShareType, SidAttr,
+};
+use smb2_packet::command::{RequestBody, ResponseBody};
+use smb2_packet::ntstatus::NTStatus;
+use smb2_packet::{parse, serialize, AccessMask, Dialect, Request, Response};
+
+const BODY: usize = 4 + 64;
+const USER_SID: [u8; 12] = [1, 1, 0, 0, 0, 0, 0, 5, 0x12, 0, 0, 0];
+const GROUP_SID: [u8; 16] = [1, 2, 0, 0, 0, 0, 0, 5, 0x20, 0, 0, 0, 0x20, 0x02, 0, 0];
+
+fn tree_request(body: TreeRequest) -> Vec<u8> {
+    roundtrip_request(4, RequestBody::TreeConnect(body), Dialect::Smb3_1_1)
+}
+
+fn parse_request(buffer: &[u8]) -> TreeRequest {
+    let (_, mut requests) = parse::<Request>(buffer, Dialect::Smb3_1_1).unwrap();
+    match requests.pop().unwrap().body {
+        RequestBody::TreeConnect(body) => body,
+        _ => panic!("Expected tree connect request!"),
+    }
+}
+
+fn identity() -> RemotedIdentity<'static> {
+    RemotedIdentity {
+        user: SidAttr {
+            sid: &USER_SID,
+            attributes: 0,
+        },
+        user_name: "SYSTEM".into(),
+        domain: "NT AUTHORITY".into(),
+        groups: vec![SidAttr {
+            sid: &GROUP_SID,
+            attributes: 0x0000_000f,
+        }],
+        restricted_groups: Vec::new(),
+        privileges: vec![Privilege {
+            luid: 0x17,
+            attributes: 0x03,
+        }],
+        primary_group: vec![SidAttr {
+            sid: &USER_SID,
+            attributes: 0,
+        }],
+        owner: &USER_SID,
+        default_dacl: &[],
+        device_groups: Vec::new(),
+        user_claims: &[],
+        device_claims: &[],
+    }
+}
+
+#[test]
+fn request_without_extension() {
+    let buffer = tree_request(TreeRequest {
+        flags: Flags::empty(),
+        path: "\\\\server\\share".into(),
+        contexts: Vec::new(),
+    });
+    // the path directly follows the fixed part
+    assert_eq!(&buffer[BODY + 4..BODY + 6], &[0x48, 0]);
+    let body = parse_request(&buffer);
+    assert_eq!(body.path, "\\\\server\\share");
+    assert!(body.contexts.is_empty());
+}
+
+#[test]
+fn request_extension_without_contexts() {
+    let buffer = tree_request(TreeRequest {
+        flags: Flags::EXTENSION_PRESENT,
+        path: "\\\\server\\share".into(),
+        contexts: Vec::new(),
+    });
+    // path offset is relative to the extension
+    assert_eq!(&buffer[BODY + 4..BODY + 6], &[16, 0]);
+    assert_eq!(&buffer[BODY + 8..BODY + 14], &[0; 6]);
+    let body = parse_request(&buffer);
+    assert_eq!(body.path, "\\\\server\\share");
+    assert!(body.contexts.is_empty());
+}
+
+#[test]
+fn request_extension_with_contexts() {
+    let buffer = tree_request(TreeRequest {
+        flags: Flags::EXTENSION_PRESENT | Flags::CLUSTER_RECONNECT,
+        path: "\\\\srv\\ipc$".into(),
+        contexts: vec![
+            Context::RemotedIdentity(Box::new(identity())),
+            Context::Unknown {
+                context_type: 0x7,
+                data: &[1, 2, 3],
+            },
+        ],
+    });
+    // the contexts follow the 20 byte path aligned to 8 bytes within the extension
+    assert_eq!(&buffer[BODY + 8..BODY + 14], &[40, 0, 0, 0, 2, 0]);
+    // ticket type, ticket size and the user right after the remoted identity header
+    assert_eq!(
+        &buffer[BODY + 8 + 40 + 8..BODY + 8 + 40 + 8 + 6],
+        &[1, 0, 170, 0, 28, 0]
+    );
+
+    let body = parse_request(&buffer);
+    assert_eq!(
+        body.flags,
+        Flags::EXTENSION_PRESENT | Flags::CLUSTER_RECONNECT
+    );
+    assert_eq!(body.path, "\\\\srv\\ipc$");
+    assert_eq!(body.contexts.len(), 2);
+    match &body.contexts[0] {
+        Context::RemotedIdentity(identity) => {
+            assert_eq!(identity.user.sid, USER_SID);
+            assert_eq!(identity.user_name, "SYSTEM");
+            assert_eq!(identity.domain, "NT AUTHORITY");
+            assert_eq!(identity.groups.len(), 1);
+            assert_eq!(identity.groups[0].sid, GROUP_SID);
+            assert_eq!(identity.groups[0].attributes, 0x0000_000f);
+            assert!(identity.restricted_groups.is_empty());
+            assert_eq!(identity.privileges.len(), 1);
+            assert_eq!(identity.privileges[0].luid, 0x17);
+            assert_eq!(identity.privileges[0].attributes, 0x03);
+            assert_eq!(identity.primary_group[0].sid, USER_SID);
+            assert_eq!(identity.owner, USER_SID);
+            assert!(identity.default_dacl.is_empty());
+            assert!(identity.device_claims.is_empty());
+        }
+        _ => panic!("Expected remoted identity!"),
+    }
+    match &body.contexts[1] {
+        Context::Unknown { context_type, data } => {
+            assert_eq!(*context_type, 0x7);
+            assert_eq!(*data, [1, 2, 3]);
+        }
+        _ => panic!("Expected unknown context!"),
+    }
+}
+
+#[test]
+fn request_remoted_identity_out_of_bounds() {
+    let mut buffer = tree_request(TreeRequest {
+        flags: Flags::EXTENSION_PRESENT,
+        path: "\\\\srv\\ipc$".into(),
+        contexts: vec![Context::RemotedIdentity(Box::new(identity()))],
+    });
+    // user name offset
+    buffer[BODY + 8 + 40 + 8 + 6] = 0xff;
+    assert!(parse::<Request>(&buffer, Dialect::Smb3_1_1).is_err());
+}
+
+#[test]
+fn response_capabilities() {
+    let response = Response {
+        header: response_header(NTStatus::StatusSuccess, 4),
+        body: ResponseBody::TreeConnect(TreeResponse {
+            share_type: ShareType::Disk,
+            caching: Caching::Manual,
+            share_flags: ShareFlags::ALLOW_NAMESPACE_CACHING | ShareFlags::IDENTITY_REMOTING,
+            capabilities: Capabilities::CONTINUOUS_AVAILABILITY | Capabilities::SCALEOUT,
+            maxmimal_access: AccessMask::GENERIC_READ,
+        }),
+    };
+    let buffer = serialize(&[response], Dialect::Smb3_1_1);
+    assert_eq!(&buffer[BODY + 4..BODY + 8], &[0x00, 0x04, 0x04, 0]);
+    assert_eq!(&buffer[BODY + 8..BODY + 12], &[0x30, 0, 0, 0]);
+    let (rem, responses) = parse::<Response>(&buffer, Dialect::Smb3_1_1).unwrap();
+    assert!(rem.is_empty());
+    match &responses[0].body {
+        ResponseBody::TreeConnect(body) => {
+            assert_eq!(
+                body.capabilities,
+                Capabilities::CONTINUOUS_AVAILABILITY | Capabilities::SCALEOUT
+            );
+            assert_eq!(
+                body.share_flags,
+                ShareFlags::ALLOW_NAMESPACE_CACHING | ShareFlags::IDENTITY_REMOTING
+            );
+        }
+        _ => panic!("Expected tree connect response!"),
+    }
+
+    // unknown share flags and capabilities are dropped
+    let mut unknown = buffer;
+    unknown[BODY + 7] |= 0x80;
+    unknown[BODY + 8] |= 0x01;
+    let (_, responses) = parse::<Response>(&unknown, Dialect::Smb3_1_1).unwrap();
+    match &responses[0].body {
+        ResponseBody::TreeConnect(body) => {
+            assert_eq!(
+                body.capabilities,
+                Capabilities::CONTINUOUS_AVAILABILITY | Capabilities::SCALEOUT
+            );
+            assert_eq!(
+                body.share_flags,
+                ShareFlags::ALLOW_NAMESPACE_CACHING | ShareFlags::IDENTITY_REMOTING
+            );
+        }
+        _ => panic!("Expected tree connect response!"),
+    }
+}